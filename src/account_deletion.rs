@@ -0,0 +1,66 @@
+use crate::services::{AuthService, KeyRing, UserService};
+use redis::Client as RedisClient;
+use rocket::{
+    fairing::{Fairing, Kind},
+    Orbit, Rocket,
+};
+use sea_orm::DatabaseConnection;
+use std::time::Duration;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deletes accounts whose deletion grace period is over, checked once an hour
+pub struct AccountDeletion;
+
+#[rocket::async_trait]
+impl Fairing for AccountDeletion {
+    fn info(&self) -> rocket::fairing::Info {
+        rocket::fairing::Info {
+            kind: Kind::Liftoff,
+            name: "Account deletion",
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(db), Some(redis), Some(key_ring)) = (
+            rocket.state::<DatabaseConnection>().cloned(),
+            rocket.state::<RedisClient>().cloned(),
+            rocket.state::<KeyRing>().cloned(),
+        ) else {
+            tracing::warn!("Account deletion is not configured -- due deletions will not run");
+            return;
+        };
+
+        rocket::tokio::spawn(async move {
+            let user_service = UserService::new(db.clone());
+            let mut interval = rocket::tokio::time::interval(PURGE_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let connection = match redis.get_async_connection().await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        tracing::error!(message = "Unable to reach redis for account deletion", error = %e);
+                        continue;
+                    }
+                };
+                let mut auth_service =
+                    AuthService::new(db.clone(), Box::new(connection), key_ring.clone());
+
+                match user_service.purge_due_deletions(&mut auth_service).await {
+                    Ok((0, 0)) => {}
+                    Ok((deleted, 0)) => tracing::info!(message = "Deleted due accounts", deleted),
+                    Ok((deleted, failed)) => {
+                        tracing::warn!(
+                            message = "Some due accounts were not deleted",
+                            deleted,
+                            failed
+                        )
+                    }
+                    Err(e) => tracing::error!(message = "Account deletion failed", error = %e),
+                }
+            }
+        });
+    }
+}
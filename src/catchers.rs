@@ -13,6 +13,13 @@ pub fn unauthorized() -> Json<ErrorResponse> {
     })
 }
 
+#[catch(403)]
+pub fn forbidden() -> Json<ErrorResponse> {
+    Json(ErrorResponse {
+        error: "You are not allowed to perform this action.".into(),
+    })
+}
+
 #[catch(500)]
 pub fn internal_error() -> Json<ErrorResponse> {
     Json(ErrorResponse {
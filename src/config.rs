@@ -0,0 +1,18 @@
+use anyhow::anyhow;
+use rocket::figment::Figment;
+use serde::de::DeserializeOwned;
+
+/// Reads a section of the Rocket config. A missing section falls back to its
+/// defaults, one that is present but can't be read is an error instead of
+/// being silently ignored.
+pub fn extract_section<T: Default + DeserializeOwned>(
+    figment: &Figment,
+    key: &str,
+) -> anyhow::Result<T> {
+    match figment.find_value(key) {
+        Err(e) if e.missing() => Ok(T::default()),
+        _ => figment
+            .extract_inner(key)
+            .map_err(|e| anyhow!("Invalid `{key}` config: {e}")),
+    }
+}
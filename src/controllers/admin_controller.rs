@@ -0,0 +1,223 @@
+use crate::{
+    dtos::{
+        admin::{AdminUserPage, AdminUserReturn, AuditLogPage},
+        blocklist::BlockedTermReturn,
+        category::{CategoryAttributeReturn, CategoryReturn},
+    },
+    models::{
+        admin::{AdminAction, RoleChange, Suspension},
+        blocklist::BlockedTermCreate,
+        category::{CategoryAttributeCreate, CategoryDetails},
+        permission::{ManageBlocklist, ManageCategories, ManageUsers},
+        user::RequirePermission,
+    },
+    services::{
+        AdminService, AdminServiceError, AuthService, BlocklistService, BlocklistServiceError,
+        CategoryService, CategoryServiceError,
+    },
+};
+use rocket::{serde::json::Json, Route};
+
+#[tracing::instrument(level = "trace")]
+#[get("/users?<q>&<page>&<per_page>")]
+async fn list_users(
+    admin_service: AdminService,
+    _admin: RequirePermission<ManageUsers>,
+    q: Option<&str>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<Json<AdminUserPage>, AdminServiceError> {
+    Ok(Json(admin_service.list_users(q, page, per_page).await?))
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/users/<id>")]
+async fn get_user(
+    admin_service: AdminService,
+    _admin: RequirePermission<ManageUsers>,
+    id: i64,
+) -> Result<Json<AdminUserReturn>, AdminServiceError> {
+    Ok(Json(admin_service.get_user(id).await?))
+}
+
+#[tracing::instrument(level = "trace")]
+#[put("/users/<id>/role", data = "<change>")]
+async fn change_role(
+    admin_service: AdminService,
+    mut auth_service: AuthService,
+    admin: RequirePermission<ManageUsers>,
+    id: i64,
+    change: Json<RoleChange>,
+) -> Result<Json<AdminUserReturn>, AdminServiceError> {
+    Ok(Json(
+        admin_service
+            .change_role(&admin.user, id, change.0, &mut auth_service)
+            .await?,
+    ))
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/users/<id>/suspend", data = "<suspension>")]
+async fn suspend_user(
+    admin_service: AdminService,
+    mut auth_service: AuthService,
+    admin: RequirePermission<ManageUsers>,
+    id: i64,
+    suspension: Json<Suspension>,
+) -> Result<Json<AdminUserReturn>, AdminServiceError> {
+    Ok(Json(
+        admin_service
+            .suspend(&admin.user, id, suspension.0, &mut auth_service)
+            .await?,
+    ))
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/users/<id>/unsuspend", data = "<action>")]
+async fn unsuspend_user(
+    admin_service: AdminService,
+    mut auth_service: AuthService,
+    admin: RequirePermission<ManageUsers>,
+    id: i64,
+    action: Json<AdminAction>,
+) -> Result<Json<AdminUserReturn>, AdminServiceError> {
+    Ok(Json(
+        admin_service
+            .unsuspend(&admin.user, id, action.0, &mut auth_service)
+            .await?,
+    ))
+}
+
+/// Ends every session the user has, they will need to log in again once their JWT expires
+#[tracing::instrument(level = "trace")]
+#[post("/users/<id>/logout", data = "<action>")]
+async fn force_logout(
+    admin_service: AdminService,
+    mut auth_service: AuthService,
+    admin: RequirePermission<ManageUsers>,
+    id: i64,
+    action: Json<AdminAction>,
+) -> Result<(), AdminServiceError> {
+    admin_service
+        .force_logout(&admin.user, id, action.0, &mut auth_service)
+        .await
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/audit?<user_id>&<page>&<per_page>")]
+async fn audit_log(
+    admin_service: AdminService,
+    _admin: RequirePermission<ManageUsers>,
+    user_id: Option<i64>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<Json<AuditLogPage>, AdminServiceError> {
+    Ok(Json(
+        admin_service.audit_log(user_id, page, per_page).await?,
+    ))
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/blocklist")]
+async fn list_blocked_terms(
+    blocklist_service: BlocklistService,
+    _moderator: RequirePermission<ManageBlocklist>,
+) -> Result<Json<Vec<BlockedTermReturn>>, BlocklistServiceError> {
+    Ok(Json(blocklist_service.list_terms().await?))
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/blocklist", data = "<term>")]
+async fn add_blocked_term(
+    blocklist_service: BlocklistService,
+    moderator: RequirePermission<ManageBlocklist>,
+    term: Json<BlockedTermCreate>,
+) -> Result<Json<BlockedTermReturn>, BlocklistServiceError> {
+    Ok(Json(
+        blocklist_service.add_term(&moderator.user, term.0).await?,
+    ))
+}
+
+#[tracing::instrument(level = "trace")]
+#[delete("/blocklist/<id>")]
+async fn remove_blocked_term(
+    blocklist_service: BlocklistService,
+    moderator: RequirePermission<ManageBlocklist>,
+    id: i64,
+) -> Result<(), BlocklistServiceError> {
+    blocklist_service.remove_term(&moderator.user, id).await
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/categories", data = "<category>")]
+async fn create_category(
+    category_service: CategoryService,
+    _admin: RequirePermission<ManageCategories>,
+    category: Json<CategoryDetails>,
+) -> Result<Json<CategoryReturn>, CategoryServiceError> {
+    Ok(Json(category_service.create_category(category.0).await?))
+}
+
+#[tracing::instrument(level = "trace")]
+#[put("/categories/<id>", data = "<category>")]
+async fn update_category(
+    category_service: CategoryService,
+    _admin: RequirePermission<ManageCategories>,
+    id: i64,
+    category: Json<CategoryDetails>,
+) -> Result<Json<CategoryReturn>, CategoryServiceError> {
+    Ok(Json(
+        category_service.update_category(id, category.0).await?,
+    ))
+}
+
+#[tracing::instrument(level = "trace")]
+#[delete("/categories/<id>")]
+async fn delete_category(
+    category_service: CategoryService,
+    _admin: RequirePermission<ManageCategories>,
+    id: i64,
+) -> Result<(), CategoryServiceError> {
+    category_service.delete_category(id).await
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/categories/<id>/attributes", data = "<attribute>")]
+async fn add_category_attribute(
+    category_service: CategoryService,
+    _admin: RequirePermission<ManageCategories>,
+    id: i64,
+    attribute: Json<CategoryAttributeCreate>,
+) -> Result<Json<CategoryAttributeReturn>, CategoryServiceError> {
+    Ok(Json(category_service.add_attribute(id, attribute.0).await?))
+}
+
+#[tracing::instrument(level = "trace")]
+#[delete("/categories/attributes/<id>")]
+async fn remove_category_attribute(
+    category_service: CategoryService,
+    _admin: RequirePermission<ManageCategories>,
+    id: i64,
+) -> Result<(), CategoryServiceError> {
+    category_service.remove_attribute(id).await
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_users,
+        get_user,
+        change_role,
+        suspend_user,
+        unsuspend_user,
+        force_logout,
+        audit_log,
+        list_blocked_terms,
+        add_blocked_term,
+        remove_blocked_term,
+        create_category,
+        update_category,
+        delete_category,
+        add_category_attribute,
+        remove_category_attribute
+    ]
+}
@@ -1,23 +1,48 @@
 use crate::{
-    models::user::{AuthUser, RefreshAuthUser, UserLogin, UserRegister},
-    services::{AuthService, AuthServiceError, UserService, UserServiceError},
+    dtos::auth::{
+        Jwks, KeyRotated, LoginOutcome, PersonalAccessTokenCreated, PersonalAccessTokenReturn,
+        RecoveryCodes, SessionReturn, TotpEnrollment, TwoFactorChallengeReturn,
+    },
+    mail::Mailer,
+    models::{
+        permission::ManageSigningKeys,
+        scope::{ReadAccess, Scope},
+        session::SessionMetadata,
+        user::{
+            AuthUser, EmailVerification, PasswordResetConfirm, PasswordResetRequest,
+            PersonalAccessTokenCreate, RefreshAuthUser, RequirePermission, ScopedAuthUser,
+            TotpCode, TotpDisable, TwoFactorLogin, UserLogin, UserRegister,
+        },
+    },
+    services::{
+        AuthService, AuthServiceError, KeyRing, UserService, UserServiceError,
+        REFRESH_TOKEN_VALIDITY_DAYS,
+    },
 };
 use rocket::{
     http::{Cookie, CookieJar},
-    response::status::Created,
+    response::status::{Accepted, Created},
     serde::json::Json,
     time::{Duration, OffsetDateTime},
-    Route,
+    Route, State,
 };
+use sea_orm::DatabaseConnection;
 use serde::Serialize;
 
-#[tracing::instrument(level = "trace")]
+#[cfg(test)]
+mod test;
+
+#[tracing::instrument(level = "trace", skip(mailer))]
 #[post("/register", format = "json", data = "<user_register>")]
 async fn register(
     user_service: UserService,
+    mailer: &State<Mailer>,
     user_register: Json<UserRegister>,
 ) -> Result<Created<()>, UserServiceError> {
-    user_service.create_user(user_register.0, false).await?;
+    let user_id = user_service.create_user(user_register.0, false).await?;
+    user_service
+        .send_verification_email(user_id, mailer.inner().as_ref())
+        .await?;
 
     let created_response = Created::new("");
 
@@ -26,7 +51,14 @@ async fn register(
 
 #[derive(Serialize)]
 pub struct JwtReturn {
-    jwt: String,
+    pub jwt: String,
+}
+
+#[derive(Responder)]
+enum LoginResponse {
+    Authenticated(Json<JwtReturn>),
+    #[response(status = 202)]
+    TwoFactorRequired(Json<TwoFactorChallengeReturn>),
 }
 
 #[tracing::instrument(level = "error")]
@@ -35,12 +67,53 @@ async fn login(
     user_service: UserService,
     auth_service: AuthService,
     login: Json<UserLogin>,
+    session: SessionMetadata,
+    cookies: &CookieJar<'_>,
+) -> Result<LoginResponse, UserServiceError> {
+    let token = match user_service.login(login.0, auth_service, session).await? {
+        LoginOutcome::Authenticated(token) => token,
+        LoginOutcome::TwoFactorRequired(challenge) => {
+            return Ok(LoginResponse::TwoFactorRequired(Json(challenge)))
+        }
+    };
+
+    let mut refresh_expires = OffsetDateTime::now_utc();
+    refresh_expires += Duration::days(REFRESH_TOKEN_VALIDITY_DAYS);
+
+    let refresh_cookie = Cookie::build("refresh", token.refresh_token)
+        .http_only(true)
+        .expires(refresh_expires)
+        .same_site(rocket::http::SameSite::Strict)
+        .secure(true)
+        .finish();
+
+    cookies.add(refresh_cookie);
+
+    Ok(LoginResponse::Authenticated(Json(JwtReturn {
+        jwt: token.jwt,
+    })))
+}
+
+#[tracing::instrument(level = "error", skip(two_factor))]
+#[post("/login/2fa", format = "json", data = "<two_factor>")]
+async fn login_two_factor(
+    user_service: UserService,
+    mut auth_service: AuthService,
+    two_factor: Json<TwoFactorLogin>,
+    session: SessionMetadata,
     cookies: &CookieJar<'_>,
 ) -> Result<Json<JwtReturn>, UserServiceError> {
-    let token = user_service.login(login.0, auth_service).await?;
+    let token = user_service
+        .complete_two_factor_login(
+            &two_factor.challenge,
+            &two_factor.code,
+            &mut auth_service,
+            session,
+        )
+        .await?;
 
     let mut refresh_expires = OffsetDateTime::now_utc();
-    refresh_expires += Duration::days(30);
+    refresh_expires += Duration::days(REFRESH_TOKEN_VALIDITY_DAYS);
 
     let refresh_cookie = Cookie::build("refresh", token.refresh_token)
         .http_only(true)
@@ -61,14 +134,17 @@ async fn refresh_login(
     auth_user: RefreshAuthUser,
     cookies: &CookieJar<'_>,
 ) -> Result<Json<JwtReturn>, AuthServiceError> {
+    let refresh_token = auth_service
+        .rotate_refresh_token(&auth_user.refresh_token)
+        .await?;
     let jwt = auth_service
-        .generate_jwt(&auth_user.user, &auth_user.refresh_token, None)
+        .generate_jwt(&auth_user.user, &refresh_token, None)
         .await?;
 
     let mut refresh_expires = OffsetDateTime::now_utc();
-    refresh_expires += Duration::days(30);
+    refresh_expires += Duration::days(REFRESH_TOKEN_VALIDITY_DAYS);
 
-    let refresh_cookie = Cookie::build("refresh", auth_user.refresh_token.to_owned())
+    let refresh_cookie = Cookie::build("refresh", refresh_token)
         .http_only(true)
         .expires(refresh_expires)
         .same_site(rocket::http::SameSite::Strict)
@@ -101,6 +177,246 @@ async fn revoke_refresh_token(
     Ok(())
 }
 
+#[tracing::instrument(level = "trace")]
+#[get("/sessions")]
+async fn list_sessions(
+    mut auth_service: AuthService,
+    auth_user: ScopedAuthUser<ReadAccess>,
+    cookies: &CookieJar<'_>,
+) -> Result<Json<Vec<SessionReturn>>, AuthServiceError> {
+    let current = cookies.get("refresh").map(|c| c.value().to_owned());
+    let sessions = auth_service.list_sessions(auth_user.user.id).await?;
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionReturn {
+                current: current.as_deref() == Some(session.token.as_str()),
+                id: session.id,
+                device_name: session.device_name,
+                user_agent: session.user_agent,
+                ip_address: session.ip_address,
+                created_at: session.created_at,
+                last_used_at: session.last_used_at,
+                expires_at: session.expires_at,
+            })
+            .collect(),
+    ))
+}
+
+#[tracing::instrument(level = "trace")]
+#[delete("/sessions?<id>")]
+async fn revoke_session(
+    mut auth_service: AuthService,
+    auth_user: AuthUser,
+    id: i64,
+    cookies: &CookieJar<'_>,
+) -> Result<(), AuthServiceError> {
+    let revoked = auth_service.revoke_session(auth_user.user.id, id).await?;
+
+    if cookies.get("refresh").map(|c| c.value()) == Some(revoked.as_str()) {
+        cookies.remove(Cookie::named("refresh"));
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(level = "trace", skip(mailer))]
+#[post("/password_reset/request", format = "json", data = "<reset_request>")]
+async fn request_password_reset(
+    user_service: UserService,
+    mailer: &State<Mailer>,
+    reset_request: Json<PasswordResetRequest>,
+) -> Result<Accepted<()>, UserServiceError> {
+    user_service
+        .request_password_reset(&reset_request.email, mailer.inner().as_ref())
+        .await?;
+
+    Ok(Accepted(None))
+}
+
+#[tracing::instrument(level = "trace", skip(reset_confirm))]
+#[post("/password_reset/confirm", format = "json", data = "<reset_confirm>")]
+async fn confirm_password_reset(
+    user_service: UserService,
+    mut auth_service: AuthService,
+    reset_confirm: Json<PasswordResetConfirm>,
+    cookies: &CookieJar<'_>,
+) -> Result<(), UserServiceError> {
+    user_service
+        .confirm_password_reset(
+            &reset_confirm.token,
+            &reset_confirm.password,
+            &mut auth_service,
+        )
+        .await?;
+
+    cookies.remove(Cookie::named("refresh"));
+
+    Ok(())
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/verify_email", format = "json", data = "<verification>")]
+async fn verify_email(
+    user_service: UserService,
+    verification: Json<EmailVerification>,
+) -> Result<(), UserServiceError> {
+    user_service.verify_email(&verification.token).await
+}
+
+#[tracing::instrument(level = "trace", skip(mailer))]
+#[post("/verify_email/resend")]
+async fn resend_verification_email(
+    user_service: UserService,
+    auth_user: AuthUser,
+    mailer: &State<Mailer>,
+) -> Result<Accepted<()>, UserServiceError> {
+    user_service
+        .send_verification_email(auth_user.user.id, mailer.inner().as_ref())
+        .await?;
+
+    Ok(Accepted(None))
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/2fa/enroll")]
+async fn enroll_two_factor(
+    user_service: UserService,
+    auth_user: AuthUser,
+) -> Result<Json<TotpEnrollment>, UserServiceError> {
+    let enrollment = user_service
+        .begin_totp_enrollment(auth_user.user.id)
+        .await?;
+
+    Ok(Json(enrollment))
+}
+
+#[tracing::instrument(level = "trace", skip(code))]
+#[post("/2fa/confirm", format = "json", data = "<code>")]
+async fn confirm_two_factor(
+    user_service: UserService,
+    auth_user: AuthUser,
+    code: Json<TotpCode>,
+) -> Result<Json<RecoveryCodes>, UserServiceError> {
+    let recovery_codes = user_service
+        .confirm_totp_enrollment(auth_user.user.id, &code.code)
+        .await?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+#[tracing::instrument(level = "trace", skip(disable))]
+#[post("/2fa/disable", format = "json", data = "<disable>")]
+async fn disable_two_factor(
+    user_service: UserService,
+    auth_user: AuthUser,
+    disable: Json<TotpDisable>,
+) -> Result<(), UserServiceError> {
+    user_service
+        .disable_totp(auth_user.user.id, &disable.password, &disable.code)
+        .await
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/tokens", format = "json", data = "<create>")]
+async fn create_personal_access_token(
+    mut auth_service: AuthService,
+    auth_user: AuthUser,
+    create: Json<PersonalAccessTokenCreate>,
+) -> Result<Created<Json<PersonalAccessTokenCreated>>, AuthServiceError> {
+    let created = auth_service
+        .create_personal_access_token(auth_user.user.id, create.0)
+        .await?;
+
+    Ok(Created::new("/api/auth/tokens").body(Json(created)))
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/tokens")]
+async fn list_personal_access_tokens(
+    mut auth_service: AuthService,
+    auth_user: ScopedAuthUser<ReadAccess>,
+) -> Result<Json<Vec<PersonalAccessTokenReturn>>, AuthServiceError> {
+    let tokens = auth_service
+        .list_personal_access_tokens(auth_user.user.id)
+        .await?;
+
+    Ok(Json(
+        tokens
+            .into_iter()
+            .map(|token| PersonalAccessTokenReturn {
+                id: token.id,
+                name: token.name,
+                token_prefix: token.token_prefix,
+                scopes: Scope::parse_list(&token.scopes).unwrap_or_default(),
+                created_at: token.created_at,
+                last_used_at: token.last_used_at,
+                expires_at: token.expires_at,
+            })
+            .collect(),
+    ))
+}
+
+#[tracing::instrument(level = "trace")]
+#[delete("/tokens?<id>")]
+async fn revoke_personal_access_token(
+    mut auth_service: AuthService,
+    auth_user: AuthUser,
+    id: i64,
+) -> Result<(), AuthServiceError> {
+    auth_service
+        .revoke_personal_access_token(auth_user.user.id, id)
+        .await
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/keys/rotate")]
+async fn rotate_signing_key(
+    mut auth_service: AuthService,
+    _admin: RequirePermission<ManageSigningKeys>,
+) -> Result<Json<KeyRotated>, AuthServiceError> {
+    let kid = auth_service.rotate_signing_key().await?;
+
+    Ok(Json(KeyRotated { kid }))
+}
+
+/// Public keys for verifying our JWTs, so other services can check tokens
+/// without calling back into this one
+#[tracing::instrument(level = "trace")]
+#[get("/jwks.json")]
+async fn jwks(
+    key_ring: &State<KeyRing>,
+    db: &State<DatabaseConnection>,
+) -> Result<Json<Jwks>, AuthServiceError> {
+    key_ring.reload_if_stale(db).await?;
+
+    Ok(Json(key_ring.jwks()?))
+}
+
+pub fn well_known_routes() -> Vec<Route> {
+    routes![jwks]
+}
+
 pub fn routes() -> Vec<Route> {
-    routes![register, login, refresh_login, revoke_refresh_token]
+    routes![
+        register,
+        login,
+        refresh_login,
+        revoke_refresh_token,
+        list_sessions,
+        revoke_session,
+        rotate_signing_key,
+        request_password_reset,
+        confirm_password_reset,
+        verify_email,
+        resend_verification_email,
+        login_two_factor,
+        enroll_two_factor,
+        confirm_two_factor,
+        disable_two_factor,
+        create_personal_access_token,
+        list_personal_access_tokens,
+        revoke_personal_access_token
+    ]
 }
@@ -0,0 +1,78 @@
+use crate::create_rocket_instance;
+use crate::{
+    db::test::establish_connection,
+    dtos::auth::Jwks,
+    mail::test::CapturingMailSender,
+    models::user::UserRegister,
+    services::{KeyEncryptionKey, KeyRing, UserService},
+};
+use rocket::{
+    http::{ContentType, Status},
+    local::asynchronous::Client,
+};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_jwks_lists_active_and_retired_keys() -> anyhow::Result<()> {
+    let db = establish_connection().await?;
+    let key_ring = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+    let retired_kid = key_ring.active_kid().unwrap();
+    let active_kid = key_ring.rotate(&db).await?;
+
+    let rocket = create_rocket_instance(Some(db)).await?;
+    let client = Client::tracked(rocket).await?;
+
+    let res = client.get("/.well-known/jwks.json").dispatch().await;
+
+    assert_eq!(Status::Ok, res.status());
+    let jwks: Jwks = serde_json::from_str(&res.into_string().await.unwrap())?;
+    let kids: Vec<_> = jwks.keys.iter().map(|key| key.kid.as_str()).collect();
+    assert_eq!(kids.len(), 2);
+    assert!(kids.contains(&active_kid.as_str()));
+    assert!(kids.contains(&retired_kid.as_str()));
+    assert!(jwks
+        .keys
+        .iter()
+        .all(|key| key.kty == "OKP" && key.crv == "Ed25519"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_password_reset_request_is_accepted_for_any_email() -> anyhow::Result<()> {
+    let db = establish_connection().await?;
+    UserService::new(db.clone())
+        .create_user(
+            UserRegister {
+                username: String::from("testUser"),
+                email: String::from("test@email.com"),
+                password: String::from("testPassword"),
+            },
+            false,
+        )
+        .await?;
+
+    let rocket = create_rocket_instance(Some(db)).await?;
+    let client = Client::tracked(rocket).await?;
+
+    for email in ["test@email.com", "unknown@email.com"] {
+        let res = client
+            .post("/api/auth/password_reset/request")
+            .header(ContentType::JSON)
+            .body(json!({ "email": email }).to_string())
+            .dispatch()
+            .await;
+
+        assert_eq!(Status::Accepted, res.status());
+    }
+
+    let sent = client
+        .rocket()
+        .state::<CapturingMailSender>()
+        .unwrap()
+        .sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "test@email.com");
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use crate::{
+    dtos::category::{CategoryAttributeReturn, CategoryTree},
+    services::{CategoryService, CategoryServiceError},
+};
+use rocket::{serde::json::Json, Route};
+
+#[tracing::instrument(level = "trace")]
+#[get("/")]
+async fn get_category_tree(
+    category_service: CategoryService,
+) -> Result<Json<Vec<CategoryTree>>, CategoryServiceError> {
+    Ok(Json(category_service.get_tree().await?))
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/<id>/attributes")]
+async fn get_category_attributes(
+    category_service: CategoryService,
+    id: i64,
+) -> Result<Json<Vec<CategoryAttributeReturn>>, CategoryServiceError> {
+    Ok(Json(category_service.get_attributes(id).await?))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![get_category_tree, get_category_attributes]
+}
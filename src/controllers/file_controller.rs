@@ -1,10 +1,10 @@
 use crate::{
     dtos::product::FileResponder,
-    models::user::AuthUser,
+    models::{file::FileOwner, scope::FilesWrite, user::ScopedAuthUser},
     services::{FileService, FileServiceError},
 };
 use anyhow::anyhow;
-use rocket::{form::Form, fs::TempFile, Route};
+use rocket::{form::Form, fs::TempFile, serde::json::Json, Route};
 
 #[derive(FromForm, Debug)]
 struct UploadData<'a> {
@@ -14,7 +14,7 @@ struct UploadData<'a> {
 #[tracing::instrument(level = "trace")]
 #[post("/upload?<product_id>", data = "<data>")]
 async fn upload_file<'a>(
-    user: AuthUser,
+    user: ScopedAuthUser<FilesWrite>,
     file_service: FileService,
     mut data: Form<Option<UploadData<'a>>>,
     product_id: i64,
@@ -28,12 +28,42 @@ async fn upload_file<'a>(
     let file = data.data;
 
     file_service
-        .create_file_data(user, file, product_id)
+        .create_file_data(user.into(), file, FileOwner::Product(product_id))
         .await?;
 
     Ok(())
 }
 
+/// Uploads a new avatar, the previous one is deleted
+#[tracing::instrument(level = "trace")]
+#[put("/avatar", data = "<data>")]
+async fn upload_avatar<'a>(
+    user: ScopedAuthUser<FilesWrite>,
+    file_service: FileService,
+    mut data: Form<Option<UploadData<'a>>>,
+) -> Result<Json<i64>, FileServiceError> {
+    let data = data
+        .take()
+        .ok_or(FileServiceError::FileCreationError(crate::AnyhowResponder(
+            anyhow!("No files found to process"),
+        )))?;
+
+    let file_id = file_service
+        .create_file_data(user.into(), data.data, FileOwner::Avatar)
+        .await?;
+
+    Ok(Json(file_id))
+}
+
+#[tracing::instrument(level = "trace")]
+#[delete("/avatar")]
+async fn delete_avatar(
+    user: ScopedAuthUser<FilesWrite>,
+    file_service: FileService,
+) -> Result<(), FileServiceError> {
+    file_service.remove_avatar(user.into()).await
+}
+
 #[tracing::instrument(level = "trace")]
 #[get("/get_file?<id>")]
 async fn get_file(id: i64, file_service: FileService) -> Result<FileResponder, FileServiceError> {
@@ -42,5 +72,5 @@ async fn get_file(id: i64, file_service: FileService) -> Result<FileResponder, F
 }
 
 pub fn routes() -> Vec<Route> {
-    routes![upload_file, get_file]
+    routes![upload_file, upload_avatar, delete_avatar, get_file]
 }
@@ -1,5 +1,8 @@
 use rocket::{Build, Rocket};
+mod admin_controller;
 mod auth_controller;
+mod category_controller;
+mod oidc_controller;
 mod product_controller;
 mod user_controller;
 mod file_controller;
@@ -12,7 +15,11 @@ fn options() {
 pub fn mount_routes(r: Rocket<Build>) -> Rocket<Build> {
     r.mount("/api/users", user_controller::routes())
         .mount("/api/products", product_controller::routes())
+        .mount("/api/categories", category_controller::routes())
         .mount("/api/auth", auth_controller::routes())
+        .mount("/api/auth/oidc", oidc_controller::routes())
         .mount("/api/files", file_controller::routes())
+        .mount("/api/admin", admin_controller::routes())
+        .mount("/.well-known", auth_controller::well_known_routes())
         .mount("/", routes![options])
 }
@@ -0,0 +1,83 @@
+use crate::{
+    dtos::auth::{AuthorizationUrl, LoginOutcome, TwoFactorChallengeReturn},
+    models::{oidc::OidcCallback, session::SessionMetadata},
+    services::{
+        AuthService, OidcService, OidcServiceError, UserService, REFRESH_TOKEN_VALIDITY_DAYS,
+    },
+};
+use rocket::{
+    http::{Cookie, CookieJar},
+    serde::json::Json,
+    time::{Duration, OffsetDateTime},
+    Route,
+};
+
+use super::auth_controller::JwtReturn;
+
+#[derive(Responder)]
+enum CallbackResponse {
+    Authenticated(Json<JwtReturn>),
+    #[response(status = 202)]
+    TwoFactorRequired(Json<TwoFactorChallengeReturn>),
+}
+
+/// Starts a sign in with `provider`. The client sends the user to the returned url.
+#[tracing::instrument(level = "trace")]
+#[get("/<provider>/authorize")]
+async fn authorize(
+    mut oidc_service: OidcService,
+    provider: &str,
+) -> Result<Json<AuthorizationUrl>, OidcServiceError> {
+    let authorization_url = oidc_service.authorization_url(provider).await?;
+
+    Ok(Json(AuthorizationUrl { authorization_url }))
+}
+
+/// Finishes a sign in with the `code` and `state` the provider redirected back with
+#[tracing::instrument(level = "error", skip(callback))]
+#[post("/<provider>/callback", format = "json", data = "<callback>")]
+async fn callback(
+    mut oidc_service: OidcService,
+    user_service: UserService,
+    mut auth_service: AuthService,
+    provider: &str,
+    callback: Json<OidcCallback>,
+    session: SessionMetadata,
+    cookies: &CookieJar<'_>,
+) -> Result<CallbackResponse, OidcServiceError> {
+    let token = match oidc_service
+        .complete_login(
+            provider,
+            callback.0,
+            &user_service,
+            &mut auth_service,
+            session,
+        )
+        .await?
+    {
+        LoginOutcome::Authenticated(token) => token,
+        LoginOutcome::TwoFactorRequired(challenge) => {
+            return Ok(CallbackResponse::TwoFactorRequired(Json(challenge)))
+        }
+    };
+
+    let mut refresh_expires = OffsetDateTime::now_utc();
+    refresh_expires += Duration::days(REFRESH_TOKEN_VALIDITY_DAYS);
+
+    let refresh_cookie = Cookie::build("refresh", token.refresh_token)
+        .http_only(true)
+        .expires(refresh_expires)
+        .same_site(rocket::http::SameSite::Strict)
+        .secure(true)
+        .finish();
+
+    cookies.add(refresh_cookie);
+
+    Ok(CallbackResponse::Authenticated(Json(JwtReturn {
+        jwt: token.jwt,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![authorize, callback]
+}
@@ -1,8 +1,12 @@
 use crate::{
-    dtos::product::{ProductCreated, ProductFilter},
+    dtos::product::{ProductCreated, ProductFilter, ProductHistory},
     models::{
-        product::{ProductDetails, ProductLocationReturn, ProductReturn, ProductReturnNoUser},
-        user::AuthUser,
+        product::{
+            ListingRenewed, ListingStatus, ProductDetails, ProductLocationReturn, ProductReturn,
+            ProductReturnNoUser, StatusChange,
+        },
+        scope::{ProductsWrite, ReadAccess},
+        user::ScopedAuthUser,
     },
     services::{FileService, ProductService, ProductServiceError},
 };
@@ -17,10 +21,10 @@ use rocket::{
 async fn create_product(
     product_service: ProductService,
     product_create: Json<ProductDetails>,
-    auth_user: AuthUser,
+    auth_user: ScopedAuthUser<ProductsWrite>,
 ) -> Result<Created<Json<ProductCreated>>, ProductServiceError> {
     let id = product_service
-        .create_new_product(product_create.0, auth_user)
+        .create_new_product(product_create.0, auth_user.into())
         .await?;
 
     let created =
@@ -34,35 +38,87 @@ async fn create_product(
 async fn get_product_by_id(
     product_service: ProductService,
     id: i64,
+    viewer: Option<ScopedAuthUser<ReadAccess>>,
 ) -> Result<Json<ProductReturn>, ProductServiceError> {
-    let found_product = product_service.get_product_by_id(id).await?;
+    let found_product = product_service
+        .get_visible_product(id, viewer.as_ref().map(|viewer| &viewer.user))
+        .await?;
 
     Ok(Json(found_product))
 }
 
+#[tracing::instrument(level = "trace")]
+#[get("/product/history?<id>")]
+async fn get_product_history(
+    product_service: ProductService,
+    id: i64,
+    user: ScopedAuthUser<ReadAccess>,
+) -> Result<Json<ProductHistory>, ProductServiceError> {
+    Ok(Json(product_service.get_history(id, user.into()).await?))
+}
+
 #[tracing::instrument(level = "trace")]
 #[put("/product?<id>", format = "json", data = "<product>")]
 async fn update_product_by_id(
     product_service: ProductService,
     id: i64,
-    user: AuthUser,
+    user: ScopedAuthUser<ProductsWrite>,
     product: Json<ProductDetails>,
 ) -> Result<Accepted<()>, ProductServiceError> {
     product_service
-        .update_product_by_id(id, product.0, user)
+        .update_product_by_id(id, product.0, user.into())
+        .await?;
+    Ok(Accepted(None))
+}
+
+#[tracing::instrument(level = "trace")]
+#[put("/product/status?<id>", format = "json", data = "<change>")]
+async fn change_product_status(
+    product_service: ProductService,
+    id: i64,
+    user: ScopedAuthUser<ProductsWrite>,
+    change: Json<StatusChange>,
+) -> Result<Accepted<()>, ProductServiceError> {
+    product_service
+        .change_status(id, change.status, user.into())
         .await?;
     Ok(Accepted(None))
 }
 
+#[tracing::instrument(level = "trace")]
+#[put("/product/renew?<id>")]
+async fn renew_product(
+    product_service: ProductService,
+    id: i64,
+    user: ScopedAuthUser<ProductsWrite>,
+) -> Result<Json<ListingRenewed>, ProductServiceError> {
+    let expires_at = product_service.renew_listing(id, user.into()).await?;
+
+    Ok(Json(ListingRenewed { expires_at }))
+}
+
+#[tracing::instrument(level = "trace")]
+#[put("/product/bump?<id>")]
+async fn bump_product(
+    product_service: ProductService,
+    id: i64,
+    user: ScopedAuthUser<ProductsWrite>,
+) -> Result<Accepted<()>, ProductServiceError> {
+    product_service.bump_listing(id, user.into()).await?;
+    Ok(Accepted(None))
+}
+
 #[tracing::instrument(level = "trace")]
 #[delete("/product?<id>")]
 async fn delete_product_by_id(
     product_service: ProductService,
     file_service: FileService,
     id: i64,
-    user: AuthUser,
+    user: ScopedAuthUser<ProductsWrite>,
 ) -> Result<(), ProductServiceError> {
-    product_service.delete_product_by_id(id, user, file_service).await?;
+    product_service
+        .delete_product_by_id(id, user.into(), file_service)
+        .await?;
 
     Ok(())
 }
@@ -72,22 +128,33 @@ async fn delete_product_by_id(
 async fn search_for_products(
     filter: Json<ProductFilter>,
     product_service: ProductService,
+    viewer: Option<ScopedAuthUser<ReadAccess>>,
 ) -> Result<Json<Vec<ProductLocationReturn>>, ProductServiceError> {
-    let found_products = product_service.search_for_products(filter.0).await?;
+    let found_products = product_service
+        .search_for_products(filter.0, viewer.as_ref().map(|viewer| &viewer.user))
+        .await?;
 
     Ok(Json(found_products))
 }
 
 #[tracing::instrument(level = "trace")]
-#[get("/by_user?<user_id>&<limit>&<lower_limit>")]
+#[get("/by_user?<user_id>&<limit>&<lower_limit>&<status>")]
 async fn get_products_by_user_id(
     product_service: ProductService,
     user_id: i64,
     limit: Option<u64>,
     lower_limit: Option<i64>,
+    status: Option<ListingStatus>,
+    viewer: Option<ScopedAuthUser<ReadAccess>>,
 ) -> Result<Json<Vec<ProductReturnNoUser>>, ProductServiceError> {
     let to_return = product_service
-        .get_products_by_user_id(user_id, limit, lower_limit)
+        .get_products_by_user_id(
+            user_id,
+            limit,
+            lower_limit,
+            status,
+            viewer.as_ref().map(|viewer| &viewer.user),
+        )
         .await?;
 
     Ok(Json(to_return))
@@ -97,7 +164,11 @@ pub fn routes() -> Vec<Route> {
     routes![
         create_product,
         get_product_by_id,
+        get_product_history,
         update_product_by_id,
+        change_product_status,
+        renew_product,
+        bump_product,
         delete_product_by_id,
         search_for_products,
         get_products_by_user_id
@@ -1,11 +1,21 @@
 use crate::{
+    dtos::{export::DataExportReturn, product::FileResponder, profile::PublicProfile},
+    mail::Mailer,
     models::{
         role::Role,
-        user::{AuthUser, UserReturnDto},
+        scope::ReadAccess,
+        user::{
+            AuthUser, DeletionRequest, DeletionScheduled, EmailChange, PasswordChange,
+            ProfilePrivacy, ProfileUpdate, ScopedAuthUser, SellerReviewCreate, UserPreferences,
+            UserReturnDto, UsernameChange,
+        },
+    },
+    services::{
+        AuthService, ExportService, ExportServiceError, ProfileService, ProfileServiceError,
+        UserService, UserServiceError,
     },
-    services::{UserService, UserServiceError},
 };
-use rocket::{serde::json::Json, Route};
+use rocket::{http::CookieJar, response::status::Accepted, serde::json::Json, Route, State};
 
 #[cfg(test)]
 mod test;
@@ -45,23 +55,255 @@ async fn email_exists(
 #[tracing::instrument(level = "trace")]
 #[get("/user/info")]
 async fn get_user_info(
-    auth_user: AuthUser,
+    auth_user: ScopedAuthUser<ReadAccess>,
     user_service: UserService,
 ) -> Result<Json<UserReturnDto>, UserServiceError> {
     let user = user_service
         .get_user_by_id(&auth_user.user.id)
         .await?
         .unwrap();
+    let role = Role::try_from(user.role).unwrap();
     let to_return = UserReturnDto {
         id: user.id,
         email: user.email,
         username: user.username,
-        role: Role::try_from(user.role).unwrap(),
+        role,
+        permissions: role.permissions(),
+        verified: user.verified_at.is_some(),
     };
 
     Ok(Json(to_return))
 }
 
+/// Other sessions are signed out, the one making the request stays logged in
+#[tracing::instrument(level = "trace", skip(change))]
+#[put("/user/password", format = "json", data = "<change>")]
+async fn change_password(
+    auth_user: AuthUser,
+    user_service: UserService,
+    mut auth_service: AuthService,
+    change: Json<PasswordChange>,
+    cookies: &CookieJar<'_>,
+) -> Result<(), UserServiceError> {
+    let current = cookies.get("refresh").map(|c| c.value().to_owned());
+
+    user_service
+        .change_password(
+            auth_user.user.id,
+            change.into_inner(),
+            current.as_deref(),
+            &mut auth_service,
+        )
+        .await
+}
+
+#[tracing::instrument(level = "trace", skip(mailer, change))]
+#[post("/user/email", format = "json", data = "<change>")]
+async fn change_email(
+    auth_user: AuthUser,
+    user_service: UserService,
+    mailer: &State<Mailer>,
+    change: Json<EmailChange>,
+) -> Result<Accepted<()>, UserServiceError> {
+    user_service
+        .request_email_change(
+            auth_user.user.id,
+            change.into_inner(),
+            mailer.inner().as_ref(),
+        )
+        .await?;
+
+    Ok(Accepted(None))
+}
+
+#[tracing::instrument(level = "trace")]
+#[put("/user/username", format = "json", data = "<change>")]
+async fn change_username(
+    auth_user: AuthUser,
+    user_service: UserService,
+    change: Json<UsernameChange>,
+) -> Result<(), UserServiceError> {
+    user_service
+        .change_username(auth_user.user.id, &change.username)
+        .await
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/user/preferences")]
+async fn get_preferences(
+    auth_user: ScopedAuthUser<ReadAccess>,
+    user_service: UserService,
+) -> Result<Json<UserPreferences>, UserServiceError> {
+    let preferences = user_service.get_preferences(auth_user.user.id).await?;
+
+    Ok(Json(preferences))
+}
+
+#[tracing::instrument(level = "trace")]
+#[put("/user/preferences", format = "json", data = "<preferences>")]
+async fn update_preferences(
+    auth_user: AuthUser,
+    user_service: UserService,
+    preferences: Json<UserPreferences>,
+) -> Result<Json<UserPreferences>, UserServiceError> {
+    let preferences = user_service
+        .update_preferences(auth_user.user.id, preferences.into_inner())
+        .await?;
+
+    Ok(Json(preferences))
+}
+
+#[tracing::instrument(level = "trace")]
+#[put("/user/bio", format = "json", data = "<update>")]
+async fn update_bio(
+    auth_user: AuthUser,
+    user_service: UserService,
+    update: Json<ProfileUpdate>,
+) -> Result<(), UserServiceError> {
+    user_service
+        .update_bio(auth_user.user.id, update.into_inner().bio)
+        .await
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/user/privacy")]
+async fn get_privacy(
+    auth_user: ScopedAuthUser<ReadAccess>,
+    user_service: UserService,
+) -> Result<Json<ProfilePrivacy>, UserServiceError> {
+    let privacy = user_service.get_privacy(auth_user.user.id).await?;
+
+    Ok(Json(privacy))
+}
+
+#[tracing::instrument(level = "trace")]
+#[put("/user/privacy", format = "json", data = "<privacy>")]
+async fn update_privacy(
+    auth_user: AuthUser,
+    user_service: UserService,
+    privacy: Json<ProfilePrivacy>,
+) -> Result<Json<ProfilePrivacy>, UserServiceError> {
+    let privacy = user_service
+        .update_privacy(auth_user.user.id, privacy.into_inner())
+        .await?;
+
+    Ok(Json(privacy))
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/<id>/profile")]
+async fn get_profile(
+    id: i64,
+    profile_service: ProfileService,
+) -> Result<Json<PublicProfile>, ProfileServiceError> {
+    let profile = profile_service.get_public_profile(id).await?;
+
+    Ok(Json(profile))
+}
+
+#[tracing::instrument(level = "trace")]
+#[post("/<id>/reviews", format = "json", data = "<review>")]
+async fn review_seller(
+    id: i64,
+    auth_user: AuthUser,
+    profile_service: ProfileService,
+    review: Json<SellerReviewCreate>,
+) -> Result<(), ProfileServiceError> {
+    profile_service
+        .review_seller(&auth_user.user, id, review.into_inner())
+        .await
+}
+
+/// Queues an export of the caller's data, it is mailed about once ready
+#[tracing::instrument(level = "trace", skip(mailer))]
+#[post("/user/exports")]
+async fn request_export(
+    auth_user: AuthUser,
+    export_service: ExportService,
+    mailer: &State<Mailer>,
+) -> Result<Accepted<Json<DataExportReturn>>, ExportServiceError> {
+    let export = export_service.request_export(auth_user.user.id).await?;
+
+    let export_id = export.id;
+    let mailer: Mailer = mailer.inner().clone();
+    rocket::tokio::spawn(async move {
+        if let Err(e) = export_service.run_export(export_id, mailer.as_ref()).await {
+            tracing::error!(message = "Data export failed", export_id, error = %e);
+        }
+    });
+
+    Ok(Accepted(Some(Json(export))))
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/user/exports")]
+async fn list_exports(
+    auth_user: ScopedAuthUser<ReadAccess>,
+    export_service: ExportService,
+) -> Result<Json<Vec<DataExportReturn>>, ExportServiceError> {
+    let exports = export_service.list_exports(auth_user.user.id).await?;
+
+    Ok(Json(exports))
+}
+
+#[tracing::instrument(level = "trace")]
+#[get("/user/exports/<id>/download")]
+async fn download_export(
+    id: i64,
+    auth_user: ScopedAuthUser<ReadAccess>,
+    export_service: ExportService,
+) -> Result<FileResponder, ExportServiceError> {
+    export_service.download_export(auth_user.user.id, id).await
+}
+
+/// The account is deleted once the grace period is over, unless cancelled before then
+#[tracing::instrument(level = "trace", skip(mailer, request))]
+#[post("/user/deletion", format = "json", data = "<request>")]
+async fn request_deletion(
+    auth_user: AuthUser,
+    user_service: UserService,
+    mailer: &State<Mailer>,
+    request: Json<DeletionRequest>,
+) -> Result<Accepted<Json<DeletionScheduled>>, UserServiceError> {
+    let scheduled_for = user_service
+        .request_deletion(
+            auth_user.user.id,
+            &request.password,
+            mailer.inner().as_ref(),
+        )
+        .await?;
+
+    Ok(Accepted(Some(Json(DeletionScheduled { scheduled_for }))))
+}
+
+#[tracing::instrument(level = "trace")]
+#[delete("/user/deletion")]
+async fn cancel_deletion(
+    auth_user: AuthUser,
+    user_service: UserService,
+) -> Result<(), UserServiceError> {
+    user_service.cancel_deletion(auth_user.user.id).await
+}
+
 pub fn routes() -> Vec<Route> {
-    routes![username_exists, email_exists, get_user_info]
+    routes![
+        username_exists,
+        email_exists,
+        get_user_info,
+        change_password,
+        change_email,
+        change_username,
+        get_preferences,
+        update_preferences,
+        update_bio,
+        get_privacy,
+        update_privacy,
+        get_profile,
+        review_seller,
+        request_export,
+        list_exports,
+        download_export,
+        request_deletion,
+        cancel_deletion
+    ]
 }
@@ -12,16 +12,24 @@ pub trait RedisRefresh: Send {
         key: &str,
     ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>>;
 
-    async fn set_item(
+    async fn set_item_with_expiry(
         &mut self,
         key: &str,
         value: &str,
+        seconds: usize,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
     async fn delete_item(
         &mut self,
         key: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Atomically adds one to a counter and restarts its expiry, returning the new count
+    async fn increment_with_expiry(
+        &mut self,
+        key: &str,
+        seconds: usize,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>>;
 }
 
 #[async_trait]
@@ -33,12 +41,13 @@ impl RedisRefresh for RedisConnection {
         Ok(self.get::<&str, Option<String>>(key).await?)
     }
 
-    async fn set_item(
+    async fn set_item_with_expiry(
         &mut self,
         key: &str,
         value: &str,
+        seconds: usize,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.set::<&str, &str, ()>(key, value).await?;
+        self.set_ex::<&str, &str, ()>(key, value, seconds).await?;
         Ok(())
     }
 
@@ -49,6 +58,21 @@ impl RedisRefresh for RedisConnection {
         self.del::<&str, ()>(key).await?;
         Ok(())
     }
+
+    async fn increment_with_expiry(
+        &mut self,
+        key: &str,
+        seconds: usize,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let (count,): (i64,) = redis::pipe()
+            .atomic()
+            .incr(key, 1)
+            .expire(key, seconds)
+            .ignore()
+            .query_async(self)
+            .await?;
+        Ok(count)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -83,8 +107,13 @@ pub async fn redis_connection() -> anyhow::Result<redis::Client> {
 
 #[cfg(test)]
 pub mod test {
+    use super::MockRedisRefresh;
     use migration::MigratorTrait;
     use sea_orm::{Database, DatabaseConnection, DbErr};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
 
     pub async fn establish_connection() -> Result<DatabaseConnection, DbErr> {
         let db = Database::connect("sqlite::memory:").await?;
@@ -93,4 +122,44 @@ pub mod test {
 
         Ok(db)
     }
+
+    /// Mock redis backed by a map, for tests that care about what ends up cached
+    pub fn in_memory_redis() -> MockRedisRefresh {
+        let store = Arc::new(Mutex::new(HashMap::<String, String>::new()));
+        let mut redis = MockRedisRefresh::default();
+
+        let get_store = store.clone();
+        redis
+            .expect_get_item()
+            .returning(move |key| Ok(get_store.lock().unwrap().get(key).cloned()));
+        let set_store = store.clone();
+        redis
+            .expect_set_item_with_expiry()
+            .returning(move |key, value, _| {
+                set_store
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_owned(), value.to_owned());
+                Ok(())
+            });
+        let incr_store = store.clone();
+        redis
+            .expect_increment_with_expiry()
+            .returning(move |key, _| {
+                let mut store = incr_store.lock().unwrap();
+                let count = store
+                    .get(key)
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .unwrap_or_default()
+                    + 1;
+                store.insert(key.to_owned(), count.to_string());
+                Ok(count)
+            });
+        redis.expect_delete_item().returning(move |key| {
+            store.lock().unwrap().remove(key);
+            Ok(())
+        });
+
+        redis
+    }
 }
@@ -0,0 +1,62 @@
+use crate::models::{permission::Permission, role::Role, user::AccountStatus};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUserReturn {
+    pub id: i64,
+    pub username: String,
+    pub email: String,
+    pub role: Role,
+    pub permissions: Vec<Permission>,
+    pub verified: bool,
+    pub two_factor_enabled: bool,
+    pub created_at: NaiveDateTime,
+    pub status: AccountStatus,
+    pub suspended_at: Option<NaiveDateTime>,
+    pub suspended_until: Option<NaiveDateTime>,
+    pub suspension_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUserPage {
+    pub users: Vec<AdminUserReturn>,
+    pub page: u64,
+    pub per_page: u64,
+    pub total: u64,
+}
+
+/// The parts of an account admin actions change, as recorded before and after each one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUserState {
+    pub role: Role,
+    pub suspended: bool,
+    #[serde(default)]
+    pub banned: bool,
+    pub active_sessions: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogReturn {
+    pub id: i64,
+    pub actor_id: Option<i64>,
+    pub target_user_id: Option<i64>,
+    pub action: String,
+    pub before: Option<AdminUserState>,
+    pub after: Option<AdminUserState>,
+    pub reason: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogPage {
+    pub entries: Vec<AuditLogReturn>,
+    pub page: u64,
+    pub per_page: u64,
+    pub total: u64,
+}
@@ -0,0 +1,13 @@
+use crate::models::blocklist::BlockedTermKind;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockedTermReturn {
+    pub id: i64,
+    pub term: String,
+    pub kind: BlockedTermKind,
+    pub created_by: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,31 @@
+use crate::models::category::AttributeKind;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryReturn {
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+}
+
+/// A category with everything nested below it, children are sorted by name
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryTree {
+    pub id: i64,
+    pub name: String,
+    pub children: Vec<CategoryTree>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryAttributeReturn {
+    pub id: i64,
+    pub category_id: i64,
+    pub name: String,
+    pub kind: AttributeKind,
+    pub unit: Option<String>,
+    pub options: Vec<String>,
+    pub required: bool,
+}
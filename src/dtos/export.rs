@@ -0,0 +1,151 @@
+use crate::{
+    dtos::product::{PriceChangeReturn, ProductRevisionReturn},
+    models::{category::AttributeValue, export::ExportStatus, role::Role},
+};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DataExportReturn {
+    pub id: i64,
+    pub status: ExportStatus,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// `user.json` in an export, credentials are left out
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedUser {
+    pub id: i64,
+    pub username: String,
+    pub email: String,
+    pub role: Role,
+    pub bio: Option<String>,
+    pub avatar_file_id: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub verified_at: Option<NaiveDateTime>,
+    pub two_factor_enabled_at: Option<NaiveDateTime>,
+    pub username_changed_at: Option<NaiveDateTime>,
+    pub suspended_at: Option<NaiveDateTime>,
+    pub suspended_until: Option<NaiveDateTime>,
+    pub suspension_reason: Option<String>,
+    pub banned: bool,
+    pub deletion_requested_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedProduct {
+    pub id: i64,
+    pub title: String,
+    pub description: String,
+    pub price: Decimal,
+    pub country: String,
+    pub state: String,
+    pub city: String,
+    pub zip: String,
+    pub latitude: Option<Decimal>,
+    pub longitude: Option<Decimal>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub status: String,
+    pub published_at: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub sold_at: Option<NaiveDateTime>,
+    /// Category names, most relevant first
+    pub categories: Vec<String>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+    pub revisions: Vec<ProductRevisionReturn>,
+    pub price_history: Vec<PriceChangeReturn>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedProductAudit {
+    pub id: i64,
+    pub product_id: i64,
+    pub review_status: i16,
+    pub review_text: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// An uploaded file, `path` is where its contents are inside the archive
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedFile {
+    pub id: i64,
+    pub path: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedSession {
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+/// `preferences.json`, account defaults and profile privacy
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedPreferences {
+    pub distance_unit: Option<String>,
+    pub search_radius: Option<Decimal>,
+    pub show_member_since: bool,
+    pub show_listing_count: bool,
+    pub show_sales_count: bool,
+    pub show_rating: bool,
+    pub show_listings: bool,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedReview {
+    pub id: i64,
+    pub seller_id: i64,
+    pub reviewer_id: Option<i64>,
+    pub product_id: Option<i64>,
+    pub rating: i16,
+    pub comment: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// `reviews.json`, reviews the user wrote and reviews of them as a seller
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedReviews {
+    pub written: Vec<ExportedReview>,
+    pub received: Vec<ExportedReview>,
+}
+
+/// A linked sign in provider
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_login_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedLoginLockout {
+    pub ip_address: Option<String>,
+    pub failures: i32,
+    pub locked_until: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
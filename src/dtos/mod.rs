@@ -1,2 +1,7 @@
+pub mod admin;
 pub mod auth;
+pub mod blocklist;
+pub mod category;
+pub mod export;
 pub mod product;
+pub mod profile;
@@ -1,14 +1,18 @@
+use crate::models::category::AttributeValue;
+use chrono::NaiveDateTime;
 use geolocation_utils::{Coordinate, DistanceUnit};
 use rocket::http::ContentType;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
+use std::{collections::BTreeMap, fs::File};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ProductFilter {
     pub coordinate: Coordinate,
-    pub radius: Decimal,
+    /// Falls back to the caller's saved search radius
+    pub radius: Option<Decimal>,
+    /// Falls back to the caller's saved distance unit, then miles
     pub units: Option<DistanceUnit>,
     pub query: Option<String>,
     pub price_low: Option<Decimal>,
@@ -16,6 +20,20 @@ pub struct ProductFilter {
     pub city: Option<String>,
     pub zip: Option<String>,
     pub product_id_lower: Option<i64>,
+    /// Also matches products in any subcategory
+    pub category: Option<i64>,
+    /// Every filter has to match
+    pub attributes: Option<Vec<AttributeFilter>>,
+}
+
+/// Matches products with an attribute of this name, `min` and `max` only apply to integers
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeFilter {
+    pub name: String,
+    pub equals: Option<AttributeValue>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -24,6 +42,36 @@ pub struct ProductCreated {
     pub id: i64,
 }
 
+/// A field's value before and after an edit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub from: serde_json::Value,
+    pub to: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductRevisionReturn {
+    pub id: i64,
+    pub edited_by: Option<i64>,
+    pub changes: BTreeMap<String, FieldChange>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceChangeReturn {
+    pub price: Decimal,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductHistory {
+    pub revisions: Vec<ProductRevisionReturn>,
+    pub prices: Vec<PriceChangeReturn>,
+}
+
 #[derive(Responder)]
 pub struct FileResponder {
     pub file: File,
@@ -0,0 +1,25 @@
+use crate::models::product::ProductReturnNoUser;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingSummary {
+    pub average: Option<f64>,
+    pub count: u64,
+}
+
+/// A seller as anyone can see them, fields the seller hid are `None`
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicProfile {
+    pub id: i64,
+    pub username: String,
+    pub bio: Option<String>,
+    pub avatar: Option<i64>,
+    pub member_since: Option<NaiveDateTime>,
+    pub active_listings: Option<u64>,
+    pub completed_sales: Option<u64>,
+    pub rating: Option<RatingSummary>,
+    pub recent_listings: Option<Vec<ProductReturnNoUser>>,
+}
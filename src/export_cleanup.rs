@@ -0,0 +1,57 @@
+use crate::services::ExportService;
+use rocket::{
+    fairing::{Fairing, Kind},
+    Orbit, Rocket,
+};
+use sea_orm::DatabaseConnection;
+use std::{path::PathBuf, time::Duration};
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deletes expired data export archives and fails exports a restart interrupted,
+/// checked on launch and once an hour after
+pub struct ExportCleanup;
+
+#[rocket::async_trait]
+impl Fairing for ExportCleanup {
+    fn info(&self) -> rocket::fairing::Info {
+        rocket::fairing::Info {
+            kind: Kind::Liftoff,
+            name: "Export cleanup",
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(db), Ok(save_path)) = (
+            rocket.state::<DatabaseConnection>().cloned(),
+            std::env::var("SAVE_PATH"),
+        ) else {
+            tracing::warn!(
+                "Export cleanup is not configured -- expired exports will not be deleted"
+            );
+            return;
+        };
+
+        rocket::tokio::spawn(async move {
+            let export_service = ExportService::new(db, PathBuf::from(save_path));
+            let mut interval = rocket::tokio::time::interval(CLEANUP_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                match export_service.fail_stale_exports().await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::warn!(message = "Failed interrupted data exports", count),
+                    Err(e) => {
+                        tracing::error!(message = "Unable to fail stale data exports", error = %e)
+                    }
+                }
+                match export_service.purge_expired().await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!(message = "Deleted expired data exports", count),
+                    Err(e) => tracing::error!(message = "Export cleanup failed", error = %e),
+                }
+            }
+        });
+    }
+}
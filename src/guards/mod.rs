@@ -1 +1,3 @@
 mod rate_limit;
+
+pub use rate_limit::TooManyRequests;
@@ -1,11 +1,7 @@
-use redis::{AsyncCommands, Client as RedisClient};
 use rocket::{
     http::{Header, Status},
-    outcome::{try_outcome, Outcome},
-    request::FromRequest,
     response::Responder,
     response::Response,
-    State,
 };
 
 #[derive(Debug)]
@@ -14,6 +10,13 @@ pub struct TooManyRequests {
     time_left: i32,
 }
 
+impl TooManyRequests {
+    /// `amount` requests were made, try again in `time_left` seconds
+    pub fn new(amount: i16, time_left: i32) -> Self {
+        Self { amount, time_left }
+    }
+}
+
 impl<'r> Responder<'r, 'static> for TooManyRequests {
     fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
         Response::build()
@@ -26,46 +29,3 @@ impl<'r> Responder<'r, 'static> for TooManyRequests {
             .ok()
     }
 }
-
-pub struct AuthRateLimit {
-    pub request_amount: i16,
-}
-
-#[async_trait]
-impl<'r> FromRequest<'r> for AuthRateLimit {
-    type Error = ();
-
-    async fn from_request(
-        request: &'r rocket::Request<'_>,
-    ) -> rocket::request::Outcome<Self, Self::Error> {
-        let redis: &RedisClient =
-            try_outcome!(request.guard::<&State<RedisClient>>().await).inner();
-        let mut conn = match redis.get_async_connection().await {
-            Ok(con) => con,
-            Err(_) => return Outcome::Failure((Status::InternalServerError, ())),
-        };
-        let path = request.uri().path().as_str();
-
-        let ip_str = match request.client_ip() {
-            Some(ip) => ip,
-            None => return Outcome::Failure((Status::TooManyRequests, ())),
-        }
-        .to_string();
-
-        let key = format!("{ip_str}{path}");
-
-        let amount = match conn.get::<&str, Option<i16>>(&key).await {
-            Ok(amt) => amt,
-            Err(_) => return Outcome::Failure((Status::InternalServerError, ())),
-        }
-        .unwrap_or(0)
-            + 1;
-
-        match conn.set_ex::<&str, i16, ()>(&key, amount, 300).await {
-            Ok(_) => Outcome::Success(Self {
-                request_amount: amount,
-            }),
-            Err(_) => Outcome::Failure((Status::InternalServerError, ())),
-        }
-    }
-}
@@ -0,0 +1,50 @@
+use crate::{mail::Mailer, models::product::ListingConfig, services::ProductService};
+use rocket::{
+    fairing::{Fairing, Kind},
+    Orbit, Rocket,
+};
+use sea_orm::DatabaseConnection;
+use std::time::Duration;
+
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Sends expiry reminders and expires stale listings, checked once an hour
+pub struct ListingExpiry;
+
+#[rocket::async_trait]
+impl Fairing for ListingExpiry {
+    fn info(&self) -> rocket::fairing::Info {
+        rocket::fairing::Info {
+            kind: Kind::Liftoff,
+            name: "Listing expiry",
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(db), Some(mailer)) = (
+            rocket.state::<DatabaseConnection>().cloned(),
+            rocket.state::<Mailer>().cloned(),
+        ) else {
+            tracing::warn!("Listing expiry is not configured -- listings will not expire");
+            return;
+        };
+        let config = rocket.state::<ListingConfig>().cloned().unwrap_or_default();
+
+        rocket::tokio::spawn(async move {
+            let product_service = ProductService::with_config(db, config);
+            let mut interval = rocket::tokio::time::interval(EXPIRY_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                match product_service.process_expirations(mailer.as_ref()).await {
+                    Ok((0, 0)) => {}
+                    Ok((reminded, expired)) => {
+                        tracing::info!(message = "Processed listing expirations", reminded, expired)
+                    }
+                    Err(e) => tracing::error!(message = "Listing expiry failed", error = %e),
+                }
+            }
+        });
+    }
+}
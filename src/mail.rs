@@ -0,0 +1,109 @@
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::{env, sync::Arc};
+
+/// A plain text email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait MailSender: Send + Sync {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()>;
+}
+
+/// Mail sender shared through rocket state
+pub type Mailer = Arc<dyn MailSender>;
+
+/// Uses SMTP when `SMTP_URL` is set, otherwise only logs outgoing mail
+pub fn mailer_from_env() -> anyhow::Result<Mailer> {
+    match env::var("SMTP_URL") {
+        Ok(url) => {
+            let from = env::var("MAIL_FROM").map_err(|e| anyhow::anyhow!(e))?;
+            Ok(Arc::new(SmtpMailSender::new(&url, &from)?))
+        }
+        Err(_) => {
+            tracing::warn!("SMTP_URL is not set -- outgoing mail will only be logged");
+            Ok(Arc::new(LogMailSender))
+        }
+    }
+}
+
+/// Base url of the frontend, used to build links sent by mail
+pub fn frontend_url() -> String {
+    env::var("FRONTEND_URL").unwrap_or_else(|_| String::from("http://localhost:3000"))
+}
+
+pub struct SmtpMailSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailSender {
+    pub fn new(url: &str, from: &str) -> anyhow::Result<Self> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build();
+
+        Ok(Self {
+            transport,
+            from: from.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl MailSender for SmtpMailSender {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(mail.to.parse()?)
+            .subject(mail.subject)
+            .body(mail.body)?;
+
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
+
+pub struct LogMailSender;
+
+#[async_trait]
+impl MailSender for LogMailSender {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        tracing::info!(
+            message = "Mail not sent, no SMTP server configured",
+            to = mail.to,
+            subject = mail.subject
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{Mail, MailSender};
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps every mail in memory so tests can inspect what would have been sent
+    #[derive(Clone, Default)]
+    pub struct CapturingMailSender {
+        sent: Arc<Mutex<Vec<Mail>>>,
+    }
+
+    impl CapturingMailSender {
+        pub fn sent(&self) -> Vec<Mail> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl MailSender for CapturingMailSender {
+        async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+            self.sent.lock().unwrap().push(mail);
+            Ok(())
+        }
+    }
+}
@@ -1,21 +1,31 @@
 #[macro_use]
 extern crate rocket;
+mod account_deletion;
 mod catchers;
+mod config;
 mod controllers;
 mod cors;
 mod db;
 mod dtos;
+mod export_cleanup;
 mod guards;
+mod listing_expiry;
 mod logger;
+mod mail;
 mod models;
+mod rate_limit;
 mod services;
 mod statsd;
+use account_deletion::AccountDeletion;
 use cors::{Cors, Options};
+use export_cleanup::ExportCleanup;
+use listing_expiry::ListingExpiry;
 use logger::{setup_loki, Loki};
 use migration::{Migrator, MigratorTrait};
+use rate_limit::{RateLimitConfig, RateLimiter};
 use rocket::{response::Responder, Config, Response};
 use serde_json::json;
-use services::{AuthService, UserService};
+use services::{KeyEncryptionKey, KeyRing, UserService};
 use statsd::Statsd;
 use std::env;
 
@@ -39,6 +49,15 @@ impl std::fmt::Display for AnyhowResponder {
     }
 }
 
+/// Refuses to start with a config section that is present but can't be read,
+/// rather than silently running without it
+fn required_config<T, E: std::fmt::Display>(section: &str, config: Result<T, E>) -> T {
+    config.unwrap_or_else(|e| {
+        tracing::error!(message = "Invalid config", section, error = %e);
+        panic!("Invalid `{section}` config: {e}")
+    })
+}
+
 #[launch]
 pub async fn rocket() -> _ {
     env::set_var("RUST_BACKTRACE", "full");
@@ -48,10 +67,31 @@ pub async fn rocket() -> _ {
     setup_loki();
     let conn = db::establish_connection().await.unwrap();
     let redis = db::redis_connection().await.unwrap();
-    let key = AuthService::get_key_pair().unwrap();
 
     Migrator::up(&conn, None).await.unwrap();
 
+    let key_ring = KeyRing::load(&conn, KeyEncryptionKey::from_env().unwrap())
+        .await
+        .unwrap();
+    let mailer = mail::mailer_from_env().unwrap();
+    let oidc_config = required_config(
+        "oidc",
+        models::oidc::OidcConfig::from_figment(&Config::figment()),
+    );
+    let listing_config = required_config(
+        "listings",
+        models::product::ListingConfig::from_figment(&Config::figment()),
+    );
+    let rate_limiter = RateLimiter::new(
+        required_config(
+            "rate_limit",
+            RateLimitConfig::from_figment(&Config::figment()),
+        ),
+        Some(redis.clone()),
+    );
+    let oidc_client: services::OidcClient = std::sync::Arc::new(services::HttpOidcTransport::new());
+    tracing::info!(kid = ?key_ring.active_kid(), "Loaded signing keys");
+
     let user_service = UserService::new(conn.clone());
     let found_admin = user_service
         .username_exists(crate::models::user::ADMIN_USERNAME)
@@ -69,9 +109,10 @@ pub async fn rocket() -> _ {
         };
         let user_id = user_service.create_user(user_register, true).await.unwrap();
         user_service
-            .update_role_for_user(user_id, models::role::Role::Admin)
+            .update_role_for_user(user_id, models::role::Role::ADMIN)
             .await
             .unwrap();
+        user_service.mark_verified(user_id).await.unwrap();
     }
 
     let mut config = Config::default();
@@ -81,16 +122,25 @@ pub async fn rocket() -> _ {
     controllers::mount_routes(rocket::build())
         .manage(conn)
         .manage(redis)
-        .manage(key)
+        .manage(key_ring)
+        .manage(mailer)
+        .manage(oidc_config)
+        .manage(listing_config)
+        .manage(oidc_client)
         .attach(Statsd::default())
+        .attach(rate_limiter)
         .attach(Cors)
         .attach(Options)
         .attach(Loki)
+        .attach(AccountDeletion)
+        .attach(ListingExpiry)
+        .attach(ExportCleanup)
         .register(
             "/",
             catchers![
                 catchers::not_found,
                 catchers::unauthorized,
+                catchers::forbidden,
                 catchers::internal_error,
                 catchers::unprocessable
             ],
@@ -104,12 +154,24 @@ pub async fn create_rocket_instance(
     memory_conn: Option<DatabaseConnection>,
 ) -> anyhow::Result<rocket::Rocket<rocket::Build>> {
     use db::test::establish_connection;
-    let key = AuthService::get_key_pair()?;
 
     let memory_conn = memory_conn.unwrap_or(establish_connection().await?);
+    let key_ring = KeyRing::load(&memory_conn, KeyEncryptionKey::test()).await?;
+    let mail_capture = mail::test::CapturingMailSender::default();
+    let mailer: mail::Mailer = std::sync::Arc::new(mail_capture.clone());
+    let oidc_client: services::OidcClient = std::sync::Arc::new(services::HttpOidcTransport::new());
     Ok(controllers::mount_routes(rocket::build())
         .manage(memory_conn)
-        .manage(key)
+        .manage(key_ring)
+        .manage(mailer)
+        .manage(mail_capture)
+        .manage(models::oidc::OidcConfig::default())
+        .manage(models::product::ListingConfig::default())
+        .manage(oidc_client)
+        .attach(RateLimiter::new(
+            RateLimitConfig::from_figment(&Config::figment())?,
+            None,
+        ))
         .attach(Cors)
         .attach(Options)
         .register(
@@ -117,6 +179,7 @@ pub async fn create_rocket_instance(
             catchers![
                 catchers::not_found,
                 catchers::unauthorized,
+                catchers::forbidden,
                 catchers::internal_error,
                 catchers::unprocessable
             ],
@@ -0,0 +1,47 @@
+use super::role::Role;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Why an admin is acting on an account, kept in the audit log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminAction {
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Suspension {
+    pub reason: String,
+    /// Lifts the suspension automatically, it lasts until lifted by an admin without one
+    pub until: Option<NaiveDateTime>,
+    /// Also hides the user's listings
+    #[serde(default)]
+    pub ban: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoleChange {
+    pub role: Role,
+    pub reason: String,
+}
+
+/// What admin actions are recorded as in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    ChangeRole,
+    Suspend,
+    Ban,
+    Unsuspend,
+    ForceLogout,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::ChangeRole => "change_role",
+            AuditAction::Suspend => "suspend",
+            AuditAction::Ban => "ban",
+            AuditAction::Unsuspend => "unsuspend",
+            AuditAction::ForceLogout => "force_logout",
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// How a blocklist entry is matched, stored as `as_str` in `blocked_term.kind`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockedTermKind {
+    /// The whole text is the term, ignoring separators
+    Exact,
+    /// The term appears as a word of its own, `siteAdmin` and `site_admin` both contain `admin`
+    Word,
+    /// The term appears anywhere, even inside other words
+    Contains,
+    /// An exception, `Contains` matches inside it are ignored
+    Allow,
+}
+
+impl BlockedTermKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockedTermKind::Exact => "exact",
+            BlockedTermKind::Word => "word",
+            BlockedTermKind::Contains => "contains",
+            BlockedTermKind::Allow => "allow",
+        }
+    }
+}
+
+impl TryFrom<&str> for BlockedTermKind {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "exact" => Ok(BlockedTermKind::Exact),
+            "word" => Ok(BlockedTermKind::Word),
+            "contains" => Ok(BlockedTermKind::Contains),
+            "allow" => Ok(BlockedTermKind::Allow),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockedTermCreate {
+    pub term: String,
+    pub kind: BlockedTermKind,
+}
+
+/// Undoes common character swaps so `sh1t` and `a$$` match their terms
+fn unleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => c,
+    }
+}
+
+/// Splits text into lowercased, de-leeted words. Anything that isn't a letter,
+/// digit or leet symbol separates words, as does a lower to upper case change.
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+
+    for c in text.nfkc() {
+        let is_part = c.is_alphanumeric() || matches!(c, '@' | '$' | '!');
+        let starts_word = !is_part || (previous_lower && c.is_uppercase());
+        if starts_word && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+
+        if is_part {
+            current.extend(c.to_lowercase().map(unleet));
+        }
+        previous_lower = c.is_lowercase();
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+
+    words
+}
+
+/// Terms loaded from `blocked_term`, normalized the same way as checked text
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    exact: Vec<String>,
+    word: Vec<String>,
+    contains: Vec<String>,
+    allow: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn add(&mut self, term: &str, kind: BlockedTermKind) {
+        let term = words(term).concat();
+        if term.is_empty() {
+            return;
+        }
+
+        match kind {
+            BlockedTermKind::Exact => self.exact.push(term),
+            BlockedTermKind::Word => self.word.push(term),
+            BlockedTermKind::Contains => self.contains.push(term),
+            BlockedTermKind::Allow => self.allow.push(term),
+        }
+    }
+
+    pub fn is_blocked(&self, text: &str) -> bool {
+        let words = words(text);
+        let joined = words.concat();
+
+        if self.allow.contains(&joined) {
+            return false;
+        }
+
+        if self.exact.contains(&joined) {
+            return true;
+        }
+
+        if words
+            .iter()
+            .any(|word| self.word.contains(word) && !self.allow.contains(word))
+        {
+            return true;
+        }
+
+        let allowed: Vec<(usize, usize)> = self
+            .allow
+            .iter()
+            .flat_map(|term| {
+                joined
+                    .match_indices(term.as_str())
+                    .map(|(start, found)| (start, start + found.len()))
+            })
+            .collect();
+
+        self.contains.iter().any(|term| {
+            joined
+                .match_indices(term.as_str())
+                .map(|(start, found)| (start, start + found.len()))
+                .any(|(start, end)| !allowed.iter().any(|&(from, to)| from <= start && end <= to))
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+/// Used to create a category and to replace one on update
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryDetails {
+    pub name: String,
+    pub parent_id: Option<i64>,
+}
+
+/// The type of a category attribute, stored as `as_str` in `category_attribute.kind`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AttributeKind {
+    /// One of the attribute's `options`
+    Enum,
+    /// A whole number, measured in the attribute's `unit` if it has one
+    Integer,
+    Boolean,
+    Text,
+}
+
+impl AttributeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttributeKind::Enum => "enum",
+            AttributeKind::Integer => "integer",
+            AttributeKind::Boolean => "boolean",
+            AttributeKind::Text => "text",
+        }
+    }
+}
+
+impl TryFrom<&str> for AttributeKind {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "enum" => Ok(AttributeKind::Enum),
+            "integer" => Ok(AttributeKind::Integer),
+            "boolean" => Ok(AttributeKind::Boolean),
+            "text" => Ok(AttributeKind::Text),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A product's value for an attribute, `enum` attributes take `Text`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Boolean(bool),
+    Integer(i64),
+    Text(String),
+}
+
+/// Adds an attribute to a category's schema, subcategories inherit it
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryAttributeCreate {
+    pub name: String,
+    pub kind: AttributeKind,
+    pub unit: Option<String>,
+    /// The allowed values of an `enum` attribute
+    pub options: Option<Vec<String>>,
+    #[serde(default)]
+    pub required: bool,
+}
@@ -0,0 +1,8 @@
+/// What an uploaded file is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileOwner {
+    /// A picture of the product with this id
+    Product(i64),
+    /// The uploading user's avatar, replacing any previous one
+    Avatar,
+}
@@ -1,3 +1,12 @@
+pub mod admin;
+pub mod blocklist;
+pub mod category;
+pub mod export;
+pub mod file;
+pub mod oidc;
+pub mod permission;
 pub mod product;
 pub mod role;
+pub mod scope;
+pub mod session;
 pub mod user;
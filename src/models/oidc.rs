@@ -0,0 +1,44 @@
+use crate::config::extract_section;
+use rocket::figment::Figment;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// OpenID Connect providers users can sign in with, read from the `oidc` key of
+/// the Rocket config. Secrets are best supplied through the environment, e.g.
+/// `ROCKET_OIDC='{providers={google={client_secret="..."}}}'`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OidcConfig {
+    #[serde(default)]
+    pub providers: HashMap<String, OidcProvider>,
+}
+
+impl OidcConfig {
+    pub fn from_figment(figment: &Figment) -> anyhow::Result<Self> {
+        extract_section(figment, "oidc")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcProvider {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+}
+
+fn default_scopes() -> Vec<String> {
+    vec![
+        String::from("openid"),
+        String::from("email"),
+        String::from("profile"),
+    ]
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcCallback {
+    pub code: String,
+    pub state: String,
+    pub device_name: Option<String>,
+}
@@ -0,0 +1,57 @@
+use super::user::UserJwtDto;
+use serde::{Deserialize, Serialize};
+
+/// Named actions roles can be granted, see `Role::permissions`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Edit or remove products listed by other users
+    ModerateProducts,
+    /// Edit the words refused in usernames and profiles
+    ManageBlocklist,
+    ManageCategories,
+    ManageUsers,
+    ManageSigningKeys,
+    /// More pictures per listing and bumping listings up in search
+    PremiumListings,
+}
+
+impl UserJwtDto {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.role.has_permission(permission)
+    }
+
+    /// Owners may act on their own resources, everyone else needs `permission`
+    pub fn owns_or_has(&self, owner_id: i64, permission: Permission) -> bool {
+        self.id == owner_id || self.has_permission(permission)
+    }
+}
+
+/// Type level permissions for `RequirePermission`
+pub trait RequiredPermission: std::fmt::Debug + Send + Sync + 'static {
+    const PERMISSION: Permission;
+}
+
+#[derive(Debug)]
+pub struct ManageUsers;
+#[derive(Debug)]
+pub struct ManageSigningKeys;
+#[derive(Debug)]
+pub struct ManageBlocklist;
+#[derive(Debug)]
+pub struct ManageCategories;
+
+impl RequiredPermission for ManageUsers {
+    const PERMISSION: Permission = Permission::ManageUsers;
+}
+
+impl RequiredPermission for ManageSigningKeys {
+    const PERMISSION: Permission = Permission::ManageSigningKeys;
+}
+
+impl RequiredPermission for ManageBlocklist {
+    const PERMISSION: Permission = Permission::ManageBlocklist;
+}
+
+impl RequiredPermission for ManageCategories {
+    const PERMISSION: Permission = Permission::ManageCategories;
+}
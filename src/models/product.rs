@@ -1,6 +1,150 @@
-use super::user::MinUserReturnDto;
+use super::{category::AttributeValue, user::MinUserReturnDto};
+use crate::{config::extract_section, dtos::category::CategoryReturn};
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use rocket::figment::Figment;
 use sea_orm::prelude::Decimal;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How long listings stay up, read from the `listings` key of the Rocket config
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListingConfig {
+    /// Days an active listing is shown before it expires
+    #[serde(default = "default_lifetime_days")]
+    pub lifetime_days: i64,
+    /// Days before expiry the seller is reminded to renew
+    #[serde(default = "default_reminder_days")]
+    pub reminder_days: i64,
+    /// Hours between two bumps of the same listing
+    #[serde(default = "default_bump_cooldown_hours")]
+    pub bump_cooldown_hours: i64,
+}
+
+fn default_lifetime_days() -> i64 {
+    30
+}
+
+fn default_reminder_days() -> i64 {
+    3
+}
+
+fn default_bump_cooldown_hours() -> i64 {
+    24
+}
+
+impl Default for ListingConfig {
+    fn default() -> Self {
+        Self {
+            lifetime_days: default_lifetime_days(),
+            reminder_days: default_reminder_days(),
+            bump_cooldown_hours: default_bump_cooldown_hours(),
+        }
+    }
+}
+
+impl ListingConfig {
+    /// Refuses a lifetime that is not positive or too short for the reminder
+    pub fn from_figment(figment: &Figment) -> anyhow::Result<Self> {
+        let config: Self = extract_section(figment, "listings")?;
+
+        if config.lifetime_days <= 0 {
+            return Err(anyhow!(
+                "listings.lifetime_days must be positive, got {}",
+                config.lifetime_days
+            ));
+        }
+        if config.reminder_days >= config.lifetime_days {
+            return Err(anyhow!(
+                "listings.reminder_days ({}) must be less than lifetime_days ({})",
+                config.reminder_days,
+                config.lifetime_days
+            ));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Where a listing is in its lifecycle, stored as `as_str` in `product.status`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+#[serde(rename_all = "camelCase")]
+pub enum ListingStatus {
+    /// Only visible to its seller
+    Draft,
+    /// Public and shown in search
+    Active,
+    /// Public, but promised to a buyer
+    Reserved,
+    Sold,
+    /// Ran out of time, renewing makes it active again
+    Expired,
+    /// Taken down by its seller or a moderator, only visible to them
+    Removed,
+}
+
+impl ListingStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListingStatus::Draft => "draft",
+            ListingStatus::Active => "active",
+            ListingStatus::Reserved => "reserved",
+            ListingStatus::Sold => "sold",
+            ListingStatus::Expired => "expired",
+            ListingStatus::Removed => "removed",
+        }
+    }
+
+    /// The transitions sellers can make, listings only expire on their own
+    pub fn can_become(&self, next: ListingStatus) -> bool {
+        use ListingStatus::*;
+
+        matches!(
+            (self, next),
+            (Draft, Active)
+                | (Draft, Removed)
+                | (Active, Reserved)
+                | (Active, Sold)
+                | (Active, Removed)
+                | (Reserved, Active)
+                | (Reserved, Sold)
+                | (Reserved, Removed)
+                | (Expired, Active)
+                | (Expired, Removed)
+        )
+    }
+
+    /// Sold and removed listings are kept as they were
+    pub fn is_editable(&self) -> bool {
+        !matches!(self, ListingStatus::Sold | ListingStatus::Removed)
+    }
+
+    /// Drafts and removed listings are only shown to their seller and moderators
+    pub fn is_public(&self) -> bool {
+        !matches!(self, ListingStatus::Draft | ListingStatus::Removed)
+    }
+}
+
+impl TryFrom<&str> for ListingStatus {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "draft" => Ok(ListingStatus::Draft),
+            "active" => Ok(ListingStatus::Active),
+            "reserved" => Ok(ListingStatus::Reserved),
+            "sold" => Ok(ListingStatus::Sold),
+            "expired" => Ok(ListingStatus::Expired),
+            "removed" => Ok(ListingStatus::Removed),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: ListingStatus,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -14,6 +158,14 @@ pub struct ProductDetails {
     pub zip: String,
     pub latitude: Option<Decimal>,
     pub longitude: Option<Decimal>,
+    /// Category ids, most relevant first. Left unchanged on update when missing.
+    pub categories: Option<Vec<i64>>,
+    /// Values for the attributes of the product's categories, keyed by attribute name.
+    /// Left unchanged on update when missing.
+    pub attributes: Option<BTreeMap<String, AttributeValue>>,
+    /// Creates the listing as a draft instead of publishing it, ignored on update
+    #[serde(default)]
+    pub draft: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -23,10 +175,19 @@ pub struct ProductReturn {
     pub title: String,
     pub description: String,
     pub price: Decimal,
+    /// The previous price, when the latest price change lowered it
+    pub price_dropped_from: Option<Decimal>,
     pub created_by: MinUserReturnDto,
     pub latitude: Option<Decimal>,
     pub longitude: Option<Decimal>,
     pub pictures: Vec<i64>,
+    pub categories: Vec<CategoryReturn>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+    pub status: ListingStatus,
+    pub published_at: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub bumped_at: Option<NaiveDateTime>,
+    pub sold_at: Option<NaiveDateTime>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -43,6 +204,14 @@ pub struct ProductReturnNoUser {
     pub country: String,
     pub zip: String,
     pub pictures: Vec<i64>,
+    pub status: ListingStatus,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListingRenewed {
+    pub expires_at: NaiveDateTime,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
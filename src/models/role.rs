@@ -1,21 +1,104 @@
-use serde::{Deserialize, Serialize};
+use super::permission::Permission;
+use bitflags::bitflags;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum Role {
-    User = 1 << 0,
-    Moderator = 1 << 1,
-    Admin = 1 << 2,
+bitflags! {
+    /// Roles a user holds, stored together as bits in `user.role`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Role: i16 {
+        const USER = 1 << 0;
+        const MODERATOR = 1 << 1;
+        const ADMIN = 1 << 2;
+        /// The paid tier
+        const PREMIUM = 1 << 3;
+    }
+}
+
+impl Role {
+    /// Permissions granted by a single role
+    fn granted(role: Role) -> &'static [Permission] {
+        match role {
+            Role::MODERATOR => &[Permission::ModerateProducts, Permission::ManageBlocklist],
+            Role::ADMIN => &[
+                Permission::ModerateProducts,
+                Permission::ManageBlocklist,
+                Permission::ManageCategories,
+                Permission::ManageUsers,
+                Permission::ManageSigningKeys,
+            ],
+            Role::PREMIUM => &[Permission::PremiumListings],
+            _ => &[],
+        }
+    }
+
+    /// Every permission granted by any of the held roles
+    pub fn permissions(&self) -> Vec<Permission> {
+        let mut permissions = Vec::new();
+        for role in self.iter() {
+            for permission in Self::granted(role) {
+                if !permissions.contains(permission) {
+                    permissions.push(*permission);
+                }
+            }
+        }
+
+        permissions
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.iter()
+            .any(|role| Self::granted(role).contains(&permission))
+    }
 }
 
 impl TryFrom<i16> for Role {
     type Error = ();
 
+    /// Accepts any non-empty combination of known roles
     fn try_from(value: i16) -> Result<Self, Self::Error> {
-        match value {
-            1 => Ok(Role::User),
-            2 => Ok(Role::Moderator),
-            4 => Ok(Role::Admin),
+        match Role::from_bits(value) {
+            Some(role) if !role.is_empty() => Ok(role),
             _ => Err(()),
         }
     }
 }
+
+/// Serialized as the list of held role names, e.g. `["User", "Moderator"]`
+impl Serialize for Role {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter_names().map(|(name, _)| title_case(name)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Role {
+    /// Also accepts a single role name, which is how JWTs issued before roles
+    /// could be combined carry it
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Names {
+            One(String),
+            Many(Vec<String>),
+        }
+
+        let names = match Names::deserialize(deserializer)? {
+            Names::One(name) => vec![name],
+            Names::Many(names) => names,
+        };
+
+        names.iter().try_fold(Role::empty(), |role, name| {
+            Role::from_name(&name.to_ascii_uppercase())
+                .map(|found| role | found)
+                .ok_or_else(|| de::Error::custom(format!("Unknown role `{name}`")))
+        })
+    }
+}
+
+fn title_case(name: &str) -> String {
+    let lower = name.to_ascii_lowercase();
+    let mut chars = lower.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
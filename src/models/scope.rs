@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// What a personal access token may be used for. Sessions from `/login` are
+/// never restricted by scopes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "products:write")]
+    ProductsWrite,
+    #[serde(rename = "files:write")]
+    FilesWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::ProductsWrite => "products:write",
+            Scope::FilesWrite => "files:write",
+        }
+    }
+
+    /// Parses the space separated form scopes are stored in
+    pub fn parse_list(scopes: &str) -> Result<Vec<Scope>, ()> {
+        scopes.split_whitespace().map(Scope::from_str).collect()
+    }
+
+    pub fn join(scopes: &[Scope]) -> String {
+        scopes
+            .iter()
+            .map(Scope::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "read" => Ok(Scope::Read),
+            "products:write" => Ok(Scope::ProductsWrite),
+            "files:write" => Ok(Scope::FilesWrite),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Type level scopes for `ScopedAuthUser`
+pub trait RequiredScope: std::fmt::Debug + Send + Sync + 'static {
+    const SCOPE: Scope;
+}
+
+#[derive(Debug)]
+pub struct ReadAccess;
+#[derive(Debug)]
+pub struct ProductsWrite;
+#[derive(Debug)]
+pub struct FilesWrite;
+
+impl RequiredScope for ReadAccess {
+    const SCOPE: Scope = Scope::Read;
+}
+
+impl RequiredScope for ProductsWrite {
+    const SCOPE: Scope = Scope::ProductsWrite;
+}
+
+impl RequiredScope for FilesWrite {
+    const SCOPE: Scope = Scope::FilesWrite;
+}
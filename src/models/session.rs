@@ -0,0 +1,42 @@
+use rocket::{
+    request::{self, FromRequest},
+    Request,
+};
+use std::convert::Infallible;
+
+const MAX_USER_AGENT_LENGTH: usize = 512;
+const MAX_DEVICE_NAME_LENGTH: usize = 128;
+
+/// Information about the client a refresh token is being issued to
+#[derive(Debug, Clone, Default)]
+pub struct SessionMetadata {
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl SessionMetadata {
+    pub fn with_device_name(mut self, device_name: Option<String>) -> Self {
+        self.device_name =
+            device_name.map(|name| name.chars().take(MAX_DEVICE_NAME_LENGTH).collect());
+        self
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SessionMetadata {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let user_agent = req
+            .headers()
+            .get_one("user-agent")
+            .map(|agent| agent.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        request::Outcome::Success(Self {
+            device_name: None,
+            user_agent,
+            ip_address: req.client_ip().map(|ip| ip.to_string()),
+        })
+    }
+}
@@ -1,17 +1,31 @@
-use super::role::Role;
-use crate::services::{AuthService, UserService};
+use super::{
+    permission::{Permission, RequiredPermission},
+    role::Role,
+    scope::{RequiredScope, Scope},
+};
+use crate::services::{AuthService, AuthServiceError, UserService};
 use chrono::NaiveDateTime;
 use entity::user::Model as UserModel;
+use geolocation_utils::DistanceUnit;
 use rocket::{
     http::Status,
     outcome::{try_outcome, Outcome},
     request::{self, FromRequest},
     Request,
 };
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use unicode_normalization::UnicodeNormalization;
 
 pub const ADMIN_USERNAME: &str = "admin";
 
+/// The form usernames and emails are compared in, so lookups are exact while
+/// `Alice`, ` alice ` and a fullwidth `ａｌｉｃｅ` still find the same account
+pub fn normalize_identity(value: &str) -> String {
+    value.trim().nfkc().collect::<String>().to_lowercase()
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthServiceModel {
     pub id: i64,
@@ -46,6 +60,45 @@ impl TryFrom<UserModel> for User {
     }
 }
 
+/// Whether an account may currently be used
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AccountStatus {
+    Active,
+    Suspended,
+    /// Suspended with their listings hidden
+    Banned,
+    /// Anonymized after an account deletion, see `UserService::delete_account`
+    Deleted,
+}
+
+impl AccountStatus {
+    /// Suspensions with a `suspended_until` lift themselves once it passes
+    pub fn of(user: &UserModel) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        let suspended =
+            user.suspended_at.is_some() && user.suspended_until.is_none_or(|until| until > now);
+
+        if user.deleted_at.is_some() {
+            return AccountStatus::Deleted;
+        }
+
+        match (suspended, user.banned) {
+            (false, _) => AccountStatus::Active,
+            (true, false) => AccountStatus::Suspended,
+            (true, true) => AccountStatus::Banned,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self == AccountStatus::Active
+    }
+
+    pub fn hides_listings(&self) -> bool {
+        matches!(self, AccountStatus::Banned | AccountStatus::Deleted)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserRegister {
     pub username: String,
@@ -53,11 +106,125 @@ pub struct UserRegister {
     pub password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerification {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorLogin {
+    pub challenge: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpDisable {
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordChange {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailChange {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsernameChange {
+    pub username: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletionRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionScheduled {
+    pub scheduled_for: NaiveDateTime,
+}
+
+/// Account defaults used when a request doesn't say otherwise
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserPreferences {
+    pub distance_unit: Option<DistanceUnit>,
+    pub search_radius: Option<Decimal>,
+}
+
+/// Which parts of the public profile other users can see
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfilePrivacy {
+    pub show_member_since: bool,
+    pub show_listing_count: bool,
+    pub show_sales_count: bool,
+    pub show_rating: bool,
+    pub show_listings: bool,
+}
+
+impl Default for ProfilePrivacy {
+    fn default() -> Self {
+        Self {
+            show_member_since: true,
+            show_listing_count: true,
+            show_sales_count: true,
+            show_rating: true,
+            show_listings: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileUpdate {
+    pub bio: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SellerReviewCreate {
+    /// The seller's listing the review is about, it must have been sold or reserved
+    pub product_id: i64,
+    pub rating: i16,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersonalAccessTokenCreate {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_in_days: Option<i64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserLogin {
     pub username: Option<String>,
     pub email: Option<String>,
     pub password: String,
+    pub device_name: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -66,6 +233,8 @@ pub struct UserReturnDto {
     pub username: String,
     pub email: String,
     pub role: Role,
+    pub permissions: Vec<Permission>,
+    pub verified: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -81,7 +250,40 @@ pub struct MinUserReturnDto {
     pub username: String,
 }
 
+/// How the credential in the `authorization` header authenticated the request
+enum Credential {
+    Session(UserJwtDto),
+    AccessToken(UserJwtDto, Vec<Scope>),
+}
+
+async fn authenticate(req: &Request<'_>) -> request::Outcome<Credential, ()> {
+    let mut auth_service: AuthService = try_outcome!(req.guard::<AuthService>().await);
+
+    let jwt = match req.headers().get("authorization").next() {
+        Some(j) => j,
+        None => return Outcome::Failure((Status::Unauthorized, ())),
+    };
+
+    let token = jwt.strip_prefix("Bearer ").unwrap_or(jwt);
+    if AuthService::is_personal_access_token(token) {
+        return match auth_service.validate_personal_access_token(token).await {
+            Ok((user, scopes)) => Outcome::Success(Credential::AccessToken(user, scopes)),
+            Err(AuthServiceError::Suspended(_)) => Outcome::Failure((Status::Forbidden, ())),
+            Err(_) => Outcome::Failure((Status::Unauthorized, ())),
+        };
+    }
+
+    match auth_service.validate_jwt(jwt.into(), None).await {
+        Ok(user) => Outcome::Success(Credential::Session(user)),
+        Err(AuthServiceError::Suspended(_)) => Outcome::Failure((Status::Forbidden, ())),
+        Err(_) => Outcome::Failure((Status::Unauthorized, ())),
+    }
+}
+
 /// Request guard that will read the JWT from headers and inject the user into the function
+///
+/// Personal access tokens are refused, so routes that change the account (password, 2FA,
+/// sessions, tokens, deletion) stay session-only. Read-only routes use `ScopedAuthUser<ReadAccess>`
 #[derive(Debug)]
 pub struct AuthUser {
     pub user: UserJwtDto,
@@ -92,19 +294,79 @@ impl<'r> FromRequest<'r> for AuthUser {
     type Error = ();
 
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        let auth_service: AuthService = try_outcome!(req.guard::<AuthService>().await);
+        match try_outcome!(authenticate(req).await) {
+            Credential::Session(user) => Outcome::Success(AuthUser { user }),
+            Credential::AccessToken(..) => Outcome::Failure((Status::Forbidden, ())),
+        }
+    }
+}
 
-        let jwt = match req.headers().get("authorization").next() {
-            Some(j) => j,
-            None => return Outcome::Failure((Status::Unauthorized, ())),
-        };
+/// Like `AuthUser`, but also accepts personal access tokens granted scope `S`
+#[derive(Debug)]
+pub struct ScopedAuthUser<S: RequiredScope> {
+    pub user: UserJwtDto,
+    scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope> From<ScopedAuthUser<S>> for AuthUser {
+    fn from(scoped: ScopedAuthUser<S>) -> Self {
+        AuthUser { user: scoped.user }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, S: RequiredScope> FromRequest<'r> for ScopedAuthUser<S> {
+    type Error = ();
 
-        let user = match auth_service.validate_jwt(jwt.into(), None) {
-            Ok(user) => user,
-            Err(_) => return Outcome::Failure((Status::Unauthorized, ())),
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let user = match try_outcome!(authenticate(req).await) {
+            Credential::Session(user) => user,
+            Credential::AccessToken(user, scopes) if scopes.contains(&S::SCOPE) => user,
+            Credential::AccessToken(..) => return Outcome::Failure((Status::Forbidden, ())),
         };
 
-        Outcome::Success(AuthUser { user })
+        Outcome::Success(ScopedAuthUser {
+            user,
+            scope: PhantomData,
+        })
+    }
+}
+
+/// Like `AuthUser`, but refuses users whose roles don't grant permission `P`
+#[derive(Debug)]
+pub struct RequirePermission<P: RequiredPermission> {
+    pub user: UserJwtDto,
+    permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission> From<RequirePermission<P>> for AuthUser {
+    fn from(permitted: RequirePermission<P>) -> Self {
+        AuthUser {
+            user: permitted.user,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, P: RequiredPermission> FromRequest<'r> for RequirePermission<P> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let auth_user: AuthUser = try_outcome!(req.guard::<AuthUser>().await);
+
+        if !auth_user.user.has_permission(P::PERMISSION) {
+            tracing::warn!(
+                message = "User lacks permission",
+                user_id = auth_user.user.id,
+                permission = ?P::PERMISSION
+            );
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+
+        Outcome::Success(RequirePermission {
+            user: auth_user.user,
+            permission: PhantomData,
+        })
     }
 }
 
@@ -146,6 +408,10 @@ impl<'r> FromRequest<'r> for RefreshAuthUser {
             None => return Outcome::Failure((Status::Unauthorized, ())),
         };
 
+        if !AccountStatus::of(&user).is_active() {
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+
         Outcome::Success(RefreshAuthUser {
             user: UserJwtDto {
                 id: user.id,
@@ -0,0 +1,319 @@
+use crate::{
+    config::extract_section,
+    guards::TooManyRequests,
+    models::user::UserJwtDto,
+    services::{AuthService, KeyRing},
+};
+use chrono::Utc;
+use jwt_simple::prelude::VerificationOptions;
+use redis::Client as RedisClient;
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    figment::Figment,
+    http::{uri::Origin, Header, Method, Status},
+    response::{self, Responder},
+    Build, Data, Request, Response, Rocket,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+};
+
+#[cfg(test)]
+mod test;
+
+const RATE_LIMITED_PATH: &str = "/__rate_limited";
+/// How long to stop trying Redis after it fails, counting in process meanwhile
+const REDIS_RETRY_SECONDS: i64 = 30;
+/// Expired in process counters are only swept once there are this many
+const MEMORY_SWEEP_THRESHOLD: usize = 10_000;
+
+/// What requests are counted against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    #[default]
+    Ip,
+    /// The authenticated user, falling back to the address for anonymous requests
+    User,
+    /// The personal access token, falling back to the address without one
+    Token,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitRule {
+    /// Requests under this path are limited by the rule. The longest matching path wins.
+    pub path: String,
+    /// Methods the rule applies to, all of them when empty
+    #[serde(default)]
+    pub methods: Vec<String>,
+    pub limit: u64,
+    pub window_seconds: u64,
+    #[serde(default)]
+    pub key: RateLimitKey,
+}
+
+impl RateLimitRule {
+    fn matches(&self, method: Method, path: &str) -> bool {
+        let prefix = self.path.trim_end_matches('/');
+        let path_matches = path == prefix
+            || path
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.starts_with('/'));
+
+        path_matches
+            && (self.methods.is_empty()
+                || self
+                    .methods
+                    .iter()
+                    .any(|m| m.eq_ignore_ascii_case(method.as_str())))
+    }
+}
+
+/// Rate limits read from the `rate_limit` key of the Rocket config
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub rules: BTreeMap<String, RateLimitRule>,
+}
+
+impl RateLimitConfig {
+    pub fn from_figment(figment: &Figment) -> anyhow::Result<Self> {
+        extract_section(figment, "rate_limit")
+    }
+
+    fn rule_for(&self, method: Method, path: &str) -> Option<(&String, &RateLimitRule)> {
+        self.rules
+            .iter()
+            .filter(|(_, rule)| rule.matches(method, path))
+            .max_by_key(|(_, rule)| rule.path.trim_end_matches('/').len())
+    }
+}
+
+/// Where a request stands against the rule it matched
+#[derive(Debug, Clone, Copy)]
+struct RateLimitState {
+    limit: u64,
+    remaining: u64,
+    reset_seconds: u64,
+    count: u64,
+    limited: bool,
+}
+
+/// Sliding window rate limiter, counting in Redis and in process when Redis is unavailable
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    redis: Option<RedisClient>,
+    redis_retry_at: AtomicI64,
+    memory: Mutex<HashMap<String, (u64, i64)>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, redis: Option<RedisClient>) -> Self {
+        Self {
+            config,
+            redis,
+            redis_retry_at: AtomicI64::new(0),
+            memory: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn identity(&self, req: &Request<'_>, key: RateLimitKey) -> String {
+        let address = || {
+            req.client_ip()
+                .map(|ip| format!("ip:{ip}"))
+                .unwrap_or_else(|| String::from("ip:unknown"))
+        };
+        let token = req
+            .headers()
+            .get_one("authorization")
+            .map(|value| value.strip_prefix("Bearer ").unwrap_or(value));
+
+        match (key, token) {
+            (RateLimitKey::Ip, _) | (_, None) => address(),
+            (_, Some(token)) if AuthService::is_personal_access_token(token) => {
+                format!("token:{}", AuthService::hash_token(token))
+            }
+            (RateLimitKey::Token, Some(_)) => address(),
+            (RateLimitKey::User, Some(token)) => req
+                .rocket()
+                .state::<KeyRing>()
+                .and_then(|key_ring| {
+                    key_ring
+                        .verify::<UserJwtDto>(token, VerificationOptions::default())
+                        .ok()
+                })
+                .map(|claims| format!("user:{}", claims.custom.id))
+                .unwrap_or_else(address),
+        }
+    }
+
+    /// Counts a hit in the current window, returning it with the previous window's count
+    async fn hit(&self, current: &str, previous: &str, ttl: u64) -> (u64, u64) {
+        let now = Utc::now().timestamp();
+
+        if let Some(ref redis) = self.redis {
+            if self.redis_retry_at.load(Ordering::Relaxed) <= now {
+                match Self::hit_redis(redis, current, previous, ttl).await {
+                    Ok(counts) => return counts,
+                    Err(e) => {
+                        tracing::warn!(
+                            message = "Rate limiting in process, Redis is unavailable",
+                            error = %e
+                        );
+                        self.redis_retry_at
+                            .store(now + REDIS_RETRY_SECONDS, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
+        self.hit_memory(current, previous, ttl, now)
+    }
+
+    async fn hit_redis(
+        redis: &RedisClient,
+        current: &str,
+        previous: &str,
+        ttl: u64,
+    ) -> redis::RedisResult<(u64, u64)> {
+        let mut conn = redis.get_async_connection().await?;
+        let (current, previous): (u64, Option<u64>) = redis::pipe()
+            .atomic()
+            .incr(current, 1)
+            .expire(current, ttl as usize)
+            .ignore()
+            .get(previous)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok((current, previous.unwrap_or(0)))
+    }
+
+    fn hit_memory(&self, current: &str, previous: &str, ttl: u64, now: i64) -> (u64, u64) {
+        let mut memory = self.memory.lock().unwrap();
+        if memory.len() > MEMORY_SWEEP_THRESHOLD {
+            memory.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+
+        let previous = memory
+            .get(previous)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map_or(0, |(count, _)| *count);
+
+        let entry = memory
+            .entry(current.to_owned())
+            .or_insert((0, now + ttl as i64));
+        entry.0 += 1;
+
+        (entry.0, previous)
+    }
+
+    async fn check(&self, req: &Request<'_>) -> Option<RateLimitState> {
+        let path = req.uri().path();
+        let (name, rule) = self.config.rule_for(req.method(), path.as_str())?;
+        let window = rule.window_seconds.max(1);
+
+        let now = Utc::now().timestamp().max(0) as u64;
+        let window_index = now / window;
+        let elapsed = now % window;
+        let identity = self.identity(req, rule.key);
+
+        let (current, previous) = self
+            .hit(
+                &format!("rate_limit:{name}:{identity}:{window_index}"),
+                &format!(
+                    "rate_limit:{name}:{identity}:{}",
+                    window_index.wrapping_sub(1)
+                ),
+                window * 2,
+            )
+            .await;
+
+        // Weight the previous window by how much of it still overlaps the sliding window
+        let count = current + previous * (window - elapsed) / window;
+
+        Some(RateLimitState {
+            limit: rule.limit,
+            remaining: rule.limit.saturating_sub(count),
+            reset_seconds: window - elapsed,
+            count,
+            limited: count > rule.limit,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for RateLimiter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate limiting",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        Ok(rocket.mount("/", routes![rate_limited]))
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        if req.method() == Method::Options {
+            return;
+        }
+
+        let state = self.check(req).await;
+        req.local_cache(|| state);
+
+        if state.is_some_and(|state| state.limited) {
+            // Fairings can't answer requests, so send limited ones to a route that refuses them
+            req.set_method(Method::Get);
+            req.set_uri(Origin::parse(RATE_LIMITED_PATH).unwrap());
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, response: &mut Response<'r>) {
+        let state = match req.local_cache(|| None::<RateLimitState>) {
+            Some(state) => *state,
+            None => return,
+        };
+
+        response.set_header(Header::new("RateLimit-Limit", state.limit.to_string()));
+        response.set_header(Header::new(
+            "RateLimit-Remaining",
+            state.remaining.to_string(),
+        ));
+        response.set_header(Header::new(
+            "RateLimit-Reset",
+            state.reset_seconds.to_string(),
+        ));
+
+        if response.status() == Status::TooManyRequests {
+            response.set_header(Header::new("Retry-After", state.reset_seconds.to_string()));
+        }
+    }
+}
+
+struct RateLimited;
+
+impl<'r> Responder<'r, 'static> for RateLimited {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let (count, reset_seconds) = req
+            .local_cache(|| None::<RateLimitState>)
+            .map_or((0, 0), |state| (state.count, state.reset_seconds));
+
+        TooManyRequests::new(
+            count.min(i16::MAX as u64) as i16,
+            reset_seconds.min(i32::MAX as u64) as i32,
+        )
+        .respond_to(req)
+    }
+}
+
+#[get("/__rate_limited")]
+fn rate_limited() -> RateLimited {
+    RateLimited
+}
@@ -0,0 +1,176 @@
+use super::*;
+use rocket::{local::asynchronous::Client, State};
+use std::sync::atomic::AtomicUsize;
+
+type E = Result<(), Box<dyn std::error::Error>>;
+
+#[derive(Default)]
+struct Hits(AtomicUsize);
+
+#[post("/orders")]
+fn create_order(hits: &State<Hits>) -> &'static str {
+    hits.0.fetch_add(1, Ordering::Relaxed);
+    "created"
+}
+
+#[get("/orders")]
+fn list_orders() -> &'static str {
+    "orders"
+}
+
+fn rule(path: &str, methods: &[&str], limit: u64, key: RateLimitKey) -> RateLimitRule {
+    RateLimitRule {
+        path: path.to_owned(),
+        methods: methods.iter().map(|m| m.to_string()).collect(),
+        limit,
+        window_seconds: 60,
+        key,
+    }
+}
+
+async fn client(rules: Vec<(&str, RateLimitRule)>, redis: Option<RedisClient>) -> Client {
+    let config = RateLimitConfig {
+        rules: rules
+            .into_iter()
+            .map(|(name, rule)| (name.to_owned(), rule))
+            .collect(),
+    };
+    let rocket = rocket::build()
+        .mount("/api", routes![create_order, list_orders])
+        .manage(Hits::default())
+        .attach(RateLimiter::new(config, redis));
+
+    Client::tracked(rocket).await.unwrap()
+}
+
+fn header(res: &rocket::local::asynchronous::LocalResponse<'_>, name: &str) -> Option<String> {
+    res.headers().get_one(name).map(str::to_owned)
+}
+
+#[tokio::test]
+async fn limits_requests_before_they_reach_the_route() -> E {
+    let client = client(
+        vec![("orders", rule("/api/orders", &[], 2, RateLimitKey::Ip))],
+        None,
+    )
+    .await;
+
+    for remaining in ["1", "0"] {
+        let res = client.post("/api/orders").dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(header(&res, "RateLimit-Limit").as_deref(), Some("2"));
+        assert_eq!(
+            header(&res, "RateLimit-Remaining").as_deref(),
+            Some(remaining)
+        );
+        assert!(header(&res, "RateLimit-Reset").is_some());
+    }
+
+    let res = client.post("/api/orders").dispatch().await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+    assert_eq!(header(&res, "RateLimit-Remaining").as_deref(), Some("0"));
+    assert!(header(&res, "Retry-After").is_some());
+    assert_eq!(header(&res, "x-rate-limit").as_deref(), Some("3"));
+
+    let hits = client.rocket().state::<Hits>().unwrap();
+    assert_eq!(hits.0.load(Ordering::Relaxed), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn uses_the_most_specific_rule_for_the_method() -> E {
+    let client = client(
+        vec![
+            ("default", rule("/", &[], 100, RateLimitKey::Ip)),
+            (
+                "writes",
+                rule("/api/orders", &["POST"], 1, RateLimitKey::Ip),
+            ),
+        ],
+        None,
+    )
+    .await;
+
+    client.post("/api/orders").dispatch().await;
+    let res = client.post("/api/orders").dispatch().await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+
+    let res = client.get("/api/orders").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(header(&res, "RateLimit-Limit").as_deref(), Some("100"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn counts_access_tokens_separately() -> E {
+    let client = client(
+        vec![("orders", rule("/api/orders", &[], 1, RateLimitKey::Token))],
+        None,
+    )
+    .await;
+
+    for token in ["tkx_first", "tkx_second"] {
+        let res = client
+            .post("/api/orders")
+            .header(Header::new("authorization", token))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    let res = client
+        .post("/api/orders")
+        .header(Header::new("authorization", "tkx_first"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn falls_back_to_counting_in_process_without_redis() -> E {
+    let unreachable = RedisClient::open("redis://127.0.0.1:1")?;
+    let client = client(
+        vec![("orders", rule("/api/orders", &[], 1, RateLimitKey::Ip))],
+        Some(unreachable),
+    )
+    .await;
+
+    let res = client.post("/api/orders").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(header(&res, "RateLimit-Remaining").as_deref(), Some("0"));
+
+    let res = client.post("/api/orders").dispatch().await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+
+    Ok(())
+}
+
+#[test]
+fn rule_paths_match_whole_segments() {
+    let rule = rule("/api/auth", &["post"], 1, RateLimitKey::Ip);
+
+    assert!(rule.matches(Method::Post, "/api/auth"));
+    assert!(rule.matches(Method::Post, "/api/auth/login"));
+    assert!(!rule.matches(Method::Post, "/api/authors"));
+    assert!(!rule.matches(Method::Get, "/api/auth/login"));
+}
+
+#[test]
+fn malformed_config_is_an_error() {
+    use rocket::figment::providers::{Format, Toml};
+
+    let missing = Figment::from(Toml::string("[other]\nkey = 1"));
+    assert!(RateLimitConfig::from_figment(&missing)
+        .unwrap()
+        .rules
+        .is_empty());
+
+    let malformed = Figment::from(Toml::string(
+        "[rate_limit.rules.login]\npath = \"/api/auth\"\nlimt = 5",
+    ));
+    assert!(RateLimitConfig::from_figment(&malformed).is_err());
+}
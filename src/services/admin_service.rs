@@ -0,0 +1,535 @@
+use super::{AuthService, AuthServiceError};
+use crate::{
+    dtos::admin::{AdminUserPage, AdminUserReturn, AdminUserState, AuditLogPage, AuditLogReturn},
+    models::{
+        admin::{AdminAction, AuditAction, RoleChange, Suspension},
+        permission::Permission,
+        role::Role,
+        user::{normalize_identity, AccountStatus, UserJwtDto},
+    },
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use entity::{
+    admin_audit_log::{
+        self, ActiveModel as AuditLogActiveModel, Entity as AuditLogEntity, Model as AuditLogModel,
+    },
+    refresh_token::{self, Entity as RefreshEntity},
+    user::{self, ActiveModel as UserActiveModel, Entity as UserEntity, Model as UserModel},
+};
+use rocket::{
+    outcome::IntoOutcome,
+    request::{self, FromRequest},
+    response::Responder,
+    Request,
+};
+use sea_orm::{
+    prelude::*, query::Condition, ActiveValue, DatabaseConnection, QueryOrder, TransactionTrait,
+};
+use thiserror::Error;
+
+#[cfg(test)]
+mod test;
+
+const DEFAULT_PAGE_SIZE: u64 = 25;
+const MAX_PAGE_SIZE: u64 = 100;
+const MAX_REASON_LENGTH: usize = 1000;
+
+#[derive(Error, Debug, Responder)]
+pub enum AdminServiceError {
+    #[error("An unknown error has occurred")]
+    #[response(status = 500)]
+    InternalError(AnyhowResponder),
+    #[error("User not found")]
+    #[response(status = 404)]
+    UserNotFound(AnyhowResponder),
+    #[error("Invalid request")]
+    #[response(status = 400)]
+    InvalidRequest(AnyhowResponder),
+    #[error(transparent)]
+    AuthServiceError(AuthServiceError),
+}
+
+/// Account administration for users holding `Permission::ManageUsers`.
+/// Every change is written to the admin audit log.
+#[derive(Debug)]
+pub struct AdminService {
+    db_connection: DatabaseConnection,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminService {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        req.rocket()
+            .state::<DatabaseConnection>()
+            .map(|conn| Self {
+                db_connection: conn.clone(),
+            })
+            .or_forward(())
+    }
+}
+
+/// Turns optional query parameters into a 1-based page and a bounded page size
+fn page_bounds(page: Option<u64>, per_page: Option<u64>) -> (u64, u64) {
+    (
+        page.unwrap_or(1).max(1),
+        per_page
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+    )
+}
+
+fn validate_reason(reason: &str) -> Result<String, AdminServiceError> {
+    let reason = reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LENGTH {
+        return Err(AdminServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+            "A reason of at most {MAX_REASON_LENGTH} characters is required"
+        ))));
+    }
+
+    Ok(reason.to_owned())
+}
+
+fn role_of(user: &UserModel) -> Result<Role, AdminServiceError> {
+    Role::try_from(user.role).map_err(|_| {
+        AdminServiceError::InternalError(AnyhowResponder(anyhow!(
+            "User {} has an unknown role {}",
+            user.id,
+            user.role
+        )))
+    })
+}
+
+fn to_return(user: UserModel) -> Result<AdminUserReturn, AdminServiceError> {
+    let role = role_of(&user)?;
+    let status = AccountStatus::of(&user);
+
+    Ok(AdminUserReturn {
+        id: user.id,
+        username: user.username,
+        email: user.email,
+        role,
+        permissions: role.permissions(),
+        verified: user.verified_at.is_some(),
+        two_factor_enabled: user.totp_enabled_at.is_some(),
+        created_at: user.created_at,
+        status,
+        suspended_at: user.suspended_at,
+        suspended_until: user.suspended_until,
+        suspension_reason: user.suspension_reason,
+    })
+}
+
+impl AdminService {
+    /// Lists users, optionally only those whose username or email contains `query`
+    pub async fn list_users(
+        &self,
+        query: Option<&str>,
+        page: Option<u64>,
+        per_page: Option<u64>,
+    ) -> Result<AdminUserPage, AdminServiceError> {
+        let (page, per_page) = page_bounds(page, per_page);
+
+        let mut find = UserEntity::find().order_by_asc(user::Column::Id);
+        if let Some(query) = query.map(str::trim).filter(|q| !q.is_empty()) {
+            find = find.filter(
+                Condition::any()
+                    .add(user::Column::UsernameNormalized.contains(normalize_identity(query)))
+                    .add(user::Column::EmailNormalized.contains(normalize_identity(query))),
+            );
+        }
+
+        let paginator = find.paginate(&self.db_connection, per_page);
+        let total = paginator
+            .num_items()
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        let users = paginator
+            .fetch_page(page - 1)
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .into_iter()
+            .map(to_return)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AdminUserPage {
+            users,
+            page,
+            per_page,
+            total,
+        })
+    }
+
+    pub async fn get_user(&self, user_id: i64) -> Result<AdminUserReturn, AdminServiceError> {
+        to_return(self.find_user(user_id).await?)
+    }
+
+    /// Replaces the user's roles. Taking a role away also ends their sessions and
+    /// refuses the access tokens they already hold, which still carry the old roles.
+    pub async fn change_role(
+        &self,
+        actor: &UserJwtDto,
+        user_id: i64,
+        change: RoleChange,
+        auth_service: &mut AuthService,
+    ) -> Result<AdminUserReturn, AdminServiceError> {
+        let reason = validate_reason(&change.reason)?;
+        if change.role.is_empty() {
+            return Err(AdminServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "Users must hold at least one role"
+            ))));
+        }
+        if actor.id == user_id && !change.role.has_permission(Permission::ManageUsers) {
+            return Err(AdminServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "User {user_id} attempted to remove their own access to user management"
+            ))));
+        }
+
+        let found = self.find_user(user_id).await?;
+        let before = self.state_of(&found).await?;
+        let demoted = !change.role.contains(before.role);
+
+        let txn = self
+            .db_connection
+            .begin()
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let updated = UserActiveModel {
+            role: ActiveValue::Set(change.role.bits()),
+            updated_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..found.clone().into()
+        }
+        .update(&txn)
+        .await
+        .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let after = AdminUserState {
+            role: change.role,
+            active_sessions: if demoted { 0 } else { before.active_sessions },
+            ..before.clone()
+        };
+        Self::record(
+            &txn,
+            actor,
+            user_id,
+            AuditAction::ChangeRole,
+            &before,
+            &after,
+            reason,
+        )
+        .await?;
+
+        txn.commit()
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if demoted {
+            auth_service
+                .revoke_refresh_token(&found)
+                .await
+                .map_err(AdminServiceError::AuthServiceError)?;
+            auth_service
+                .deny_access_tokens(user_id, None)
+                .await
+                .map_err(AdminServiceError::AuthServiceError)?;
+        }
+
+        to_return(updated)
+    }
+
+    /// Suspends an account and ends all of its sessions, including access
+    /// tokens that haven't expired yet
+    pub async fn suspend(
+        &self,
+        actor: &UserJwtDto,
+        user_id: i64,
+        suspension: Suspension,
+        auth_service: &mut AuthService,
+    ) -> Result<AdminUserReturn, AdminServiceError> {
+        let reason = validate_reason(&suspension.reason)?;
+        if actor.id == user_id {
+            return Err(AdminServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "User {user_id} attempted to suspend themselves"
+            ))));
+        }
+        if suspension
+            .until
+            .is_some_and(|until| until <= Utc::now().naive_utc())
+        {
+            return Err(AdminServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "Suspensions must end in the future"
+            ))));
+        }
+
+        let found = self.find_user(user_id).await?;
+        if !AccountStatus::of(&found).is_active() {
+            return Err(AdminServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "User {user_id} is already suspended"
+            ))));
+        }
+        let before = self.state_of(&found).await?;
+
+        let action = if suspension.ban {
+            AuditAction::Ban
+        } else {
+            AuditAction::Suspend
+        };
+        let until = suspension.until;
+        let updated = self
+            .set_suspended(
+                actor,
+                found.clone(),
+                action,
+                before,
+                reason,
+                Some(suspension),
+            )
+            .await?;
+
+        // Only once the suspension is stored, so a failed write doesn't sign them out for nothing
+        auth_service
+            .revoke_refresh_token(&found)
+            .await
+            .map_err(AdminServiceError::AuthServiceError)?;
+        auth_service
+            .deny_access_tokens(user_id, until)
+            .await
+            .map_err(AdminServiceError::AuthServiceError)?;
+
+        Ok(updated)
+    }
+
+    /// Lifts a suspension or ban
+    pub async fn unsuspend(
+        &self,
+        actor: &UserJwtDto,
+        user_id: i64,
+        action: AdminAction,
+        auth_service: &mut AuthService,
+    ) -> Result<AdminUserReturn, AdminServiceError> {
+        let reason = validate_reason(&action.reason)?;
+
+        let found = self.find_user(user_id).await?;
+        if matches!(
+            AccountStatus::of(&found),
+            AccountStatus::Active | AccountStatus::Deleted
+        ) {
+            return Err(AdminServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "User {user_id} is not suspended"
+            ))));
+        }
+        let before = self.state_of(&found).await?;
+
+        let updated = self
+            .set_suspended(actor, found, AuditAction::Unsuspend, before, reason, None)
+            .await?;
+
+        auth_service
+            .allow_access_tokens(user_id)
+            .await
+            .map_err(AdminServiceError::AuthServiceError)?;
+
+        Ok(updated)
+    }
+
+    /// Revokes every refresh token the user holds. Access tokens already
+    /// issued stay valid until they expire.
+    pub async fn force_logout(
+        &self,
+        actor: &UserJwtDto,
+        user_id: i64,
+        action: AdminAction,
+        auth_service: &mut AuthService,
+    ) -> Result<(), AdminServiceError> {
+        let reason = validate_reason(&action.reason)?;
+
+        let found = self.find_user(user_id).await?;
+        let before = self.state_of(&found).await?;
+
+        auth_service
+            .revoke_refresh_token(&found)
+            .await
+            .map_err(AdminServiceError::AuthServiceError)?;
+
+        let after = AdminUserState {
+            active_sessions: 0,
+            ..before.clone()
+        };
+        Self::record(
+            &self.db_connection,
+            actor,
+            user_id,
+            AuditAction::ForceLogout,
+            &before,
+            &after,
+            reason,
+        )
+        .await
+    }
+
+    /// Audit log entries, newest first, optionally only those about `user_id`
+    pub async fn audit_log(
+        &self,
+        user_id: Option<i64>,
+        page: Option<u64>,
+        per_page: Option<u64>,
+    ) -> Result<AuditLogPage, AdminServiceError> {
+        let (page, per_page) = page_bounds(page, per_page);
+
+        let mut find = AuditLogEntity::find().order_by_desc(admin_audit_log::Column::Id);
+        if let Some(user_id) = user_id {
+            find = find.filter(admin_audit_log::Column::TargetUserId.eq(user_id));
+        }
+
+        let paginator = find.paginate(&self.db_connection, per_page);
+        let total = paginator
+            .num_items()
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        let entries = paginator
+            .fetch_page(page - 1)
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .into_iter()
+            .map(|entry: AuditLogModel| AuditLogReturn {
+                id: entry.id,
+                actor_id: entry.actor_id,
+                target_user_id: entry.target_user_id,
+                action: entry.action,
+                before: entry.before.and_then(|s| serde_json::from_str(&s).ok()),
+                after: entry.after.and_then(|s| serde_json::from_str(&s).ok()),
+                reason: entry.reason,
+                created_at: entry.created_at,
+            })
+            .collect();
+
+        Ok(AuditLogPage {
+            entries,
+            page,
+            per_page,
+            total,
+        })
+    }
+
+    async fn find_user(&self, user_id: i64) -> Result<UserModel, AdminServiceError> {
+        UserEntity::find_by_id(user_id)
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(AdminServiceError::UserNotFound(AnyhowResponder(anyhow!(
+                "User {user_id} not found"
+            ))))
+    }
+
+    async fn state_of(&self, user: &UserModel) -> Result<AdminUserState, AdminServiceError> {
+        let active_sessions = RefreshEntity::find()
+            .filter(refresh_token::Column::UserId.eq(user.id))
+            .filter(refresh_token::Column::RotatedAt.is_null())
+            .filter(refresh_token::Column::ExpiresAt.gt(Utc::now().naive_utc()))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let status = AccountStatus::of(user);
+
+        Ok(AdminUserState {
+            role: role_of(user)?,
+            suspended: !status.is_active(),
+            banned: status == AccountStatus::Banned,
+            active_sessions,
+        })
+    }
+
+    /// Applies `suspension` to the account, or lifts it when `None`
+    async fn set_suspended(
+        &self,
+        actor: &UserJwtDto,
+        found: UserModel,
+        action: AuditAction,
+        before: AdminUserState,
+        reason: String,
+        suspension: Option<Suspension>,
+    ) -> Result<AdminUserReturn, AdminServiceError> {
+        let user_id = found.id;
+        let now = Utc::now().naive_utc();
+
+        let txn = self
+            .db_connection
+            .begin()
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let updated = UserActiveModel {
+            suspended_at: ActiveValue::Set(suspension.as_ref().map(|_| now)),
+            suspended_until: ActiveValue::Set(suspension.as_ref().and_then(|s| s.until)),
+            suspension_reason: ActiveValue::Set(suspension.as_ref().map(|_| reason.clone())),
+            banned: ActiveValue::Set(suspension.as_ref().is_some_and(|s| s.ban)),
+            updated_at: ActiveValue::Set(now),
+            ..found.into()
+        }
+        .update(&txn)
+        .await
+        .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let after = AdminUserState {
+            suspended: suspension.is_some(),
+            banned: updated.banned,
+            active_sessions: if suspension.is_some() {
+                0
+            } else {
+                before.active_sessions
+            },
+            ..before.clone()
+        };
+        Self::record(&txn, actor, user_id, action, &before, &after, reason).await?;
+
+        txn.commit()
+            .await
+            .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        to_return(updated)
+    }
+
+    async fn record<C: ConnectionTrait>(
+        db: &C,
+        actor: &UserJwtDto,
+        user_id: i64,
+        action: AuditAction,
+        before: &AdminUserState,
+        after: &AdminUserState,
+        reason: String,
+    ) -> Result<(), AdminServiceError> {
+        tracing::info!(
+            message = "Admin action",
+            actor_id = actor.id,
+            user_id,
+            action = action.as_str(),
+            reason
+        );
+
+        let to_json = |state: &AdminUserState| {
+            serde_json::to_string(state)
+                .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+        };
+
+        AuditLogActiveModel {
+            actor_id: ActiveValue::Set(Some(actor.id)),
+            target_user_id: ActiveValue::Set(Some(user_id)),
+            action: ActiveValue::Set(action.as_str().to_owned()),
+            before: ActiveValue::Set(Some(to_json(before)?)),
+            after: ActiveValue::Set(Some(to_json(after)?)),
+            reason: ActiveValue::Set(reason),
+            created_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .map_err(|e| AdminServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,359 @@
+use super::*;
+use crate::{
+    db::test::{establish_connection, in_memory_redis},
+    models::{session::SessionMetadata, user::UserRegister},
+    services::{KeyEncryptionKey, KeyRing, UserService},
+};
+
+type E = Result<(), Box<dyn std::error::Error>>;
+
+async fn create_user(
+    db: &DatabaseConnection,
+    username: &str,
+) -> Result<UserModel, Box<dyn std::error::Error>> {
+    let user_service = UserService::new(db.clone());
+    let id = user_service
+        .create_user(
+            UserRegister {
+                username: username.into(),
+                email: format!("{username}@test.com"),
+                password: "testPass".into(),
+            },
+            true,
+        )
+        .await?;
+
+    Ok(user_service.get_user_by_id(&id).await?.unwrap())
+}
+
+async fn auth_service(db: &DatabaseConnection) -> Result<AuthService, Box<dyn std::error::Error>> {
+    Ok(AuthService::new(
+        db.clone(),
+        Box::new(in_memory_redis()),
+        KeyRing::load(db, KeyEncryptionKey::test()).await?,
+    ))
+}
+
+fn actor(user: &UserModel) -> UserJwtDto {
+    UserJwtDto {
+        id: user.id,
+        username: user.username.clone(),
+        role: Role::ADMIN,
+    }
+}
+
+fn reason(reason: &str) -> AdminAction {
+    AdminAction {
+        reason: reason.into(),
+    }
+}
+
+fn suspension(reason: &str, until: Option<chrono::NaiveDateTime>, ban: bool) -> Suspension {
+    Suspension {
+        reason: reason.into(),
+        until,
+        ban,
+    }
+}
+
+#[tokio::test]
+async fn lists_and_searches_users_by_page() -> E {
+    let db = establish_connection().await?;
+    for username in ["alice", "alfred", "bob"] {
+        create_user(&db, username).await?;
+    }
+    let admin_service = AdminService { db_connection: db };
+
+    let page = admin_service.list_users(None, Some(2), Some(2)).await?;
+    assert_eq!(page.total, 3);
+    assert_eq!(page.users.len(), 1);
+    assert_eq!(page.users[0].username, "bob");
+
+    let found = admin_service.list_users(Some("al"), None, None).await?;
+    assert_eq!(found.total, 2);
+    assert!(found
+        .users
+        .iter()
+        .all(|user| user.username.starts_with("al")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn role_changes_are_audited() -> E {
+    let db = establish_connection().await?;
+    let admin = create_user(&db, "admin").await?;
+    let user = create_user(&db, "user").await?;
+    let admin_service = AdminService {
+        db_connection: db.clone(),
+    };
+
+    let updated = admin_service
+        .change_role(
+            &actor(&admin),
+            user.id,
+            RoleChange {
+                role: Role::USER | Role::MODERATOR,
+                reason: "Helping with listings".into(),
+            },
+            &mut auth_service(&db).await?,
+        )
+        .await?;
+    assert_eq!(updated.role, Role::USER | Role::MODERATOR);
+    assert!(updated.permissions.contains(&Permission::ModerateProducts));
+
+    let log = admin_service.audit_log(Some(user.id), None, None).await?;
+    assert_eq!(log.total, 1);
+    let entry = &log.entries[0];
+    assert_eq!(entry.actor_id, Some(admin.id));
+    assert_eq!(entry.action, "change_role");
+    assert_eq!(entry.reason, "Helping with listings");
+    assert_eq!(entry.before.as_ref().unwrap().role, Role::USER);
+    assert_eq!(
+        entry.after.as_ref().unwrap().role,
+        Role::USER | Role::MODERATOR
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn actions_need_a_reason() -> E {
+    let db = establish_connection().await?;
+    let admin = create_user(&db, "admin").await?;
+    let user = create_user(&db, "user").await?;
+    let admin_service = AdminService {
+        db_connection: db.clone(),
+    };
+
+    let res = admin_service
+        .suspend(
+            &actor(&admin),
+            user.id,
+            suspension("  ", None, false),
+            &mut auth_service(&db).await?,
+        )
+        .await;
+
+    assert!(matches!(res, Err(AdminServiceError::InvalidRequest(_))));
+    assert_eq!(admin_service.audit_log(None, None, None).await?.total, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn suspension_ends_sessions_and_blocks_login() -> E {
+    let db = establish_connection().await?;
+    let admin = create_user(&db, "admin").await?;
+    let user = create_user(&db, "user").await?;
+    entity::refresh_token::ActiveModel {
+        token: ActiveValue::Set("refresh".into()),
+        family_id: ActiveValue::Set("refresh".into()),
+        user_id: ActiveValue::Set(user.id),
+        expires_at: ActiveValue::Set(Utc::now().naive_utc() + chrono::Duration::days(1)),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    let admin_service = AdminService {
+        db_connection: db.clone(),
+    };
+    let mut auth_service = auth_service(&db).await?;
+
+    let suspended = admin_service
+        .suspend(
+            &actor(&admin),
+            user.id,
+            suspension("Spam", None, false),
+            &mut auth_service,
+        )
+        .await?;
+    assert!(suspended.suspended_at.is_some());
+    assert_eq!(RefreshEntity::find().count(&db).await?, 0);
+
+    let entry = admin_service.audit_log(Some(user.id), None, None).await?;
+    let entry = &entry.entries[0];
+    assert_eq!(entry.before.as_ref().unwrap().active_sessions, 1);
+    assert!(entry.after.as_ref().unwrap().suspended);
+
+    let found = admin_service.find_user(user.id).await?;
+    let res = auth_service
+        .issue_login(&found, &SessionMetadata::default())
+        .await;
+    assert!(matches!(res, Err(AuthServiceError::Suspended(_))));
+
+    let restored = admin_service
+        .unsuspend(
+            &actor(&admin),
+            user.id,
+            reason("Appeal accepted"),
+            &mut auth_service,
+        )
+        .await?;
+    assert!(restored.suspended_at.is_none());
+    assert_eq!(admin_service.audit_log(None, None, None).await?.total, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn admins_cannot_lock_themselves_out() -> E {
+    let db = establish_connection().await?;
+    let admin = create_user(&db, "admin").await?;
+    let admin_service = AdminService {
+        db_connection: db.clone(),
+    };
+
+    let res = admin_service
+        .suspend(
+            &actor(&admin),
+            admin.id,
+            suspension("Oops", None, false),
+            &mut auth_service(&db).await?,
+        )
+        .await;
+    assert!(matches!(res, Err(AdminServiceError::InvalidRequest(_))));
+
+    let res = admin_service
+        .change_role(
+            &actor(&admin),
+            admin.id,
+            RoleChange {
+                role: Role::USER,
+                reason: "Oops".into(),
+            },
+            &mut auth_service(&db).await?,
+        )
+        .await;
+    assert!(matches!(res, Err(AdminServiceError::InvalidRequest(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn suspension_revokes_access_tokens_already_issued() -> E {
+    let db = establish_connection().await?;
+    let admin = create_user(&db, "admin").await?;
+    let user = create_user(&db, "user").await?;
+    let admin_service = AdminService {
+        db_connection: db.clone(),
+    };
+    let mut auth_service = auth_service(&db).await?;
+    let login = auth_service
+        .issue_login(&user, &SessionMetadata::default())
+        .await?;
+
+    admin_service
+        .suspend(
+            &actor(&admin),
+            user.id,
+            suspension("Spam", None, false),
+            &mut auth_service,
+        )
+        .await?;
+    let res = auth_service.validate_jwt(login.jwt.clone(), None).await;
+    assert!(matches!(res, Err(AuthServiceError::Suspended(_))));
+
+    admin_service
+        .unsuspend(
+            &actor(&admin),
+            user.id,
+            reason("Appeal accepted"),
+            &mut auth_service,
+        )
+        .await?;
+    assert!(auth_service.validate_jwt(login.jwt, None).await.is_ok());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn demotion_ends_sessions_and_access_tokens() -> E {
+    let db = establish_connection().await?;
+    let admin = create_user(&db, "admin").await?;
+    let user = create_user(&db, "user").await?;
+    let admin_service = AdminService {
+        db_connection: db.clone(),
+    };
+    let mut auth_service = auth_service(&db).await?;
+    let login = auth_service
+        .issue_login(&user, &SessionMetadata::default())
+        .await?;
+
+    admin_service
+        .change_role(
+            &actor(&admin),
+            user.id,
+            RoleChange {
+                role: Role::USER | Role::MODERATOR,
+                reason: "Helping with listings".into(),
+            },
+            &mut auth_service,
+        )
+        .await?;
+    assert!(auth_service
+        .validate_jwt(login.jwt.clone(), None)
+        .await
+        .is_ok());
+
+    admin_service
+        .change_role(
+            &actor(&admin),
+            user.id,
+            RoleChange {
+                role: Role::USER,
+                reason: "Stepped down".into(),
+            },
+            &mut auth_service,
+        )
+        .await?;
+    let res = auth_service.validate_jwt(login.jwt, None).await;
+    assert!(matches!(res, Err(AuthServiceError::Suspended(_))));
+
+    let log = admin_service.audit_log(Some(user.id), None, None).await?;
+    assert_eq!(log.entries[0].after.as_ref().unwrap().active_sessions, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn suspensions_lift_once_they_expire() -> E {
+    let db = establish_connection().await?;
+    let admin = create_user(&db, "admin").await?;
+    let user = create_user(&db, "user").await?;
+    let admin_service = AdminService {
+        db_connection: db.clone(),
+    };
+    let mut auth_service = auth_service(&db).await?;
+    let until = Utc::now().naive_utc() + chrono::Duration::hours(1);
+
+    let banned = admin_service
+        .suspend(
+            &actor(&admin),
+            user.id,
+            suspension("Fraud", Some(until), true),
+            &mut auth_service,
+        )
+        .await?;
+    assert_eq!(banned.status, AccountStatus::Banned);
+    assert_eq!(banned.suspended_until, Some(until));
+    assert_eq!(banned.suspension_reason.as_deref(), Some("Fraud"));
+
+    UserEntity::update_many()
+        .col_expr(
+            user::Column::SuspendedUntil,
+            Expr::value(Utc::now().naive_utc() - chrono::Duration::minutes(1)),
+        )
+        .filter(user::Column::Id.eq(user.id))
+        .exec(&db)
+        .await?;
+
+    let found = admin_service.find_user(user.id).await?;
+    assert_eq!(AccountStatus::of(&found), AccountStatus::Active);
+    assert!(auth_service
+        .issue_login(&found, &SessionMetadata::default())
+        .await
+        .is_ok());
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use crate::{
     db::RedisRefresh,
     dtos::auth::LoginReturn,
-    models::{role::Role, user::UserJwtDto},
+    guards::TooManyRequests,
+    models::{role::Role, session::SessionMetadata, user::UserJwtDto},
     AnyhowResponder,
 };
 use anyhow::anyhow;
@@ -22,18 +23,79 @@ use rocket::{
     Request, State,
 };
 use sea_orm::DatabaseConnection;
-use sea_orm::{prelude::*, ActiveValue};
-use std::{
-    fs::OpenOptions,
-    io::{Read, Write},
-    time::Duration,
-};
+use sea_orm::{prelude::*, sea_query::Expr, ActiveValue, QueryOrder, TransactionTrait};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
 use thiserror::Error;
 
+mod key_ring;
+mod login_throttle;
+mod personal_access_token;
+mod suspension;
 #[cfg(test)]
 mod test;
 
-const KEY_LOCATION: &str = "./auth.key";
+pub use key_ring::{KeyEncryptionKey, KeyRing};
+pub const REFRESH_TOKEN_VALIDITY_DAYS: i64 = 30;
+const ACCESS_TOKEN_VALIDITY_SECONDS: u64 = 60 * 60;
+
+fn refresh_cache_key(token: &str) -> String {
+    format!("refresh:{token}")
+}
+
+const TWO_FACTOR_CHALLENGE_AUDIENCE: &str = "two_factor_challenge";
+const TWO_FACTOR_CHALLENGE_VALIDITY_SECONDS: u64 = 5 * 60;
+const TWO_FACTOR_MAX_ATTEMPTS: u32 = 5;
+
+fn two_factor_cache_key(challenge_id: &str) -> String {
+    format!("two_factor:{challenge_id}")
+}
+
+fn two_factor_attempts_key(challenge_id: &str) -> String {
+    format!("two_factor_attempts:{challenge_id}")
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TwoFactorChallengeClaims {
+    user_id: i64,
+    device_name: Option<String>,
+}
+
+/// A verified two-factor challenge waiting for its code
+#[derive(Debug)]
+pub struct TwoFactorChallenge {
+    id: String,
+    pub user_id: i64,
+    pub device_name: Option<String>,
+}
+
+/// What the refresh cache knows about a presented token
+#[derive(Debug, PartialEq)]
+enum CachedRefresh {
+    Active { user_id: i64, family_id: String },
+    Rotated { family_id: String },
+}
+
+impl CachedRefresh {
+    fn parse(value: &str) -> Option<Self> {
+        match value.split_once(':')? {
+            ("rotated", family_id) => Some(Self::Rotated {
+                family_id: family_id.to_owned(),
+            }),
+            (user_id, family_id) => Some(Self::Active {
+                user_id: user_id.parse().ok()?,
+                family_id: family_id.to_owned(),
+            }),
+        }
+    }
+
+    fn to_cache_value(&self) -> String {
+        match self {
+            Self::Active { user_id, family_id } => format!("{user_id}:{family_id}"),
+            Self::Rotated { family_id } => format!("rotated:{family_id}"),
+        }
+    }
+}
 
 #[derive(Error, Debug, Responder)]
 pub enum AuthServiceError {
@@ -49,12 +111,26 @@ pub enum AuthServiceError {
     #[error("Missing refresh token")]
     #[response(status = 401)]
     MissingRefreshToken(AnyhowResponder),
+    #[error("Session not found")]
+    #[response(status = 404)]
+    SessionNotFound(AnyhowResponder),
+    #[error("Access token not found")]
+    #[response(status = 404)]
+    TokenNotFound(AnyhowResponder),
+    #[error("Unable to create this access token")]
+    #[response(status = 400)]
+    InvalidTokenRequest(AnyhowResponder),
+    #[error("Too many failed login attempts, try again later")]
+    TooManyAttempts(TooManyRequests),
+    #[error("This account is suspended")]
+    #[response(status = 403)]
+    Suspended(AnyhowResponder),
 }
 
 pub struct AuthService {
     db: DatabaseConnection,
     redis: Box<dyn RedisRefresh>,
-    signing_key: Ed25519KeyPair,
+    key_ring: KeyRing,
 }
 
 impl core::fmt::Debug for AuthService {
@@ -72,73 +148,27 @@ impl<'r> FromRequest<'r> for AuthService {
             try_outcome!(request.guard::<&State<DatabaseConnection>>().await);
 
         let redis: &State<RedisClient> = try_outcome!(request.guard::<&State<RedisClient>>().await);
-        let key: &State<Ed25519KeyPair> =
-            try_outcome!(request.guard::<&State<Ed25519KeyPair>>().await);
+        let key_ring: &State<KeyRing> = try_outcome!(request.guard::<&State<KeyRing>>().await);
 
         redis
             .inner()
             .get_async_connection()
             .await
             .ok()
-            .map(|r| Self::new(db.inner().clone(), Box::new(r), key.inner().clone()))
+            .map(|r| Self::new(db.inner().clone(), Box::new(r), key_ring.inner().clone()))
             .or_forward(())
     }
 }
 
 impl AuthService {
-    pub fn new(db: DatabaseConnection, redis: Box<dyn RedisRefresh>, key: Ed25519KeyPair) -> Self {
+    pub fn new(db: DatabaseConnection, redis: Box<dyn RedisRefresh>, key_ring: KeyRing) -> Self {
         Self {
             db,
             redis,
-            signing_key: key,
+            key_ring,
         }
     }
 
-    pub fn get_key_pair() -> Result<Ed25519KeyPair, AuthServiceError> {
-        let save_key = |key: &Ed25519KeyPair| -> Result<(), AuthServiceError> {
-            let mut file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .open(KEY_LOCATION)
-                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
-
-            file.write(&key.to_bytes())
-                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
-
-            Ok(())
-        };
-        let key = (|| -> Result<Ed25519KeyPair, AuthServiceError> {
-            match OpenOptions::new().read(true).open(KEY_LOCATION) {
-                Ok(mut file) => {
-                    let file_size = file
-                        .metadata()
-                        .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
-                        .len() as usize;
-
-                    let mut file_buffer = vec![0u8; file_size];
-                    file.read(&mut file_buffer).map_err(|e| {
-                        AuthServiceError::InternalError(AnyhowResponder(anyhow!(e)))
-                    })?;
-
-                    if let Ok(key) = Ed25519KeyPair::from_bytes(&file_buffer) {
-                        Ok(key)
-                    } else {
-                        let key = Ed25519KeyPair::generate();
-                        save_key(&key)?;
-                        Ok(key)
-                    }
-                }
-                Err(_) => {
-                    let key = Ed25519KeyPair::generate();
-                    save_key(&key)?;
-                    Ok(key)
-                }
-            }
-        })()?;
-
-        Ok(key)
-    }
-
     pub fn hash_password(password: &str) -> Result<String, AuthServiceError> {
         let salt = SaltString::generate(OsRng);
         let argon = Argon2::default();
@@ -164,110 +194,339 @@ impl AuthService {
         }
     }
 
+    /// Random token for single-use links sent by mail. Only its hash should be stored.
+    pub fn generate_link_token() -> String {
+        format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        )
+    }
+
+    pub fn hash_token(token: &str) -> String {
+        format!("{:x}", Sha256::digest(token.as_bytes()))
+    }
+
     pub async fn generate_refresh_token(
         &mut self,
         user: &UserModel,
+        session: &SessionMetadata,
     ) -> Result<String, AuthServiceError> {
-        let res = RefreshEntity::find()
-            .filter(refresh_token::Column::UserId.eq(user.id))
+        let token = uuid::Uuid::new_v4().to_string().replace("-", "");
+        let now = chrono::Utc::now().naive_utc();
+        let validity = chrono::Duration::days(REFRESH_TOKEN_VALIDITY_DAYS);
+
+        RefreshActiveModel {
+            token: ActiveValue::Set(token.clone()),
+            user_id: ActiveValue::Set(user.id),
+            family_id: ActiveValue::Set(token.clone()),
+            device_name: ActiveValue::Set(session.device_name.clone()),
+            user_agent: ActiveValue::Set(session.user_agent.clone()),
+            ip_address: ActiveValue::Set(session.ip_address.clone()),
+            last_used_at: ActiveValue::Set(now),
+            expires_at: ActiveValue::Set(now + validity),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await
+        .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        self.cache_refresh(
+            &token,
+            CachedRefresh::Active {
+                user_id: user.id,
+                family_id: token.clone(),
+            },
+            validity.num_seconds(),
+        )
+        .await?;
+
+        Ok(token)
+    }
+
+    async fn cache_refresh(
+        &mut self,
+        token: &str,
+        value: CachedRefresh,
+        seconds: i64,
+    ) -> Result<(), AuthServiceError> {
+        self.redis
+            .set_item_with_expiry(
+                &refresh_cache_key(token),
+                &value.to_cache_value(),
+                seconds.max(1) as usize,
+            )
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    /// Looks up the user a refresh token belongs to. Presenting a token that has already
+    /// been rotated is treated as theft, and revokes every token descended from the same login.
+    pub async fn get_user_from_refresh(
+        &mut self,
+        refresh: String,
+    ) -> Result<Option<i64>, AuthServiceError> {
+        let cached = self
+            .redis
+            .get_item(&refresh_cache_key(&refresh))
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .and_then(|value| CachedRefresh::parse(&value));
+
+        match cached {
+            Some(CachedRefresh::Active { user_id, .. }) => return Ok(Some(user_id)),
+            Some(CachedRefresh::Rotated { family_id }) => {
+                self.handle_refresh_reuse(&family_id).await?;
+                return Ok(None);
+            }
+            None => {}
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let found = RefreshEntity::find()
+            .filter(refresh_token::Column::Token.eq(&refresh))
+            .one(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let session = match found {
+            Some(session) if session.rotated_at.is_some() => {
+                self.handle_refresh_reuse(&session.family_id).await?;
+                return Ok(None);
+            }
+            Some(session) if session.expires_at > now => session,
+            _ => return Ok(None),
+        };
+
+        self.cache_refresh(
+            &refresh,
+            CachedRefresh::Active {
+                user_id: session.user_id,
+                family_id: session.family_id,
+            },
+            (session.expires_at - now).num_seconds(),
+        )
+        .await?;
+
+        Ok(Some(session.user_id))
+    }
+
+    pub async fn validate_refresh_token(
+        &mut self,
+        user_id: i64,
+        refresh: &str,
+    ) -> Result<bool, AuthServiceError> {
+        let found = self.get_user_from_refresh(refresh.to_owned()).await?;
+
+        Ok(found == Some(user_id))
+    }
+
+    /// Exchanges a refresh token for a new one in the same family. The old token is kept,
+    /// marked as rotated, so that a later attempt to use it can be detected.
+    pub async fn rotate_refresh_token(
+        &mut self,
+        refresh: &str,
+    ) -> Result<String, AuthServiceError> {
+        let now = chrono::Utc::now().naive_utc();
+        let session = RefreshEntity::find()
+            .filter(refresh_token::Column::Token.eq(refresh))
             .one(&self.db)
             .await
             .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
-            .map(|r| r.token);
+            .ok_or(AuthServiceError::MissingRefreshToken(AnyhowResponder(
+                anyhow!("Refresh token not found"),
+            )))?;
 
-        if let Some(ref token) = res {
-            self.redis
-                .set_item(token, &user.id.to_string())
+        if session.rotated_at.is_some() {
+            self.handle_refresh_reuse(&session.family_id).await?;
+            return Err(AuthServiceError::MissingRefreshToken(AnyhowResponder(
+                anyhow!("Refresh token has already been rotated"),
+            )));
+        }
+        if session.expires_at <= now {
+            return Err(AuthServiceError::MissingRefreshToken(AnyhowResponder(
+                anyhow!("Refresh token has expired"),
+            )));
+        }
+
+        let txn = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        // Claim the token before issuing its successor, so of two concurrent
+        // refreshes with the same token only one wins and the other counts as reuse
+        let claimed = RefreshEntity::update_many()
+            .col_expr(refresh_token::Column::RotatedAt, Expr::value(now))
+            .filter(refresh_token::Column::Token.eq(refresh))
+            .filter(refresh_token::Column::RotatedAt.is_null())
+            .exec(&txn)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if claimed.rows_affected == 0 {
+            txn.rollback()
                 .await
                 .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
-            return Ok(token.to_owned());
+            self.handle_refresh_reuse(&session.family_id).await?;
+            return Err(AuthServiceError::MissingRefreshToken(AnyhowResponder(
+                anyhow!("Refresh token has already been rotated"),
+            )));
         }
 
         let token = uuid::Uuid::new_v4().to_string().replace("-", "");
+        let validity = chrono::Duration::days(REFRESH_TOKEN_VALIDITY_DAYS);
+        let user_id = session.user_id;
+        let family_id = session.family_id.clone();
 
         RefreshActiveModel {
             token: ActiveValue::Set(token.clone()),
-            user_id: ActiveValue::Set(user.id),
+            user_id: ActiveValue::Set(user_id),
+            family_id: ActiveValue::Set(family_id.clone()),
+            device_name: ActiveValue::Set(session.device_name),
+            user_agent: ActiveValue::Set(session.user_agent),
+            ip_address: ActiveValue::Set(session.ip_address),
+            created_at: ActiveValue::Set(session.created_at),
+            last_used_at: ActiveValue::Set(now),
+            expires_at: ActiveValue::Set(now + validity),
             ..Default::default()
         }
-        .insert(&self.db)
+        .insert(&txn)
         .await
         .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
 
-        self.redis
-            .set_item(&user.id.to_string(), &token)
+        txn.commit()
             .await
             .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
 
+        let old_expiry = session.expires_at;
+        self.cache_refresh(
+            refresh,
+            CachedRefresh::Rotated {
+                family_id: family_id.clone(),
+            },
+            (old_expiry - now).num_seconds(),
+        )
+        .await?;
+        self.cache_refresh(
+            &token,
+            CachedRefresh::Active { user_id, family_id },
+            validity.num_seconds(),
+        )
+        .await?;
+
         Ok(token)
     }
 
-    pub async fn get_user_from_refresh(
-        &mut self,
-        refresh: String,
-    ) -> Result<Option<i64>, AuthServiceError> {
-        let res = self
-            .redis
-            .get_item(&refresh)
+    async fn handle_refresh_reuse(&mut self, family_id: &str) -> Result<(), AuthServiceError> {
+        let user_id = RefreshEntity::find()
+            .filter(refresh_token::Column::FamilyId.eq(family_id))
+            .one(&self.db)
             .await
-            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .map(|session| session.user_id);
 
-        let mut id = None;
+        tracing::warn!(
+            message = "Rotated refresh token was presented again, revoking token family",
+            event = "refresh_token_reuse",
+            family_id,
+            user_id
+        );
 
-        if let Some(ref id_str) = res {
-            id = Some(
-                id_str
-                    .parse::<i64>()
-                    .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?,
-            );
-        }
+        self.revoke_family(family_id).await
+    }
+
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), AuthServiceError> {
+        let sessions = RefreshEntity::find()
+            .filter(refresh_token::Column::FamilyId.eq(family_id))
+            .all(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
 
-        if id.is_none() {
-            id = RefreshEntity::find()
-                .filter(refresh_token::Column::Token.eq(&refresh))
-                .one(&self.db)
+        for session in sessions {
+            self.redis
+                .delete_item(&refresh_cache_key(&session.token))
                 .await
-                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
-                .map(|model| model.user_id);
-
-            if let Some(ref id) = id {
-                self.redis
-                    .set_item(&refresh, &id.to_string())
-                    .await
-                    .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
-            }
+                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
         }
 
-        Ok(id)
+        RefreshEntity::delete_many()
+            .filter(refresh_token::Column::FamilyId.eq(family_id))
+            .exec(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
     }
 
-    pub async fn validate_refresh_token(
+    pub async fn list_sessions(
         &mut self,
         user_id: i64,
-    ) -> Result<Option<String>, AuthServiceError> {
-        let mut found = self
-            .redis
-            .get_item(&user_id.to_string())
+    ) -> Result<Vec<refresh_token::Model>, AuthServiceError> {
+        RefreshEntity::find()
+            .filter(refresh_token::Column::UserId.eq(user_id))
+            .filter(refresh_token::Column::RotatedAt.is_null())
+            .filter(refresh_token::Column::ExpiresAt.gt(chrono::Utc::now().naive_utc()))
+            .order_by_desc(refresh_token::Column::LastUsedAt)
+            .all(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    /// Revokes a single session belonging to `user_id`, returning the revoked token
+    pub async fn revoke_session(
+        &mut self,
+        user_id: i64,
+        session_id: i64,
+    ) -> Result<String, AuthServiceError> {
+        let session = RefreshEntity::find_by_id(session_id)
+            .filter(refresh_token::Column::UserId.eq(user_id))
+            .filter(refresh_token::Column::RotatedAt.is_null())
+            .one(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(AuthServiceError::SessionNotFound(AnyhowResponder(anyhow!(
+                "Session {session_id} not found for user {user_id}"
+            ))))?;
+
+        self.revoke_family(&session.family_id).await?;
+
+        Ok(session.token)
+    }
+
+    /// Revokes every session of `user_id` except the one `keep` belongs to
+    pub async fn revoke_other_sessions(
+        &mut self,
+        user_id: i64,
+        keep: Option<&str>,
+    ) -> Result<(), AuthServiceError> {
+        let sessions = RefreshEntity::find()
+            .filter(refresh_token::Column::UserId.eq(user_id))
+            .all(&self.db)
             .await
             .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
 
-        if found.is_none() {
-            found = RefreshEntity::find()
-                .filter(refresh_token::Column::UserId.eq(user_id))
-                .one(&self.db)
-                .await
-                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
-                .map(|r_token| r_token.token);
-
-            if let Some(ref token) = found {
-                self.redis
-                    .set_item(&user_id.to_string(), token)
-                    .await
-                    .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
-            }
+        let kept_family = keep.and_then(|token| {
+            sessions
+                .iter()
+                .find(|session| session.token == token)
+                .map(|session| session.family_id.clone())
+        });
+
+        let mut families: Vec<String> = sessions
+            .into_iter()
+            .map(|session| session.family_id)
+            .filter(|family_id| Some(family_id) != kept_family.as_ref())
+            .collect();
+        families.sort();
+        families.dedup();
+
+        for family_id in families {
+            self.revoke_family(&family_id).await?;
         }
 
-        Ok(found)
+        Ok(())
     }
 
     pub async fn generate_jwt(
@@ -279,59 +538,75 @@ impl AuthService {
         let claims = Claims::with_custom_claims(
             user.clone(),
             validity
-                .unwrap_or_else(|| Duration::from_secs(60 * 60))
+                .unwrap_or_else(|| Duration::from_secs(ACCESS_TOKEN_VALIDITY_SECONDS))
                 .into(),
         );
 
-        let stored_refresh = self.validate_refresh_token(user.id).await?;
-        if let None = stored_refresh {
+        if !self.validate_refresh_token(user.id, refresh).await? {
             return Err(AuthServiceError::MissingRefreshToken(AnyhowResponder(
-                anyhow!("Missing refresh token"),
-            )));
-        }
-        let stored_refresh = stored_refresh.unwrap();
-        if stored_refresh != refresh {
-            return Err(AuthServiceError::MissingRefreshToken(AnyhowResponder(
-                anyhow!("Mismatched refresh token"),
+                anyhow!("Missing or mismatched refresh token"),
             )));
         }
 
-        let token = self
-            .signing_key
-            .sign(claims)
-            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        self.key_ring.reload_if_stale(&self.db).await?;
+        let token = self.key_ring.sign(claims)?;
 
         Ok(token)
     }
 
-    pub fn validate_jwt(
-        &self,
+    pub async fn validate_jwt(
+        &mut self,
         jwt: String,
         tolerance: Option<Duration>,
     ) -> Result<UserJwtDto, AuthServiceError> {
-        let claims = self
-            .signing_key
-            .public_key()
-            .verify_token::<UserJwtDto>(
-                &jwt,
-                Some(VerificationOptions {
-                    time_tolerance: Some(tolerance.unwrap_or(Duration::new(0, 0)).into()),
-                    ..Default::default()
-                }),
-            )
-            .map_err(|e| AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(e))))?;
+        self.key_ring.reload_if_stale(&self.db).await?;
+        self.key_ring.reload_for_token(&self.db, &jwt).await?;
+
+        let claims = self.key_ring.verify::<UserJwtDto>(
+            &jwt,
+            VerificationOptions {
+                time_tolerance: Some(tolerance.unwrap_or(Duration::new(0, 0)).into()),
+                ..Default::default()
+            },
+        )?;
+
+        // Access tokens never carry an audience, anything else is a different kind of token
+        if claims.audiences.is_some() {
+            return Err(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+                "Token is not an access token"
+            ))));
+        }
+
+        self.check_denylist(
+            claims.custom.id,
+            claims.issued_at.map(|issued_at| issued_at.as_secs()),
+        )
+        .await?;
 
         Ok(claims.custom)
     }
 
-    pub async fn revoke_refresh_token(&mut self, user: &UserModel) -> Result<(), AuthServiceError> {
-        let user_id_str = user.id.to_string();
+    pub async fn rotate_signing_key(&mut self) -> Result<String, AuthServiceError> {
+        let kid = self.key_ring.rotate(&self.db).await?;
+        tracing::info!(message = "Rotated JWT signing key", kid);
 
-        self.redis
-            .delete_item(&user_id_str)
+        Ok(kid)
+    }
+
+    pub async fn revoke_refresh_token(&mut self, user: &UserModel) -> Result<(), AuthServiceError> {
+        let sessions = RefreshEntity::find()
+            .filter(refresh_token::Column::UserId.eq(user.id))
+            .all(&self.db)
             .await
             .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
 
+        for session in sessions {
+            self.redis
+                .delete_item(&refresh_cache_key(&session.token))
+                .await
+                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        }
+
         RefreshEntity::delete_many()
             .filter(refresh_token::Column::UserId.eq(user.id))
             .exec(&self.db)
@@ -345,6 +620,7 @@ impl AuthService {
         &mut self,
         potential_password: String,
         user: &UserModel,
+        session: &SessionMetadata,
     ) -> Result<LoginReturn, AuthServiceError> {
         if !Self::verify_password(&user.password, &potential_password)? {
             return Err(AuthServiceError::LoginError(AnyhowResponder(anyhow!(
@@ -352,6 +628,17 @@ impl AuthService {
             ))));
         }
 
+        self.issue_login(user, session).await
+    }
+
+    /// Issues a refresh token and JWT for a user whose credentials were already checked
+    pub async fn issue_login(
+        &mut self,
+        user: &UserModel,
+        session: &SessionMetadata,
+    ) -> Result<LoginReturn, AuthServiceError> {
+        Self::ensure_active(user)?;
+
         let user_jwt = UserJwtDto {
             id: user.id,
             role: Role::try_from(user.role).map_err(|_| {
@@ -362,9 +649,117 @@ impl AuthService {
             username: user.username.clone(),
         };
 
-        let refresh_token = self.generate_refresh_token(user).await?;
+        let refresh_token = self.generate_refresh_token(user, session).await?;
         let jwt = self.generate_jwt(&user_jwt, &refresh_token, None).await?;
 
         Ok(LoginReturn { jwt, refresh_token })
     }
+
+    /// Short-lived token proving the password step of a two-factor login succeeded
+    pub async fn generate_two_factor_challenge(
+        &mut self,
+        user_id: i64,
+        device_name: Option<String>,
+    ) -> Result<String, AuthServiceError> {
+        let claims = Claims::with_custom_claims(
+            TwoFactorChallengeClaims {
+                user_id,
+                device_name,
+            },
+            Duration::from_secs(TWO_FACTOR_CHALLENGE_VALIDITY_SECONDS).into(),
+        )
+        .with_audience(TWO_FACTOR_CHALLENGE_AUDIENCE)
+        .with_jwt_id(uuid::Uuid::new_v4().simple().to_string());
+
+        self.key_ring.reload_if_stale(&self.db).await?;
+        self.key_ring.sign(claims)
+    }
+
+    /// Checks a challenge token is valid, unused and has attempts left
+    pub async fn validate_two_factor_challenge(
+        &mut self,
+        challenge: &str,
+    ) -> Result<TwoFactorChallenge, AuthServiceError> {
+        self.key_ring.reload_if_stale(&self.db).await?;
+        self.key_ring.reload_for_token(&self.db, challenge).await?;
+
+        let claims = self.key_ring.verify::<TwoFactorChallengeClaims>(
+            challenge,
+            VerificationOptions {
+                allowed_audiences: Some(HashSet::from_strings(&[TWO_FACTOR_CHALLENGE_AUDIENCE])),
+                ..Default::default()
+            },
+        )?;
+
+        let id = claims
+            .jwt_id
+            .ok_or(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+                "Two factor challenge has no id"
+            ))))?;
+
+        let used = self
+            .redis
+            .get_item(&two_factor_cache_key(&id))
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        let attempts = self
+            .redis
+            .get_item(&two_factor_attempts_key(&id))
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .and_then(|attempts| attempts.parse::<u32>().ok())
+            .unwrap_or(0);
+
+        if used.is_some() || attempts >= TWO_FACTOR_MAX_ATTEMPTS {
+            return Err(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+                "Two factor challenge {id} was used or has no attempts left"
+            ))));
+        }
+
+        Ok(TwoFactorChallenge {
+            id,
+            user_id: claims.custom.user_id,
+            device_name: claims.custom.device_name,
+        })
+    }
+
+    /// Takes one of the challenge's attempts before its code is checked. Counted
+    /// atomically, so parallel guesses can't all slip in under the limit.
+    pub async fn take_two_factor_attempt(
+        &mut self,
+        challenge: &TwoFactorChallenge,
+    ) -> Result<(), AuthServiceError> {
+        let attempts = self
+            .redis
+            .increment_with_expiry(
+                &two_factor_attempts_key(&challenge.id),
+                TWO_FACTOR_CHALLENGE_VALIDITY_SECONDS as usize,
+            )
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if attempts > TWO_FACTOR_MAX_ATTEMPTS as i64 {
+            return Err(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+                "Two factor challenge {} has no attempts left",
+                challenge.id
+            ))));
+        }
+
+        Ok(())
+    }
+
+    /// Stops a challenge from being exchanged for a second session
+    pub async fn consume_two_factor_challenge(
+        &mut self,
+        challenge: &TwoFactorChallenge,
+    ) -> Result<(), AuthServiceError> {
+        self.redis
+            .set_item_with_expiry(
+                &two_factor_cache_key(&challenge.id),
+                "used",
+                TWO_FACTOR_CHALLENGE_VALIDITY_SECONDS as usize,
+            )
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
 }
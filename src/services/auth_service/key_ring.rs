@@ -0,0 +1,391 @@
+use super::AuthServiceError;
+use crate::{
+    dtos::auth::{Jwk, Jwks},
+    AnyhowResponder,
+};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDateTime, Utc};
+use entity::signing_key::{self, ActiveModel as SigningKeyActiveModel, Entity as SigningKeyEntity};
+use jwt_simple::prelude::*;
+use sea_orm::{prelude::*, ActiveValue, Condition, DatabaseConnection, QueryOrder};
+use serde::de::DeserializeOwned;
+use std::{
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+/// How long a retired key keeps verifying tokens. Must outlive the longest JWT it signed.
+const RETIRED_KEY_LIFETIME_HOURS: i64 = 2;
+/// How often an instance picks up keys rotated by another instance
+const RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Minimum time between reloads triggered by tokens with an unknown `kid`
+const UNKNOWN_KID_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// Bytes of AES-GCM nonce stored in front of each encrypted private key
+const NONCE_LENGTH: usize = 12;
+
+/// Key that encrypts the signing keys stored in the database. It comes from the
+/// environment so a database dump alone can't be used to forge tokens.
+#[derive(Clone)]
+pub struct KeyEncryptionKey {
+    cipher: Aes256Gcm,
+}
+
+impl core::fmt::Debug for KeyEncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyEncryptionKey").finish()
+    }
+}
+
+impl KeyEncryptionKey {
+    /// Reads a base64 encoded 32 byte key from the file named by `SIGNING_KEY_KEK_FILE`,
+    /// or from `SIGNING_KEY_KEK` itself
+    pub fn from_env() -> anyhow::Result<Self> {
+        let encoded = match std::env::var("SIGNING_KEY_KEK_FILE") {
+            Ok(path) => std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Unable to read key encryption key from {path}: {e}"))?,
+            Err(_) => std::env::var("SIGNING_KEY_KEK")
+                .map_err(|_| anyhow!("Neither SIGNING_KEY_KEK_FILE nor SIGNING_KEY_KEK is set"))?,
+        };
+        let bytes = Base64::decode_to_vec(encoded.trim(), None)
+            .map_err(|e| anyhow!("Key encryption key is not valid base64: {e}"))?;
+
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let cipher = Aes256Gcm::new_from_slice(bytes)
+            .map_err(|_| anyhow!("Key encryption key must be 32 bytes"))?;
+
+        Ok(Self { cipher })
+    }
+
+    /// Same key every time, so several instances in one test can read each other's keys
+    #[cfg(test)]
+    pub fn test() -> Self {
+        Self::from_bytes(&[7; 32]).unwrap()
+    }
+
+    /// Encrypts a private key, bound to its `kid` so rows can't be swapped around
+    fn seal(&self, kid: &str, private_key: &[u8]) -> Result<String, AuthServiceError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: private_key,
+                    aad: kid.as_bytes(),
+                },
+            )
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Base64::encode_to_string([nonce.as_slice(), &ciphertext].concat())
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    fn open(&self, kid: &str, sealed: &str) -> Result<Vec<u8>, AuthServiceError> {
+        let bytes = Base64::decode_to_vec(sealed, None)
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        if bytes.len() <= NONCE_LENGTH {
+            return Err(AuthServiceError::InternalError(AnyhowResponder(anyhow!(
+                "Signing key {kid} is truncated"
+            ))));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: kid.as_bytes(),
+                },
+            )
+            .map_err(|_| {
+                AuthServiceError::InternalError(AnyhowResponder(anyhow!(
+                    "Unable to decrypt signing key {kid} -- wrong key encryption key?"
+                )))
+            })
+    }
+}
+
+#[derive(Clone)]
+struct RingKey {
+    kid: String,
+    key_pair: Ed25519KeyPair,
+    retired_at: Option<NaiveDateTime>,
+    expires_at: Option<NaiveDateTime>,
+}
+
+impl RingKey {
+    fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.expires_at
+            .map(|expires| expires <= now)
+            .unwrap_or(false)
+    }
+}
+
+struct KeyRingState {
+    keys: Vec<RingKey>,
+    loaded_at: Instant,
+    unknown_kid_reload_at: Option<Instant>,
+}
+
+/// Set of Ed25519 keys used to sign and verify JWTs, identified by their `kid`.
+/// The newest key that has not been retired signs new tokens, while retired keys
+/// are kept for verification until they expire.
+#[derive(Clone)]
+pub struct KeyRing {
+    state: Arc<RwLock<KeyRingState>>,
+    kek: KeyEncryptionKey,
+}
+
+impl core::fmt::Debug for KeyRing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyRing").finish()
+    }
+}
+
+fn generate_kid() -> String {
+    uuid::Uuid::new_v4().to_string().replace('-', "")
+}
+
+impl KeyRing {
+    /// Loads every unexpired key from the database, creating a signing key if none is active
+    pub async fn load(
+        db: &DatabaseConnection,
+        kek: KeyEncryptionKey,
+    ) -> Result<Self, AuthServiceError> {
+        let keys = Self::fetch_keys(db, &kek).await?;
+        let key_ring = Self {
+            state: Arc::new(RwLock::new(KeyRingState {
+                keys,
+                loaded_at: Instant::now(),
+                unknown_kid_reload_at: None,
+            })),
+            kek,
+        };
+
+        if key_ring.active_key().is_none() {
+            key_ring.rotate(db).await?;
+        }
+
+        Ok(key_ring)
+    }
+
+    async fn fetch_keys(
+        db: &DatabaseConnection,
+        kek: &KeyEncryptionKey,
+    ) -> Result<Vec<RingKey>, AuthServiceError> {
+        let now = Utc::now().naive_utc();
+        let rows = SigningKeyEntity::find()
+            .filter(
+                Condition::any()
+                    .add(signing_key::Column::ExpiresAt.is_null())
+                    .add(signing_key::Column::ExpiresAt.gt(now)),
+            )
+            .order_by_desc(signing_key::Column::CreatedAt)
+            .all(db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        rows.into_iter()
+            .map(|row| {
+                let bytes = kek.open(&row.kid, &row.private_key)?;
+                let key_pair = Ed25519KeyPair::from_bytes(&bytes)
+                    .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+                    .with_key_id(&row.kid);
+
+                Ok(RingKey {
+                    kid: row.kid,
+                    key_pair,
+                    retired_at: row.retired_at,
+                    expires_at: row.expires_at,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn reload(&self, db: &DatabaseConnection) -> Result<(), AuthServiceError> {
+        let keys = Self::fetch_keys(db, &self.kek).await?;
+        let mut state = self.state.write().unwrap();
+        state.keys = keys;
+        state.loaded_at = Instant::now();
+
+        Ok(())
+    }
+
+    /// Picks up keys rotated by other instances
+    pub async fn reload_if_stale(&self, db: &DatabaseConnection) -> Result<(), AuthServiceError> {
+        let loaded_at = self.state.read().unwrap().loaded_at;
+        if loaded_at.elapsed() >= RELOAD_INTERVAL {
+            self.reload(db).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reloads when a token names a key this instance has not seen yet
+    pub async fn reload_for_token(
+        &self,
+        db: &DatabaseConnection,
+        token: &str,
+    ) -> Result<(), AuthServiceError> {
+        let kid = match Token::decode_metadata(token)
+            .ok()
+            .and_then(|meta| meta.key_id().map(|kid| kid.to_owned()))
+        {
+            Some(kid) => kid,
+            None => return Ok(()),
+        };
+
+        let should_reload = {
+            let mut state = self.state.write().unwrap();
+            let known = state.keys.iter().any(|key| key.kid == kid);
+            let throttled = state
+                .unknown_kid_reload_at
+                .map(|at| at.elapsed() < UNKNOWN_KID_RELOAD_INTERVAL)
+                .unwrap_or(false);
+
+            if !known && !throttled {
+                state.unknown_kid_reload_at = Some(Instant::now());
+            }
+            !known && !throttled
+        };
+
+        if should_reload {
+            self.reload(db).await?;
+        }
+
+        Ok(())
+    }
+
+    fn active_key(&self) -> Option<RingKey> {
+        let now = Utc::now().naive_utc();
+        self.state
+            .read()
+            .unwrap()
+            .keys
+            .iter()
+            .find(|key| key.retired_at.is_none() && !key.is_expired(now))
+            .cloned()
+    }
+
+    /// `kid` of the key currently used for signing
+    pub fn active_kid(&self) -> Option<String> {
+        self.active_key().map(|key| key.kid)
+    }
+
+    pub fn sign<T: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<T>,
+    ) -> Result<String, AuthServiceError> {
+        let key = self
+            .active_key()
+            .ok_or(AuthServiceError::InternalError(AnyhowResponder(anyhow!(
+                "No active signing key"
+            ))))?;
+
+        key.key_pair
+            .sign(claims)
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    pub fn verify<T: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: VerificationOptions,
+    ) -> Result<JWTClaims<T>, AuthServiceError> {
+        let kid = Token::decode_metadata(token)
+            .map_err(|e| AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(e))))?
+            .key_id()
+            .map(|kid| kid.to_owned())
+            .ok_or(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+                "Token has no key id"
+            ))))?;
+
+        let now = Utc::now().naive_utc();
+        let public_key = self
+            .state
+            .read()
+            .unwrap()
+            .keys
+            .iter()
+            .find(|key| key.kid == kid && !key.is_expired(now))
+            .map(|key| key.key_pair.public_key())
+            .ok_or(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+                "Unknown or expired key id {kid}"
+            ))))?;
+
+        public_key
+            .verify_token::<T>(token, Some(options))
+            .map_err(|e| AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(e))))
+    }
+
+    /// Generates a new signing key and retires the previous ones. Retired keys keep
+    /// verifying tokens for `RETIRED_KEY_LIFETIME_HOURS`.
+    pub async fn rotate(&self, db: &DatabaseConnection) -> Result<String, AuthServiceError> {
+        let now = Utc::now().naive_utc();
+        let kid = generate_kid();
+        let key_pair = Ed25519KeyPair::generate();
+
+        SigningKeyEntity::update_many()
+            .col_expr(signing_key::Column::RetiredAt, Expr::value(now))
+            .col_expr(
+                signing_key::Column::ExpiresAt,
+                Expr::value(now + chrono::Duration::hours(RETIRED_KEY_LIFETIME_HOURS)),
+            )
+            .filter(signing_key::Column::RetiredAt.is_null())
+            .exec(db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        SigningKeyActiveModel {
+            kid: ActiveValue::Set(kid.clone()),
+            private_key: ActiveValue::Set(self.kek.seal(&kid, &key_pair.to_bytes())?),
+            created_at: ActiveValue::Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        self.reload(db).await?;
+
+        Ok(kid)
+    }
+
+    /// Public half of every key that can still verify tokens
+    pub fn jwks(&self) -> Result<Jwks, AuthServiceError> {
+        let now = Utc::now().naive_utc();
+        let keys =
+            self.state
+                .read()
+                .unwrap()
+                .keys
+                .iter()
+                .filter(|key| !key.is_expired(now))
+                .map(|key| {
+                    let x = Base64UrlSafeNoPadding::encode_to_string(
+                        key.key_pair.public_key().to_bytes(),
+                    )
+                    .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+                    Ok(Jwk {
+                        kty: String::from("OKP"),
+                        crv: String::from("Ed25519"),
+                        alg: String::from("EdDSA"),
+                        key_use: String::from("sig"),
+                        kid: key.kid.clone(),
+                        x,
+                    })
+                })
+                .collect::<Result<Vec<_>, AuthServiceError>>()?;
+
+        Ok(Jwks { keys })
+    }
+}
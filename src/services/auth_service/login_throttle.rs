@@ -0,0 +1,183 @@
+use super::{AuthService, AuthServiceError};
+use crate::{guards::TooManyRequests, AnyhowResponder};
+use anyhow::anyhow;
+use chrono::Utc;
+use entity::login_lockout::ActiveModel as LoginLockoutActiveModel;
+use sea_orm::{ActiveModelTrait, ActiveValue};
+
+/// Failures allowed before every further attempt has to wait
+const FREE_LOGIN_FAILURES: u32 = 3;
+const MAX_LOGIN_DELAY_SECONDS: i64 = 60;
+/// Failures on a single account before it is locked
+const ACCOUNT_LOCKOUT_FAILURES: u32 = 10;
+/// Failures from a single address, across any accounts, before it is locked
+const ADDRESS_LOCKOUT_FAILURES: u32 = 50;
+const LOCKOUT_SECONDS: i64 = 15 * 60;
+/// Failures are forgotten after this long without another one
+const FAILURE_WINDOW_SECONDS: usize = 60 * 60;
+
+/// What failed logins are counted against
+#[derive(Debug, Clone, Copy)]
+enum Throttled<'a> {
+    Account(i64),
+    Address(&'a str),
+}
+
+impl Throttled<'_> {
+    /// Counter of recent failures, only ever changed atomically
+    fn cache_key(&self) -> String {
+        match self {
+            Throttled::Account(user_id) => format!("login_failures:user:{user_id}"),
+            Throttled::Address(ip_address) => format!("login_failures:ip:{ip_address}"),
+        }
+    }
+
+    /// Unix timestamp before which no attempt is allowed
+    fn blocked_key(&self) -> String {
+        match self {
+            Throttled::Account(user_id) => format!("login_blocked:user:{user_id}"),
+            Throttled::Address(ip_address) => format!("login_blocked:ip:{ip_address}"),
+        }
+    }
+
+    fn lockout_failures(&self) -> u32 {
+        match self {
+            Throttled::Account(_) => ACCOUNT_LOCKOUT_FAILURES,
+            Throttled::Address(_) => ADDRESS_LOCKOUT_FAILURES,
+        }
+    }
+}
+
+fn throttled<'a>(user_id: Option<i64>, ip_address: Option<&'a str>) -> Vec<Throttled<'a>> {
+    user_id
+        .map(Throttled::Account)
+        .into_iter()
+        .chain(ip_address.map(Throttled::Address))
+        .collect()
+}
+
+impl AuthService {
+    async fn cached_number(&mut self, key: &str) -> Result<i64, AuthServiceError> {
+        let cached = self
+            .redis
+            .get_item(key)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(cached
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default())
+    }
+
+    /// Refuses a login attempt while the account or address is being throttled
+    pub async fn check_login_throttle(
+        &mut self,
+        user_id: Option<i64>,
+        ip_address: Option<&str>,
+    ) -> Result<(), AuthServiceError> {
+        let now = Utc::now().timestamp();
+
+        for throttled in throttled(user_id, ip_address) {
+            let blocked_until = self.cached_number(&throttled.blocked_key()).await?;
+
+            if blocked_until > now {
+                let failures = self.cached_number(&throttled.cache_key()).await?;
+                return Err(AuthServiceError::TooManyAttempts(TooManyRequests::new(
+                    failures.clamp(0, i16::MAX as i64) as i16,
+                    (blocked_until - now) as i32,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts a failed login, delaying further attempts and locking the
+    /// account or address once it has failed too often
+    pub async fn record_login_failure(
+        &mut self,
+        user_id: Option<i64>,
+        ip_address: Option<&str>,
+    ) -> Result<(), AuthServiceError> {
+        let now = Utc::now();
+
+        for throttled in throttled(user_id, ip_address) {
+            // Incremented in redis so concurrent guesses can't overwrite each other's count
+            let failures = self
+                .redis
+                .increment_with_expiry(&throttled.cache_key(), FAILURE_WINDOW_SECONDS)
+                .await
+                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+                .max(0) as u32;
+
+            let blocked_until = if failures >= throttled.lockout_failures() {
+                let locked_until = now + chrono::Duration::seconds(LOCKOUT_SECONDS);
+                self.record_lockout(throttled, ip_address, failures, locked_until.naive_utc())
+                    .await?;
+                locked_until.timestamp()
+            } else if failures > FREE_LOGIN_FAILURES {
+                let delay = 1_i64 << (failures - FREE_LOGIN_FAILURES).min(6);
+                now.timestamp() + delay.min(MAX_LOGIN_DELAY_SECONDS)
+            } else {
+                continue;
+            };
+
+            self.redis
+                .set_item_with_expiry(
+                    &throttled.blocked_key(),
+                    &blocked_until.to_string(),
+                    FAILURE_WINDOW_SECONDS,
+                )
+                .await
+                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        }
+
+        Ok(())
+    }
+
+    /// Forgets failed logins against an account after a successful one
+    pub async fn clear_login_failures(&mut self, user_id: i64) -> Result<(), AuthServiceError> {
+        let throttled = Throttled::Account(user_id);
+        for key in [throttled.cache_key(), throttled.blocked_key()] {
+            self.redis
+                .delete_item(&key)
+                .await
+                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        }
+
+        Ok(())
+    }
+
+    async fn record_lockout(
+        &mut self,
+        throttled: Throttled<'_>,
+        ip_address: Option<&str>,
+        failures: u32,
+        locked_until: chrono::NaiveDateTime,
+    ) -> Result<(), AuthServiceError> {
+        let user_id = match throttled {
+            Throttled::Account(user_id) => Some(user_id),
+            Throttled::Address(_) => None,
+        };
+        tracing::warn!(
+            message = "Locked out login after repeated failures",
+            user_id,
+            ip_address,
+            failures
+        );
+
+        LoginLockoutActiveModel {
+            user_id: ActiveValue::Set(user_id),
+            ip_address: ActiveValue::Set(ip_address.map(str::to_owned)),
+            failures: ActiveValue::Set(failures as i32),
+            locked_until: ActiveValue::Set(locked_until),
+            created_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await
+        .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+}
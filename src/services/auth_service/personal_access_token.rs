@@ -0,0 +1,195 @@
+use super::{AuthService, AuthServiceError};
+use crate::{
+    dtos::auth::PersonalAccessTokenCreated,
+    models::{role::Role, scope::Scope, user::PersonalAccessTokenCreate, user::UserJwtDto},
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use entity::personal_access_token::{
+    self, ActiveModel as PersonalAccessTokenActiveModel, Entity as PersonalAccessTokenEntity,
+    Model as PersonalAccessTokenModel,
+};
+use sea_orm::{prelude::*, ActiveValue, QueryOrder};
+
+/// Marks a credential as a personal access token rather than a JWT
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "tkx_";
+const PERSONAL_ACCESS_TOKEN_DISPLAY_LENGTH: usize = 12;
+const MAX_PERSONAL_ACCESS_TOKENS: u64 = 25;
+const MAX_TOKEN_NAME_LENGTH: usize = 128;
+/// Skip rewriting `last_used_at` for tokens used more recently than this
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+impl AuthService {
+    pub fn is_personal_access_token(token: &str) -> bool {
+        token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX)
+    }
+
+    /// Creates a token for `user_id`. The token itself is only ever returned here.
+    pub async fn create_personal_access_token(
+        &mut self,
+        user_id: i64,
+        create: PersonalAccessTokenCreate,
+    ) -> Result<PersonalAccessTokenCreated, AuthServiceError> {
+        let name = create.name.trim().to_owned();
+        if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+            return Err(AuthServiceError::InvalidTokenRequest(AnyhowResponder(
+                anyhow!("Token names must be between 1 and {MAX_TOKEN_NAME_LENGTH} characters"),
+            )));
+        }
+        if create.scopes.is_empty() {
+            return Err(AuthServiceError::InvalidTokenRequest(AnyhowResponder(
+                anyhow!("Tokens need at least one scope"),
+            )));
+        }
+        if create.expires_in_days.is_some_and(|days| days < 1) {
+            return Err(AuthServiceError::InvalidTokenRequest(AnyhowResponder(
+                anyhow!("Tokens must be valid for at least one day"),
+            )));
+        }
+
+        let existing = PersonalAccessTokenEntity::find()
+            .filter(personal_access_token::Column::UserId.eq(user_id))
+            .count(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        if existing >= MAX_PERSONAL_ACCESS_TOKENS {
+            return Err(AuthServiceError::InvalidTokenRequest(AnyhowResponder(
+                anyhow!("Users may have at most {MAX_PERSONAL_ACCESS_TOKENS} access tokens"),
+            )));
+        }
+
+        let mut scopes: Vec<Scope> = Vec::new();
+        for scope in create.scopes {
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+
+        let token = format!(
+            "{PERSONAL_ACCESS_TOKEN_PREFIX}{}",
+            Self::generate_link_token()
+        );
+        let now = Utc::now().naive_utc();
+        let expires_at = create
+            .expires_in_days
+            .map(|days| now + chrono::Duration::days(days));
+
+        let model = PersonalAccessTokenActiveModel {
+            user_id: ActiveValue::Set(user_id),
+            name: ActiveValue::Set(name),
+            token_hash: ActiveValue::Set(Self::hash_token(&token)),
+            token_prefix: ActiveValue::Set(
+                token[..PERSONAL_ACCESS_TOKEN_DISPLAY_LENGTH].to_owned(),
+            ),
+            scopes: ActiveValue::Set(Scope::join(&scopes)),
+            created_at: ActiveValue::Set(now),
+            expires_at: ActiveValue::Set(expires_at),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await
+        .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(PersonalAccessTokenCreated {
+            id: model.id,
+            name: model.name,
+            token,
+            scopes,
+            expires_at,
+        })
+    }
+
+    pub async fn list_personal_access_tokens(
+        &mut self,
+        user_id: i64,
+    ) -> Result<Vec<PersonalAccessTokenModel>, AuthServiceError> {
+        PersonalAccessTokenEntity::find()
+            .filter(personal_access_token::Column::UserId.eq(user_id))
+            .order_by_desc(personal_access_token::Column::CreatedAt)
+            .all(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    pub async fn revoke_personal_access_token(
+        &mut self,
+        user_id: i64,
+        token_id: i64,
+    ) -> Result<(), AuthServiceError> {
+        let deleted = PersonalAccessTokenEntity::delete_many()
+            .filter(personal_access_token::Column::Id.eq(token_id))
+            .filter(personal_access_token::Column::UserId.eq(user_id))
+            .exec(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if deleted.rows_affected == 0 {
+            return Err(AuthServiceError::TokenNotFound(AnyhowResponder(anyhow!(
+                "User {user_id} has no access token {token_id}"
+            ))));
+        }
+
+        Ok(())
+    }
+
+    /// Resolves a personal access token to its user and scopes
+    pub async fn validate_personal_access_token(
+        &mut self,
+        token: &str,
+    ) -> Result<(UserJwtDto, Vec<Scope>), AuthServiceError> {
+        let now = Utc::now().naive_utc();
+        let (found, user) = PersonalAccessTokenEntity::find()
+            .filter(personal_access_token::Column::TokenHash.eq(Self::hash_token(token)))
+            .find_also_related(entity::user::Entity)
+            .one(&self.db)
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+                "Unknown personal access token"
+            ))))?;
+
+        if found.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+                "Personal access token {} has expired",
+                found.id
+            ))));
+        }
+
+        let user = user.ok_or(AuthServiceError::InvalidJWT(AnyhowResponder(anyhow!(
+            "Personal access token {} has no user",
+            found.id
+        ))))?;
+        Self::ensure_active(&user)?;
+        let scopes = Scope::parse_list(&found.scopes).map_err(|_| {
+            AuthServiceError::InternalError(AnyhowResponder(anyhow!(
+                "Personal access token {} has unknown scopes",
+                found.id
+            )))
+        })?;
+
+        let stale = found
+            .last_used_at
+            .is_none_or(|last| (now - last).num_seconds() >= LAST_USED_RESOLUTION_SECONDS);
+        if stale {
+            PersonalAccessTokenEntity::update_many()
+                .col_expr(personal_access_token::Column::LastUsedAt, Expr::value(now))
+                .filter(personal_access_token::Column::Id.eq(found.id))
+                .exec(&self.db)
+                .await
+                .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        }
+
+        let user_jwt = UserJwtDto {
+            id: user.id,
+            role: Role::try_from(user.role).map_err(|_| {
+                AuthServiceError::InternalError(AnyhowResponder(anyhow!(
+                    "Unable to convert `i64` to `Role`"
+                )))
+            })?,
+            username: user.username,
+        };
+
+        Ok((user_jwt, scopes))
+    }
+}
@@ -0,0 +1,83 @@
+use super::{AuthService, AuthServiceError, ACCESS_TOKEN_VALIDITY_SECONDS};
+use crate::{models::user::AccountStatus, AnyhowResponder};
+use anyhow::anyhow;
+use chrono::{NaiveDateTime, Utc};
+use entity::user::Model as UserModel;
+
+fn denylist_cache_key(user_id: i64) -> String {
+    format!("jwt_denylist:user:{user_id}")
+}
+
+impl AuthService {
+    pub(super) fn ensure_active(user: &UserModel) -> Result<(), AuthServiceError> {
+        if AccountStatus::of(user).is_active() {
+            return Ok(());
+        }
+
+        Err(AuthServiceError::Suspended(AnyhowResponder(anyhow!(
+            "User {} is suspended",
+            user.id
+        ))))
+    }
+
+    /// Refuses every access token issued to the user up to now, for when they are
+    /// suspended or lose a role. Tokens issued afterwards are accepted, so the entry
+    /// only has to outlive the suspension or the longest lived access token,
+    /// whichever ends first.
+    pub async fn deny_access_tokens(
+        &mut self,
+        user_id: i64,
+        until: Option<NaiveDateTime>,
+    ) -> Result<(), AuthServiceError> {
+        let remaining = until
+            .map(|until| (until - Utc::now().naive_utc()).num_seconds().max(1) as u64)
+            .unwrap_or(ACCESS_TOKEN_VALIDITY_SECONDS)
+            .min(ACCESS_TOKEN_VALIDITY_SECONDS);
+
+        self.redis
+            .set_item_with_expiry(
+                &denylist_cache_key(user_id),
+                &Utc::now().timestamp().to_string(),
+                remaining as usize,
+            )
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    pub async fn allow_access_tokens(&mut self, user_id: i64) -> Result<(), AuthServiceError> {
+        self.redis
+            .delete_item(&denylist_cache_key(user_id))
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    pub(super) async fn check_denylist(
+        &mut self,
+        user_id: i64,
+        issued_at: Option<u64>,
+    ) -> Result<(), AuthServiceError> {
+        let denied_before = self
+            .redis
+            .get_item(&denylist_cache_key(user_id))
+            .await
+            .map_err(|e| AuthServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        // Entries that don't hold a time refuse every token
+        let denied = match (denied_before, issued_at) {
+            (None, _) => false,
+            (Some(denied_before), Some(issued_at)) => denied_before
+                .parse::<u64>()
+                .map(|denied_before| issued_at <= denied_before)
+                .unwrap_or(true),
+            (Some(_), None) => true,
+        };
+
+        if denied {
+            return Err(AuthServiceError::Suspended(AnyhowResponder(anyhow!(
+                "User {user_id} presented an access token issued before their access changed"
+            ))));
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,10 @@
 use super::*;
 use crate::{
-    db::{test::establish_connection, MockRedisRefresh},
-    models::user::UserRegister,
+    db::{
+        test::{establish_connection, in_memory_redis},
+        MockRedisRefresh,
+    },
+    models::{session::SessionMetadata, user::UserRegister},
     services::UserService,
 };
 
@@ -30,11 +33,146 @@ async fn get_test_user(db: DatabaseConnection) -> entity::user::Model {
     user
 }
 
-#[test]
-fn get_key_pair() {
-    let kp = AuthService::get_key_pair();
+mod key_ring {
+    use super::*;
+
+    fn claims() -> JWTClaims<UserJwtDto> {
+        Claims::with_custom_claims(
+            UserJwtDto {
+                id: 1,
+                username: String::from("test"),
+                role: Role::USER,
+            },
+            Duration::from_secs(60).into(),
+        )
+    }
+
+    #[tokio::test]
+    async fn load_creates_an_active_key() -> E {
+        let db = establish_connection().await?;
+
+        let key_ring = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+        assert!(key_ring.active_kid().is_some());
+
+        let reloaded = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+        assert_eq!(key_ring.active_kid(), reloaded.active_kid());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn signs_with_the_active_kid() -> E {
+        let db = establish_connection().await?;
+        let key_ring = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+
+        let token = key_ring.sign(claims())?;
+        let metadata = Token::decode_metadata(&token)?;
+
+        assert_eq!(metadata.key_id(), key_ring.active_kid().as_deref());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retired_keys_still_verify() -> E {
+        let db = establish_connection().await?;
+        let key_ring = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+        let old_kid = key_ring.active_kid();
+        let token = key_ring.sign(claims())?;
+
+        key_ring.rotate(&db).await?;
+
+        assert_ne!(old_kid, key_ring.active_kid());
+        assert!(key_ring
+            .verify::<UserJwtDto>(&token, VerificationOptions::default())
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_keys_do_not_verify() -> E {
+        let db = establish_connection().await?;
+        let key_ring = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+        let token = key_ring.sign(claims())?;
+
+        key_ring.rotate(&db).await?;
+        entity::signing_key::Entity::update_many()
+            .col_expr(
+                entity::signing_key::Column::ExpiresAt,
+                Expr::value(chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1)),
+            )
+            .filter(entity::signing_key::Column::RetiredAt.is_not_null())
+            .exec(&db)
+            .await?;
+        key_ring.reload(&db).await?;
+
+        assert!(key_ring
+            .verify::<UserJwtDto>(&token, VerificationOptions::default())
+            .is_err());
+        assert_eq!(key_ring.jwks()?.keys.len(), 1);
+
+        Ok(())
+    }
 
-    assert!(kp.is_ok());
+    #[tokio::test]
+    async fn carries_combined_and_legacy_roles() -> E {
+        let db = establish_connection().await?;
+        let key_ring = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+
+        let mut combined = claims();
+        combined.custom.role = Role::USER | Role::MODERATOR;
+        let token = key_ring.sign(combined)?;
+        let verified = key_ring.verify::<UserJwtDto>(&token, VerificationOptions::default())?;
+        assert_eq!(verified.custom.role, Role::USER | Role::MODERATOR);
+
+        let legacy = Claims::with_custom_claims(
+            serde_json::json!({ "id": 1, "username": "test", "role": "Admin" }),
+            Duration::from_secs(60).into(),
+        );
+        let token = key_ring.sign(legacy)?;
+        let verified = key_ring.verify::<UserJwtDto>(&token, VerificationOptions::default())?;
+        assert_eq!(verified.custom.role, Role::ADMIN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn private_keys_are_stored_encrypted() -> E {
+        let db = establish_connection().await?;
+        let key_ring = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+        let token = key_ring.sign(claims())?;
+
+        let stored = entity::signing_key::Entity::find().one(&db).await?.unwrap();
+        let stored = Base64::decode_to_vec(&stored.private_key, None)?;
+        assert!(Ed25519KeyPair::from_bytes(&stored).is_err());
+
+        let other_kek = KeyEncryptionKey::from_bytes(&[1; 32])?;
+        assert!(KeyRing::load(&db, other_kek).await.is_err());
+
+        let reloaded = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+        assert!(reloaded
+            .verify::<UserJwtDto>(&token, VerificationOptions::default())
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rotation_is_picked_up_by_other_instances() -> E {
+        let db = establish_connection().await?;
+        let first_instance = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+        let second_instance = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+
+        first_instance.rotate(&db).await?;
+        let token = first_instance.sign(claims())?;
+
+        let mut auth_service =
+            AuthService::new(db.clone(), Box::new(in_memory_redis()), second_instance);
+        assert!(auth_service.validate_jwt(token, None).await.is_ok());
+
+        Ok(())
+    }
 }
 
 #[test]
@@ -67,18 +205,272 @@ mod generate_refresh_token {
         let mut redis = MockRedisRefresh::default();
 
         let db = establish_connection().await?;
-        let key = AuthService::get_key_pair()?;
+        let key = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
 
         let test_user = get_test_user(db.clone()).await;
 
-        redis.expect_set_item().times(1).returning(|_, _| Ok(()));
+        redis
+            .expect_set_item_with_expiry()
+            .times(1)
+            .returning(|_, _, _| Ok(()));
 
         let mut auth_service = AuthService::new(db, Box::new(redis), key);
-        let token = auth_service.generate_refresh_token(&test_user).await;
+        let token = auth_service
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await;
 
         assert!(token.is_ok());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn creates_a_session_per_login() -> E {
+        let mut redis = MockRedisRefresh::default();
+        let db = establish_connection().await?;
+        let test_user = get_test_user(db.clone()).await;
+
+        redis
+            .expect_set_item_with_expiry()
+            .times(2)
+            .returning(|_, _, _| Ok(()));
+
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+        let phone = auth_service
+            .generate_refresh_token(
+                &test_user,
+                &SessionMetadata {
+                    device_name: Some(String::from("phone")),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let laptop = auth_service
+            .generate_refresh_token(
+                &test_user,
+                &SessionMetadata {
+                    device_name: Some(String::from("laptop")),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        assert_ne!(phone, laptop);
+        let sessions = auth_service.list_sessions(test_user.id).await?;
+        assert_eq!(sessions.len(), 2);
+
+        Ok(())
+    }
+}
+
+mod rotate_refresh_token {
+    use super::*;
+
+    #[tokio::test]
+    async fn issues_a_new_token_and_retires_the_old_one() -> E {
+        let db = establish_connection().await?;
+        let test_user = get_test_user(db.clone()).await;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let original = auth_service
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await?;
+        let rotated = auth_service.rotate_refresh_token(&original).await?;
+
+        assert_ne!(original, rotated);
+        assert!(
+            auth_service
+                .validate_refresh_token(test_user.id, &rotated)
+                .await?
+        );
+
+        let sessions = auth_service.list_sessions(test_user.id).await?;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].token, rotated);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reuse_revokes_the_whole_family() -> E {
+        let db = establish_connection().await?;
+        let test_user = get_test_user(db.clone()).await;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let original = auth_service
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await?;
+        let other_device = auth_service
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await?;
+        let rotated = auth_service.rotate_refresh_token(&original).await?;
+
+        let reused = auth_service.get_user_from_refresh(original.clone()).await?;
+        assert!(reused.is_none());
+        assert!(
+            !auth_service
+                .validate_refresh_token(test_user.id, &rotated)
+                .await?
+        );
+        assert!(auth_service.rotate_refresh_token(&rotated).await.is_err());
+
+        assert!(
+            auth_service
+                .validate_refresh_token(test_user.id, &other_device)
+                .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reuse_is_detected_without_the_cache() -> E {
+        let db = establish_connection().await?;
+        let test_user = get_test_user(db.clone()).await;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let original = auth_service
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await?;
+        let rotated = auth_service.rotate_refresh_token(&original).await?;
+
+        let mut cold_auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+        let res = cold_auth_service.rotate_refresh_token(&original).await;
+
+        assert!(res.is_err());
+        assert!(
+            !cold_auth_service
+                .validate_refresh_token(test_user.id, &rotated)
+                .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_rotations_do_not_fork_the_family() -> E {
+        let db = establish_connection().await?;
+        let test_user = get_test_user(db.clone()).await;
+        let mut first = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+        let mut second = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let original = first
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await?;
+        let (a, b) = tokio::join!(
+            first.rotate_refresh_token(&original),
+            second.rotate_refresh_token(&original)
+        );
+
+        assert_eq!([&a, &b].iter().filter(|res| res.is_ok()).count(), 1);
+        assert!(first.list_sessions(test_user.id).await?.is_empty());
+
+        Ok(())
+    }
+}
+
+mod revoke_session {
+    use super::*;
+
+    #[tokio::test]
+    async fn revokes_only_the_requested_session() -> E {
+        let mut redis = MockRedisRefresh::default();
+        let db = establish_connection().await?;
+        let test_user = get_test_user(db.clone()).await;
+
+        redis
+            .expect_set_item_with_expiry()
+            .times(2)
+            .returning(|_, _, _| Ok(()));
+        redis.expect_delete_item().times(1).returning(|_| Ok(()));
+
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+        let first = auth_service
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await?;
+        auth_service
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await?;
+
+        let session_id = auth_service
+            .list_sessions(test_user.id)
+            .await?
+            .into_iter()
+            .find(|session| session.token == first)
+            .unwrap()
+            .id;
+
+        let revoked = auth_service
+            .revoke_session(test_user.id, session_id)
+            .await?;
+        assert_eq!(revoked, first);
+
+        let remaining = auth_service.list_sessions(test_user.id).await?;
+        assert_eq!(remaining.len(), 1);
+        assert_ne!(remaining[0].token, first);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cannot_revoke_another_users_session() -> E {
+        let mut redis = MockRedisRefresh::default();
+        let db = establish_connection().await?;
+        let test_user = get_test_user(db.clone()).await;
+
+        redis
+            .expect_set_item_with_expiry()
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+        auth_service
+            .generate_refresh_token(&test_user, &SessionMetadata::default())
+            .await?;
+        let session_id = auth_service.list_sessions(test_user.id).await?[0].id;
+
+        let res = auth_service
+            .revoke_session(test_user.id + 1, session_id)
+            .await;
+        assert!(matches!(res, Err(AuthServiceError::SessionNotFound(_))));
+
+        Ok(())
+    }
 }
 
 mod validate_refresh_token {
@@ -90,16 +482,23 @@ mod validate_refresh_token {
         let test_user = get_test_user(db.clone()).await;
         let mut redis = MockRedisRefresh::default();
 
+        let cached = format!("{}:family", test_user.id);
         redis
             .expect_get_item()
-            .returning(|_| Ok(Some(String::from("refresh_token"))))
+            .returning(move |_| Ok(Some(cached.clone())))
             .times(1);
 
-        let mut auth_service = AuthService::new(db, Box::new(redis), AuthService::get_key_pair()?);
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
 
-        let res = auth_service.validate_refresh_token(test_user.id).await?;
+        let res = auth_service
+            .validate_refresh_token(test_user.id, "refresh_token")
+            .await?;
 
-        assert!(res.is_some());
+        assert!(res);
 
         Ok(())
     }
@@ -110,29 +509,74 @@ mod validate_refresh_token {
         let db_token = String::from("refresh_token");
 
         let test_user = get_test_user(db.clone()).await;
+        let now = chrono::Utc::now().naive_utc();
         entity::refresh_token::ActiveModel {
             token: ActiveValue::Set(db_token.to_owned()),
+            family_id: ActiveValue::Set(String::from("family")),
             user_id: ActiveValue::Set(test_user.id),
+            last_used_at: ActiveValue::Set(now),
+            expires_at: ActiveValue::Set(now + chrono::Duration::days(1)),
             ..Default::default()
         }
         .insert(&db)
         .await?;
         let mut redis = MockRedisRefresh::default();
 
+        let cached = format!("{}:family", test_user.id);
         redis.expect_get_item().returning(|_| Ok(None)).times(1);
         redis
-            .expect_set_item()
-            .returning(move |_, value| {
-                assert_eq!(&db_token, value);
+            .expect_set_item_with_expiry()
+            .returning(move |key, value, _| {
+                assert!(key.ends_with(&db_token));
+                assert_eq!(&cached, value);
                 Ok(())
             })
             .times(1);
 
-        let mut auth_service = AuthService::new(db, Box::new(redis), AuthService::get_key_pair()?);
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
 
-        let res = auth_service.validate_refresh_token(test_user.id).await?;
+        let res = auth_service
+            .validate_refresh_token(test_user.id, "refresh_token")
+            .await?;
 
-        assert!(res.is_some());
+        assert!(res);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn has_expired_entry() -> E {
+        let db = establish_connection().await?;
+        let test_user = get_test_user(db.clone()).await;
+        let now = chrono::Utc::now().naive_utc();
+        entity::refresh_token::ActiveModel {
+            token: ActiveValue::Set(String::from("refresh_token")),
+            user_id: ActiveValue::Set(test_user.id),
+            last_used_at: ActiveValue::Set(now),
+            expires_at: ActiveValue::Set(now - chrono::Duration::days(1)),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await?;
+        let mut redis = MockRedisRefresh::default();
+
+        redis.expect_get_item().returning(|_| Ok(None)).times(1);
+
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let res = auth_service
+            .validate_refresh_token(test_user.id, "refresh_token")
+            .await?;
+
+        assert!(!res);
 
         Ok(())
     }
@@ -141,15 +585,17 @@ mod validate_refresh_token {
     async fn has_no_entry() -> E {
         let db = establish_connection().await?;
         let mut redis = MockRedisRefresh::default();
-        let key = AuthService::get_key_pair()?;
+        let key = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
         let test_user = get_test_user(db.clone()).await;
 
         redis.expect_get_item().returning(|_| Ok(None)).times(1);
 
         let mut auth_service = AuthService::new(db, Box::new(redis), key);
-        let found = auth_service.validate_refresh_token(test_user.id).await?;
+        let found = auth_service
+            .validate_refresh_token(test_user.id, "refresh_token")
+            .await?;
 
-        assert!(found.is_none());
+        assert!(!found);
 
         Ok(())
     }
@@ -163,23 +609,22 @@ mod generate_jwt {
         let refresh_token = String::from("refresh");
         let db = establish_connection().await?;
         let mut redis = MockRedisRefresh::default();
+        let key = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
+        let test_user = get_test_user(db.clone()).await;
 
-        let s = refresh_token.to_owned();
+        let s = format!("{}:family", test_user.id);
         redis
             .expect_get_item()
             .returning(move |_| Ok(Some(s.clone())))
             .times(1);
 
-        let key = AuthService::get_key_pair()?;
-        let test_user = get_test_user(db.clone()).await;
-
         let mut auth_service = AuthService::new(db, Box::new(redis), key);
         let jwt = auth_service
             .generate_jwt(
                 &UserJwtDto {
                     id: test_user.id,
                     username: test_user.username,
-                    role: Role::User,
+                    role: Role::USER,
                 },
                 &refresh_token,
                 None,
@@ -198,13 +643,17 @@ mod generate_jwt {
 
         redis.expect_get_item().returning(|_| Ok(None)).times(1);
 
-        let mut auth_service = AuthService::new(db, Box::new(redis), AuthService::get_key_pair()?);
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
         let found = auth_service
             .generate_jwt(
                 &UserJwtDto {
                     id: user.id,
                     username: user.username,
-                    role: Role::User,
+                    role: Role::USER,
                 },
                 "refresh",
                 None,
@@ -225,27 +674,35 @@ mod validate_jwt {
         let refresh = String::from("refresh");
         let mut redis = MockRedisRefresh::default();
         let db = establish_connection().await?;
-        let s = refresh.to_owned();
+        let user = get_test_user(db.clone()).await;
+        let s = format!("{}:family", user.id);
+        redis
+            .expect_get_item()
+            .withf(|key| key.starts_with("jwt_denylist:"))
+            .returning(|_| Ok(None));
         redis
             .expect_get_item()
             .returning(move |_| Ok(Some(s.to_owned())))
             .times(1);
-        let user = get_test_user(db.clone()).await;
 
-        let mut auth_service = AuthService::new(db, Box::new(redis), AuthService::get_key_pair()?);
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
         let token = auth_service
             .generate_jwt(
                 &UserJwtDto {
                     id: user.id,
                     username: user.username,
-                    role: Role::User,
+                    role: Role::USER,
                 },
                 &refresh,
                 None,
             )
             .await?;
 
-        let res = auth_service.validate_jwt(token, None);
+        let res = auth_service.validate_jwt(token, None).await;
         assert!(res.is_ok());
 
         Ok(())
@@ -256,27 +713,35 @@ mod validate_jwt {
         let refresh = String::from("refresh");
         let mut redis = MockRedisRefresh::default();
         let db = establish_connection().await?;
-        let s = refresh.to_owned();
+        let user = get_test_user(db.clone()).await;
+        let s = format!("{}:family", user.id);
+        redis
+            .expect_get_item()
+            .withf(|key| key.starts_with("jwt_denylist:"))
+            .returning(|_| Ok(None));
         redis
             .expect_get_item()
             .returning(move |_| Ok(Some(s.to_owned())))
             .times(1);
-        let user = get_test_user(db.clone()).await;
 
-        let mut auth_service = AuthService::new(db, Box::new(redis), AuthService::get_key_pair()?);
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
         let token = auth_service
             .generate_jwt(
                 &UserJwtDto {
                     id: user.id,
                     username: user.username,
-                    role: Role::User,
+                    role: Role::USER,
                 },
                 &refresh,
                 Some(Duration::from_secs(0)),
             )
             .await?;
 
-        let res = auth_service.validate_jwt(token, None);
+        let res = auth_service.validate_jwt(token, None).await;
 
         assert!(res.is_err());
 
@@ -288,27 +753,37 @@ mod validate_jwt {
         let refresh = String::from("refresh");
         let mut redis = MockRedisRefresh::default();
         let db = establish_connection().await?;
-        let s = refresh.to_owned();
+        let user = get_test_user(db.clone()).await;
+        let s = format!("{}:family", user.id);
+        redis
+            .expect_get_item()
+            .withf(|key| key.starts_with("jwt_denylist:"))
+            .returning(|_| Ok(None));
         redis
             .expect_get_item()
             .returning(move |_| Ok(Some(s.to_owned())))
             .times(1);
-        let user = get_test_user(db.clone()).await;
 
-        let mut auth_service = AuthService::new(db, Box::new(redis), AuthService::get_key_pair()?);
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
         let token = auth_service
             .generate_jwt(
                 &UserJwtDto {
                     id: user.id,
                     username: user.username,
-                    role: Role::User,
+                    role: Role::USER,
                 },
                 &refresh,
                 Some(Duration::from_secs(0)),
             )
             .await?;
 
-        let res = auth_service.validate_jwt(token, Some(Duration::from_secs(10)));
+        let res = auth_service
+            .validate_jwt(token, Some(Duration::from_secs(10)))
+            .await;
 
         assert!(res.is_ok());
 
@@ -319,9 +794,15 @@ mod validate_jwt {
     async fn unsuccessful_invalid() -> E {
         let redis = MockRedisRefresh::default();
         let db = establish_connection().await?;
-        let auth_service = AuthService::new(db, Box::new(redis), AuthService::get_key_pair()?);
-
-        let res = auth_service.validate_jwt(String::from("testing"), Some(Duration::from_secs(10)));
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let res = auth_service
+            .validate_jwt(String::from("testing"), Some(Duration::from_secs(10)))
+            .await;
 
         assert!(res.is_err());
 
@@ -338,15 +819,17 @@ mod login {
     async fn login_successful() -> E {
         let db = establish_connection().await?;
         let mut redis = MockRedisRefresh::default();
-        let key = AuthService::get_key_pair()?;
+        let key = KeyRing::load(&db, KeyEncryptionKey::test()).await?;
         let user = get_test_user(db.clone()).await;
 
         let (sx, rx) = channel::<String>();
 
-        redis.expect_set_item().returning(move |_, v| {
-            sx.send(v.to_owned()).unwrap();
-            Ok(())
-        });
+        redis
+            .expect_set_item_with_expiry()
+            .returning(move |_, v, _| {
+                sx.send(v.to_owned()).unwrap();
+                Ok(())
+            });
 
         redis
             .expect_get_item()
@@ -355,9 +838,361 @@ mod login {
 
         let mut auth_service = AuthService::new(db, Box::new(redis), key);
 
-        let res = auth_service.login(String::from("password"), &user).await;
+        let res = auth_service
+            .login(String::from("password"), &user, &SessionMetadata::default())
+            .await;
 
         assert!(res.is_ok());
         Ok(())
     }
 }
+
+mod personal_access_token {
+    use super::*;
+    use crate::models::{scope::Scope, user::PersonalAccessTokenCreate};
+
+    fn token_request(
+        scopes: Vec<Scope>,
+        expires_in_days: Option<i64>,
+    ) -> PersonalAccessTokenCreate {
+        PersonalAccessTokenCreate {
+            name: String::from("inventory sync"),
+            scopes,
+            expires_in_days,
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_to_user_and_scopes() -> E {
+        let db = establish_connection().await?;
+        let user = get_test_user(db.clone()).await;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let created = auth_service
+            .create_personal_access_token(
+                user.id,
+                token_request(vec![Scope::ProductsWrite, Scope::Read, Scope::Read], None),
+            )
+            .await?;
+        assert!(AuthService::is_personal_access_token(&created.token));
+        assert_eq!(created.scopes, vec![Scope::ProductsWrite, Scope::Read]);
+
+        let (found, scopes) = auth_service
+            .validate_personal_access_token(&created.token)
+            .await?;
+        assert_eq!(found.id, user.id);
+        assert_eq!(scopes, vec![Scope::ProductsWrite, Scope::Read]);
+
+        let stored = entity::personal_access_token::Entity::find_by_id(created.id)
+            .one(&db)
+            .await?
+            .unwrap();
+        assert_ne!(stored.token_hash, created.token);
+        assert!(created.token.starts_with(&stored.token_prefix));
+        assert!(stored.last_used_at.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_and_unknown_tokens() -> E {
+        let db = establish_connection().await?;
+        let user = get_test_user(db.clone()).await;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let created = auth_service
+            .create_personal_access_token(user.id, token_request(vec![Scope::Read], Some(1)))
+            .await?;
+        let mut stored: entity::personal_access_token::ActiveModel =
+            entity::personal_access_token::Entity::find_by_id(created.id)
+                .one(&db)
+                .await?
+                .unwrap()
+                .into();
+        stored.expires_at = ActiveValue::Set(Some(
+            chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1),
+        ));
+        stored.update(&db).await?;
+
+        assert!(auth_service
+            .validate_personal_access_token(&created.token)
+            .await
+            .is_err());
+        assert!(auth_service
+            .validate_personal_access_token("tkx_unknown")
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn requires_a_name_and_scopes() -> E {
+        let db = establish_connection().await?;
+        let user = get_test_user(db.clone()).await;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let no_scopes = auth_service
+            .create_personal_access_token(user.id, token_request(vec![], None))
+            .await;
+        assert!(matches!(
+            no_scopes,
+            Err(AuthServiceError::InvalidTokenRequest(_))
+        ));
+
+        let no_name = auth_service
+            .create_personal_access_token(
+                user.id,
+                PersonalAccessTokenCreate {
+                    name: String::from("  "),
+                    scopes: vec![Scope::Read],
+                    expires_in_days: None,
+                },
+            )
+            .await;
+        assert!(matches!(
+            no_name,
+            Err(AuthServiceError::InvalidTokenRequest(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoked_tokens_stop_working() -> E {
+        let db = establish_connection().await?;
+        let user = get_test_user(db.clone()).await;
+        let other = UserService::new(db.clone())
+            .create_user(
+                UserRegister {
+                    username: String::from("other"),
+                    email: String::from("other@email.com"),
+                    password: String::from("password"),
+                },
+                false,
+            )
+            .await?;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let created = auth_service
+            .create_personal_access_token(user.id, token_request(vec![Scope::Read], None))
+            .await?;
+
+        let by_other = auth_service
+            .revoke_personal_access_token(other, created.id)
+            .await;
+        assert!(matches!(by_other, Err(AuthServiceError::TokenNotFound(_))));
+        assert_eq!(
+            auth_service
+                .list_personal_access_tokens(user.id)
+                .await?
+                .len(),
+            1
+        );
+
+        auth_service
+            .revoke_personal_access_token(user.id, created.id)
+            .await?;
+        assert!(auth_service
+            .validate_personal_access_token(&created.token)
+            .await
+            .is_err());
+        assert!(auth_service
+            .list_personal_access_tokens(user.id)
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+}
+
+mod login_throttle {
+    use super::*;
+    use crate::models::user::UserLogin;
+
+    fn wrong_login() -> UserLogin {
+        UserLogin {
+            username: Some(String::from("JoeDiertay")),
+            email: None,
+            password: String::from("wrong password"),
+            device_name: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn delays_attempts_after_repeated_failures() -> E {
+        let db = establish_connection().await?;
+        let user = get_test_user(db.clone()).await;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        for _ in 0..3 {
+            auth_service
+                .check_login_throttle(Some(user.id), None)
+                .await?;
+            auth_service
+                .record_login_failure(Some(user.id), None)
+                .await?;
+        }
+        auth_service
+            .check_login_throttle(Some(user.id), None)
+            .await?;
+
+        auth_service
+            .record_login_failure(Some(user.id), None)
+            .await?;
+        let throttled = auth_service.check_login_throttle(Some(user.id), None).await;
+        assert!(matches!(
+            throttled,
+            Err(AuthServiceError::TooManyAttempts(_))
+        ));
+
+        auth_service.clear_login_failures(user.id).await?;
+        auth_service
+            .check_login_throttle(Some(user.id), None)
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn locks_account_and_records_the_lockout() -> E {
+        let db = establish_connection().await?;
+        let user = get_test_user(db.clone()).await;
+        let user_service = UserService::new(db.clone());
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        for _ in 0..10 {
+            auth_service
+                .record_login_failure(Some(user.id), Some("127.0.0.1"))
+                .await?;
+        }
+
+        let lockouts = entity::login_lockout::Entity::find().all(&db).await?;
+        assert_eq!(lockouts.len(), 1);
+        assert_eq!(lockouts[0].user_id, Some(user.id));
+        assert_eq!(lockouts[0].ip_address.as_deref(), Some("127.0.0.1"));
+        assert!(lockouts[0].locked_until > chrono::Utc::now().naive_utc());
+
+        // The right password doesn't help while the lockout lasts
+        let res = user_service
+            .login(
+                UserLogin {
+                    password: String::from("password"),
+                    ..wrong_login()
+                },
+                auth_service,
+                SessionMetadata::default(),
+            )
+            .await;
+        assert!(matches!(
+            res,
+            Err(crate::services::UserServiceError::AuthServiceError(
+                AuthServiceError::TooManyAttempts(_)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_logins_are_counted_per_address() -> E {
+        let db = establish_connection().await?;
+        get_test_user(db.clone()).await;
+        let user_service = UserService::new(db.clone());
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+        for _ in 0..4 {
+            auth_service
+                .record_login_failure(None, Some("10.0.0.1"))
+                .await?;
+        }
+
+        let res = user_service
+            .login(
+                wrong_login(),
+                auth_service,
+                SessionMetadata {
+                    ip_address: Some(String::from("10.0.0.1")),
+                    ..Default::default()
+                },
+            )
+            .await;
+        assert!(matches!(
+            res,
+            Err(crate::services::UserServiceError::AuthServiceError(
+                AuthServiceError::TooManyAttempts(_)
+            ))
+        ));
+
+        Ok(())
+    }
+}
+
+mod two_factor_challenge {
+    use super::*;
+
+    #[tokio::test]
+    async fn attempts_validated_together_still_share_the_limit() -> E {
+        let db = establish_connection().await?;
+        let user = get_test_user(db.clone()).await;
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+        let token = auth_service
+            .generate_two_factor_challenge(user.id, None)
+            .await?;
+
+        // Requests racing each other all pass validation before any attempt is taken
+        let mut challenges = Vec::new();
+        for _ in 0..TWO_FACTOR_MAX_ATTEMPTS + 1 {
+            challenges.push(auth_service.validate_two_factor_challenge(&token).await?);
+        }
+
+        let mut taken = 0;
+        for challenge in &challenges {
+            if auth_service
+                .take_two_factor_attempt(challenge)
+                .await
+                .is_ok()
+            {
+                taken += 1;
+            }
+        }
+        assert_eq!(taken, TWO_FACTOR_MAX_ATTEMPTS);
+        assert!(matches!(
+            auth_service.validate_two_factor_challenge(&token).await,
+            Err(AuthServiceError::InvalidJWT(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    dtos::blocklist::BlockedTermReturn,
+    models::{
+        blocklist::{BlockedTermCreate, BlockedTermKind, Blocklist},
+        user::UserJwtDto,
+    },
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use entity::blocked_term::{
+    self, ActiveModel as BlockedTermActiveModel, Entity as BlockedTermEntity,
+    Model as BlockedTermModel,
+};
+use rocket::{
+    outcome::IntoOutcome,
+    request::{self, FromRequest},
+    response::Responder,
+    Request,
+};
+use sea_orm::{prelude::*, ActiveValue, DatabaseConnection, QueryOrder};
+use thiserror::Error;
+
+#[cfg(test)]
+mod test;
+
+const MAX_TERM_LENGTH: usize = 64;
+
+#[derive(Error, Debug, Responder)]
+pub enum BlocklistServiceError {
+    #[error("An unknown error has occurred")]
+    #[response(status = 500)]
+    InternalError(AnyhowResponder),
+    #[error("Blocked term not found")]
+    #[response(status = 404)]
+    TermNotFound(AnyhowResponder),
+    #[error("Invalid request")]
+    #[response(status = 400)]
+    InvalidRequest(AnyhowResponder),
+    #[error("This term is already on the blocklist")]
+    #[response(status = 400)]
+    DuplicateTerm(AnyhowResponder),
+}
+
+/// Words refused in usernames and profile text. The list is read from the
+/// database on every check, so edits apply to every instance right away.
+#[derive(Debug)]
+pub struct BlocklistService {
+    db_connection: DatabaseConnection,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BlocklistService {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        req.rocket()
+            .state::<DatabaseConnection>()
+            .map(|conn| Self {
+                db_connection: conn.clone(),
+            })
+            .or_forward(())
+    }
+}
+
+fn to_return(term: BlockedTermModel) -> BlockedTermReturn {
+    BlockedTermReturn {
+        kind: BlockedTermKind::try_from(term.kind.as_str()).unwrap_or(BlockedTermKind::Word),
+        id: term.id,
+        term: term.term,
+        created_by: term.created_by,
+        created_at: term.created_at,
+    }
+}
+
+impl BlocklistService {
+    pub fn new(db_connection: DatabaseConnection) -> Self {
+        Self { db_connection }
+    }
+
+    async fn load(&self) -> Result<Blocklist, BlocklistServiceError> {
+        let terms = BlockedTermEntity::find()
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| BlocklistServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let mut blocklist = Blocklist::default();
+        for term in terms {
+            match BlockedTermKind::try_from(term.kind.as_str()) {
+                Ok(kind) => blocklist.add(&term.term, kind),
+                Err(_) => tracing::warn!(
+                    message = "Skipping blocked term with an unknown kind",
+                    id = term.id,
+                    kind = term.kind
+                ),
+            }
+        }
+
+        Ok(blocklist)
+    }
+
+    pub async fn is_blocked(&self, text: &str) -> Result<bool, BlocklistServiceError> {
+        Ok(self.load().await?.is_blocked(text))
+    }
+
+    pub async fn list_terms(&self) -> Result<Vec<BlockedTermReturn>, BlocklistServiceError> {
+        let terms = BlockedTermEntity::find()
+            .order_by_asc(blocked_term::Column::Term)
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| BlocklistServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(terms.into_iter().map(to_return).collect())
+    }
+
+    pub async fn add_term(
+        &self,
+        actor: &UserJwtDto,
+        create: BlockedTermCreate,
+    ) -> Result<BlockedTermReturn, BlocklistServiceError> {
+        let term = create.term.trim().to_lowercase();
+        if term.is_empty() || term.chars().count() > MAX_TERM_LENGTH {
+            return Err(BlocklistServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!("Terms must have between 1 and {MAX_TERM_LENGTH} characters"),
+            )));
+        }
+
+        let existing = BlockedTermEntity::find()
+            .filter(blocked_term::Column::Term.eq(term.as_str()))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| BlocklistServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if existing > 0 {
+            return Err(BlocklistServiceError::DuplicateTerm(AnyhowResponder(
+                anyhow!("User {} attempted to add existing term {term}", actor.id),
+            )));
+        }
+
+        let created = BlockedTermActiveModel {
+            term: ActiveValue::Set(term),
+            kind: ActiveValue::Set(create.kind.as_str().to_owned()),
+            created_by: ActiveValue::Set(Some(actor.id)),
+            created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(|e| BlocklistServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        tracing::info!(
+            message = "Added blocked term",
+            actor_id = actor.id,
+            term = created.term,
+            kind = created.kind
+        );
+
+        Ok(to_return(created))
+    }
+
+    pub async fn remove_term(
+        &self,
+        actor: &UserJwtDto,
+        id: i64,
+    ) -> Result<(), BlocklistServiceError> {
+        let removed = BlockedTermEntity::delete_by_id(id)
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| BlocklistServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if removed.rows_affected == 0 {
+            return Err(BlocklistServiceError::TermNotFound(AnyhowResponder(
+                anyhow!("Blocked term {id} not found"),
+            )));
+        }
+
+        tracing::info!(message = "Removed blocked term", actor_id = actor.id, id);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use super::*;
+use crate::{
+    db::test::establish_connection,
+    models::{role::Role, user::UserRegister},
+    services::UserService,
+};
+
+type E = Result<(), Box<dyn std::error::Error>>;
+
+async fn moderator(db: &DatabaseConnection) -> Result<UserJwtDto, Box<dyn std::error::Error>> {
+    let id = UserService::new(db.clone())
+        .create_user(
+            UserRegister {
+                username: "reviewer".into(),
+                email: "reviewer@test.com".into(),
+                password: "password".into(),
+            },
+            false,
+        )
+        .await?;
+
+    Ok(UserJwtDto {
+        id,
+        username: "reviewer".into(),
+        role: Role::USER | Role::MODERATOR,
+    })
+}
+
+#[tokio::test]
+async fn default_terms_match_words_not_substrings() -> E {
+    let db = establish_connection().await?;
+    let blocklist_service = BlocklistService::new(db);
+
+    for blocked in [
+        "siteAdmin",
+        "site_admin",
+        "realadmin",
+        "adminteam",
+        "admin1",
+        "admin2",
+        "ModeratorBob",
+        "twatwaffle",
+        "sh1t_happens",
+        "MyA$$",
+    ] {
+        assert!(blocklist_service.is_blocked(blocked).await?, "{blocked}");
+    }
+    for allowed in [
+        "classic",
+        "bassist",
+        "passionate",
+        "Scunthorpe",
+        "badminton",
+    ] {
+        assert!(!blocklist_service.is_blocked(allowed).await?, "{allowed}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn edits_apply_to_the_next_check() -> E {
+    let db = establish_connection().await?;
+    let moderator = moderator(&db).await?;
+    let blocklist_service = BlocklistService::new(db);
+    assert!(!blocklist_service.is_blocked("spammer").await?);
+
+    let added = blocklist_service
+        .add_term(
+            &moderator,
+            BlockedTermCreate {
+                term: " Spammer ".into(),
+                kind: BlockedTermKind::Exact,
+            },
+        )
+        .await?;
+    assert_eq!(added.term, "spammer");
+    assert!(blocklist_service.is_blocked("Spammer").await?);
+    assert!(blocklist_service.is_blocked("sp4mmer").await?);
+    assert!(!blocklist_service.is_blocked("spammer_hunter").await?);
+
+    let duplicate = blocklist_service
+        .add_term(
+            &moderator,
+            BlockedTermCreate {
+                term: "spammer".into(),
+                kind: BlockedTermKind::Word,
+            },
+        )
+        .await;
+    assert!(matches!(
+        duplicate,
+        Err(BlocklistServiceError::DuplicateTerm(_))
+    ));
+
+    blocklist_service.remove_term(&moderator, added.id).await?;
+    assert!(!blocklist_service.is_blocked("spammer").await?);
+
+    let missing = blocklist_service.remove_term(&moderator, added.id).await;
+    assert!(matches!(
+        missing,
+        Err(BlocklistServiceError::TermNotFound(_))
+    ));
+
+    Ok(())
+}
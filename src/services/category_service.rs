@@ -0,0 +1,568 @@
+use crate::{
+    dtos::category::{CategoryAttributeReturn, CategoryReturn, CategoryTree},
+    models::category::{AttributeKind, AttributeValue, CategoryAttributeCreate, CategoryDetails},
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use entity::{
+    category::{
+        self, ActiveModel as CategoryActiveModel, Entity as CategoryEntity, Model as CategoryModel,
+    },
+    category_attribute::{
+        self, ActiveModel as CategoryAttributeActiveModel, Entity as CategoryAttributeEntity,
+        Model as CategoryAttributeModel,
+    },
+};
+use rocket::{
+    outcome::IntoOutcome,
+    request::{self, FromRequest},
+    response::Responder,
+    Request,
+};
+use sea_orm::{prelude::*, ActiveValue, DatabaseConnection, QueryOrder, Set};
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+#[cfg(test)]
+mod test;
+
+const MAX_CATEGORY_NAME_LENGTH: usize = 128;
+const MAX_PRODUCT_CATEGORIES: usize = 5;
+const MAX_ATTRIBUTE_NAME_LENGTH: usize = 64;
+const MAX_ATTRIBUTE_UNIT_LENGTH: usize = 16;
+const MAX_ATTRIBUTE_TEXT_LENGTH: usize = 256;
+
+#[derive(Error, Debug, Responder)]
+pub enum CategoryServiceError {
+    #[error("An unknown error has occurred")]
+    #[response(status = 500)]
+    InternalError(AnyhowResponder),
+    #[error("Category not found")]
+    #[response(status = 404)]
+    CategoryNotFound(AnyhowResponder),
+    #[error("Invalid request")]
+    #[response(status = 400)]
+    InvalidRequest(AnyhowResponder),
+    #[error("A category with this name already exists")]
+    #[response(status = 400)]
+    DuplicateCategory(AnyhowResponder),
+    #[error("This category still has subcategories or products")]
+    #[response(status = 400)]
+    CategoryInUse(AnyhowResponder),
+    #[error("Attribute not found")]
+    #[response(status = 404)]
+    AttributeNotFound(AnyhowResponder),
+    #[error("This category or a related one already has an attribute with this name")]
+    #[response(status = 400)]
+    DuplicateAttribute(AnyhowResponder),
+    #[error("Invalid product attributes")]
+    #[response(status = 400)]
+    InvalidAttributes(AnyhowResponder),
+}
+
+/// Product categories, nested through `category.parent_id`
+#[derive(Debug)]
+pub struct CategoryService {
+    db_connection: DatabaseConnection,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CategoryService {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        req.rocket()
+            .state::<DatabaseConnection>()
+            .map(|conn| Self {
+                db_connection: conn.clone(),
+            })
+            .or_forward(())
+    }
+}
+
+fn to_return(category: CategoryModel) -> CategoryReturn {
+    CategoryReturn {
+        id: category.id,
+        name: category.category_name,
+        parent_id: category.parent_id,
+    }
+}
+
+fn to_attribute_return(attribute: CategoryAttributeModel) -> CategoryAttributeReturn {
+    CategoryAttributeReturn {
+        kind: AttributeKind::try_from(attribute.kind.as_str()).unwrap_or(AttributeKind::Text),
+        options: attribute
+            .options
+            .as_deref()
+            .and_then(|options| serde_json::from_str(options).ok())
+            .unwrap_or_default(),
+        id: attribute.id,
+        category_id: attribute.category_id,
+        name: attribute.name,
+        unit: attribute.unit,
+        required: attribute.required,
+    }
+}
+
+/// Checks a single value against an attribute's definition
+fn check_value(
+    attribute: &CategoryAttributeReturn,
+    value: &AttributeValue,
+) -> Result<(), CategoryServiceError> {
+    let valid = match (attribute.kind, value) {
+        (AttributeKind::Enum, AttributeValue::Text(text)) => attribute.options.contains(text),
+        (AttributeKind::Integer, AttributeValue::Integer(_)) => true,
+        (AttributeKind::Boolean, AttributeValue::Boolean(_)) => true,
+        (AttributeKind::Text, AttributeValue::Text(text)) => {
+            !text.trim().is_empty() && text.chars().count() <= MAX_ATTRIBUTE_TEXT_LENGTH
+        }
+        _ => false,
+    };
+
+    if !valid {
+        return Err(CategoryServiceError::InvalidAttributes(AnyhowResponder(
+            anyhow!(
+                "{value:?} is not a valid {} value for attribute {}",
+                attribute.kind.as_str(),
+                attribute.name
+            ),
+        )));
+    }
+
+    Ok(())
+}
+
+fn build_tree(
+    parent: Option<i64>,
+    children: &HashMap<Option<i64>, Vec<&CategoryModel>>,
+) -> Vec<CategoryTree> {
+    children
+        .get(&parent)
+        .map(|found| {
+            found
+                .iter()
+                .map(|category| CategoryTree {
+                    id: category.id,
+                    name: category.category_name.clone(),
+                    children: build_tree(Some(category.id), children),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl CategoryService {
+    pub fn new(db_connection: DatabaseConnection) -> Self {
+        Self { db_connection }
+    }
+
+    async fn all(&self) -> Result<Vec<CategoryModel>, CategoryServiceError> {
+        CategoryEntity::find()
+            .order_by_asc(category::Column::CategoryName)
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    async fn find_category(&self, id: i64) -> Result<CategoryModel, CategoryServiceError> {
+        CategoryEntity::find_by_id(id)
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(CategoryServiceError::CategoryNotFound(AnyhowResponder(
+                anyhow!("Category {id} not found"),
+            )))
+    }
+
+    /// Every category, nested below its parent
+    pub async fn get_tree(&self) -> Result<Vec<CategoryTree>, CategoryServiceError> {
+        let categories = self.all().await?;
+
+        let mut children: HashMap<Option<i64>, Vec<&CategoryModel>> = HashMap::new();
+        for category in &categories {
+            children
+                .entry(category.parent_id)
+                .or_default()
+                .push(category);
+        }
+
+        Ok(build_tree(None, &children))
+    }
+
+    /// The category and every category nested below it
+    pub async fn with_descendants(&self, id: i64) -> Result<Vec<i64>, CategoryServiceError> {
+        let categories = self.all().await?;
+
+        let mut found = vec![id];
+        let mut index = 0;
+        while index < found.len() {
+            let parent = found[index];
+            found.extend(
+                categories
+                    .iter()
+                    .filter(|category| category.parent_id == Some(parent))
+                    .map(|category| category.id),
+            );
+            index += 1;
+        }
+
+        Ok(found)
+    }
+
+    /// The categories and every category they are nested below
+    async fn with_ancestors(&self, ids: &[i64]) -> Result<Vec<i64>, CategoryServiceError> {
+        let parents: HashMap<i64, Option<i64>> = self
+            .all()
+            .await?
+            .into_iter()
+            .map(|category| (category.id, category.parent_id))
+            .collect();
+
+        let mut found = Vec::new();
+        for id in ids {
+            let mut current = Some(*id);
+            while let Some(id) = current {
+                if found.contains(&id) {
+                    break;
+                }
+                found.push(id);
+                current = parents.get(&id).copied().flatten();
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Every attribute that applies to products in these categories, including inherited ones
+    async fn schema_for(
+        &self,
+        ids: &[i64],
+    ) -> Result<Vec<CategoryAttributeReturn>, CategoryServiceError> {
+        let categories = self.with_ancestors(ids).await?;
+
+        let attributes = CategoryAttributeEntity::find()
+            .filter(category_attribute::Column::CategoryId.is_in(categories))
+            .order_by_asc(category_attribute::Column::Name)
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(attributes.into_iter().map(to_attribute_return).collect())
+    }
+
+    /// The attribute schema of a category, including the attributes of its parents
+    pub async fn get_attributes(
+        &self,
+        id: i64,
+    ) -> Result<Vec<CategoryAttributeReturn>, CategoryServiceError> {
+        self.find_category(id).await?;
+        self.schema_for(&[id]).await
+    }
+
+    /// Checks a product's attribute values against the schema of its categories. Returns the
+    /// attribute id every value is stored under, a name shared by several of the categories
+    /// has to be valid for each of them.
+    pub async fn resolve_attributes(
+        &self,
+        categories: &[i64],
+        values: &BTreeMap<String, AttributeValue>,
+    ) -> Result<Vec<(i64, AttributeValue)>, CategoryServiceError> {
+        let schema = self.schema_for(categories).await?;
+
+        let mut resolved = Vec::new();
+        for (name, value) in values {
+            let mut matching = schema
+                .iter()
+                .filter(|attribute| &attribute.name == name)
+                .peekable();
+
+            if matching.peek().is_none() {
+                return Err(CategoryServiceError::InvalidAttributes(AnyhowResponder(
+                    anyhow!("Attribute {name} does not apply to categories {categories:?}"),
+                )));
+            }
+
+            for attribute in matching {
+                check_value(attribute, value)?;
+                resolved.push((attribute.id, value.clone()));
+            }
+        }
+
+        if let Some(missing) = schema
+            .iter()
+            .find(|attribute| attribute.required && !values.contains_key(&attribute.name))
+        {
+            return Err(CategoryServiceError::InvalidAttributes(AnyhowResponder(
+                anyhow!("Attribute {} is required", missing.name),
+            )));
+        }
+
+        Ok(resolved)
+    }
+
+    /// Adding a required attribute doesn't touch existing products, they have to provide a
+    /// value the next time their attributes or categories are updated
+    pub async fn add_attribute(
+        &self,
+        category_id: i64,
+        create: CategoryAttributeCreate,
+    ) -> Result<CategoryAttributeReturn, CategoryServiceError> {
+        self.find_category(category_id).await?;
+
+        let name = create.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_ATTRIBUTE_NAME_LENGTH {
+            return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!(
+                    "Attribute names must have between 1 and {MAX_ATTRIBUTE_NAME_LENGTH} characters"
+                ),
+            )));
+        }
+
+        if create.unit.is_some() && create.kind != AttributeKind::Integer {
+            return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!("Only integer attributes can have a unit"),
+            )));
+        }
+        if create.unit.as_ref().is_some_and(|unit| {
+            unit.trim().is_empty() || unit.chars().count() > MAX_ATTRIBUTE_UNIT_LENGTH
+        }) {
+            return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!("Units must have between 1 and {MAX_ATTRIBUTE_UNIT_LENGTH} characters"),
+            )));
+        }
+
+        let options = match (create.kind, create.options) {
+            (AttributeKind::Enum, Some(options))
+                if !options.is_empty()
+                    && options.iter().all(|option| {
+                        !option.trim().is_empty()
+                            && option.chars().count() <= MAX_ATTRIBUTE_TEXT_LENGTH
+                    }) =>
+            {
+                Some(
+                    serde_json::to_string(&options).map_err(|e| {
+                        CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e)))
+                    })?,
+                )
+            }
+            (AttributeKind::Enum, _) => {
+                return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                    "Enum attributes need at least one option of up to {MAX_ATTRIBUTE_TEXT_LENGTH} characters"
+                ))))
+            }
+            (_, None) => None,
+            (_, Some(_)) => {
+                return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(
+                    anyhow!("Only enum attributes can have options"),
+                )))
+            }
+        };
+
+        // Products in a subcategory get the attributes of both, so a name can only be used once
+        // along a branch of the tree
+        let mut related = self.with_ancestors(&[category_id]).await?;
+        related.extend(self.with_descendants(category_id).await?);
+        let duplicate = CategoryAttributeEntity::find()
+            .filter(category_attribute::Column::CategoryId.is_in(related))
+            .filter(category_attribute::Column::Name.eq(name))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if duplicate > 0 {
+            return Err(CategoryServiceError::DuplicateAttribute(AnyhowResponder(
+                anyhow!("Attribute {name} is already defined along category {category_id}"),
+            )));
+        }
+
+        let created = CategoryAttributeActiveModel {
+            category_id: ActiveValue::Set(category_id),
+            name: ActiveValue::Set(name.to_owned()),
+            kind: ActiveValue::Set(create.kind.as_str().to_owned()),
+            unit: ActiveValue::Set(create.unit.map(|unit| unit.trim().to_owned())),
+            options: ActiveValue::Set(options),
+            required: ActiveValue::Set(create.required),
+            created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(to_attribute_return(created))
+    }
+
+    /// Also removes every product's value for the attribute
+    pub async fn remove_attribute(&self, id: i64) -> Result<(), CategoryServiceError> {
+        entity::product_attribute::Entity::delete_many()
+            .filter(entity::product_attribute::Column::AttributeId.eq(id))
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let removed = CategoryAttributeEntity::delete_by_id(id)
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if removed.rows_affected == 0 {
+            return Err(CategoryServiceError::AttributeNotFound(AnyhowResponder(
+                anyhow!("Attribute {id} not found"),
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks a product's category list, in order of priority, before it is saved
+    pub async fn validate_assignment(&self, ids: &[i64]) -> Result<(), CategoryServiceError> {
+        if ids.len() > MAX_PRODUCT_CATEGORIES {
+            return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!("A product can have at most {MAX_PRODUCT_CATEGORIES} categories"),
+            )));
+        }
+
+        for (index, id) in ids.iter().enumerate() {
+            if ids[..index].contains(id) {
+                return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(
+                    anyhow!("Category {id} was given more than once"),
+                )));
+            }
+        }
+
+        let found = CategoryEntity::find()
+            .filter(category::Column::Id.is_in(ids.iter().copied()))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if found != ids.len() as u64 {
+            return Err(CategoryServiceError::CategoryNotFound(AnyhowResponder(
+                anyhow!("One of the categories {ids:?} does not exist"),
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn validate_details(
+        &self,
+        id: Option<i64>,
+        details: &CategoryDetails,
+    ) -> Result<String, CategoryServiceError> {
+        let name = details.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_CATEGORY_NAME_LENGTH {
+            return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!(
+                    "Category names must have between 1 and {MAX_CATEGORY_NAME_LENGTH} characters"
+                ),
+            )));
+        }
+
+        let mut duplicate = CategoryEntity::find().filter(category::Column::CategoryName.eq(name));
+        if let Some(id) = id {
+            duplicate = duplicate.filter(category::Column::Id.ne(id));
+        }
+        let duplicate = duplicate
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if duplicate > 0 {
+            return Err(CategoryServiceError::DuplicateCategory(AnyhowResponder(
+                anyhow!("Category {name} already exists"),
+            )));
+        }
+
+        if let Some(parent_id) = details.parent_id {
+            self.find_category(parent_id).await?;
+
+            // A category can't be moved below itself
+            if let Some(id) = id {
+                if self.with_descendants(id).await?.contains(&parent_id) {
+                    return Err(CategoryServiceError::InvalidRequest(AnyhowResponder(
+                        anyhow!("Category {parent_id} is nested below category {id}"),
+                    )));
+                }
+            }
+        }
+
+        Ok(name.to_owned())
+    }
+
+    pub async fn create_category(
+        &self,
+        details: CategoryDetails,
+    ) -> Result<CategoryReturn, CategoryServiceError> {
+        let name = self.validate_details(None, &details).await?;
+
+        let created = CategoryActiveModel {
+            category_name: ActiveValue::Set(name),
+            parent_id: ActiveValue::Set(details.parent_id),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(to_return(created))
+    }
+
+    pub async fn update_category(
+        &self,
+        id: i64,
+        details: CategoryDetails,
+    ) -> Result<CategoryReturn, CategoryServiceError> {
+        let existing = self.find_category(id).await?;
+        let name = self.validate_details(Some(id), &details).await?;
+
+        let mut active: CategoryActiveModel = existing.into();
+        active.category_name = Set(name);
+        active.parent_id = Set(details.parent_id);
+        active.updated_at = Set(chrono::Utc::now().naive_utc());
+        let updated = active
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(to_return(updated))
+    }
+
+    /// Only empty categories can be deleted, products and subcategories have to be moved first
+    pub async fn delete_category(&self, id: i64) -> Result<(), CategoryServiceError> {
+        self.find_category(id).await?;
+
+        let children = CategoryEntity::find()
+            .filter(category::Column::ParentId.eq(id))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        let products = entity::product_category::Entity::find()
+            .filter(entity::product_category::Column::CategoryId.eq(id))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if children > 0 || products > 0 {
+            return Err(CategoryServiceError::CategoryInUse(AnyhowResponder(
+                anyhow!("Category {id} has {children} subcategories and {products} products"),
+            )));
+        }
+
+        let attributes = CategoryAttributeEntity::find()
+            .filter(category_attribute::Column::CategoryId.eq(id))
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        for attribute in attributes {
+            self.remove_attribute(attribute.id).await?;
+        }
+
+        CategoryEntity::delete_by_id(id)
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| CategoryServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+}
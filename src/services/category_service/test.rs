@@ -0,0 +1,184 @@
+use super::*;
+use crate::db::test::establish_connection;
+
+type E = Result<(), Box<dyn std::error::Error>>;
+
+async fn create(
+    category_service: &CategoryService,
+    name: &str,
+    parent_id: Option<i64>,
+) -> Result<i64, CategoryServiceError> {
+    Ok(category_service
+        .create_category(CategoryDetails {
+            name: name.into(),
+            parent_id,
+        })
+        .await?
+        .id)
+}
+
+#[tokio::test]
+async fn builds_a_nested_tree() -> E {
+    let db = establish_connection().await?;
+    let category_service = CategoryService::new(db);
+    let electronics = create(&category_service, "Electronics", None).await?;
+    let phones = create(&category_service, "Phones", Some(electronics)).await?;
+    let laptops = create(&category_service, "Laptops", Some(electronics)).await?;
+    let cases = create(&category_service, "Phone cases", Some(phones)).await?;
+    let garden = create(&category_service, "Garden", None).await?;
+
+    let tree = category_service.get_tree().await?;
+    assert_eq!(
+        tree,
+        vec![
+            CategoryTree {
+                id: electronics,
+                name: "Electronics".into(),
+                children: vec![
+                    CategoryTree {
+                        id: laptops,
+                        name: "Laptops".into(),
+                        children: vec![],
+                    },
+                    CategoryTree {
+                        id: phones,
+                        name: "Phones".into(),
+                        children: vec![CategoryTree {
+                            id: cases,
+                            name: "Phone cases".into(),
+                            children: vec![],
+                        }],
+                    },
+                ],
+            },
+            CategoryTree {
+                id: garden,
+                name: "Garden".into(),
+                children: vec![],
+            },
+        ]
+    );
+
+    let mut below = category_service.with_descendants(electronics).await?;
+    below.sort();
+    assert_eq!(below, vec![electronics, phones, laptops, cases]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_invalid_changes() -> E {
+    let db = establish_connection().await?;
+    let category_service = CategoryService::new(db);
+    let electronics = create(&category_service, "Electronics", None).await?;
+    let phones = create(&category_service, "Phones", Some(electronics)).await?;
+
+    let duplicate = create(&category_service, "Phones", None).await;
+    assert!(matches!(
+        duplicate,
+        Err(CategoryServiceError::DuplicateCategory(_))
+    ));
+
+    let missing_parent = create(&category_service, "Garden", Some(phones + 100)).await;
+    assert!(matches!(
+        missing_parent,
+        Err(CategoryServiceError::CategoryNotFound(_))
+    ));
+
+    let cycle = category_service
+        .update_category(
+            electronics,
+            CategoryDetails {
+                name: "Electronics".into(),
+                parent_id: Some(phones),
+            },
+        )
+        .await;
+    assert!(matches!(
+        cycle,
+        Err(CategoryServiceError::InvalidRequest(_))
+    ));
+
+    let in_use = category_service.delete_category(electronics).await;
+    assert!(matches!(
+        in_use,
+        Err(CategoryServiceError::CategoryInUse(_))
+    ));
+
+    category_service.delete_category(phones).await?;
+    category_service.delete_category(electronics).await?;
+    assert!(category_service.get_tree().await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn subcategories_inherit_attributes() -> E {
+    let db = establish_connection().await?;
+    let category_service = CategoryService::new(db);
+    let computers = create(&category_service, "Computers", None).await?;
+    let laptops = create(&category_service, "Laptops", Some(computers)).await?;
+    let attribute = |name: &str, kind| CategoryAttributeCreate {
+        name: name.into(),
+        kind,
+        unit: None,
+        options: None,
+        required: false,
+    };
+
+    category_service
+        .add_attribute(computers, attribute("ram", AttributeKind::Integer))
+        .await?;
+    category_service
+        .add_attribute(laptops, attribute("touchscreen", AttributeKind::Boolean))
+        .await?;
+
+    let schema = category_service.get_attributes(laptops).await?;
+    assert_eq!(
+        schema.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(),
+        vec!["ram", "touchscreen"]
+    );
+    assert_eq!(category_service.get_attributes(computers).await?.len(), 1);
+
+    let duplicate = category_service
+        .add_attribute(laptops, attribute("ram", AttributeKind::Text))
+        .await;
+    assert!(matches!(
+        duplicate,
+        Err(CategoryServiceError::DuplicateAttribute(_))
+    ));
+    let enum_without_options = category_service
+        .add_attribute(laptops, attribute("condition", AttributeKind::Enum))
+        .await;
+    assert!(matches!(
+        enum_without_options,
+        Err(CategoryServiceError::InvalidRequest(_))
+    ));
+
+    let values = BTreeMap::from([
+        ("ram".to_owned(), AttributeValue::Integer(16)),
+        ("touchscreen".to_owned(), AttributeValue::Boolean(true)),
+    ]);
+    assert_eq!(
+        category_service
+            .resolve_attributes(&[laptops], &values)
+            .await?
+            .len(),
+        2
+    );
+    let wrong_type = BTreeMap::from([("ram".to_owned(), AttributeValue::Text("16".into()))]);
+    assert!(matches!(
+        category_service
+            .resolve_attributes(&[laptops], &wrong_type)
+            .await,
+        Err(CategoryServiceError::InvalidAttributes(_))
+    ));
+    assert!(matches!(
+        category_service
+            .resolve_attributes(&[computers], &values)
+            .await,
+        Err(CategoryServiceError::InvalidAttributes(_))
+    ));
+
+    Ok(())
+}
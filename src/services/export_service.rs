@@ -0,0 +1,687 @@
+use crate::{
+    dtos::{
+        auth::PersonalAccessTokenReturn,
+        export::{
+            DataExportReturn, ExportedFile, ExportedIdentity, ExportedLoginLockout,
+            ExportedPreferences, ExportedProduct, ExportedProductAudit, ExportedReview,
+            ExportedReviews, ExportedSession, ExportedUser,
+        },
+        product::{FileResponder, PriceChangeReturn, ProductRevisionReturn},
+    },
+    mail::{frontend_url, Mail, MailSender},
+    models::{category::AttributeValue, export::ExportStatus, role::Role, scope::Scope},
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use entity::data_export::{
+    self, ActiveModel as DataExportActiveModel, Entity as DataExportEntity,
+    Model as DataExportModel,
+};
+use rocket::{
+    http::ContentType,
+    outcome::{try_outcome, IntoOutcome},
+    request::{self, FromRequest},
+    response::Responder,
+    Request,
+};
+use sea_orm::{prelude::*, sea_query::Expr, ActiveValue, DatabaseConnection, QueryOrder, Set};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[cfg(test)]
+mod test;
+
+const EXPORT_VALIDITY_DAYS: i64 = 7;
+const EXPORT_COOLDOWN_HOURS: i64 = 24;
+/// Exports still pending after this long were interrupted, e.g. by a restart
+const STALE_EXPORT_MINUTES: i64 = 60;
+
+#[derive(Error, Debug, Responder)]
+pub enum ExportServiceError {
+    #[error("An unknown error has occurred")]
+    #[response(status = 500)]
+    InternalError(AnyhowResponder),
+    #[error("Export not found")]
+    #[response(status = 404)]
+    ExportNotFound(AnyhowResponder),
+    #[error("This export is not ready to download")]
+    #[response(status = 400)]
+    ExportNotReady(AnyhowResponder),
+    #[error("An export was requested recently, please wait before requesting another")]
+    #[response(status = 429)]
+    ExportRecentlyRequested(AnyhowResponder),
+}
+
+/// Machine readable exports of everything stored about a user, built in the background.
+///
+/// Every table that stores data about a user has to be included by `build_archive`.
+/// A change adding such a table extends the export in the same change, and removes
+/// the rows in `UserService::delete_account` too.
+#[derive(Debug, Clone)]
+pub struct ExportService {
+    db_connection: DatabaseConnection,
+    base_file_path: PathBuf,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ExportService {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let path: String = try_outcome!(std::env::var("SAVE_PATH").map_err(|_| ()).or_forward(()));
+
+        req.rocket()
+            .state::<DatabaseConnection>()
+            .map(|conn| Self {
+                db_connection: conn.clone(),
+                base_file_path: path.into(),
+            })
+            .or_forward(())
+    }
+}
+
+fn to_return(export: DataExportModel) -> DataExportReturn {
+    DataExportReturn {
+        id: export.id,
+        status: ExportStatus::try_from(export.status.as_str()).unwrap_or(ExportStatus::Failed),
+        created_at: export.created_at,
+        completed_at: export.completed_at,
+        expires_at: export.expires_at,
+    }
+}
+
+fn to_exported_review(review: entity::seller_review::Model) -> ExportedReview {
+    ExportedReview {
+        id: review.id,
+        seller_id: review.seller_id,
+        reviewer_id: review.reviewer_id,
+        product_id: review.product_id,
+        rating: review.rating,
+        comment: review.comment,
+        created_at: review.created_at,
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, ExportServiceError> {
+    serde_json::to_vec_pretty(value)
+        .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+}
+
+/// Writes the JSON documents and copies of the uploaded files into a zip at `location`
+fn write_archive(
+    location: &Path,
+    documents: Vec<(String, Vec<u8>)>,
+    files: Vec<(String, PathBuf)>,
+) -> anyhow::Result<()> {
+    let mut archive = zip::ZipWriter::new(std::fs::File::create(location)?);
+    let options = zip::write::FileOptions::default();
+
+    for (name, contents) in documents {
+        archive.start_file(name, options)?;
+        archive.write_all(&contents)?;
+    }
+
+    for (name, source) in files {
+        let mut file = match std::fs::File::open(&source) {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::warn!(message = "Skipping missing file in data export", path = ?source, error = %e);
+                continue;
+            }
+        };
+
+        archive.start_file(name, options)?;
+        std::io::copy(&mut file, &mut archive)?;
+    }
+
+    archive.finish()?;
+
+    Ok(())
+}
+
+impl ExportService {
+    pub fn new(db_connection: DatabaseConnection, base_file_path: PathBuf) -> Self {
+        Self {
+            db_connection,
+            base_file_path,
+        }
+    }
+
+    async fn find_export(
+        &self,
+        user_id: i64,
+        export_id: i64,
+    ) -> Result<DataExportModel, ExportServiceError> {
+        DataExportEntity::find_by_id(export_id)
+            .filter(data_export::Column::UserId.eq(user_id))
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(ExportServiceError::ExportNotFound(AnyhowResponder(
+                anyhow!("Export {export_id} not found for user {user_id}"),
+            )))
+    }
+
+    /// Removes archives whose download link has expired, returning how many were removed
+    pub async fn purge_expired(&self) -> Result<usize, ExportServiceError> {
+        let expired = DataExportEntity::find()
+            .filter(data_export::Column::ExpiresAt.lt(Utc::now().naive_utc()))
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let count = expired.len();
+        for export in expired {
+            if let Some(location) = export.file_location {
+                let _ = std::fs::remove_file(location);
+            }
+
+            DataExportEntity::delete_by_id(export.id)
+                .exec(&self.db_connection)
+                .await
+                .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        }
+
+        Ok(count)
+    }
+
+    /// Marks exports that never finished as failed, so they stop counting towards
+    /// the cooldown and their owner can request another
+    pub async fn fail_stale_exports(&self) -> Result<u64, ExportServiceError> {
+        let stale_before = Utc::now().naive_utc() - chrono::Duration::minutes(STALE_EXPORT_MINUTES);
+
+        let failed = DataExportEntity::update_many()
+            .col_expr(
+                data_export::Column::Status,
+                Expr::value(ExportStatus::Failed.as_str()),
+            )
+            .filter(data_export::Column::Status.eq(ExportStatus::Pending.as_str()))
+            .filter(data_export::Column::CreatedAt.lt(stale_before))
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(failed.rows_affected)
+    }
+
+    /// Queues an export, `run_export` builds it
+    pub async fn request_export(
+        &self,
+        user_id: i64,
+    ) -> Result<DataExportReturn, ExportServiceError> {
+        self.purge_expired().await?;
+
+        let now = Utc::now().naive_utc();
+        let recent = DataExportEntity::find()
+            .filter(data_export::Column::UserId.eq(user_id))
+            .filter(data_export::Column::Status.ne(ExportStatus::Failed.as_str()))
+            .filter(
+                data_export::Column::CreatedAt
+                    .gt(now - chrono::Duration::hours(EXPORT_COOLDOWN_HOURS)),
+            )
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if recent > 0 {
+            return Err(ExportServiceError::ExportRecentlyRequested(
+                AnyhowResponder(anyhow!("User {user_id} requested data exports too quickly")),
+            ));
+        }
+
+        let export = DataExportActiveModel {
+            user_id: ActiveValue::Set(user_id),
+            status: ActiveValue::Set(ExportStatus::Pending.as_str().to_owned()),
+            created_at: ActiveValue::Set(now),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(to_return(export))
+    }
+
+    /// Builds the archive for a pending export and mails its owner once it is ready
+    pub async fn run_export(
+        &self,
+        export_id: i64,
+        mailer: &dyn MailSender,
+    ) -> Result<(), ExportServiceError> {
+        let export = DataExportEntity::find_by_id(export_id)
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(ExportServiceError::ExportNotFound(AnyhowResponder(
+                anyhow!("Export {export_id} not found"),
+            )))?;
+        let user_id = export.user_id;
+        let mut active: DataExportActiveModel = export.into();
+
+        let location = match self.build_archive(user_id).await {
+            Ok(location) => location,
+            Err(e) => {
+                active.status = Set(ExportStatus::Failed.as_str().to_owned());
+                active
+                    .update(&self.db_connection)
+                    .await
+                    .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+                return Err(e);
+            }
+        };
+
+        let now = Utc::now().naive_utc();
+        active.status = Set(ExportStatus::Ready.as_str().to_owned());
+        active.file_location = Set(location.to_str().map(|s| s.to_owned()));
+        active.completed_at = Set(Some(now));
+        active.expires_at = Set(Some(now + chrono::Duration::days(EXPORT_VALIDITY_DAYS)));
+        active
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let user = entity::user::Entity::find_by_id(user_id)
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if let Some(user) = user {
+            let mail = Mail {
+                to: user.email,
+                subject: String::from("Your Tekxchange data export is ready"),
+                body: format!(
+                    "Hi {},\n\nThe export of your account data is ready. Download it from your account settings within {EXPORT_VALIDITY_DAYS} days.\n\n{}/account/exports",
+                    user.username,
+                    frontend_url()
+                ),
+            };
+
+            if let Err(e) = mailer.send(mail).await {
+                tracing::error!(message = "Unable to send data export mail", user_id, error = %e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn build_archive(&self, user_id: i64) -> Result<PathBuf, ExportServiceError> {
+        let db = &self.db_connection;
+        let internal = |e: DbErr| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e)));
+
+        let user = entity::user::Entity::find_by_id(user_id)
+            .one(db)
+            .await
+            .map_err(internal)?
+            .ok_or(ExportServiceError::ExportNotFound(AnyhowResponder(
+                anyhow!("User {user_id} no longer exists"),
+            )))?;
+        let products = entity::product::Entity::find()
+            .filter(entity::product::Column::CreatedBy.eq(user_id))
+            .order_by_asc(entity::product::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let audits = entity::product_audit::Entity::find()
+            .filter(
+                entity::product_audit::Column::ProductId
+                    .is_in(products.iter().map(|product| product.id)),
+            )
+            .order_by_asc(entity::product_audit::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let files = entity::file::Entity::find()
+            .filter(entity::file::Column::CreatedBy.eq(user_id))
+            .order_by_asc(entity::file::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let sessions = entity::refresh_token::Entity::find()
+            .filter(entity::refresh_token::Column::UserId.eq(user_id))
+            .filter(entity::refresh_token::Column::RotatedAt.is_null())
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let product_ids: Vec<i64> = products.iter().map(|product| product.id).collect();
+        let mut categories: HashMap<i64, Vec<String>> = HashMap::new();
+        for (row, category) in entity::product_category::Entity::find()
+            .filter(entity::product_category::Column::ProductId.is_in(product_ids.clone()))
+            .order_by_asc(entity::product_category::Column::PriorityIndex)
+            .find_also_related(entity::category::Entity)
+            .all(db)
+            .await
+            .map_err(internal)?
+        {
+            if let Some(category) = category {
+                categories
+                    .entry(row.product_id)
+                    .or_default()
+                    .push(category.category_name);
+            }
+        }
+        let mut attributes: HashMap<i64, BTreeMap<String, AttributeValue>> = HashMap::new();
+        for (value, attribute) in entity::product_attribute::Entity::find()
+            .filter(entity::product_attribute::Column::ProductId.is_in(product_ids.clone()))
+            .find_also_related(entity::category_attribute::Entity)
+            .all(db)
+            .await
+            .map_err(internal)?
+        {
+            let product_id = value.product_id;
+            let value = match (value.boolean_value, value.integer_value, value.text_value) {
+                (Some(value), _, _) => AttributeValue::Boolean(value),
+                (_, Some(value), _) => AttributeValue::Integer(value),
+                (_, _, Some(value)) => AttributeValue::Text(value),
+                _ => continue,
+            };
+            if let Some(attribute) = attribute {
+                attributes
+                    .entry(product_id)
+                    .or_default()
+                    .insert(attribute.name, value);
+            }
+        }
+        let mut revisions: HashMap<i64, Vec<ProductRevisionReturn>> = HashMap::new();
+        for revision in entity::product_revision::Entity::find()
+            .filter(entity::product_revision::Column::ProductId.is_in(product_ids.clone()))
+            .order_by_asc(entity::product_revision::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?
+        {
+            revisions
+                .entry(revision.product_id)
+                .or_default()
+                .push(ProductRevisionReturn {
+                    changes: serde_json::from_str(&revision.changes).unwrap_or_default(),
+                    id: revision.id,
+                    edited_by: revision.edited_by,
+                    created_at: revision.created_at,
+                });
+        }
+        let mut prices: HashMap<i64, Vec<PriceChangeReturn>> = HashMap::new();
+        for price in entity::product_price::Entity::find()
+            .filter(entity::product_price::Column::ProductId.is_in(product_ids))
+            .order_by_asc(entity::product_price::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?
+        {
+            prices
+                .entry(price.product_id)
+                .or_default()
+                .push(PriceChangeReturn {
+                    price: price.price,
+                    created_at: price.created_at,
+                });
+        }
+        let preferences = entity::user_preference::Entity::find()
+            .filter(entity::user_preference::Column::UserId.eq(user_id))
+            .one(db)
+            .await
+            .map_err(internal)?;
+        let reviews_written = entity::seller_review::Entity::find()
+            .filter(entity::seller_review::Column::ReviewerId.eq(user_id))
+            .order_by_asc(entity::seller_review::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let reviews_received = entity::seller_review::Entity::find()
+            .filter(entity::seller_review::Column::SellerId.eq(user_id))
+            .order_by_asc(entity::seller_review::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let identities = entity::user_identity::Entity::find()
+            .filter(entity::user_identity::Column::UserId.eq(user_id))
+            .order_by_asc(entity::user_identity::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let access_tokens = entity::personal_access_token::Entity::find()
+            .filter(entity::personal_access_token::Column::UserId.eq(user_id))
+            .order_by_asc(entity::personal_access_token::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let lockouts = entity::login_lockout::Entity::find()
+            .filter(entity::login_lockout::Column::UserId.eq(user_id))
+            .order_by_asc(entity::login_lockout::Column::Id)
+            .all(db)
+            .await
+            .map_err(internal)?;
+
+        let exported_user = ExportedUser {
+            id: user.id,
+            role: Role::try_from(user.role).unwrap_or(Role::USER),
+            username: user.username,
+            email: user.email,
+            bio: user.bio,
+            avatar_file_id: user.avatar_file_id,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            verified_at: user.verified_at,
+            two_factor_enabled_at: user.totp_enabled_at,
+            username_changed_at: user.username_changed_at,
+            suspended_at: user.suspended_at,
+            suspended_until: user.suspended_until,
+            suspension_reason: user.suspension_reason,
+            banned: user.banned,
+            deletion_requested_at: user.deletion_requested_at,
+        };
+        let exported_products: Vec<ExportedProduct> = products
+            .into_iter()
+            .map(|product| ExportedProduct {
+                id: product.id,
+                title: product.product_title,
+                description: product.description,
+                price: product.price,
+                country: product.location_country,
+                state: product.location_state,
+                city: product.location_city,
+                zip: product.location_zip,
+                latitude: product.location_latitude,
+                longitude: product.location_longitude,
+                created_at: product.created_at,
+                updated_at: product.updated_at,
+                status: product.status,
+                published_at: product.published_at,
+                expires_at: product.expires_at,
+                sold_at: product.sold_at,
+                categories: categories.remove(&product.id).unwrap_or_default(),
+                attributes: attributes.remove(&product.id).unwrap_or_default(),
+                revisions: revisions.remove(&product.id).unwrap_or_default(),
+                price_history: prices.remove(&product.id).unwrap_or_default(),
+            })
+            .collect();
+        let exported_audits: Vec<ExportedProductAudit> = audits
+            .into_iter()
+            .map(|audit| ExportedProductAudit {
+                id: audit.id,
+                product_id: audit.product_id,
+                review_status: audit.review_status,
+                review_text: audit.review_text,
+                created_at: audit.created_at,
+                updated_at: audit.updated_at,
+            })
+            .collect();
+        let exported_sessions: Vec<ExportedSession> = sessions
+            .into_iter()
+            .map(|session| ExportedSession {
+                device_name: session.device_name,
+                user_agent: session.user_agent,
+                ip_address: session.ip_address,
+                created_at: session.created_at,
+                last_used_at: session.last_used_at,
+                expires_at: session.expires_at,
+            })
+            .collect();
+        let exported_preferences = preferences.map(|preferences| ExportedPreferences {
+            distance_unit: preferences.distance_unit,
+            search_radius: preferences.search_radius,
+            show_member_since: preferences.show_member_since,
+            show_listing_count: preferences.show_listing_count,
+            show_sales_count: preferences.show_sales_count,
+            show_rating: preferences.show_rating,
+            show_listings: preferences.show_listings,
+            updated_at: preferences.updated_at,
+        });
+        let exported_reviews = ExportedReviews {
+            written: reviews_written
+                .into_iter()
+                .map(to_exported_review)
+                .collect(),
+            received: reviews_received
+                .into_iter()
+                .map(to_exported_review)
+                .collect(),
+        };
+        let exported_identities: Vec<ExportedIdentity> = identities
+            .into_iter()
+            .map(|identity| ExportedIdentity {
+                provider: identity.provider,
+                subject: identity.subject,
+                email: identity.email,
+                created_at: identity.created_at,
+                last_login_at: identity.last_login_at,
+            })
+            .collect();
+        let exported_access_tokens: Vec<PersonalAccessTokenReturn> = access_tokens
+            .into_iter()
+            .map(|token| PersonalAccessTokenReturn {
+                id: token.id,
+                name: token.name,
+                token_prefix: token.token_prefix,
+                scopes: Scope::parse_list(&token.scopes).unwrap_or_default(),
+                created_at: token.created_at,
+                last_used_at: token.last_used_at,
+                expires_at: token.expires_at,
+            })
+            .collect();
+        let exported_lockouts: Vec<ExportedLoginLockout> = lockouts
+            .into_iter()
+            .map(|lockout| ExportedLoginLockout {
+                ip_address: lockout.ip_address,
+                failures: lockout.failures,
+                locked_until: lockout.locked_until,
+                created_at: lockout.created_at,
+            })
+            .collect();
+
+        let mut exported_files = Vec::new();
+        let mut copies = Vec::new();
+        for file in files {
+            let source = PathBuf::from(file.file_location);
+            let path = match source.extension().and_then(|ext| ext.to_str()) {
+                Some(ext) => format!("files/{}.{ext}", file.id),
+                None => format!("files/{}", file.id),
+            };
+
+            exported_files.push(ExportedFile {
+                id: file.id,
+                path: path.clone(),
+                created_at: file.created_at,
+            });
+            copies.push((path, source));
+        }
+
+        let documents = vec![
+            (String::from("user.json"), to_json(&exported_user)?),
+            (String::from("products.json"), to_json(&exported_products)?),
+            (
+                String::from("product_audits.json"),
+                to_json(&exported_audits)?,
+            ),
+            (String::from("files.json"), to_json(&exported_files)?),
+            (String::from("sessions.json"), to_json(&exported_sessions)?),
+            (
+                String::from("preferences.json"),
+                to_json(&exported_preferences)?,
+            ),
+            (String::from("reviews.json"), to_json(&exported_reviews)?),
+            (
+                String::from("identities.json"),
+                to_json(&exported_identities)?,
+            ),
+            (
+                String::from("access_tokens.json"),
+                to_json(&exported_access_tokens)?,
+            ),
+            (
+                String::from("login_lockouts.json"),
+                to_json(&exported_lockouts)?,
+            ),
+        ];
+
+        let location = self
+            .base_file_path
+            .join(format!("export-{}.zip", uuid::Uuid::new_v4()));
+        let archive_location = location.clone();
+
+        rocket::tokio::task::spawn_blocking(move || {
+            write_archive(&archive_location, documents, copies)
+        })
+        .await
+        .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+        .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(e)))?;
+
+        Ok(location)
+    }
+
+    pub async fn list_exports(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<DataExportReturn>, ExportServiceError> {
+        Ok(DataExportEntity::find()
+            .filter(data_export::Column::UserId.eq(user_id))
+            .order_by_desc(data_export::Column::CreatedAt)
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .into_iter()
+            .map(to_return)
+            .collect())
+    }
+
+    /// Opens a finished archive, only for the user it belongs to and until it expires
+    pub async fn download_export(
+        &self,
+        user_id: i64,
+        export_id: i64,
+    ) -> Result<FileResponder, ExportServiceError> {
+        let export = self.find_export(user_id, export_id).await?;
+        let now = Utc::now().naive_utc();
+
+        let location = match (export.status.as_str(), export.file_location) {
+            (status, Some(location))
+                if status == ExportStatus::Ready.as_str()
+                    && export.expires_at.is_some_and(|expires_at| expires_at > now) =>
+            {
+                location
+            }
+            _ => {
+                return Err(ExportServiceError::ExportNotReady(AnyhowResponder(
+                    anyhow!("Export {export_id} is not ready or has expired"),
+                )))
+            }
+        };
+
+        let file = OpenOptions::new()
+            .read(true)
+            .open(location)
+            .map_err(|e| ExportServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(FileResponder {
+            file,
+            content_type: ContentType::ZIP,
+        })
+    }
+}
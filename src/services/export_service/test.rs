@@ -0,0 +1,347 @@
+use super::*;
+use crate::{
+    db::test::establish_connection, mail::test::CapturingMailSender, models::user::UserRegister,
+    services::UserService,
+};
+use rust_decimal::Decimal;
+use std::io::Read;
+
+type E = Result<(), Box<dyn std::error::Error>>;
+
+async fn setup() -> Result<(ExportService, i64, PathBuf), Box<dyn std::error::Error>> {
+    let db = establish_connection().await?;
+    let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir)?;
+
+    let user_id = UserService::new(db.clone())
+        .create_user(
+            UserRegister {
+                username: "test".into(),
+                email: "test@test.com".into(),
+                password: "password".into(),
+            },
+            false,
+        )
+        .await?;
+
+    Ok((ExportService::new(db, dir.clone()), user_id, dir))
+}
+
+fn read_entry(location: &str, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(location)?)?;
+    let mut contents = String::new();
+    archive.by_name(name)?.read_to_string(&mut contents)?;
+
+    Ok(contents)
+}
+
+#[tokio::test]
+async fn builds_an_archive_without_credentials() -> E {
+    let (export_service, user_id, dir) = setup().await?;
+    let mailer = CapturingMailSender::default();
+    let picture = dir.join("picture.png");
+    std::fs::write(&picture, b"picture")?;
+    let entity::file::Model { id: file_id, .. } = entity::file::ActiveModel {
+        created_by: ActiveValue::Set(user_id),
+        file_location: ActiveValue::Set(picture.to_str().unwrap().to_owned()),
+        ..Default::default()
+    }
+    .insert(&export_service.db_connection)
+    .await?;
+
+    let export = export_service.request_export(user_id).await?;
+    assert_eq!(export.status, ExportStatus::Pending);
+    export_service.run_export(export.id, &mailer).await?;
+
+    let stored = export_service.find_export(user_id, export.id).await?;
+    assert_eq!(stored.status, ExportStatus::Ready.as_str());
+    assert!(stored.expires_at.is_some());
+    assert_eq!(mailer.sent()[0].to, "test@test.com");
+
+    let location = stored.file_location.unwrap();
+    let user: serde_json::Value = serde_json::from_str(&read_entry(&location, "user.json")?)?;
+    assert_eq!(user["username"], "test");
+    assert!(user.get("password").is_none());
+    for document in ["products.json", "product_audits.json", "sessions.json"] {
+        read_entry(&location, document)?;
+    }
+    assert_eq!(
+        read_entry(&location, &format!("files/{file_id}.png"))?,
+        "picture"
+    );
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn includes_everything_stored_about_the_user() -> E {
+    let (export_service, user_id, dir) = setup().await?;
+    let db = export_service.db_connection.clone();
+    let now = chrono::Utc::now().naive_utc();
+    let other_id = UserService::new(db.clone())
+        .create_user(
+            UserRegister {
+                username: "other".into(),
+                email: "other@test.com".into(),
+                password: "password".into(),
+            },
+            false,
+        )
+        .await?;
+
+    entity::user::ActiveModel {
+        id: ActiveValue::Unchanged(user_id),
+        deletion_requested_at: ActiveValue::Set(Some(now)),
+        ..Default::default()
+    }
+    .update(&db)
+    .await?;
+    let product = entity::product::ActiveModel {
+        product_title: ActiveValue::Set("laptop".into()),
+        description: ActiveValue::Set("description".into()),
+        price: ActiveValue::Set(Decimal::new(5, 0)),
+        location_country: ActiveValue::Set("country".into()),
+        location_state: ActiveValue::Set("state".into()),
+        location_city: ActiveValue::Set("city".into()),
+        location_zip: ActiveValue::Set("zip".into()),
+        created_by: ActiveValue::Set(user_id),
+        created_at: ActiveValue::Set(now),
+        updated_at: ActiveValue::Set(now),
+        published_at: ActiveValue::Set(Some(now)),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    let category = entity::category::ActiveModel {
+        category_name: ActiveValue::Set("laptops".into()),
+        created_at: ActiveValue::Set(now),
+        updated_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    entity::product_category::ActiveModel {
+        product_id: ActiveValue::Set(product.id),
+        category_id: ActiveValue::Set(category.id),
+        priority_index: ActiveValue::Set(0),
+        created_at: ActiveValue::Set(now),
+        updated_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    let attribute = entity::category_attribute::ActiveModel {
+        category_id: ActiveValue::Set(category.id),
+        name: ActiveValue::Set("ram".into()),
+        kind: ActiveValue::Set("integer".into()),
+        required: ActiveValue::Set(false),
+        created_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    entity::product_attribute::ActiveModel {
+        product_id: ActiveValue::Set(product.id),
+        attribute_id: ActiveValue::Set(attribute.id),
+        integer_value: ActiveValue::Set(Some(16)),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    entity::product_revision::ActiveModel {
+        product_id: ActiveValue::Set(product.id),
+        edited_by: ActiveValue::Set(Some(user_id)),
+        changes: ActiveValue::Set(r#"{"title":{"from":"lap","to":"laptop"}}"#.into()),
+        created_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    entity::product_price::ActiveModel {
+        product_id: ActiveValue::Set(product.id),
+        price: ActiveValue::Set(Decimal::new(5, 0)),
+        created_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    entity::user_preference::ActiveModel {
+        user_id: ActiveValue::Set(user_id),
+        distance_unit: ActiveValue::Set(Some("kilometers".into())),
+        search_radius: ActiveValue::Set(Some(Decimal::new(25, 0))),
+        updated_at: ActiveValue::Set(now),
+        show_member_since: ActiveValue::Set(true),
+        show_listing_count: ActiveValue::Set(true),
+        show_sales_count: ActiveValue::Set(false),
+        show_rating: ActiveValue::Set(true),
+        show_listings: ActiveValue::Set(true),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    for (seller_id, reviewer_id) in [(other_id, user_id), (user_id, other_id)] {
+        entity::seller_review::ActiveModel {
+            seller_id: ActiveValue::Set(seller_id),
+            reviewer_id: ActiveValue::Set(Some(reviewer_id)),
+            rating: ActiveValue::Set(5),
+            created_at: ActiveValue::Set(now),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await?;
+    }
+    entity::user_identity::ActiveModel {
+        user_id: ActiveValue::Set(user_id),
+        provider: ActiveValue::Set("google".into()),
+        subject: ActiveValue::Set("subject".into()),
+        created_at: ActiveValue::Set(now),
+        last_login_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    entity::personal_access_token::ActiveModel {
+        user_id: ActiveValue::Set(user_id),
+        name: ActiveValue::Set("scripts".into()),
+        token_hash: ActiveValue::Set("hash".into()),
+        token_prefix: ActiveValue::Set("tkx_abcd".into()),
+        scopes: ActiveValue::Set("read".into()),
+        created_at: ActiveValue::Set(now),
+        last_used_at: ActiveValue::Set(Some(now)),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    entity::login_lockout::ActiveModel {
+        user_id: ActiveValue::Set(Some(user_id)),
+        ip_address: ActiveValue::Set(Some("127.0.0.1".into())),
+        failures: ActiveValue::Set(10),
+        locked_until: ActiveValue::Set(now),
+        created_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+
+    let export = export_service.request_export(user_id).await?;
+    export_service
+        .run_export(export.id, &CapturingMailSender::default())
+        .await?;
+    let location = export_service
+        .find_export(user_id, export.id)
+        .await?
+        .file_location
+        .unwrap();
+    let document = |name: &str| -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&read_entry(&location, name)?)?)
+    };
+
+    assert!(!document("user.json")?["deletionRequestedAt"].is_null());
+    let products = document("products.json")?;
+    assert_eq!(products[0]["categories"], serde_json::json!(["laptops"]));
+    assert_eq!(products[0]["attributes"]["ram"], 16);
+    assert!(!products[0]["publishedAt"].is_null());
+    assert_eq!(
+        products[0]["revisions"][0]["changes"]["title"]["to"],
+        "laptop"
+    );
+    assert_eq!(products[0]["priceHistory"].as_array().unwrap().len(), 1);
+    let preferences = document("preferences.json")?;
+    assert_eq!(preferences["distanceUnit"], "kilometers");
+    assert_eq!(preferences["showSalesCount"], false);
+    let reviews = document("reviews.json")?;
+    assert_eq!(reviews["written"][0]["sellerId"], other_id);
+    assert_eq!(reviews["received"][0]["reviewerId"], other_id);
+    assert_eq!(document("identities.json")?[0]["provider"], "google");
+    let tokens = document("access_tokens.json")?;
+    assert_eq!(tokens[0]["name"], "scripts");
+    assert_eq!(tokens[0]["scopes"], serde_json::json!(["read"]));
+    assert!(tokens[0].get("tokenHash").is_none());
+    assert_eq!(document("login_lockouts.json")?[0]["failures"], 10);
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn downloads_are_limited_to_the_owner() -> E {
+    let (export_service, user_id, dir) = setup().await?;
+    let mailer = CapturingMailSender::default();
+
+    let export = export_service.request_export(user_id).await?;
+    let pending = export_service.download_export(user_id, export.id).await;
+    assert!(matches!(
+        pending,
+        Err(ExportServiceError::ExportNotReady(_))
+    ));
+
+    export_service.run_export(export.id, &mailer).await?;
+    export_service.download_export(user_id, export.id).await?;
+    let other = export_service.download_export(user_id + 1, export.id).await;
+    assert!(matches!(other, Err(ExportServiceError::ExportNotFound(_))));
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn exports_are_throttled_and_expire() -> E {
+    let (export_service, user_id, dir) = setup().await?;
+    let mailer = CapturingMailSender::default();
+
+    let export = export_service.request_export(user_id).await?;
+    let again = export_service.request_export(user_id).await;
+    assert!(matches!(
+        again,
+        Err(ExportServiceError::ExportRecentlyRequested(_))
+    ));
+
+    export_service.run_export(export.id, &mailer).await?;
+    let stored = export_service.find_export(user_id, export.id).await?;
+    let location = stored.file_location.clone().unwrap();
+    let past = Utc::now().naive_utc() - chrono::Duration::days(EXPORT_VALIDITY_DAYS + 1);
+    DataExportActiveModel {
+        created_at: Set(past),
+        expires_at: Set(Some(past)),
+        ..stored.into()
+    }
+    .update(&export_service.db_connection)
+    .await?;
+
+    export_service.request_export(user_id).await?;
+    assert!(!Path::new(&location).exists());
+    assert_eq!(export_service.list_exports(user_id).await?.len(), 1);
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn interrupted_exports_are_failed() -> E {
+    let (export_service, user_id, dir) = setup().await?;
+
+    let export = export_service.request_export(user_id).await?;
+    assert_eq!(export_service.fail_stale_exports().await?, 0);
+
+    let stored = export_service.find_export(user_id, export.id).await?;
+    DataExportActiveModel {
+        created_at: Set(Utc::now().naive_utc() - chrono::Duration::hours(2)),
+        ..stored.into()
+    }
+    .update(&export_service.db_connection)
+    .await?;
+    assert_eq!(export_service.fail_stale_exports().await?, 1);
+
+    let failed = export_service.find_export(user_id, export.id).await?;
+    assert_eq!(failed.status, ExportStatus::Failed.as_str());
+    export_service.request_export(user_id).await?;
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
@@ -1,4 +1,8 @@
-use crate::{dtos::product::FileResponder, models::user::AuthUser, AnyhowResponder};
+use crate::{
+    dtos::product::FileResponder,
+    models::{file::FileOwner, permission::Permission, user::AuthUser},
+    AnyhowResponder,
+};
 use anyhow::anyhow;
 use rocket::{
     fs::TempFile,
@@ -14,8 +18,11 @@ use std::{
 };
 use thiserror::Error;
 
+#[cfg(test)]
+mod test;
+
 const MAX_PICTURES_NON_PREMIUM: u16 = 5;
-const _MAX_PICTURES_PREMIUM: u16 = 20;
+const MAX_PICTURES_PREMIUM: u16 = 20;
 
 #[derive(Responder, Error, Debug)]
 pub enum FileServiceError {
@@ -37,6 +44,12 @@ pub enum FileServiceError {
     #[error("Query failed")]
     #[response(status = 500)]
     OrmError(AnyhowResponder),
+    #[error("Verify your email address before uploading files")]
+    #[response(status = 403)]
+    Unverified(AnyhowResponder),
+    #[error("Only images can be used as an avatar")]
+    #[response(status = 400)]
+    NotAnImage(AnyhowResponder),
 }
 
 #[async_trait]
@@ -68,11 +81,16 @@ impl FileService {
     }
 
     pub async fn delete_files(&self, ids: &[i64], user: AuthUser) -> Result<(), FileServiceError> {
+        self.delete_owned_files(ids, user.user.id).await
+    }
+
+    /// Removes the files `user_id` created from disk and the database
+    async fn delete_owned_files(&self, ids: &[i64], user_id: i64) -> Result<(), FileServiceError> {
         let files: Vec<_> = ids
             .iter()
             .map(|id| async move {
                 entity::file::Entity::find_by_id(*id)
-                    .filter(entity::file::Column::CreatedBy.eq(user.user.id))
+                    .filter(entity::file::Column::CreatedBy.eq(user_id))
                     .one(&self.db)
                     .await
             })
@@ -87,7 +105,7 @@ impl FileService {
                 let location = PathBuf::from(file.file_location);
                 let _ = std::fs::remove_file(location);
                 let _ = entity::file::Entity::delete_by_id(file.id)
-                    .filter(entity::file::Column::CreatedBy.eq(user.user.id))
+                    .filter(entity::file::Column::CreatedBy.eq(user_id))
                     .exec(&self.db)
                     .await;
             }
@@ -100,8 +118,21 @@ impl FileService {
         &self,
         user: AuthUser,
         mut data: TempFile<'a>,
-        for_product: i64,
+        owner: FileOwner,
     ) -> Result<i64, FileServiceError> {
+        let verified = entity::user::Entity::find_by_id(user.user.id)
+            .filter(entity::user::Column::VerifiedAt.is_not_null())
+            .count(&self.db)
+            .await
+            .map_err(|e| FileServiceError::OrmError(AnyhowResponder(anyhow!(e))))?;
+
+        if verified == 0 {
+            return Err(FileServiceError::Unverified(AnyhowResponder(anyhow!(
+                "Unverified user {} attempted to upload a file",
+                user.user.id
+            ))));
+        }
+
         let extension = data
             .content_type()
             .map(|c| {
@@ -113,6 +144,18 @@ impl FileService {
             .ok_or(FileServiceError::Unknown(crate::AnyhowResponder(anyhow!(
                 "Recieved an unknown file extension"
             ))))??;
+
+        if owner == FileOwner::Avatar && data.content_type().is_none_or(|c| c.top() != "image") {
+            return Err(FileServiceError::NotAnImage(AnyhowResponder(anyhow!(
+                "User {} uploaded an avatar that is not an image",
+                user.user.id
+            ))));
+        }
+
+        if let FileOwner::Product(for_product) = owner {
+            self.check_product_pictures(&user, for_product).await?;
+        }
+
         let file_location: PathBuf;
         loop {
             let new_key = uuid::Uuid::new_v4().to_string();
@@ -123,6 +166,48 @@ impl FileService {
             }
         }
 
+        let path_str = file_location
+            .to_str()
+            .ok_or(FileServiceError::Unknown(AnyhowResponder(anyhow!(
+                "Unable to convert Path to String"
+            ))))?;
+
+        data.persist_to(&file_location)
+            .await
+            .map_err(|e| FileServiceError::FileCreationError(AnyhowResponder(anyhow!(e))))?;
+
+        let entity::file::Model { id: file_id, .. } = entity::file::ActiveModel {
+            created_by: ActiveValue::Set(user.user.id),
+            file_location: ActiveValue::Set(path_str.to_owned()),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await
+        .map_err(|e| FileServiceError::FileCreationError(AnyhowResponder(anyhow!(e))))?;
+
+        match owner {
+            FileOwner::Product(product_id) => {
+                entity::product_picture::ActiveModel {
+                    product_id: ActiveValue::Set(product_id),
+                    file_id: ActiveValue::Set(file_id),
+                    ..Default::default()
+                }
+                .insert(&self.db)
+                .await
+                .map_err(|e| FileServiceError::FileCreationError(AnyhowResponder(anyhow!(e))))?;
+            }
+            FileOwner::Avatar => self.set_avatar(user.user.id, Some(file_id)).await?,
+        }
+
+        Ok(file_id)
+    }
+
+    /// Only the owner can add pictures to a product, up to the picture limit of their tier
+    async fn check_product_pictures(
+        &self,
+        user: &AuthUser,
+        for_product: i64,
+    ) -> Result<(), FileServiceError> {
         let entity::product::Model {
             created_by,
             id: product_id,
@@ -147,41 +232,46 @@ impl FileService {
             .await
             .map_err(|e| FileServiceError::Unknown(AnyhowResponder(anyhow!(e))))?;
 
-        if current_pic_count >= MAX_PICTURES_NON_PREMIUM as u64 {
+        let max_pictures = match user.user.has_permission(Permission::PremiumListings) {
+            true => MAX_PICTURES_PREMIUM,
+            false => MAX_PICTURES_NON_PREMIUM,
+        };
+        if current_pic_count >= max_pictures as u64 {
             return Err(FileServiceError::TooManyPictures(AnyhowResponder(anyhow!(
                 "User already has {current_pic_count} pictures for product. Unable to add any new pictures."
             ))));
         }
 
-        let path_str = file_location
-            .to_str()
+        Ok(())
+    }
+
+    /// Points the user's avatar at `file_id` and cleans up the one it replaces
+    async fn set_avatar(&self, user_id: i64, file_id: Option<i64>) -> Result<(), FileServiceError> {
+        let user = entity::user::Entity::find_by_id(user_id)
+            .one(&self.db)
+            .await
+            .map_err(|e| FileServiceError::OrmError(AnyhowResponder(anyhow!(e))))?
             .ok_or(FileServiceError::Unknown(AnyhowResponder(anyhow!(
-                "Unable to convert Path to String"
+                "User with id {user_id} not found"
             ))))?;
+        let previous = user.avatar_file_id;
 
-        data.persist_to(&file_location)
+        let mut active_user: entity::user::ActiveModel = user.into();
+        active_user.avatar_file_id = ActiveValue::Set(file_id);
+        active_user
+            .update(&self.db)
             .await
-            .map_err(|e| FileServiceError::FileCreationError(AnyhowResponder(anyhow!(e))))?;
+            .map_err(|e| FileServiceError::OrmError(AnyhowResponder(anyhow!(e))))?;
 
-        let entity::file::Model { id: file_id, .. } = entity::file::ActiveModel {
-            created_by: ActiveValue::Set(user.user.id),
-            file_location: ActiveValue::Set(path_str.to_owned()),
-            ..Default::default()
+        if let Some(previous) = previous.filter(|previous| Some(*previous) != file_id) {
+            self.delete_owned_files(&[previous], user_id).await?;
         }
-        .insert(&self.db)
-        .await
-        .map_err(|e| FileServiceError::FileCreationError(AnyhowResponder(anyhow!(e))))?;
 
-        entity::product_picture::ActiveModel {
-            product_id: ActiveValue::Set(product_id),
-            file_id: ActiveValue::Set(file_id),
-            ..Default::default()
-        }
-        .insert(&self.db)
-        .await
-        .map_err(|e| FileServiceError::FileCreationError(AnyhowResponder(anyhow!(e))))?;
+        Ok(())
+    }
 
-        Ok(file_id)
+    pub async fn remove_avatar(&self, user: AuthUser) -> Result<(), FileServiceError> {
+        self.set_avatar(user.user.id, None).await
     }
 
     pub async fn get_file_data(&self, id: i64) -> Result<FileResponder, FileServiceError> {
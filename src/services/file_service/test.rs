@@ -0,0 +1,80 @@
+use super::*;
+use crate::{
+    db::test::establish_connection,
+    models::{role::Role, user::UserRegister},
+    services::UserService,
+};
+use std::path::PathBuf;
+
+type E = Result<(), Box<dyn std::error::Error>>;
+
+async fn stored_file(
+    db: &DatabaseConnection,
+    user_id: i64,
+    dir: &Path,
+    name: &str,
+) -> Result<(i64, PathBuf), Box<dyn std::error::Error>> {
+    let location = dir.join(name);
+    std::fs::write(&location, b"avatar")?;
+
+    let entity::file::Model { id, .. } = entity::file::ActiveModel {
+        created_by: ActiveValue::Set(user_id),
+        file_location: ActiveValue::Set(location.to_str().unwrap().to_owned()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok((id, location))
+}
+
+#[tokio::test]
+async fn replacing_an_avatar_removes_the_old_file() -> E {
+    let db = establish_connection().await?;
+    let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir)?;
+    let file_service = FileService::new(db.clone(), dir.clone());
+    let user_service = UserService::new(db.clone());
+    let user_id = user_service
+        .create_user(
+            UserRegister {
+                username: "test".into(),
+                email: "test@test.com".into(),
+                password: "password".into(),
+            },
+            false,
+        )
+        .await?;
+
+    let (first, first_location) = stored_file(&db, user_id, &dir, "first.png").await?;
+    let (second, second_location) = stored_file(&db, user_id, &dir, "second.png").await?;
+
+    file_service.set_avatar(user_id, Some(first)).await?;
+    file_service.set_avatar(user_id, Some(second)).await?;
+
+    let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+    assert_eq!(user.avatar_file_id, Some(second));
+    assert!(!first_location.exists());
+    assert!(entity::file::Entity::find_by_id(first)
+        .one(&db)
+        .await?
+        .is_none());
+
+    file_service
+        .remove_avatar(AuthUser {
+            user: crate::models::user::UserJwtDto {
+                id: user_id,
+                username: user.username,
+                role: Role::USER,
+            },
+        })
+        .await?;
+
+    let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+    assert!(user.avatar_file_id.is_none());
+    assert!(!second_location.exists());
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
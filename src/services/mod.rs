@@ -1,9 +1,23 @@
+mod admin_service;
 mod auth_service;
+mod blocklist_service;
+mod category_service;
+mod export_service;
 mod file_service;
+mod oidc_service;
 mod product_service;
+mod profile_service;
 mod user_service;
 
-pub use auth_service::{AuthService, AuthServiceError};
+pub use admin_service::{AdminService, AdminServiceError};
+pub use auth_service::{
+    AuthService, AuthServiceError, KeyEncryptionKey, KeyRing, REFRESH_TOKEN_VALIDITY_DAYS,
+};
+pub use blocklist_service::{BlocklistService, BlocklistServiceError};
+pub use category_service::{CategoryService, CategoryServiceError};
+pub use export_service::{ExportService, ExportServiceError};
 pub use file_service::{FileService, FileServiceError};
+pub use oidc_service::{HttpOidcTransport, OidcClient, OidcService, OidcServiceError};
 pub use product_service::{ProductService, ProductServiceError};
+pub use profile_service::{ProfileService, ProfileServiceError};
 pub use user_service::{UserService, UserServiceError};
@@ -0,0 +1,585 @@
+use super::{
+    auth_service::{AuthService, AuthServiceError},
+    user_service::{UserService, UserServiceError},
+};
+use crate::{
+    db::RedisRefresh,
+    dtos::auth::{LoginOutcome, TwoFactorChallengeReturn},
+    models::{
+        oidc::{OidcCallback, OidcConfig, OidcProvider},
+        session::SessionMetadata,
+        user::UserRegister,
+    },
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use entity::user_identity::{
+    self, ActiveModel as UserIdentityActiveModel, Entity as UserIdentityEntity,
+};
+use jwt_simple::prelude::*;
+use redis::Client as RedisClient;
+use rocket::{
+    outcome::{try_outcome, IntoOutcome},
+    request::FromRequest,
+    response::Responder,
+    Request, State,
+};
+use sea_orm::{prelude::*, ActiveValue, DatabaseConnection};
+use serde::de::Deserializer;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[cfg(test)]
+mod test;
+mod transport;
+
+pub use transport::{HttpOidcTransport, JsonWebKeySet, OidcTransport, ProviderMetadata};
+
+/// How long a user has to finish signing in with the provider
+const PENDING_LOGIN_SECONDS: usize = 10 * 60;
+const MAX_USERNAME_ATTEMPTS: usize = 5;
+
+fn pending_login_cache_key(state: &str) -> String {
+    format!("oidc:{state}")
+}
+
+/// OIDC transport shared through rocket state
+pub type OidcClient = Arc<dyn OidcTransport>;
+
+#[derive(Error, Debug, Responder)]
+pub enum OidcServiceError {
+    #[error("An unknown error has occurred")]
+    #[response(status = 500)]
+    InternalError(AnyhowResponder),
+    #[error("Unknown sign in provider")]
+    #[response(status = 404)]
+    UnknownProvider(AnyhowResponder),
+    #[error("This sign in attempt is invalid or has expired")]
+    #[response(status = 400)]
+    InvalidState(AnyhowResponder),
+    #[error("The sign in provider could not be reached")]
+    #[response(status = 502)]
+    ProviderError(AnyhowResponder),
+    #[error("The sign in provider returned an invalid identity")]
+    #[response(status = 401)]
+    InvalidIdToken(AnyhowResponder),
+    #[error("An account with this email already exists. Log in with your password first.")]
+    #[response(status = 409)]
+    UnverifiedEmail(AnyhowResponder),
+    #[error(transparent)]
+    UserServiceError(UserServiceError),
+    #[error(transparent)]
+    AuthServiceError(AuthServiceError),
+}
+
+/// What is remembered between sending the user to the provider and their return
+#[derive(Serialize, Deserialize, Debug)]
+struct PendingLogin {
+    provider: String,
+    nonce: String,
+    code_verifier: String,
+}
+
+fn bool_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BoolOrString {
+        Bool(bool),
+        String(String),
+    }
+
+    Ok(match Option::<BoolOrString>::deserialize(deserializer)? {
+        Some(BoolOrString::Bool(value)) => value,
+        Some(BoolOrString::String(value)) => value == "true",
+        None => false,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct IdTokenClaims {
+    email: Option<String>,
+    #[serde(default, deserialize_with = "bool_or_string")]
+    email_verified: bool,
+    preferred_username: Option<String>,
+    name: Option<String>,
+}
+
+fn pkce_challenge(code_verifier: &str) -> Result<String, OidcServiceError> {
+    Base64UrlSafeNoPadding::encode_to_string(Sha256::digest(code_verifier.as_bytes()))
+        .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+}
+
+fn random_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+fn decode_jwk_field(value: &Option<String>) -> Result<Vec<u8>, OidcServiceError> {
+    let value = value
+        .as_ref()
+        .ok_or(OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(
+            "Signing key is missing a component"
+        ))))?;
+
+    Base64UrlSafeNoPadding::decode_to_vec(value, None)
+        .map_err(|e| OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(e))))
+}
+
+/// Lowercase letters, digits and underscores from `raw`, for use as a username
+fn sanitize_username(raw: &str) -> String {
+    raw.chars()
+        .filter_map(|c| match c {
+            'a'..='z' | '0'..='9' | '_' => Some(c),
+            'A'..='Z' => Some(c.to_ascii_lowercase()),
+            '.' | '-' | ' ' => Some('_'),
+            _ => None,
+        })
+        .take(24)
+        .collect()
+}
+
+pub struct OidcService {
+    db: DatabaseConnection,
+    redis: Box<dyn RedisRefresh>,
+    client: OidcClient,
+    config: OidcConfig,
+}
+
+impl core::fmt::Debug for OidcService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OidcService")
+            .field("db", &self.db)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for OidcService {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        let db: &State<DatabaseConnection> =
+            try_outcome!(request.guard::<&State<DatabaseConnection>>().await);
+        let redis: &State<RedisClient> = try_outcome!(request.guard::<&State<RedisClient>>().await);
+        let client: &State<OidcClient> = try_outcome!(request.guard::<&State<OidcClient>>().await);
+        let config: &State<OidcConfig> = try_outcome!(request.guard::<&State<OidcConfig>>().await);
+
+        redis
+            .inner()
+            .get_async_connection()
+            .await
+            .ok()
+            .map(|r| {
+                Self::new(
+                    db.inner().clone(),
+                    Box::new(r),
+                    client.inner().clone(),
+                    config.inner().clone(),
+                )
+            })
+            .or_forward(())
+    }
+}
+
+impl OidcService {
+    pub fn new(
+        db: DatabaseConnection,
+        redis: Box<dyn RedisRefresh>,
+        client: OidcClient,
+        config: OidcConfig,
+    ) -> Self {
+        Self {
+            db,
+            redis,
+            client,
+            config,
+        }
+    }
+
+    fn provider(&self, name: &str) -> Result<&OidcProvider, OidcServiceError> {
+        self.config
+            .providers
+            .get(name)
+            .ok_or(OidcServiceError::UnknownProvider(AnyhowResponder(anyhow!(
+                "OIDC provider {name} is not configured"
+            ))))
+    }
+
+    async fn discover(
+        &mut self,
+        provider: &OidcProvider,
+    ) -> Result<ProviderMetadata, OidcServiceError> {
+        let metadata = self
+            .client
+            .discover(&provider.issuer)
+            .await
+            .map_err(|e| OidcServiceError::ProviderError(AnyhowResponder(e)))?;
+
+        if metadata.issuer.trim_end_matches('/') != provider.issuer.trim_end_matches('/') {
+            return Err(OidcServiceError::ProviderError(AnyhowResponder(anyhow!(
+                "Discovery document for {} names issuer {}",
+                provider.issuer,
+                metadata.issuer
+            ))));
+        }
+
+        Ok(metadata)
+    }
+
+    /// Starts a sign in, returning the provider url the user should be sent to
+    pub async fn authorization_url(
+        &mut self,
+        provider_name: &str,
+    ) -> Result<String, OidcServiceError> {
+        let provider = self.provider(provider_name)?.clone();
+        let metadata = self.discover(&provider).await?;
+
+        let state = random_token();
+        let pending = PendingLogin {
+            provider: provider_name.to_owned(),
+            nonce: random_token(),
+            code_verifier: format!("{}{}", random_token(), random_token()),
+        };
+
+        let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| OidcServiceError::ProviderError(AnyhowResponder(anyhow!(e))))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", &provider.redirect_uri)
+            .append_pair("scope", &provider.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &pkce_challenge(&pending.code_verifier)?)
+            .append_pair("code_challenge_method", "S256");
+
+        let value = serde_json::to_string(&pending)
+            .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        self.redis
+            .set_item_with_expiry(
+                &pending_login_cache_key(&state),
+                &value,
+                PENDING_LOGIN_SECONDS,
+            )
+            .await
+            .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(url.to_string())
+    }
+
+    /// Single use: the pending login is removed as soon as it is read
+    async fn take_pending_login(&mut self, state: &str) -> Result<PendingLogin, OidcServiceError> {
+        let key = pending_login_cache_key(state);
+        let value = self
+            .redis
+            .get_item(&key)
+            .await
+            .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(OidcServiceError::InvalidState(AnyhowResponder(anyhow!(
+                "Unknown or expired OIDC state"
+            ))))?;
+
+        self.redis
+            .delete_item(&key)
+            .await
+            .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        serde_json::from_str(&value)
+            .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    fn verify_id_token(
+        &self,
+        id_token: &str,
+        keys: &JsonWebKeySet,
+        provider: &OidcProvider,
+        metadata: &ProviderMetadata,
+        nonce: &str,
+    ) -> Result<JWTClaims<IdTokenClaims>, OidcServiceError> {
+        let token_metadata = Token::decode_metadata(id_token)
+            .map_err(|e| OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(e))))?;
+
+        let key = keys
+            .keys
+            .iter()
+            .find(|key| match token_metadata.key_id() {
+                Some(kid) => key.kid.as_deref() == Some(kid),
+                None => keys.keys.len() == 1,
+            })
+            .ok_or(OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(
+                "No provider key matches the id token"
+            ))))?;
+
+        let options = VerificationOptions {
+            allowed_issuers: Some(HashSet::from_strings(&[&metadata.issuer])),
+            allowed_audiences: Some(HashSet::from_strings(&[&provider.client_id])),
+            required_nonce: Some(nonce.to_owned()),
+            ..Default::default()
+        };
+
+        if key
+            .alg
+            .as_deref()
+            .is_some_and(|alg| alg != token_metadata.algorithm())
+        {
+            return Err(OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(
+                "Id token algorithm does not match the provider key"
+            ))));
+        }
+
+        let claims = match (token_metadata.algorithm(), key.kty.as_str()) {
+            ("RS256", "RSA") => RS256PublicKey::from_components(
+                &decode_jwk_field(&key.n)?,
+                &decode_jwk_field(&key.e)?,
+            )
+            .and_then(|public_key| {
+                public_key.verify_token::<IdTokenClaims>(id_token, Some(options))
+            }),
+            ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+                let mut point = vec![0x04];
+                point.extend(decode_jwk_field(&key.x)?);
+                point.extend(decode_jwk_field(&key.y)?);
+
+                ES256PublicKey::from_bytes(&point).and_then(|public_key| {
+                    public_key.verify_token::<IdTokenClaims>(id_token, Some(options))
+                })
+            }
+            (algorithm, kty) => {
+                return Err(OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(
+                    "Unsupported id token algorithm {algorithm} for {kty} key"
+                ))))
+            }
+        }
+        .map_err(|e| OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(e))))?;
+
+        if claims.subject.is_none() {
+            return Err(OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(
+                "Id token has no subject"
+            ))));
+        }
+
+        Ok(claims)
+    }
+
+    /// Finishes a sign in with the code the provider redirected back with
+    pub async fn complete_login(
+        &mut self,
+        provider_name: &str,
+        callback: OidcCallback,
+        user_service: &UserService,
+        auth_service: &mut AuthService,
+        session: SessionMetadata,
+    ) -> Result<LoginOutcome, OidcServiceError> {
+        let pending = self.take_pending_login(&callback.state).await?;
+        if pending.provider != provider_name {
+            return Err(OidcServiceError::InvalidState(AnyhowResponder(anyhow!(
+                "OIDC state was issued for {} but used with {provider_name}",
+                pending.provider
+            ))));
+        }
+
+        let provider = self.provider(provider_name)?.clone();
+        let metadata = self.discover(&provider).await?;
+
+        let mut form = vec![
+            (
+                String::from("grant_type"),
+                String::from("authorization_code"),
+            ),
+            (String::from("code"), callback.code),
+            (String::from("redirect_uri"), provider.redirect_uri.clone()),
+            (String::from("client_id"), provider.client_id.clone()),
+            (String::from("code_verifier"), pending.code_verifier),
+        ];
+        if let Some(ref secret) = provider.client_secret {
+            form.push((String::from("client_secret"), secret.clone()));
+        }
+
+        let tokens = self
+            .client
+            .exchange_code(&metadata.token_endpoint, form)
+            .await
+            .map_err(|e| OidcServiceError::ProviderError(AnyhowResponder(e)))?;
+        let keys = self
+            .client
+            .fetch_jwks(&metadata.jwks_uri)
+            .await
+            .map_err(|e| OidcServiceError::ProviderError(AnyhowResponder(e)))?;
+
+        let claims = self.verify_id_token(
+            &tokens.id_token,
+            &keys,
+            &provider,
+            &metadata,
+            &pending.nonce,
+        )?;
+        let user_id = self
+            .link_identity(provider_name, &claims, user_service)
+            .await?;
+
+        let user = user_service
+            .get_user_by_id(&user_id)
+            .await
+            .map_err(OidcServiceError::UserServiceError)?
+            .ok_or(OidcServiceError::InternalError(AnyhowResponder(anyhow!(
+                "Linked user {user_id} no longer exists"
+            ))))?;
+
+        let session = session.with_device_name(callback.device_name);
+        if user.totp_enabled_at.is_some() {
+            let challenge = auth_service
+                .generate_two_factor_challenge(user.id, session.device_name)
+                .await
+                .map_err(OidcServiceError::AuthServiceError)?;
+
+            return Ok(LoginOutcome::TwoFactorRequired(TwoFactorChallengeReturn {
+                challenge,
+            }));
+        }
+
+        let login = auth_service
+            .issue_login(&user, &session)
+            .await
+            .map_err(OidcServiceError::AuthServiceError)?;
+
+        Ok(LoginOutcome::Authenticated(login))
+    }
+
+    /// Finds the user behind an external identity, linking it to an existing
+    /// account with the same verified email or creating a new account
+    async fn link_identity(
+        &mut self,
+        provider_name: &str,
+        claims: &JWTClaims<IdTokenClaims>,
+        user_service: &UserService,
+    ) -> Result<i64, OidcServiceError> {
+        let subject = claims.subject.clone().unwrap_or_default();
+        let now = Utc::now().naive_utc();
+
+        let existing = UserIdentityEntity::find()
+            .filter(user_identity::Column::Provider.eq(provider_name))
+            .filter(user_identity::Column::Subject.eq(subject.as_str()))
+            .one(&self.db)
+            .await
+            .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if let Some(identity) = existing {
+            let user_id = identity.user_id;
+            let mut identity: UserIdentityActiveModel = identity.into();
+            identity.last_login_at = ActiveValue::Set(now);
+            identity.email = ActiveValue::Set(claims.custom.email.clone());
+            identity
+                .update(&self.db)
+                .await
+                .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+            return Ok(user_id);
+        }
+
+        let email = claims
+            .custom
+            .email
+            .clone()
+            .ok_or(OidcServiceError::InvalidIdToken(AnyhowResponder(anyhow!(
+                "Provider {provider_name} did not share an email address"
+            ))))?;
+
+        let user_id = match user_service
+            .get_by_email(&email)
+            .await
+            .map_err(OidcServiceError::UserServiceError)?
+        {
+            // Linking on an address the provider hasn't verified would let anyone
+            // take over an account by registering its email with the provider
+            Some(_) if !claims.custom.email_verified => {
+                return Err(OidcServiceError::UnverifiedEmail(AnyhowResponder(anyhow!(
+                    "Refusing to link {provider_name} identity with unverified email"
+                ))))
+            }
+            // Nor can the local account be trusted until it proved it owns the address,
+            // anyone could have registered it with a password ahead of the real owner
+            Some(user) if user.verified_at.is_none() => {
+                return Err(OidcServiceError::UnverifiedEmail(AnyhowResponder(anyhow!(
+                    "Refusing to link {provider_name} identity to unverified user {}",
+                    user.id
+                ))))
+            }
+            Some(user) => user.id,
+            None => {
+                let user_id = self
+                    .create_user(&email, &claims.custom, user_service)
+                    .await?;
+                if claims.custom.email_verified {
+                    user_service
+                        .mark_verified(user_id)
+                        .await
+                        .map_err(OidcServiceError::UserServiceError)?;
+                }
+
+                user_id
+            }
+        };
+
+        UserIdentityActiveModel {
+            user_id: ActiveValue::Set(user_id),
+            provider: ActiveValue::Set(provider_name.to_owned()),
+            subject: ActiveValue::Set(subject),
+            email: ActiveValue::Set(Some(email)),
+            created_at: ActiveValue::Set(now),
+            last_login_at: ActiveValue::Set(now),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await
+        .map_err(|e| OidcServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(user_id)
+    }
+
+    async fn create_user(
+        &mut self,
+        email: &str,
+        claims: &IdTokenClaims,
+        user_service: &UserService,
+    ) -> Result<i64, OidcServiceError> {
+        let mut base = claims
+            .preferred_username
+            .as_deref()
+            .or(claims.name.as_deref())
+            .or(email.split('@').next())
+            .map(sanitize_username)
+            .filter(|name| name.len() >= 3)
+            .unwrap_or_else(|| String::from("user"));
+
+        for attempt in 0..MAX_USERNAME_ATTEMPTS {
+            let username = match attempt {
+                0 => base.clone(),
+                _ => format!("{base}{}", &uuid::Uuid::new_v4().simple().to_string()[..6]),
+            };
+
+            // Accounts created through a provider get a random password; a reset sets a real one
+            let register = UserRegister {
+                username,
+                email: email.to_owned(),
+                password: random_token(),
+            };
+
+            match user_service.create_user(register, false).await {
+                Ok(id) => return Ok(id),
+                Err(UserServiceError::DuplicateUserError(_)) => continue,
+                Err(UserServiceError::ForbiddenWords(_)) => base = String::from("user"),
+                Err(e) => return Err(OidcServiceError::UserServiceError(e)),
+            }
+        }
+
+        Err(OidcServiceError::InternalError(AnyhowResponder(anyhow!(
+            "Unable to find a free username for {email}"
+        ))))
+    }
+}
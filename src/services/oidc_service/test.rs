@@ -0,0 +1,476 @@
+use super::{
+    transport::{JsonWebKey, TokenResponse},
+    *,
+};
+use crate::{
+    db::test::{establish_connection, in_memory_redis},
+    services::{KeyEncryptionKey, KeyRing},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+type E = Result<(), Box<dyn std::error::Error>>;
+
+const ISSUER: &str = "https://issuer.test";
+const CLIENT_ID: &str = "tekxchange";
+const PROVIDER: &str = "test";
+const KEY_ID: &str = "test-key";
+
+/// What the fake provider will put in the next id token it issues
+#[derive(Clone)]
+struct IssuedLogin {
+    subject: String,
+    email: String,
+    email_verified: bool,
+    nonce: String,
+    code_challenge: String,
+}
+
+/// A provider that signs id tokens locally and checks the PKCE verifier the
+/// way a real one would
+struct MockIssuer {
+    key_pair: ES256KeyPair,
+    login: Mutex<Option<IssuedLogin>>,
+}
+
+impl MockIssuer {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            key_pair: ES256KeyPair::generate().with_key_id(KEY_ID),
+            login: Mutex::new(None),
+        })
+    }
+}
+
+#[async_trait]
+impl OidcTransport for MockIssuer {
+    async fn discover(&self, issuer: &str) -> anyhow::Result<ProviderMetadata> {
+        Ok(ProviderMetadata {
+            issuer: issuer.to_owned(),
+            authorization_endpoint: format!("{issuer}/authorize"),
+            token_endpoint: format!("{issuer}/token"),
+            jwks_uri: format!("{issuer}/jwks"),
+        })
+    }
+
+    async fn fetch_jwks(&self, _: &str) -> anyhow::Result<JsonWebKeySet> {
+        let point = self
+            .key_pair
+            .public_key()
+            .public_key()
+            .to_bytes_uncompressed();
+
+        Ok(JsonWebKeySet {
+            keys: vec![JsonWebKey {
+                kty: String::from("EC"),
+                kid: Some(String::from(KEY_ID)),
+                alg: Some(String::from("ES256")),
+                n: None,
+                e: None,
+                crv: Some(String::from("P-256")),
+                x: Some(Base64UrlSafeNoPadding::encode_to_string(&point[1..33])?),
+                y: Some(Base64UrlSafeNoPadding::encode_to_string(&point[33..65])?),
+            }],
+        })
+    }
+
+    async fn exchange_code(
+        &self,
+        _: &str,
+        form: Vec<(String, String)>,
+    ) -> anyhow::Result<TokenResponse> {
+        let login = self
+            .login
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(anyhow!("No login in progress"))?;
+        let verifier = form
+            .iter()
+            .find(|(key, _)| key == "code_verifier")
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default();
+
+        if pkce_challenge(verifier).ok() != Some(login.code_challenge) {
+            return Err(anyhow!("PKCE verification failed"));
+        }
+
+        let claims = Claims::with_custom_claims(
+            serde_json::json!({
+                "email": login.email,
+                "email_verified": login.email_verified,
+            }),
+            jwt_simple::prelude::Duration::from_mins(5),
+        )
+        .with_issuer(ISSUER)
+        .with_audience(CLIENT_ID)
+        .with_subject(login.subject)
+        .with_nonce(login.nonce);
+
+        Ok(TokenResponse {
+            id_token: self.key_pair.sign(claims)?,
+        })
+    }
+}
+
+fn oidc_service(db: &DatabaseConnection, issuer: &Arc<MockIssuer>) -> OidcService {
+    let provider = OidcProvider {
+        issuer: String::from(ISSUER),
+        client_id: String::from(CLIENT_ID),
+        client_secret: Some(String::from("secret")),
+        redirect_uri: String::from("http://localhost:3000/oidc/test/callback"),
+        scopes: vec![String::from("openid"), String::from("email")],
+    };
+
+    OidcService::new(
+        db.clone(),
+        Box::new(in_memory_redis()),
+        issuer.clone(),
+        OidcConfig {
+            providers: HashMap::from([(String::from(PROVIDER), provider)]),
+        },
+    )
+}
+
+async fn auth_service(db: &DatabaseConnection) -> Result<AuthService, Box<dyn std::error::Error>> {
+    Ok(AuthService::new(
+        db.clone(),
+        Box::new(in_memory_redis()),
+        KeyRing::load(db, KeyEncryptionKey::test()).await?,
+    ))
+}
+
+/// Starts a sign in and has the provider authenticate `subject`, returning
+/// what the provider would redirect back with
+async fn sign_in_at_provider(
+    service: &mut OidcService,
+    issuer: &MockIssuer,
+    subject: &str,
+    email: &str,
+    email_verified: bool,
+) -> Result<OidcCallback, Box<dyn std::error::Error>> {
+    let url = reqwest::Url::parse(&service.authorization_url(PROVIDER).await?)?;
+    let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert_eq!(params["client_id"], CLIENT_ID);
+
+    *issuer.login.lock().unwrap() = Some(IssuedLogin {
+        subject: subject.to_owned(),
+        email: email.to_owned(),
+        email_verified,
+        nonce: params["nonce"].clone(),
+        code_challenge: params["code_challenge"].clone(),
+    });
+
+    Ok(OidcCallback {
+        code: String::from("code"),
+        state: params["state"].clone(),
+        device_name: None,
+    })
+}
+
+async fn create_user(db: &DatabaseConnection, username: &str) -> Result<i64, UserServiceError> {
+    UserService::new(db.clone())
+        .create_user(
+            UserRegister {
+                username: username.to_owned(),
+                email: format!("{username}@example.com"),
+                password: String::from("password"),
+            },
+            false,
+        )
+        .await
+}
+
+async fn identity_count(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    UserIdentityEntity::find().count(db).await
+}
+
+#[tokio::test]
+async fn creates_verified_user_for_new_identity() -> E {
+    let db = establish_connection().await?;
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+    let user_service = UserService::new(db.clone());
+    let mut auth_service = auth_service(&db).await?;
+
+    for _ in 0..2 {
+        let callback = sign_in_at_provider(
+            &mut service,
+            &issuer,
+            "subject",
+            "new.person@example.com",
+            true,
+        )
+        .await?;
+        let outcome = service
+            .complete_login(
+                PROVIDER,
+                callback,
+                &user_service,
+                &mut auth_service,
+                SessionMetadata::default(),
+            )
+            .await?;
+        assert!(matches!(outcome, LoginOutcome::Authenticated(_)));
+    }
+
+    let users = entity::user::Entity::find().all(&db).await?;
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].username, "new_person");
+    assert!(users[0].verified_at.is_some());
+    assert_eq!(identity_count(&db).await?, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn links_verified_email_to_existing_user() -> E {
+    let db = establish_connection().await?;
+    let user_id = create_user(&db, "existing").await?;
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+    let user_service = UserService::new(db.clone());
+    user_service.mark_verified(user_id).await?;
+    let mut auth_service = auth_service(&db).await?;
+
+    let callback = sign_in_at_provider(
+        &mut service,
+        &issuer,
+        "subject",
+        "existing@example.com",
+        true,
+    )
+    .await?;
+    service
+        .complete_login(
+            PROVIDER,
+            callback,
+            &user_service,
+            &mut auth_service,
+            SessionMetadata::default(),
+        )
+        .await?;
+
+    let identity = UserIdentityEntity::find().one(&db).await?.unwrap();
+    assert_eq!(identity.user_id, user_id);
+    assert_eq!(entity::user::Entity::find().count(&db).await?, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_to_link_unverified_existing_user() -> E {
+    let db = establish_connection().await?;
+    let user_id = create_user(&db, "existing").await?;
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+    let user_service = UserService::new(db.clone());
+    let mut auth_service = auth_service(&db).await?;
+
+    let callback = sign_in_at_provider(
+        &mut service,
+        &issuer,
+        "subject",
+        "existing@example.com",
+        true,
+    )
+    .await?;
+    let res = service
+        .complete_login(
+            PROVIDER,
+            callback,
+            &user_service,
+            &mut auth_service,
+            SessionMetadata::default(),
+        )
+        .await;
+
+    assert!(matches!(res, Err(OidcServiceError::UnverifiedEmail(_))));
+    assert_eq!(identity_count(&db).await?, 0);
+    let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+    assert!(user.verified_at.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_unverified_email_matching_existing_user() -> E {
+    let db = establish_connection().await?;
+    create_user(&db, "existing").await?;
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+    let user_service = UserService::new(db.clone());
+    let mut auth_service = auth_service(&db).await?;
+
+    let callback = sign_in_at_provider(
+        &mut service,
+        &issuer,
+        "subject",
+        "existing@example.com",
+        false,
+    )
+    .await?;
+    let res = service
+        .complete_login(
+            PROVIDER,
+            callback,
+            &user_service,
+            &mut auth_service,
+            SessionMetadata::default(),
+        )
+        .await;
+
+    assert!(matches!(res, Err(OidcServiceError::UnverifiedEmail(_))));
+    assert_eq!(identity_count(&db).await?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn state_is_single_use() -> E {
+    let db = establish_connection().await?;
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+    let user_service = UserService::new(db.clone());
+    let mut auth_service = auth_service(&db).await?;
+
+    let callback =
+        sign_in_at_provider(&mut service, &issuer, "subject", "person@example.com", true).await?;
+    let replayed = OidcCallback {
+        code: callback.code.clone(),
+        state: callback.state.clone(),
+        device_name: None,
+    };
+    service
+        .complete_login(
+            PROVIDER,
+            callback,
+            &user_service,
+            &mut auth_service,
+            SessionMetadata::default(),
+        )
+        .await?;
+
+    let res = service
+        .complete_login(
+            PROVIDER,
+            replayed,
+            &user_service,
+            &mut auth_service,
+            SessionMetadata::default(),
+        )
+        .await;
+    assert!(matches!(res, Err(OidcServiceError::InvalidState(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_mismatched_nonce() -> E {
+    let db = establish_connection().await?;
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+    let user_service = UserService::new(db.clone());
+    let mut auth_service = auth_service(&db).await?;
+
+    let callback =
+        sign_in_at_provider(&mut service, &issuer, "subject", "person@example.com", true).await?;
+    issuer.login.lock().unwrap().as_mut().unwrap().nonce = String::from("another nonce");
+
+    let res = service
+        .complete_login(
+            PROVIDER,
+            callback,
+            &user_service,
+            &mut auth_service,
+            SessionMetadata::default(),
+        )
+        .await;
+    assert!(matches!(res, Err(OidcServiceError::InvalidIdToken(_))));
+    assert_eq!(identity_count(&db).await?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_code_from_another_sign_in() -> E {
+    let db = establish_connection().await?;
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+    let user_service = UserService::new(db.clone());
+    let mut auth_service = auth_service(&db).await?;
+
+    let callback =
+        sign_in_at_provider(&mut service, &issuer, "subject", "person@example.com", true).await?;
+    // The provider authenticated a different sign in, so our PKCE verifier won't match
+    sign_in_at_provider(&mut service, &issuer, "subject", "person@example.com", true).await?;
+
+    let res = service
+        .complete_login(
+            PROVIDER,
+            callback,
+            &user_service,
+            &mut auth_service,
+            SessionMetadata::default(),
+        )
+        .await;
+    assert!(matches!(res, Err(OidcServiceError::ProviderError(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn two_factor_users_get_a_challenge() -> E {
+    let db = establish_connection().await?;
+    let user_id = create_user(&db, "existing").await?;
+    let mut user: entity::user::ActiveModel = entity::user::Entity::find_by_id(user_id)
+        .one(&db)
+        .await?
+        .unwrap()
+        .into();
+    user.totp_enabled_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+    user.verified_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+    user.update(&db).await?;
+
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+    let user_service = UserService::new(db.clone());
+    let mut auth_service = auth_service(&db).await?;
+
+    let callback = sign_in_at_provider(
+        &mut service,
+        &issuer,
+        "subject",
+        "existing@example.com",
+        true,
+    )
+    .await?;
+    let outcome = service
+        .complete_login(
+            PROVIDER,
+            callback,
+            &user_service,
+            &mut auth_service,
+            SessionMetadata::default(),
+        )
+        .await?;
+
+    assert!(matches!(outcome, LoginOutcome::TwoFactorRequired(_)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_provider_is_not_found() -> E {
+    let db = establish_connection().await?;
+    let issuer = MockIssuer::new();
+    let mut service = oidc_service(&db, &issuer);
+
+    let res = service.authorization_url("unknown").await;
+    assert!(matches!(res, Err(OidcServiceError::UnknownProvider(_))));
+
+    Ok(())
+}
@@ -1,9 +1,17 @@
-use super::FileService;
+use super::{CategoryService, CategoryServiceError, FileService, UserService};
 use crate::{
-    dtos::product::ProductFilter,
+    dtos::{
+        category::CategoryReturn,
+        product::{AttributeFilter, ProductFilter},
+    },
     models::{
-        product::{ProductDetails, ProductLocationReturn, ProductReturn, ProductReturnNoUser},
-        user::{AuthUser, MinUserReturnDto},
+        category::AttributeValue,
+        permission::Permission,
+        product::{
+            ListingConfig, ListingStatus, ProductDetails, ProductLocationReturn, ProductReturn,
+            ProductReturnNoUser,
+        },
+        user::{AccountStatus, AuthUser, MinUserReturnDto, UserJwtDto, UserPreferences},
     },
     AnyhowResponder,
 };
@@ -19,14 +27,85 @@ use rocket::{
 };
 use rust_decimal::prelude::*;
 use sea_orm::{
-    entity::prelude::*, query::Condition, ActiveModelTrait, ActiveValue, DatabaseConnection,
-    QueryOrder, QuerySelect,
+    entity::prelude::*,
+    query::Condition,
+    sea_query::{Query, SelectStatement},
+    ActiveModelTrait, ActiveValue, DatabaseConnection, QueryOrder, QuerySelect,
 };
+use std::collections::BTreeMap;
 use thiserror::Error;
 
+mod expiry;
+mod history;
 #[cfg(test)]
 mod test;
 
+/// Users whose listings are hidden, see `AccountStatus::hides_listings`
+fn hidden_sellers() -> SelectStatement {
+    Query::select()
+        .column(entity::user::Column::Id)
+        .from(entity::user::Entity)
+        .cond_where(
+            Condition::any()
+                .add(
+                    Condition::all()
+                        .add(entity::user::Column::Banned.eq(true))
+                        .add(entity::user::Column::SuspendedAt.is_not_null())
+                        .add(
+                            Condition::any()
+                                .add(entity::user::Column::SuspendedUntil.is_null())
+                                .add(
+                                    entity::user::Column::SuspendedUntil
+                                        .gt(chrono::Utc::now().naive_utc()),
+                                ),
+                        ),
+                )
+                .add(entity::user::Column::DeletedAt.is_not_null()),
+        )
+        .to_owned()
+}
+
+/// Products with a value for the attribute that passes every part of the filter
+fn attribute_matches(filter: AttributeFilter) -> SelectStatement {
+    let mut query = Query::select();
+    query
+        .column(entity::product_attribute::Column::ProductId)
+        .from(entity::product_attribute::Entity)
+        .inner_join(
+            entity::category_attribute::Entity,
+            Expr::col((
+                entity::category_attribute::Entity,
+                entity::category_attribute::Column::Id,
+            ))
+            .equals((
+                entity::product_attribute::Entity,
+                entity::product_attribute::Column::AttributeId,
+            )),
+        )
+        .and_where(entity::category_attribute::Column::Name.eq(filter.name));
+
+    match filter.equals {
+        Some(AttributeValue::Boolean(value)) => {
+            query.and_where(entity::product_attribute::Column::BooleanValue.eq(value));
+        }
+        Some(AttributeValue::Integer(value)) => {
+            query.and_where(entity::product_attribute::Column::IntegerValue.eq(value));
+        }
+        Some(AttributeValue::Text(value)) => {
+            query.and_where(entity::product_attribute::Column::TextValue.eq(value));
+        }
+        None => {}
+    }
+    if let Some(min) = filter.min {
+        query.and_where(entity::product_attribute::Column::IntegerValue.gte(min));
+    }
+    if let Some(max) = filter.max {
+        query.and_where(entity::product_attribute::Column::IntegerValue.lte(max));
+    }
+
+    query.to_owned()
+}
+
 #[derive(Error, Debug, Responder)]
 pub enum ProductServiceError {
     #[error("An unknown error has occurred")]
@@ -38,11 +117,31 @@ pub enum ProductServiceError {
     #[error("You are not authorized to perform changes on this product")]
     #[response(status = 403)]
     NotAllowed(AnyhowResponder),
+    #[error("Verify your email address before creating listings")]
+    #[response(status = 403)]
+    Unverified(AnyhowResponder),
+    #[error("This isn't possible while the listing has its current status")]
+    #[response(status = 400)]
+    InvalidStatus(AnyhowResponder),
+    #[error("This listing was bumped recently, try again later")]
+    #[response(status = 429)]
+    BumpCooldown(AnyhowResponder),
+    #[error("Invalid request")]
+    #[response(status = 400)]
+    InvalidRequest(AnyhowResponder),
+    #[error(transparent)]
+    CategoryServiceError(CategoryServiceError),
+}
+
+/// Listings stored with a status this version doesn't know are treated as removed
+fn status_of(product: &entity::product::Model) -> ListingStatus {
+    ListingStatus::try_from(product.status.as_str()).unwrap_or(ListingStatus::Removed)
 }
 
 #[derive(Debug)]
 pub struct ProductService {
     db_connection: DatabaseConnection,
+    config: ListingConfig,
 }
 
 #[rocket::async_trait]
@@ -52,14 +151,32 @@ impl<'r> FromRequest<'r> for ProductService {
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
         req.rocket()
             .state::<DatabaseConnection>()
-            .map(|db| Self::new(db.clone()))
+            .map(|db| {
+                let config = req
+                    .rocket()
+                    .state::<ListingConfig>()
+                    .cloned()
+                    .unwrap_or_default();
+                Self::with_config(db.clone(), config)
+            })
             .or_forward(())
     }
 }
 
 impl ProductService {
     pub fn new(db: DatabaseConnection) -> Self {
-        Self { db_connection: db }
+        Self::with_config(db, ListingConfig::default())
+    }
+
+    pub fn with_config(db: DatabaseConnection, config: ListingConfig) -> Self {
+        Self {
+            db_connection: db,
+            config,
+        }
+    }
+
+    fn lifetime(&self) -> chrono::Duration {
+        chrono::Duration::days(self.config.lifetime_days)
     }
 
     pub async fn create_new_product(
@@ -67,7 +184,49 @@ impl ProductService {
         create: ProductDetails,
         creating_user: AuthUser,
     ) -> Result<i64, ProductServiceError> {
+        let verified = entity::user::Entity::find_by_id(creating_user.user.id)
+            .filter(entity::user::Column::VerifiedAt.is_not_null())
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if verified == 0 {
+            return Err(ProductServiceError::Unverified(AnyhowResponder(anyhow!(
+                "Unverified user {} attempted to create a product",
+                creating_user.user.id
+            ))));
+        }
+
+        let category_service = CategoryService::new(self.db_connection.clone());
+        if let Some(ref categories) = create.categories {
+            category_service
+                .validate_assignment(categories)
+                .await
+                .map_err(ProductServiceError::CategoryServiceError)?;
+        }
+        let attributes = category_service
+            .resolve_attributes(
+                create.categories.as_deref().unwrap_or_default(),
+                &create.attributes.unwrap_or_default(),
+            )
+            .await
+            .map_err(ProductServiceError::CategoryServiceError)?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let (status, published_at, expires_at) = match create.draft {
+            true => (ListingStatus::Draft, None, None),
+            false => (
+                ListingStatus::Active,
+                Some(now),
+                Some(now + self.lifetime()),
+            ),
+        };
+
         let to_create = ProductActiveModel {
+            status: ActiveValue::Set(status.as_str().to_owned()),
+            published_at: ActiveValue::Set(published_at),
+            ranked_at: ActiveValue::Set(published_at),
+            expires_at: ActiveValue::Set(expires_at),
             price: ActiveValue::Set(create.price),
             description: ActiveValue::Set(create.description),
             product_title: ActiveValue::Set(create.title),
@@ -85,10 +244,129 @@ impl ProductService {
             .insert(&self.db_connection)
             .await
             .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        self.record_price(created.id, created.price).await?;
+
+        if let Some(categories) = create.categories {
+            self.set_categories(created.id, &categories).await?;
+        }
+        self.set_attributes(created.id, &attributes).await?;
 
         Ok(created.id)
     }
 
+    /// Replaces the product's categories, their order is kept in `priority_index`
+    async fn set_categories(
+        &self,
+        product_id: i64,
+        categories: &[i64],
+    ) -> Result<(), ProductServiceError> {
+        entity::product_category::Entity::delete_many()
+            .filter(entity::product_category::Column::ProductId.eq(product_id))
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        for (priority, category_id) in categories.iter().enumerate() {
+            entity::product_category::ActiveModel {
+                product_id: ActiveValue::Set(product_id),
+                category_id: ActiveValue::Set(*category_id),
+                priority_index: ActiveValue::Set(priority as i32),
+                ..Default::default()
+            }
+            .insert(&self.db_connection)
+            .await
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the product's attribute values with ones checked by
+    /// `CategoryService::resolve_attributes`
+    async fn set_attributes(
+        &self,
+        product_id: i64,
+        attributes: &[(i64, AttributeValue)],
+    ) -> Result<(), ProductServiceError> {
+        entity::product_attribute::Entity::delete_many()
+            .filter(entity::product_attribute::Column::ProductId.eq(product_id))
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        for (attribute_id, value) in attributes {
+            let mut row = entity::product_attribute::ActiveModel {
+                product_id: ActiveValue::Set(product_id),
+                attribute_id: ActiveValue::Set(*attribute_id),
+                ..Default::default()
+            };
+            match value {
+                AttributeValue::Boolean(value) => {
+                    row.boolean_value = ActiveValue::Set(Some(*value))
+                }
+                AttributeValue::Integer(value) => {
+                    row.integer_value = ActiveValue::Set(Some(*value))
+                }
+                AttributeValue::Text(value) => {
+                    row.text_value = ActiveValue::Set(Some(value.clone()))
+                }
+            }
+            row.insert(&self.db_connection)
+                .await
+                .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_attributes(
+        &self,
+        product_id: i64,
+    ) -> Result<BTreeMap<String, AttributeValue>, ProductServiceError> {
+        let found = entity::product_attribute::Entity::find()
+            .filter(entity::product_attribute::Column::ProductId.eq(product_id))
+            .find_also_related(entity::category_attribute::Entity)
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(found
+            .into_iter()
+            .filter_map(|(value, attribute)| {
+                let value = match (value.boolean_value, value.integer_value, value.text_value) {
+                    (Some(value), _, _) => AttributeValue::Boolean(value),
+                    (_, Some(value), _) => AttributeValue::Integer(value),
+                    (_, _, Some(value)) => AttributeValue::Text(value),
+                    _ => return None,
+                };
+                Some((attribute?.name, value))
+            })
+            .collect())
+    }
+
+    async fn get_categories(
+        &self,
+        product_id: i64,
+    ) -> Result<Vec<CategoryReturn>, ProductServiceError> {
+        let found = entity::product_category::Entity::find()
+            .filter(entity::product_category::Column::ProductId.eq(product_id))
+            .order_by_asc(entity::product_category::Column::PriorityIndex)
+            .find_also_related(entity::category::Entity)
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(found
+            .into_iter()
+            .filter_map(|(_, category)| category)
+            .map(|category| CategoryReturn {
+                id: category.id,
+                name: category.category_name,
+                parent_id: category.parent_id,
+            })
+            .collect())
+    }
+
     pub async fn get_product_by_id(&self, id: i64) -> Result<ProductReturn, ProductServiceError> {
         let found = ProductEntity::find_by_id(id)
             .find_also_related(entity::user::Entity)
@@ -96,18 +374,29 @@ impl ProductService {
             .await
             .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
 
+        // Listings of banned users are hidden until the ban is lifted
+        let found = found.filter(|(_, user)| {
+            !user
+                .as_ref()
+                .is_some_and(|user| AccountStatus::of(user).hides_listings())
+        });
+
         if let Some((prod, Some(user))) = found {
             let pics = entity::product_picture::Entity::find()
                 .filter(entity::product_picture::Column::ProductId.eq(prod.id))
                 .all(&self.db_connection)
                 .await
                 .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+            let categories = self.get_categories(prod.id).await?;
+            let attributes = self.get_attributes(prod.id).await?;
 
             Ok(ProductReturn {
+                status: status_of(&prod),
                 id: prod.id,
                 title: prod.product_title,
                 description: prod.description,
                 price: prod.price,
+                price_dropped_from: self.price_dropped_from(prod.id).await?,
                 created_by: MinUserReturnDto {
                     id: user.id,
                     username: user.username,
@@ -115,6 +404,12 @@ impl ProductService {
                 latitude: prod.location_latitude,
                 longitude: prod.location_longitude,
                 pictures: pics.into_iter().map(|i| i.id).collect(),
+                categories,
+                attributes,
+                published_at: prod.published_at,
+                expires_at: prod.expires_at,
+                bumped_at: prod.bumped_at,
+                sold_at: prod.sold_at,
             })
         } else {
             Err(ProductServiceError::NotFound(AnyhowResponder(anyhow!(
@@ -123,6 +418,111 @@ impl ProductService {
         }
     }
 
+    /// Like `get_product_by_id`, but drafts and removed listings are only found by their seller
+    /// and moderators
+    pub async fn get_visible_product(
+        &self,
+        id: i64,
+        viewer: Option<&UserJwtDto>,
+    ) -> Result<ProductReturn, ProductServiceError> {
+        let found = self.get_product_by_id(id).await?;
+
+        let visible = found.status.is_public()
+            || viewer.is_some_and(|viewer| {
+                viewer.owns_or_has(found.created_by.id, Permission::ModerateProducts)
+            });
+        if !visible {
+            return Err(ProductServiceError::NotFound(AnyhowResponder(anyhow!(
+                "Product id {id} is {} and hidden from {:?}",
+                found.status.as_str(),
+                viewer.map(|viewer| viewer.id)
+            ))));
+        }
+
+        Ok(found)
+    }
+
+    /// Moves the listing along its lifecycle, see `ListingStatus::can_become`
+    pub async fn change_status(
+        &self,
+        id: i64,
+        next: ListingStatus,
+        user: AuthUser,
+    ) -> Result<(), ProductServiceError> {
+        let found = ProductEntity::find_by_id(id)
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(ProductServiceError::NotFound(AnyhowResponder(anyhow!(
+                "Product id {id} not found"
+            ))))?;
+
+        if !user
+            .user
+            .owns_or_has(found.created_by, Permission::ModerateProducts)
+        {
+            return Err(ProductServiceError::NotAllowed(AnyhowResponder(anyhow!(
+                "User {} does not have privelages to change the status of product {id}",
+                user.user.id
+            ))));
+        }
+
+        let current = status_of(&found);
+        if !current.can_become(next) {
+            return Err(ProductServiceError::InvalidStatus(AnyhowResponder(
+                anyhow!(
+                    "User {} attempted to move product {id} from {} to {}",
+                    user.user.id,
+                    current.as_str(),
+                    next.as_str()
+                ),
+            )));
+        }
+
+        self.set_status(found, next).await
+    }
+
+    /// Stores the new status and the time the listing reached it, without checking the transition
+    async fn set_status(
+        &self,
+        product: entity::product::Model,
+        next: ListingStatus,
+    ) -> Result<(), ProductServiceError> {
+        let now = chrono::Utc::now().naive_utc();
+        let current = status_of(&product);
+
+        let mut active: ProductActiveModel = product.into();
+        active.status = ActiveValue::Set(next.as_str().to_owned());
+        match next {
+            ListingStatus::Active => {
+                if matches!(current, ListingStatus::Draft | ListingStatus::Expired) {
+                    active.published_at = ActiveValue::Set(Some(now));
+                    active.expires_at = ActiveValue::Set(Some(now + self.lifetime()));
+                    active.expiry_notified_at = ActiveValue::Set(None);
+                }
+                // Only the first publication moves a listing up, coming back after expiring
+                // keeps its place
+                if current == ListingStatus::Draft {
+                    active.ranked_at = ActiveValue::Set(Some(now));
+                }
+                active.reserved_at = ActiveValue::Set(None);
+                active.expired_at = ActiveValue::Set(None);
+            }
+            ListingStatus::Reserved => active.reserved_at = ActiveValue::Set(Some(now)),
+            ListingStatus::Sold => active.sold_at = ActiveValue::Set(Some(now)),
+            ListingStatus::Expired => active.expired_at = ActiveValue::Set(Some(now)),
+            ListingStatus::Removed => active.removed_at = ActiveValue::Set(Some(now)),
+            ListingStatus::Draft => {}
+        }
+
+        active
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+
     pub async fn update_product_by_id(
         &self,
         id: i64,
@@ -130,7 +530,10 @@ impl ProductService {
         user: AuthUser,
     ) -> Result<(), ProductServiceError> {
         let db_product = self.get_product_by_id(id).await?;
-        if db_product.created_by.id != user.user.id {
+        if !user
+            .user
+            .owns_or_has(db_product.created_by.id, Permission::ModerateProducts)
+        {
             return Err(ProductServiceError::NotAllowed(AnyhowResponder(anyhow!(
                 format!(
                     "User {0} does not have privelages to update product {1}",
@@ -139,15 +542,55 @@ impl ProductService {
             ))));
         }
 
-        let active_product: entity::product::ActiveModel = ProductEntity::find()
-            .filter(Condition::all().add(entity::product::Column::Id.eq(id)))
+        if !db_product.status.is_editable() {
+            return Err(ProductServiceError::InvalidStatus(AnyhowResponder(
+                anyhow!(
+                    "User {} attempted to edit {} product {id}",
+                    user.user.id,
+                    db_product.status.as_str()
+                ),
+            )));
+        }
+
+        let existing = ProductEntity::find_by_id(id)
             .one(&self.db_connection)
             .await
             .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
             .ok_or(ProductServiceError::NotFound(AnyhowResponder(anyhow!(
                 format!("Product id {id} not found")
-            ))))?
-            .into();
+            ))))?;
+        let changes = history::changes_between(&existing, &db_product, &product);
+
+        let category_service = CategoryService::new(self.db_connection.clone());
+        if let Some(ref categories) = product.categories {
+            category_service
+                .validate_assignment(categories)
+                .await
+                .map_err(ProductServiceError::CategoryServiceError)?;
+        }
+
+        // Attributes are checked again whenever the categories change, kept values have to fit
+        // the new schema too
+        let attributes = if product.categories.is_some() || product.attributes.is_some() {
+            let categories = product.categories.clone().unwrap_or_else(|| {
+                db_product
+                    .categories
+                    .iter()
+                    .map(|category| category.id)
+                    .collect()
+            });
+            let values = product.attributes.unwrap_or(db_product.attributes);
+            Some(
+                category_service
+                    .resolve_attributes(&categories, &values)
+                    .await
+                    .map_err(ProductServiceError::CategoryServiceError)?,
+            )
+        } else {
+            None
+        };
+
+        let active_product: entity::product::ActiveModel = existing.into();
 
         let new_prod = entity::product::ActiveModel {
             description: ActiveValue::Set(product.description),
@@ -167,6 +610,14 @@ impl ProductService {
             .await
             .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
 
+        if let Some(categories) = product.categories {
+            self.set_categories(id, &categories).await?;
+        }
+        if let Some(attributes) = attributes {
+            self.set_attributes(id, &attributes).await?;
+        }
+        self.record_revision(id, user.user.id, changes).await?;
+
         Ok(())
     }
 
@@ -192,7 +643,16 @@ impl ProductService {
 
         let pic_ids = pics.iter().map(|pic| pic.id).collect::<Vec<_>>();
 
-        if product.created_by.clone() != user.user.id {
+        if status_of(product) == ListingStatus::Removed {
+            return Err(ProductServiceError::NotFound(AnyhowResponder(anyhow!(
+                "Product id {id} was already removed"
+            ))));
+        }
+
+        if !user
+            .user
+            .owns_or_has(product.created_by, Permission::ModerateProducts)
+        {
             return Err(ProductServiceError::NotAllowed(AnyhowResponder(anyhow!(
                 format!(
                     "User {0} does not have privelages to update product {1}",
@@ -206,23 +666,40 @@ impl ProductService {
             .await
             .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(e.into())))?;
 
-        entity::product::Entity::delete_by_id(id)
-            .exec(&self.db_connection)
-            .await
-            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        // The listing is only marked as removed, its edit and price history
+        // stays for moderators
+        self.set_status(product.clone(), ListingStatus::Removed)
+            .await?;
 
         Ok(())
     }
 
+    /// Active listings around `filter.coordinate`. A radius or distance unit the filter
+    /// leaves out comes from the viewer's preferences.
     pub async fn search_for_products(
         &self,
         filter: ProductFilter,
+        viewer: Option<&UserJwtDto>,
     ) -> Result<Vec<ProductLocationReturn>, ProductServiceError> {
-        let distance_units = filter.units.clone().unwrap_or(DistanceUnit::Miles);
+        let preferences = match viewer {
+            Some(viewer) => UserService::new(self.db_connection.clone())
+                .get_preferences(viewer.id)
+                .await
+                .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?,
+            None => UserPreferences::default(),
+        };
+        let units = filter.units.clone().or(preferences.distance_unit);
+        let radius = filter.radius.or(preferences.search_radius).ok_or_else(|| {
+            ProductServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "Searches need a radius unless a default one is saved"
+            )))
+        })?;
+
+        let distance_units = units.clone().unwrap_or(DistanceUnit::Miles);
         let bounds = geolocation_utils::CoordinateBoundaries::new(
             filter.coordinate.clone(),
-            filter.radius.to_f64().unwrap(),
-            filter.units.clone(),
+            radius.to_f64().unwrap(),
+            units,
         )
         .ok_or_else(|| {
             ProductServiceError::InternalError(AnyhowResponder(anyhow!(
@@ -235,7 +712,9 @@ impl ProductService {
                 .add(product::Column::LocationLatitude.gte(bounds.min_latitude()))
                 .add(product::Column::LocationLatitude.lte(bounds.max_latitude()))
                 .add(product::Column::LocationLongitude.gte(bounds.min_longitude()))
-                .add(product::Column::LocationLongitude.lte(bounds.max_longitude())),
+                .add(product::Column::LocationLongitude.lte(bounds.max_longitude()))
+                .add(product::Column::Status.eq(ListingStatus::Active.as_str()))
+                .add(product::Column::CreatedBy.not_in_subquery(hidden_sellers())),
         );
 
         if let Some(high) = filter.price_high {
@@ -246,7 +725,23 @@ impl ProductService {
         }
         if let Some(query) = filter.query {
             found = found.filter(
-                Expr::col(entity::product::Column::ProductTitle).ilike(format!("%{query}%")),
+                Condition::any()
+                    .add(
+                        Expr::col(entity::product::Column::ProductTitle)
+                            .ilike(format!("%{query}%")),
+                    )
+                    .add(
+                        product::Column::Id.in_subquery(
+                            Query::select()
+                                .column(entity::product_attribute::Column::ProductId)
+                                .from(entity::product_attribute::Entity)
+                                .and_where(
+                                    Expr::col(entity::product_attribute::Column::TextValue)
+                                        .ilike(format!("%{query}%")),
+                                )
+                                .to_owned(),
+                        ),
+                    ),
             );
         }
         if let Some(zip) = filter.zip {
@@ -258,9 +753,29 @@ impl ProductService {
         if let Some(id_lower) = filter.product_id_lower {
             found = found.filter(product::Column::Id.gte(id_lower));
         }
+        if let Some(category) = filter.category {
+            let categories = CategoryService::new(self.db_connection.clone())
+                .with_descendants(category)
+                .await
+                .map_err(ProductServiceError::CategoryServiceError)?;
+            found = found.filter(
+                product::Column::Id.in_subquery(
+                    Query::select()
+                        .column(entity::product_category::Column::ProductId)
+                        .from(entity::product_category::Entity)
+                        .and_where(entity::product_category::Column::CategoryId.is_in(categories))
+                        .to_owned(),
+                ),
+            );
+        }
+
+        for attribute in filter.attributes.unwrap_or_default() {
+            found = found.filter(product::Column::Id.in_subquery(attribute_matches(attribute)));
+        }
 
         let found = found
             .limit(25)
+            .order_by(product::Column::RankedAt, sea_orm::Order::Desc)
             .order_by(product::Column::Id, sea_orm::Order::Desc)
             .all(&self.db_connection)
             .await
@@ -286,23 +801,41 @@ impl ProductService {
                 let coordinate2 = filter.coordinate.clone();
                 return coordinate2.in_radius(
                     &coordinate1,
-                    filter.radius.to_f64().unwrap(),
+                    radius.to_f64().unwrap(),
                     &distance_units,
                 );
             })
             .collect())
     }
 
+    /// Active listings by default, listings with a status that isn't public can only be listed
+    /// by their seller and moderators
     pub async fn get_products_by_user_id(
         &self,
         user_id: i64,
         limit: Option<u64>,
         lower_limit: Option<i64>,
+        status: Option<ListingStatus>,
+        viewer: Option<&UserJwtDto>,
     ) -> Result<Vec<ProductReturnNoUser>, ProductServiceError> {
         let limit = limit.unwrap_or(10);
+        let status = status.unwrap_or(ListingStatus::Active);
+
+        if !status.is_public()
+            && !viewer
+                .is_some_and(|viewer| viewer.owns_or_has(user_id, Permission::ModerateProducts))
+        {
+            return Err(ProductServiceError::NotAllowed(AnyhowResponder(anyhow!(
+                "{:?} attempted to list {} products of user {user_id}",
+                viewer.map(|viewer| viewer.id),
+                status.as_str()
+            ))));
+        }
 
         let mut query = ProductEntity::find()
             .filter(product::Column::CreatedBy.eq(user_id))
+            .filter(product::Column::Status.eq(status.as_str()))
+            .filter(product::Column::CreatedBy.not_in_subquery(hidden_sellers()))
             .limit(limit)
             .find_with_related(entity::product_picture::Entity)
             .order_by_desc(product::Column::Id);
@@ -319,6 +852,8 @@ impl ProductService {
 
         Ok(found
             .map(|(product, picture)| ProductReturnNoUser {
+                status: status_of(&product),
+                expires_at: product.expires_at,
                 id: product.id,
                 description: product.description,
                 latitude: product.location_latitude,
@@ -0,0 +1,192 @@
+use super::{status_of, ProductService, ProductServiceError};
+use crate::{
+    mail::{frontend_url, Mail, MailSender},
+    models::{permission::Permission, product::ListingStatus, user::AuthUser},
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDateTime, Utc};
+use entity::product::{self, ActiveModel as ProductActiveModel, Entity as ProductEntity};
+use sea_orm::{entity::prelude::*, sea_query::Expr, ActiveValue};
+
+fn internal(e: DbErr) -> ProductServiceError {
+    ProductServiceError::InternalError(AnyhowResponder(anyhow!(e)))
+}
+
+impl ProductService {
+    async fn find_listing(&self, id: i64) -> Result<product::Model, ProductServiceError> {
+        ProductEntity::find_by_id(id)
+            .one(&self.db_connection)
+            .await
+            .map_err(internal)?
+            .ok_or(ProductServiceError::NotFound(AnyhowResponder(anyhow!(
+                "Product id {id} not found"
+            ))))
+    }
+
+    /// Keeps the listing up for another full period, expired listings become active again.
+    /// Returns when it will expire next.
+    pub async fn renew_listing(
+        &self,
+        id: i64,
+        user: AuthUser,
+    ) -> Result<NaiveDateTime, ProductServiceError> {
+        let found = self.find_listing(id).await?;
+
+        if !user
+            .user
+            .owns_or_has(found.created_by, Permission::ModerateProducts)
+        {
+            return Err(ProductServiceError::NotAllowed(AnyhowResponder(anyhow!(
+                "User {} does not have privelages to renew product {id}",
+                user.user.id
+            ))));
+        }
+
+        match status_of(&found) {
+            ListingStatus::Active => {
+                let mut active: ProductActiveModel = found.into();
+                active.expires_at =
+                    ActiveValue::Set(Some(Utc::now().naive_utc() + self.lifetime()));
+                active.expiry_notified_at = ActiveValue::Set(None);
+                active.update(&self.db_connection).await.map_err(internal)?;
+            }
+            ListingStatus::Expired => self.set_status(found, ListingStatus::Active).await?,
+            status => {
+                return Err(ProductServiceError::InvalidStatus(AnyhowResponder(
+                    anyhow!(
+                        "User {} attempted to renew {} product {id}",
+                        user.user.id,
+                        status.as_str()
+                    ),
+                )))
+            }
+        }
+
+        self.find_listing(id)
+            .await?
+            .expires_at
+            .ok_or(ProductServiceError::InternalError(AnyhowResponder(
+                anyhow!("Renewed product {id} has no expiry date"),
+            )))
+    }
+
+    /// Moves an active listing to the top of search results, keeping its id and expiry date.
+    /// Only premium sellers can bump, at most once per `ListingConfig::bump_cooldown_hours`.
+    pub async fn bump_listing(&self, id: i64, user: AuthUser) -> Result<(), ProductServiceError> {
+        let found = self.find_listing(id).await?;
+
+        if found.created_by != user.user.id
+            || !user.user.has_permission(Permission::PremiumListings)
+        {
+            return Err(ProductServiceError::NotAllowed(AnyhowResponder(anyhow!(
+                "User {} can not bump product {id}",
+                user.user.id
+            ))));
+        }
+
+        let status = status_of(&found);
+        if status != ListingStatus::Active {
+            return Err(ProductServiceError::InvalidStatus(AnyhowResponder(
+                anyhow!(
+                    "User {} attempted to bump {} product {id}",
+                    user.user.id,
+                    status.as_str()
+                ),
+            )));
+        }
+
+        let now = Utc::now().naive_utc();
+        let cooldown = chrono::Duration::hours(self.config.bump_cooldown_hours);
+        if let Some(bumped_at) = found
+            .bumped_at
+            .filter(|bumped_at| *bumped_at + cooldown > now)
+        {
+            return Err(ProductServiceError::BumpCooldown(AnyhowResponder(anyhow!(
+                "Product {id} was already bumped at {bumped_at}"
+            ))));
+        }
+
+        let mut active: ProductActiveModel = found.into();
+        active.bumped_at = ActiveValue::Set(Some(now));
+        active.ranked_at = ActiveValue::Set(Some(now));
+        active.update(&self.db_connection).await.map_err(internal)?;
+
+        Ok(())
+    }
+
+    /// Reminds sellers of listings about to expire and expires the ones that are due.
+    /// Returns how many reminders were sent and how many listings expired.
+    pub async fn process_expirations(
+        &self,
+        mailer: &dyn MailSender,
+    ) -> Result<(usize, usize), ProductServiceError> {
+        let now = Utc::now().naive_utc();
+        let active = product::Column::Status.eq(ListingStatus::Active.as_str());
+
+        // Listings published before expiry existed get a full period from now
+        ProductEntity::update_many()
+            .col_expr(
+                product::Column::ExpiresAt,
+                Expr::value(now + self.lifetime()),
+            )
+            .filter(active.clone())
+            .filter(product::Column::ExpiresAt.is_null())
+            .exec(&self.db_connection)
+            .await
+            .map_err(internal)?;
+
+        let remind_before = now + chrono::Duration::days(self.config.reminder_days);
+        let to_remind = ProductEntity::find()
+            .filter(active.clone())
+            .filter(product::Column::ExpiresAt.gt(now))
+            .filter(product::Column::ExpiresAt.lte(remind_before))
+            .filter(product::Column::ExpiryNotifiedAt.is_null())
+            .find_also_related(entity::user::Entity)
+            .all(&self.db_connection)
+            .await
+            .map_err(internal)?;
+
+        let reminded = to_remind.len();
+        for (listing, seller) in to_remind {
+            let id = listing.id;
+            if let (Some(seller), Some(expires_at)) = (seller, listing.expires_at) {
+                let mail = Mail {
+                    to: seller.email,
+                    subject: format!("Your listing \"{}\" expires soon", listing.product_title),
+                    body: format!(
+                        "Hi {},\n\nYour listing \"{}\" will expire on {}. Renew it to keep it in search results.\n\n{}/products/{id}",
+                        seller.username,
+                        listing.product_title,
+                        expires_at.format("%Y-%m-%d"),
+                        frontend_url()
+                    ),
+                };
+                if let Err(e) = mailer.send(mail).await {
+                    tracing::error!(message = "Unable to send listing expiry mail", product_id = id, error = %e);
+                }
+            }
+
+            let mut notified: ProductActiveModel = listing.into();
+            notified.expiry_notified_at = ActiveValue::Set(Some(now));
+            notified
+                .update(&self.db_connection)
+                .await
+                .map_err(internal)?;
+        }
+
+        let due = ProductEntity::find()
+            .filter(active)
+            .filter(product::Column::ExpiresAt.lte(now))
+            .all(&self.db_connection)
+            .await
+            .map_err(internal)?;
+
+        let expired = due.len();
+        for listing in due {
+            self.set_status(listing, ListingStatus::Expired).await?;
+        }
+
+        Ok((reminded, expired))
+    }
+}
@@ -0,0 +1,220 @@
+use super::{ProductService, ProductServiceError};
+use crate::{
+    dtos::product::{FieldChange, PriceChangeReturn, ProductHistory, ProductRevisionReturn},
+    models::{
+        permission::Permission,
+        product::{ProductDetails, ProductReturn},
+        user::AuthUser,
+    },
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use entity::{product, product_price, product_revision};
+use rust_decimal::Decimal;
+use sea_orm::{entity::prelude::*, ActiveValue, QueryOrder, QuerySelect};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+fn internal(e: DbErr) -> ProductServiceError {
+    ProductServiceError::InternalError(AnyhowResponder(anyhow!(e)))
+}
+
+fn track<T: Serialize + PartialEq>(
+    changes: &mut BTreeMap<String, FieldChange>,
+    field: &str,
+    from: &T,
+    to: &T,
+) {
+    if from != to {
+        changes.insert(
+            field.to_owned(),
+            FieldChange {
+                from: serde_json::to_value(from).unwrap_or_default(),
+                to: serde_json::to_value(to).unwrap_or_default(),
+            },
+        );
+    }
+}
+
+/// What an update changes, keyed by the field names of `ProductDetails`. Categories and
+/// attributes are only compared when the update replaces them.
+pub(super) fn changes_between(
+    before: &product::Model,
+    current: &ProductReturn,
+    after: &ProductDetails,
+) -> BTreeMap<String, FieldChange> {
+    let mut changes = BTreeMap::new();
+
+    track(&mut changes, "title", &before.product_title, &after.title);
+    track(
+        &mut changes,
+        "description",
+        &before.description,
+        &after.description,
+    );
+    track(&mut changes, "price", &before.price, &after.price);
+    track(
+        &mut changes,
+        "country",
+        &before.location_country,
+        &after.country,
+    );
+    track(&mut changes, "state", &before.location_state, &after.state);
+    track(&mut changes, "city", &before.location_city, &after.city);
+    track(&mut changes, "zip", &before.location_zip, &after.zip);
+    track(
+        &mut changes,
+        "latitude",
+        &before.location_latitude,
+        &after.latitude,
+    );
+    track(
+        &mut changes,
+        "longitude",
+        &before.location_longitude,
+        &after.longitude,
+    );
+
+    if let Some(ref categories) = after.categories {
+        let current_categories: Vec<i64> = current
+            .categories
+            .iter()
+            .map(|category| category.id)
+            .collect();
+        track(&mut changes, "categories", &current_categories, categories);
+    }
+    if let Some(ref attributes) = after.attributes {
+        track(&mut changes, "attributes", &current.attributes, attributes);
+    }
+
+    changes
+}
+
+impl ProductService {
+    /// Appends an edit to the revision log, and to the price history when the price changed
+    pub(super) async fn record_revision(
+        &self,
+        product_id: i64,
+        edited_by: i64,
+        changes: BTreeMap<String, FieldChange>,
+    ) -> Result<(), ProductServiceError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(price) = changes.get("price") {
+            let price: Decimal = serde_json::from_value(price.to.clone())
+                .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+            self.record_price(product_id, price).await?;
+        }
+
+        let changes = serde_json::to_string(&changes)
+            .map_err(|e| ProductServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+        product_revision::ActiveModel {
+            product_id: ActiveValue::Set(product_id),
+            edited_by: ActiveValue::Set(Some(edited_by)),
+            changes: ActiveValue::Set(changes),
+            created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(internal)?;
+
+        Ok(())
+    }
+
+    pub(super) async fn record_price(
+        &self,
+        product_id: i64,
+        price: Decimal,
+    ) -> Result<(), ProductServiceError> {
+        product_price::ActiveModel {
+            product_id: ActiveValue::Set(product_id),
+            price: ActiveValue::Set(price),
+            created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(internal)?;
+
+        Ok(())
+    }
+
+    /// The previous price, when the latest price change lowered it
+    pub(super) async fn price_dropped_from(
+        &self,
+        product_id: i64,
+    ) -> Result<Option<Decimal>, ProductServiceError> {
+        let latest = product_price::Entity::find()
+            .filter(product_price::Column::ProductId.eq(product_id))
+            .order_by_desc(product_price::Column::Id)
+            .limit(2)
+            .all(&self.db_connection)
+            .await
+            .map_err(internal)?;
+
+        Ok(match latest.as_slice() {
+            [current, previous] if current.price < previous.price => Some(previous.price),
+            _ => None,
+        })
+    }
+
+    /// Every edit and price the listing had, newest first. Only for its seller and moderators.
+    pub async fn get_history(
+        &self,
+        id: i64,
+        user: AuthUser,
+    ) -> Result<ProductHistory, ProductServiceError> {
+        let found = product::Entity::find_by_id(id)
+            .one(&self.db_connection)
+            .await
+            .map_err(internal)?
+            .ok_or(ProductServiceError::NotFound(AnyhowResponder(anyhow!(
+                "Product id {id} not found"
+            ))))?;
+
+        if !user
+            .user
+            .owns_or_has(found.created_by, Permission::ModerateProducts)
+        {
+            return Err(ProductServiceError::NotAllowed(AnyhowResponder(anyhow!(
+                "User {} does not have privelages to view the history of product {id}",
+                user.user.id
+            ))));
+        }
+
+        let revisions = product_revision::Entity::find()
+            .filter(product_revision::Column::ProductId.eq(id))
+            .order_by_desc(product_revision::Column::Id)
+            .all(&self.db_connection)
+            .await
+            .map_err(internal)?;
+        let prices = product_price::Entity::find()
+            .filter(product_price::Column::ProductId.eq(id))
+            .order_by_desc(product_price::Column::Id)
+            .all(&self.db_connection)
+            .await
+            .map_err(internal)?;
+
+        Ok(ProductHistory {
+            revisions: revisions
+                .into_iter()
+                .map(|revision| ProductRevisionReturn {
+                    changes: serde_json::from_str(&revision.changes).unwrap_or_default(),
+                    id: revision.id,
+                    edited_by: revision.edited_by,
+                    created_at: revision.created_at,
+                })
+                .collect(),
+            prices: prices
+                .into_iter()
+                .map(|price| PriceChangeReturn {
+                    price: price.price,
+                    created_at: price.created_at,
+                })
+                .collect(),
+        })
+    }
+}
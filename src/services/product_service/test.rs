@@ -6,7 +6,7 @@ use crate::models::{
 use crate::{
     db::test::establish_connection,
     models::user::UserRegister,
-    services::{ProductService, UserService},
+    services::{ProductService, ProductServiceError, UserService},
 };
 use entity::user::Model as UserModel;
 use geolocation_utils::Coordinate;
@@ -28,6 +28,7 @@ async fn create_test_user(db: DatabaseConnection, username: &str) -> UserModel {
         )
         .await
         .unwrap();
+    us.mark_verified(id).await.unwrap();
     let user = us.get_user_by_id(&id).await.unwrap().unwrap();
 
     user
@@ -50,6 +51,9 @@ async fn create_test_product(
                 zip: "zip".into(),
                 latitude: Some(Decimal::from_f64(coords.latitude).unwrap()),
                 longitude: Some(Decimal::from_f64(coords.longitude).unwrap()),
+                categories: None,
+                attributes: None,
+                draft: false,
             },
             AuthUser {
                 user: UserJwtDto {
@@ -86,12 +90,15 @@ mod create_new_product {
                     zip: "some zip".into(),
                     latitude: Some(Decimal::new(0, 0)),
                     longitude: Some(Decimal::new(0, 0)),
+                    categories: None,
+                    attributes: None,
+                    draft: false,
                     price: Decimal::new(0, 15),
                 },
                 AuthUser {
                     user: UserJwtDto {
                         id: u.id,
-                        role: Role::User,
+                        role: Role::USER,
                         username: u.username,
                     },
                 },
@@ -119,12 +126,15 @@ mod create_new_product {
                     zip: "zip".into(),
                     latitude: Some(Decimal::from_f64(1.24).unwrap()),
                     longitude: Some(Decimal::from_f64(1.24).unwrap()),
+                    categories: None,
+                    attributes: None,
+                    draft: false,
                 },
                 AuthUser {
                     user: UserJwtDto {
                         id: 12,
                         username: "test".into(),
-                        role: Role::User,
+                        role: Role::USER,
                     },
                 },
             )
@@ -134,10 +144,58 @@ mod create_new_product {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn unsuccessful_creation_unverified_user() -> E {
+        let db = establish_connection().await?;
+        let us = UserService::new(db.clone());
+        let id = us
+            .create_user(
+                UserRegister {
+                    email: "unverified@test.com".into(),
+                    password: "testPass".into(),
+                    username: "unverified".into(),
+                },
+                false,
+            )
+            .await?;
+        let ps = ProductService::new(db);
+
+        let res = ps
+            .create_new_product(
+                ProductDetails {
+                    description: "test".into(),
+                    title: "title".into(),
+                    price: Decimal::new(0, 15),
+                    country: "country".into(),
+                    state: "state".into(),
+                    city: "city".into(),
+                    zip: "zip".into(),
+                    latitude: Some(Decimal::from_f64(1.24).unwrap()),
+                    longitude: Some(Decimal::from_f64(1.24).unwrap()),
+                    categories: None,
+                    attributes: None,
+                    draft: false,
+                },
+                AuthUser {
+                    user: UserJwtDto {
+                        id,
+                        username: "unverified".into(),
+                        role: Role::USER,
+                    },
+                },
+            )
+            .await;
+
+        assert!(matches!(res, Err(ProductServiceError::Unverified(_))));
+
+        Ok(())
+    }
 }
 
 mod search_for_products {
-    use crate::dtos::product::ProductFilter;
+    use crate::{dtos::product::ProductFilter, models::user::UserPreferences};
+    use geolocation_utils::DistanceUnit;
 
     use super::*;
 
@@ -156,20 +214,811 @@ mod search_for_products {
         create_test_product(&product_service, user, Coordinate::new(1.0, 1.25)).await;
 
         let found = product_service
-            .search_for_products(ProductFilter {
-                city: None,
-                query: None,
-                zip: None,
-                coordinate: Coordinate::new(1.0, 1.250003),
-                price_high: None,
-                price_low: None,
-                product_id_lower: None,
-                radius: Decimal::from_f64(1.0).unwrap(),
-                units: None,
-            })
+            .search_for_products(
+                ProductFilter {
+                    city: None,
+                    query: None,
+                    zip: None,
+                    coordinate: Coordinate::new(1.0, 1.250003),
+                    price_high: None,
+                    price_low: None,
+                    product_id_lower: None,
+                    category: None,
+                    attributes: None,
+                    radius: Some(Decimal::from_f64(1.0).unwrap()),
+                    units: None,
+                },
+                None,
+            )
+            .await?;
+        assert_eq!(found.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_saved_preferences() -> E {
+        let db = establish_connection().await?;
+        let user = create_test_user(db.clone(), "testUser").await;
+        let product_service = ProductService::new(db.clone());
+        create_test_product(&product_service, user.clone(), Coordinate::new(1.0, 1.25)).await;
+        // About 11km or 6.9mi away
+        create_test_product(&product_service, user.clone(), Coordinate::new(1.0, 1.35)).await;
+        let filter = ProductFilter {
+            city: None,
+            query: None,
+            zip: None,
+            coordinate: Coordinate::new(1.0, 1.25),
+            price_high: None,
+            price_low: None,
+            product_id_lower: None,
+            category: None,
+            attributes: None,
+            radius: None,
+            units: None,
+        };
+        let viewer = UserJwtDto {
+            id: user.id,
+            username: user.username.clone(),
+            role: Role::USER,
+        };
+
+        let res = product_service
+            .search_for_products(filter.clone(), None)
+            .await;
+        assert!(matches!(res, Err(ProductServiceError::InvalidRequest(_))));
+
+        UserService::new(db)
+            .update_preferences(
+                user.id,
+                UserPreferences {
+                    distance_unit: Some(DistanceUnit::Kilometers),
+                    search_radius: Some(Decimal::from(10)),
+                },
+            )
+            .await?;
+        let found = product_service
+            .search_for_products(filter.clone(), Some(&viewer))
+            .await?;
+        assert_eq!(found.len(), 1);
+
+        // Whatever the filter sets wins over the preferences
+        let found = product_service
+            .search_for_products(
+                ProductFilter {
+                    units: Some(DistanceUnit::Miles),
+                    ..filter
+                },
+                Some(&viewer),
+            )
             .await?;
         assert_eq!(found.len(), 2);
 
         Ok(())
     }
 }
+
+mod update_product_by_id {
+    use super::*;
+
+    fn details(title: &str) -> ProductDetails {
+        ProductDetails {
+            description: "description".into(),
+            title: title.into(),
+            price: Decimal::new(5, 15),
+            country: "country".into(),
+            state: "state".into(),
+            city: "city".into(),
+            zip: "zip".into(),
+            latitude: None,
+            longitude: None,
+            categories: None,
+            attributes: None,
+            draft: false,
+        }
+    }
+
+    fn acting_as(user: &UserModel, role: Role) -> AuthUser {
+        AuthUser {
+            user: UserJwtDto {
+                id: user.id,
+                username: user.username.clone(),
+                role,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_other_users() -> E {
+        let db = establish_connection().await?;
+        let owner = create_test_user(db.clone(), "owner").await;
+        let other = create_test_user(db.clone(), "other").await;
+        let ps = ProductService::new(db);
+        let product = create_test_product(&ps, owner, Coordinate::new(1.0, 1.0)).await;
+
+        let res = ps
+            .update_product_by_id(product.id, details("new"), acting_as(&other, Role::USER))
+            .await;
+
+        assert!(matches!(res, Err(ProductServiceError::NotAllowed(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn allows_moderators() -> E {
+        let db = establish_connection().await?;
+        let owner = create_test_user(db.clone(), "owner").await;
+        let moderator = create_test_user(db.clone(), "reviewer").await;
+        let ps = ProductService::new(db);
+        let product = create_test_product(&ps, owner, Coordinate::new(1.0, 1.0)).await;
+
+        ps.update_product_by_id(
+            product.id,
+            details("moderated"),
+            acting_as(&moderator, Role::USER | Role::MODERATOR),
+        )
+        .await?;
+
+        assert_eq!(ps.get_product_by_id(product.id).await?.title, "moderated");
+
+        Ok(())
+    }
+}
+
+mod banned_users {
+    use super::*;
+    use crate::dtos::product::ProductFilter;
+    use sea_orm::{prelude::*, ActiveValue};
+
+    async fn ban(db: &DatabaseConnection, user: &UserModel) -> E {
+        entity::user::ActiveModel {
+            suspended_at: ActiveValue::Set(Some(chrono::Utc::now().naive_utc())),
+            banned: ActiveValue::Set(true),
+            ..user.clone().into()
+        }
+        .update(db)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn listings_are_hidden() -> E {
+        let db = establish_connection().await?;
+        let user = create_test_user(db.clone(), "testUser").await;
+        let product_service = ProductService::new(db.clone());
+        let coordinate = Coordinate::new(1.0, 1.0);
+        let product = create_test_product(&product_service, user.clone(), coordinate.clone()).await;
+
+        ban(&db, &user).await?;
+
+        let res = product_service.get_product_by_id(product.id).await;
+        assert!(matches!(res, Err(ProductServiceError::NotFound(_))));
+
+        let found = product_service
+            .search_for_products(
+                ProductFilter {
+                    coordinate,
+                    radius: Some(Decimal::from(10)),
+                    units: None,
+                    query: None,
+                    price_low: None,
+                    price_high: None,
+                    city: None,
+                    zip: None,
+                    product_id_lower: None,
+                    category: None,
+                    attributes: None,
+                },
+                None,
+            )
+            .await?;
+        assert!(found.is_empty());
+
+        let by_user = product_service
+            .get_products_by_user_id(user.id, None, None, None, None)
+            .await?;
+        assert!(by_user.is_empty());
+
+        Ok(())
+    }
+}
+
+mod attributes {
+    use super::*;
+    use crate::{
+        dtos::product::{AttributeFilter, ProductFilter},
+        models::category::{
+            AttributeKind, AttributeValue, CategoryAttributeCreate, CategoryDetails,
+        },
+        services::{CategoryService, CategoryServiceError},
+    };
+    use std::collections::BTreeMap;
+
+    fn laptop(category: i64, ram: Option<i64>, condition: &str) -> ProductDetails {
+        let mut attributes = BTreeMap::new();
+        if let Some(ram) = ram {
+            attributes.insert("ram".to_owned(), AttributeValue::Integer(ram));
+        }
+        attributes.insert(
+            "condition".to_owned(),
+            AttributeValue::Text(condition.into()),
+        );
+
+        ProductDetails {
+            description: "description".into(),
+            title: "laptop".into(),
+            price: Decimal::new(5, 15),
+            country: "country".into(),
+            state: "state".into(),
+            city: "city".into(),
+            zip: "zip".into(),
+            latitude: Some(Decimal::from_f64(1.0).unwrap()),
+            longitude: Some(Decimal::from_f64(1.0).unwrap()),
+            categories: Some(vec![category]),
+            attributes: Some(attributes),
+            draft: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn validates_and_filters_by_attributes() -> E {
+        let db = establish_connection().await?;
+        let user = create_test_user(db.clone(), "seller").await;
+        let seller = || AuthUser {
+            user: UserJwtDto {
+                id: user.id,
+                username: user.username.clone(),
+                role: Role::USER,
+            },
+        };
+        let category_service = CategoryService::new(db.clone());
+        let laptops = category_service
+            .create_category(CategoryDetails {
+                name: "Laptops".into(),
+                parent_id: None,
+            })
+            .await?;
+        category_service
+            .add_attribute(
+                laptops.id,
+                CategoryAttributeCreate {
+                    name: "ram".into(),
+                    kind: AttributeKind::Integer,
+                    unit: Some("GB".into()),
+                    options: None,
+                    required: true,
+                },
+            )
+            .await?;
+        category_service
+            .add_attribute(
+                laptops.id,
+                CategoryAttributeCreate {
+                    name: "condition".into(),
+                    kind: AttributeKind::Enum,
+                    unit: None,
+                    options: Some(vec!["new".into(), "used".into()]),
+                    required: false,
+                },
+            )
+            .await?;
+        let ps = ProductService::new(db);
+
+        let missing = ps
+            .create_new_product(laptop(laptops.id, None, "used"), seller())
+            .await;
+        assert!(matches!(
+            missing,
+            Err(ProductServiceError::CategoryServiceError(
+                CategoryServiceError::InvalidAttributes(_)
+            ))
+        ));
+        let not_an_option = ps
+            .create_new_product(laptop(laptops.id, Some(16), "broken"), seller())
+            .await;
+        assert!(matches!(
+            not_an_option,
+            Err(ProductServiceError::CategoryServiceError(
+                CategoryServiceError::InvalidAttributes(_)
+            ))
+        ));
+
+        let big = ps
+            .create_new_product(laptop(laptops.id, Some(16), "used"), seller())
+            .await?;
+        let small = ps
+            .create_new_product(laptop(laptops.id, Some(8), "new"), seller())
+            .await?;
+        assert_eq!(
+            ps.get_product_by_id(big).await?.attributes["ram"],
+            AttributeValue::Integer(16)
+        );
+
+        let search = |attributes| ProductFilter {
+            city: None,
+            query: None,
+            zip: None,
+            coordinate: Coordinate::new(1.0, 1.0),
+            price_high: None,
+            price_low: None,
+            product_id_lower: None,
+            category: None,
+            attributes: Some(attributes),
+            radius: Some(Decimal::from_f64(1.0).unwrap()),
+            units: None,
+        };
+
+        let found = ps
+            .search_for_products(
+                search(vec![AttributeFilter {
+                    name: "ram".into(),
+                    equals: None,
+                    min: Some(12),
+                    max: None,
+                }]),
+                None,
+            )
+            .await?;
+        assert_eq!(found.iter().map(|p| p.id).collect::<Vec<_>>(), vec![big]);
+
+        let found = ps
+            .search_for_products(
+                search(vec![AttributeFilter {
+                    name: "condition".into(),
+                    equals: Some(AttributeValue::Text("new".into())),
+                    min: None,
+                    max: None,
+                }]),
+                None,
+            )
+            .await?;
+        assert_eq!(found.iter().map(|p| p.id).collect::<Vec<_>>(), vec![small]);
+
+        Ok(())
+    }
+}
+
+mod lifecycle {
+    use super::*;
+    use crate::{dtos::product::ProductFilter, models::product::ListingStatus};
+
+    fn acting_as(user: &UserModel) -> AuthUser {
+        AuthUser {
+            user: UserJwtDto {
+                id: user.id,
+                username: user.username.clone(),
+                role: Role::USER,
+            },
+        }
+    }
+
+    async fn search(ps: &ProductService) -> Result<Vec<i64>, ProductServiceError> {
+        Ok(ps
+            .search_for_products(
+                ProductFilter {
+                    coordinate: Coordinate::new(1.0, 1.0),
+                    radius: Some(Decimal::from(10)),
+                    units: None,
+                    query: None,
+                    price_low: None,
+                    price_high: None,
+                    city: None,
+                    zip: None,
+                    product_id_lower: None,
+                    category: None,
+                    attributes: None,
+                },
+                None,
+            )
+            .await?
+            .into_iter()
+            .map(|product| product.id)
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn drafts_are_private_until_published() -> E {
+        let db = establish_connection().await?;
+        let seller = create_test_user(db.clone(), "seller").await;
+        let buyer = create_test_user(db.clone(), "buyer").await;
+        let ps = ProductService::new(db);
+        let id = ps
+            .create_new_product(
+                ProductDetails {
+                    description: "description".into(),
+                    title: "title".into(),
+                    price: Decimal::new(5, 15),
+                    country: "country".into(),
+                    state: "state".into(),
+                    city: "city".into(),
+                    zip: "zip".into(),
+                    latitude: Some(Decimal::from(1)),
+                    longitude: Some(Decimal::from(1)),
+                    categories: None,
+                    attributes: None,
+                    draft: true,
+                },
+                acting_as(&seller),
+            )
+            .await?;
+
+        assert!(search(&ps).await?.is_empty());
+        let hidden = ps
+            .get_visible_product(id, Some(&acting_as(&buyer).user))
+            .await;
+        assert!(matches!(hidden, Err(ProductServiceError::NotFound(_))));
+        let drafts = ps
+            .get_products_by_user_id(seller.id, None, None, Some(ListingStatus::Draft), None)
+            .await;
+        assert!(matches!(drafts, Err(ProductServiceError::NotAllowed(_))));
+        let drafts = ps
+            .get_products_by_user_id(
+                seller.id,
+                None,
+                None,
+                Some(ListingStatus::Draft),
+                Some(&acting_as(&seller).user),
+            )
+            .await?;
+        assert_eq!(drafts.len(), 1);
+
+        ps.change_status(id, ListingStatus::Active, acting_as(&seller))
+            .await?;
+        let published = ps.get_visible_product(id, None).await?;
+        assert_eq!(published.status, ListingStatus::Active);
+        assert!(published.published_at.is_some());
+        assert_eq!(search(&ps).await?, vec![id]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn enforces_transitions() -> E {
+        let db = establish_connection().await?;
+        let seller = create_test_user(db.clone(), "seller").await;
+        let buyer = create_test_user(db.clone(), "buyer").await;
+        let ps = ProductService::new(db);
+        let product = create_test_product(&ps, seller.clone(), Coordinate::new(1.0, 1.0)).await;
+        assert_eq!(product.status, ListingStatus::Active);
+
+        let not_theirs = ps
+            .change_status(product.id, ListingStatus::Sold, acting_as(&buyer))
+            .await;
+        assert!(matches!(
+            not_theirs,
+            Err(ProductServiceError::NotAllowed(_))
+        ));
+        let expire = ps
+            .change_status(product.id, ListingStatus::Expired, acting_as(&seller))
+            .await;
+        assert!(matches!(expire, Err(ProductServiceError::InvalidStatus(_))));
+
+        ps.change_status(product.id, ListingStatus::Reserved, acting_as(&seller))
+            .await?;
+        assert!(search(&ps).await?.is_empty());
+        ps.change_status(product.id, ListingStatus::Sold, acting_as(&seller))
+            .await?;
+
+        let sold = ps.get_product_by_id(product.id).await?;
+        assert_eq!(sold.status, ListingStatus::Sold);
+        assert!(sold.sold_at.is_some());
+
+        let relist = ps
+            .change_status(product.id, ListingStatus::Active, acting_as(&seller))
+            .await;
+        assert!(matches!(relist, Err(ProductServiceError::InvalidStatus(_))));
+        let edit = ps
+            .update_product_by_id(
+                product.id,
+                ProductDetails {
+                    description: "description".into(),
+                    title: "changed".into(),
+                    price: Decimal::new(5, 15),
+                    country: "country".into(),
+                    state: "state".into(),
+                    city: "city".into(),
+                    zip: "zip".into(),
+                    latitude: None,
+                    longitude: None,
+                    categories: None,
+                    attributes: None,
+                    draft: false,
+                },
+                acting_as(&seller),
+            )
+            .await;
+        assert!(matches!(edit, Err(ProductServiceError::InvalidStatus(_))));
+
+        let active = ps
+            .get_products_by_user_id(seller.id, None, None, None, None)
+            .await?;
+        assert!(active.is_empty());
+        let sold = ps
+            .get_products_by_user_id(seller.id, None, None, Some(ListingStatus::Sold), None)
+            .await?;
+        assert_eq!(sold.len(), 1);
+
+        Ok(())
+    }
+}
+
+mod expiry {
+    use super::*;
+    use crate::{
+        dtos::product::ProductFilter, mail::test::CapturingMailSender,
+        models::product::ListingStatus,
+    };
+    use sea_orm::{ActiveModelTrait, ActiveValue};
+
+    fn acting_as(user: &UserModel, role: Role) -> AuthUser {
+        AuthUser {
+            user: UserJwtDto {
+                id: user.id,
+                username: user.username.clone(),
+                role,
+            },
+        }
+    }
+
+    async fn expire_in(db: &DatabaseConnection, id: i64, duration: chrono::Duration) -> E {
+        entity::product::ActiveModel {
+            id: ActiveValue::Set(id),
+            expires_at: ActiveValue::Set(Some(chrono::Utc::now().naive_utc() + duration)),
+            ..Default::default()
+        }
+        .update(db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn search(ps: &ProductService) -> Result<Vec<i64>, ProductServiceError> {
+        Ok(ps
+            .search_for_products(
+                ProductFilter {
+                    coordinate: Coordinate::new(1.0, 1.0),
+                    radius: Some(Decimal::from(10)),
+                    units: None,
+                    query: None,
+                    price_low: None,
+                    price_high: None,
+                    city: None,
+                    zip: None,
+                    product_id_lower: None,
+                    category: None,
+                    attributes: None,
+                },
+                None,
+            )
+            .await?
+            .into_iter()
+            .map(|product| product.id)
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn reminds_expires_and_renews() -> E {
+        let db = establish_connection().await?;
+        let seller = create_test_user(db.clone(), "seller").await;
+        let ps = ProductService::new(db.clone());
+        let mailer = CapturingMailSender::default();
+        let product = create_test_product(&ps, seller.clone(), Coordinate::new(1.0, 1.0)).await;
+        assert!(product.expires_at.is_some());
+
+        assert_eq!(ps.process_expirations(&mailer).await?, (0, 0));
+
+        expire_in(&db, product.id, chrono::Duration::days(1)).await?;
+        assert_eq!(ps.process_expirations(&mailer).await?, (1, 0));
+        assert_eq!(ps.process_expirations(&mailer).await?, (0, 0));
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "seller@test.com");
+
+        expire_in(&db, product.id, chrono::Duration::minutes(-1)).await?;
+        assert_eq!(ps.process_expirations(&mailer).await?, (0, 1));
+        assert_eq!(
+            ps.get_product_by_id(product.id).await?.status,
+            ListingStatus::Expired
+        );
+        assert!(search(&ps).await?.is_empty());
+
+        let expires_at = ps
+            .renew_listing(product.id, acting_as(&seller, Role::USER))
+            .await?;
+        assert!(expires_at > chrono::Utc::now().naive_utc() + chrono::Duration::days(29));
+        assert_eq!(search(&ps).await?, vec![product.id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn listing_config_is_validated() {
+        use crate::models::product::ListingConfig;
+        use rocket::figment::{
+            providers::{Format, Toml},
+            Figment,
+        };
+
+        let config = |toml: &str| ListingConfig::from_figment(&Figment::from(Toml::string(toml)));
+
+        assert_eq!(config("").unwrap().lifetime_days, 30);
+        assert_eq!(
+            config("[listings]\nlifetime_days = 14")
+                .unwrap()
+                .lifetime_days,
+            14
+        );
+        assert!(config("[listings]\nlifetime_days = \"month\"").is_err());
+        assert!(config("[listings]\nlifetime_days = 0").is_err());
+        assert!(config("[listings]\nlifetime_days = 3\nreminder_days = 3").is_err());
+    }
+
+    #[tokio::test]
+    async fn premium_sellers_can_bump() -> E {
+        let db = establish_connection().await?;
+        let seller = create_test_user(db.clone(), "seller").await;
+        let ps = ProductService::new(db);
+        let older = create_test_product(&ps, seller.clone(), Coordinate::new(1.0, 1.0)).await;
+        let newer = create_test_product(&ps, seller.clone(), Coordinate::new(1.0, 1.0)).await;
+        assert_eq!(search(&ps).await?, vec![newer.id, older.id]);
+
+        let not_premium = ps
+            .bump_listing(older.id, acting_as(&seller, Role::USER))
+            .await;
+        assert!(matches!(
+            not_premium,
+            Err(ProductServiceError::NotAllowed(_))
+        ));
+
+        ps.bump_listing(older.id, acting_as(&seller, Role::USER | Role::PREMIUM))
+            .await?;
+        assert_eq!(search(&ps).await?, vec![older.id, newer.id]);
+
+        let again = ps
+            .bump_listing(older.id, acting_as(&seller, Role::USER | Role::PREMIUM))
+            .await;
+        assert!(matches!(again, Err(ProductServiceError::BumpCooldown(_))));
+
+        Ok(())
+    }
+}
+
+mod history {
+    use super::*;
+    use crate::services::FileService;
+
+    fn priced(title: &str, price: i64) -> ProductDetails {
+        ProductDetails {
+            description: "description".into(),
+            title: title.into(),
+            price: Decimal::from(price),
+            country: "country".into(),
+            state: "state".into(),
+            city: "city".into(),
+            zip: "zip".into(),
+            latitude: Some(Decimal::from(1)),
+            longitude: Some(Decimal::from(1)),
+            categories: None,
+            attributes: None,
+            draft: false,
+        }
+    }
+
+    fn acting_as(user: &UserModel, role: Role) -> AuthUser {
+        AuthUser {
+            user: UserJwtDto {
+                id: user.id,
+                username: user.username.clone(),
+                role,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn records_edits_and_price_drops() -> E {
+        let db = establish_connection().await?;
+        let seller = create_test_user(db.clone(), "seller").await;
+        let ps = ProductService::new(db);
+        let id = ps
+            .create_new_product(priced("laptop", 500), acting_as(&seller, Role::USER))
+            .await?;
+        assert_eq!(ps.get_product_by_id(id).await?.price_dropped_from, None);
+
+        // Saving without changes leaves no revision
+        ps.update_product_by_id(id, priced("laptop", 500), acting_as(&seller, Role::USER))
+            .await?;
+        ps.update_product_by_id(
+            id,
+            priced("gaming laptop", 400),
+            acting_as(&seller, Role::USER),
+        )
+        .await?;
+
+        let history = ps.get_history(id, acting_as(&seller, Role::USER)).await?;
+        assert_eq!(history.revisions.len(), 1);
+        let revision = &history.revisions[0];
+        assert_eq!(revision.edited_by, Some(seller.id));
+        assert_eq!(
+            revision.changes.keys().collect::<Vec<_>>(),
+            vec!["price", "title"]
+        );
+        assert_eq!(revision.changes["title"].from, "laptop");
+        assert_eq!(revision.changes["title"].to, "gaming laptop");
+        assert_eq!(
+            history
+                .prices
+                .iter()
+                .map(|change| change.price)
+                .collect::<Vec<_>>(),
+            vec![Decimal::from(400), Decimal::from(500)]
+        );
+        assert_eq!(
+            ps.get_product_by_id(id).await?.price_dropped_from,
+            Some(Decimal::from(500))
+        );
+
+        ps.update_product_by_id(
+            id,
+            priced("gaming laptop", 450),
+            acting_as(&seller, Role::USER),
+        )
+        .await?;
+        assert_eq!(ps.get_product_by_id(id).await?.price_dropped_from, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_sellers_and_moderators_see_history() -> E {
+        let db = establish_connection().await?;
+        let seller = create_test_user(db.clone(), "seller").await;
+        let other = create_test_user(db.clone(), "other").await;
+        let ps = ProductService::new(db);
+        let product = create_test_product(&ps, seller, Coordinate::new(1.0, 1.0)).await;
+
+        let res = ps
+            .get_history(product.id, acting_as(&other, Role::USER))
+            .await;
+        assert!(matches!(res, Err(ProductServiceError::NotAllowed(_))));
+
+        let history = ps
+            .get_history(product.id, acting_as(&other, Role::USER | Role::MODERATOR))
+            .await?;
+        assert!(history.revisions.is_empty());
+        assert_eq!(history.prices.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deleting_keeps_the_history() -> E {
+        let db = establish_connection().await?;
+        let seller = create_test_user(db.clone(), "seller").await;
+        let other = create_test_user(db.clone(), "other").await;
+        let ps = ProductService::new(db.clone());
+        let id = ps
+            .create_new_product(priced("laptop", 500), acting_as(&seller, Role::USER))
+            .await?;
+        ps.update_product_by_id(id, priced("laptop", 400), acting_as(&seller, Role::USER))
+            .await?;
+
+        ps.delete_product_by_id(
+            id,
+            acting_as(&seller, Role::USER),
+            FileService::new(db.clone(), std::env::temp_dir()),
+        )
+        .await?;
+
+        let hidden = ps.get_visible_product(id, None).await;
+        assert!(matches!(hidden, Err(ProductServiceError::NotFound(_))));
+        let history = ps
+            .get_history(id, acting_as(&other, Role::USER | Role::MODERATOR))
+            .await?;
+        assert_eq!(history.revisions.len(), 1);
+        assert_eq!(history.prices.len(), 2);
+
+        Ok(())
+    }
+}
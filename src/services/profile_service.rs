@@ -0,0 +1,263 @@
+use super::{ProductService, ProductServiceError, UserService, UserServiceError};
+use crate::{
+    dtos::profile::{PublicProfile, RatingSummary},
+    models::{
+        product::ListingStatus,
+        user::{AccountStatus, SellerReviewCreate, UserJwtDto},
+    },
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use entity::{
+    product::{self, Entity as ProductEntity},
+    seller_review::{self, ActiveModel as SellerReviewActiveModel, Entity as SellerReviewEntity},
+    user::{Entity as UserEntity, Model as UserModel},
+};
+use rocket::{
+    outcome::IntoOutcome,
+    request::{self, FromRequest},
+    response::Responder,
+    Request,
+};
+use sea_orm::{prelude::*, ActiveValue, DatabaseConnection, QuerySelect};
+use thiserror::Error;
+
+#[cfg(test)]
+mod test;
+
+const RECENT_LISTINGS: u64 = 6;
+const MAX_REVIEW_LENGTH: usize = 2000;
+
+#[derive(Error, Debug, Responder)]
+pub enum ProfileServiceError {
+    #[error("An unknown error has occurred")]
+    #[response(status = 500)]
+    InternalError(AnyhowResponder),
+    #[error("User not found")]
+    #[response(status = 404)]
+    UserNotFound(AnyhowResponder),
+    #[error("Invalid request")]
+    #[response(status = 400)]
+    InvalidRequest(AnyhowResponder),
+    #[error("You have already reviewed this seller")]
+    #[response(status = 400)]
+    AlreadyReviewed(AnyhowResponder),
+    #[error("Verify your email address before reviewing sellers")]
+    #[response(status = 403)]
+    Unverified(AnyhowResponder),
+    #[error("Sellers can only be reviewed for a listing that was sold or reserved")]
+    #[response(status = 400)]
+    ListingNotSold(AnyhowResponder),
+    #[error(transparent)]
+    UserServiceError(UserServiceError),
+    #[error(transparent)]
+    ProductServiceError(ProductServiceError),
+}
+
+/// Public seller profiles and the reviews they are rated by
+#[derive(Debug)]
+pub struct ProfileService {
+    db_connection: DatabaseConnection,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ProfileService {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        req.rocket()
+            .state::<DatabaseConnection>()
+            .map(|conn| Self {
+                db_connection: conn.clone(),
+            })
+            .or_forward(())
+    }
+}
+
+impl ProfileService {
+    /// Sellers whose listings are hidden have their profile hidden too
+    async fn find_seller(&self, user_id: i64) -> Result<UserModel, ProfileServiceError> {
+        UserEntity::find_by_id(user_id)
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| ProfileServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .filter(|user| !AccountStatus::of(user).hides_listings())
+            .ok_or(ProfileServiceError::UserNotFound(AnyhowResponder(anyhow!(
+                "Seller {user_id} does not exist, is banned or was deleted"
+            ))))
+    }
+
+    async fn count_listings(
+        &self,
+        user_id: i64,
+        status: ListingStatus,
+    ) -> Result<u64, ProfileServiceError> {
+        ProductEntity::find()
+            .filter(product::Column::CreatedBy.eq(user_id))
+            .filter(product::Column::Status.eq(status.as_str()))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| ProfileServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    pub async fn rating_summary(&self, user_id: i64) -> Result<RatingSummary, ProfileServiceError> {
+        let (total, count) = SellerReviewEntity::find()
+            .select_only()
+            .column_as(seller_review::Column::Rating.sum(), "total")
+            .column_as(seller_review::Column::Id.count(), "count")
+            .filter(seller_review::Column::SellerId.eq(user_id))
+            .into_tuple::<(Option<i64>, i64)>()
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| ProfileServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .unwrap_or((None, 0));
+
+        Ok(RatingSummary {
+            average: total
+                .filter(|_| count > 0)
+                .map(|total| (total as f64 / count as f64 * 100.0).round() / 100.0),
+            count: count as u64,
+        })
+    }
+
+    pub async fn get_public_profile(
+        &self,
+        user_id: i64,
+    ) -> Result<PublicProfile, ProfileServiceError> {
+        let user = self.find_seller(user_id).await?;
+        let privacy = UserService::new(self.db_connection.clone())
+            .get_privacy(user_id)
+            .await
+            .map_err(ProfileServiceError::UserServiceError)?;
+
+        let active_listings = match privacy.show_listing_count {
+            true => Some(self.count_listings(user_id, ListingStatus::Active).await?),
+            false => None,
+        };
+        let completed_sales = match privacy.show_sales_count {
+            true => Some(self.count_listings(user_id, ListingStatus::Sold).await?),
+            false => None,
+        };
+        let rating = match privacy.show_rating {
+            true => Some(self.rating_summary(user_id).await?),
+            false => None,
+        };
+        let recent_listings = match privacy.show_listings {
+            true => Some(
+                ProductService::new(self.db_connection.clone())
+                    .get_products_by_user_id(user_id, Some(RECENT_LISTINGS), None, None, None)
+                    .await
+                    .map_err(ProfileServiceError::ProductServiceError)?,
+            ),
+            false => None,
+        };
+
+        Ok(PublicProfile {
+            id: user.id,
+            username: user.username,
+            bio: user.bio,
+            avatar: user.avatar_file_id,
+            member_since: privacy.show_member_since.then_some(user.created_at),
+            active_listings,
+            completed_sales,
+            rating,
+            recent_listings,
+        })
+    }
+
+    /// Rates a seller from 1 to 5, once per reviewer, for one of their listings that
+    /// was sold or reserved. Reviewers need a verified email like sellers do.
+    pub async fn review_seller(
+        &self,
+        reviewer: &UserJwtDto,
+        seller_id: i64,
+        review: SellerReviewCreate,
+    ) -> Result<(), ProfileServiceError> {
+        if reviewer.id == seller_id {
+            return Err(ProfileServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!("User {seller_id} attempted to review themselves"),
+            )));
+        }
+
+        if !(1..=5).contains(&review.rating) {
+            return Err(ProfileServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!("Ratings must be between 1 and 5"),
+            )));
+        }
+
+        let comment = review
+            .comment
+            .map(|comment| comment.trim().to_owned())
+            .filter(|comment| !comment.is_empty());
+
+        if comment
+            .as_ref()
+            .is_some_and(|comment| comment.chars().count() > MAX_REVIEW_LENGTH)
+        {
+            return Err(ProfileServiceError::InvalidRequest(AnyhowResponder(
+                anyhow!("Review comments must be at most {MAX_REVIEW_LENGTH} characters"),
+            )));
+        }
+
+        let verified = UserEntity::find_by_id(reviewer.id)
+            .filter(entity::user::Column::VerifiedAt.is_not_null())
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| ProfileServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if verified == 0 {
+            return Err(ProfileServiceError::Unverified(AnyhowResponder(anyhow!(
+                "Unverified user {} attempted to review seller {seller_id}",
+                reviewer.id
+            ))));
+        }
+
+        self.find_seller(seller_id).await?;
+
+        // Listings don't record who bought them, so this only ensures there was a sale
+        let listing = ProductEntity::find_by_id(review.product_id)
+            .filter(product::Column::CreatedBy.eq(seller_id))
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| ProfileServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if !listing
+            .is_some_and(|listing| listing.sold_at.is_some() || listing.reserved_at.is_some())
+        {
+            return Err(ProfileServiceError::ListingNotSold(AnyhowResponder(
+                anyhow!(
+                    "Listing {} of seller {seller_id} was never sold or reserved",
+                    review.product_id
+                ),
+            )));
+        }
+
+        let existing = SellerReviewEntity::find()
+            .filter(seller_review::Column::SellerId.eq(seller_id))
+            .filter(seller_review::Column::ReviewerId.eq(reviewer.id))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| ProfileServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if existing > 0 {
+            return Err(ProfileServiceError::AlreadyReviewed(AnyhowResponder(
+                anyhow!("User {} already reviewed seller {seller_id}", reviewer.id),
+            )));
+        }
+
+        SellerReviewActiveModel {
+            seller_id: ActiveValue::Set(seller_id),
+            reviewer_id: ActiveValue::Set(Some(reviewer.id)),
+            rating: ActiveValue::Set(review.rating),
+            comment: ActiveValue::Set(comment),
+            created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            product_id: ActiveValue::Set(Some(review.product_id)),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(|e| ProfileServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+}
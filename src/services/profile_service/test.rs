@@ -0,0 +1,268 @@
+use super::*;
+use crate::{
+    db::test::establish_connection,
+    models::{
+        product::ProductDetails,
+        role::Role,
+        user::{AuthUser, ProfilePrivacy, UserRegister},
+    },
+    services::{ProductService, UserService},
+};
+use sea_orm::Set;
+
+type E = Result<(), Box<dyn std::error::Error>>;
+
+async fn create_user(
+    db: &DatabaseConnection,
+    username: &str,
+) -> Result<UserJwtDto, Box<dyn std::error::Error>> {
+    let user_service = UserService::new(db.clone());
+    let id = user_service
+        .create_user(
+            UserRegister {
+                username: username.into(),
+                email: format!("{username}@test.com"),
+                password: "testPass".into(),
+            },
+            false,
+        )
+        .await?;
+    user_service.mark_verified(id).await?;
+
+    Ok(UserJwtDto {
+        id,
+        username: username.into(),
+        role: Role::USER,
+    })
+}
+
+async fn create_listing(
+    db: &DatabaseConnection,
+    seller: &UserJwtDto,
+    title: &str,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    Ok(ProductService::new(db.clone())
+        .create_new_product(
+            ProductDetails {
+                description: "description".into(),
+                title: title.into(),
+                price: Decimal::from(5),
+                country: "country".into(),
+                state: "state".into(),
+                city: "city".into(),
+                zip: "zip".into(),
+                latitude: None,
+                longitude: None,
+                categories: None,
+                attributes: None,
+                draft: false,
+            },
+            AuthUser {
+                user: seller.clone(),
+            },
+        )
+        .await?)
+}
+
+async fn mark_sold(db: &DatabaseConnection, product_id: i64) -> E {
+    entity::product::ActiveModel {
+        id: Set(product_id),
+        status: Set("sold".into()),
+        sold_at: Set(Some(chrono::Utc::now().naive_utc())),
+        ..Default::default()
+    }
+    .update(db)
+    .await?;
+
+    Ok(())
+}
+
+fn review(product_id: i64, rating: i16) -> SellerReviewCreate {
+    SellerReviewCreate {
+        product_id,
+        rating,
+        comment: Some("Smooth sale".into()),
+    }
+}
+
+#[tokio::test]
+async fn profile_includes_listings_and_stats() -> E {
+    let db = establish_connection().await?;
+    let profile_service = ProfileService {
+        db_connection: db.clone(),
+    };
+    let seller = create_user(&db, "seller").await?;
+    let first = create_user(&db, "buyer").await?;
+    let second = create_user(&db, "otherBuyer").await?;
+    create_listing(&db, &seller, "laptop").await?;
+    let sold = create_listing(&db, &seller, "phone").await?;
+    mark_sold(&db, sold).await?;
+    UserService::new(db.clone())
+        .update_bio(seller.id, Some("  Refurbished laptops  ".into()))
+        .await?;
+
+    profile_service
+        .review_seller(&first, seller.id, review(sold, 5))
+        .await?;
+    profile_service
+        .review_seller(&second, seller.id, review(sold, 4))
+        .await?;
+
+    let profile = profile_service.get_public_profile(seller.id).await?;
+
+    assert_eq!(profile.username, "seller");
+    assert_eq!(profile.bio.as_deref(), Some("Refurbished laptops"));
+    assert!(profile.member_since.is_some());
+    assert_eq!(profile.active_listings, Some(1));
+    assert_eq!(profile.completed_sales, Some(1));
+    assert_eq!(
+        profile.rating,
+        Some(RatingSummary {
+            average: Some(4.5),
+            count: 2
+        })
+    );
+    assert_eq!(profile.recent_listings.map(|l| l.len()), Some(1));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn privacy_settings_hide_fields() -> E {
+    let db = establish_connection().await?;
+    let profile_service = ProfileService {
+        db_connection: db.clone(),
+    };
+    let seller = create_user(&db, "seller").await?;
+    create_listing(&db, &seller, "laptop").await?;
+
+    UserService::new(db.clone())
+        .update_privacy(
+            seller.id,
+            ProfilePrivacy {
+                show_member_since: false,
+                show_rating: false,
+                show_listings: false,
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let profile = profile_service.get_public_profile(seller.id).await?;
+
+    assert!(profile.member_since.is_none());
+    assert!(profile.rating.is_none());
+    assert!(profile.recent_listings.is_none());
+    assert_eq!(profile.active_listings, Some(1));
+    assert_eq!(profile.completed_sales, Some(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reviews_are_validated() -> E {
+    let db = establish_connection().await?;
+    let profile_service = ProfileService {
+        db_connection: db.clone(),
+    };
+    let seller = create_user(&db, "seller").await?;
+    let buyer = create_user(&db, "buyer").await?;
+    let sold = create_listing(&db, &seller, "laptop").await?;
+    mark_sold(&db, sold).await?;
+
+    let own = profile_service
+        .review_seller(&seller, seller.id, review(sold, 5))
+        .await;
+    assert!(matches!(own, Err(ProfileServiceError::InvalidRequest(_))));
+
+    let out_of_range = profile_service
+        .review_seller(&buyer, seller.id, review(sold, 6))
+        .await;
+    assert!(matches!(
+        out_of_range,
+        Err(ProfileServiceError::InvalidRequest(_))
+    ));
+
+    profile_service
+        .review_seller(&buyer, seller.id, review(sold, 3))
+        .await?;
+    let again = profile_service
+        .review_seller(&buyer, seller.id, review(sold, 1))
+        .await;
+    assert!(matches!(
+        again,
+        Err(ProfileServiceError::AlreadyReviewed(_))
+    ));
+
+    let missing = profile_service.get_public_profile(seller.id + 100).await;
+    assert!(matches!(missing, Err(ProfileServiceError::UserNotFound(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reviews_need_a_verified_reviewer_and_a_sale() -> E {
+    let db = establish_connection().await?;
+    let profile_service = ProfileService {
+        db_connection: db.clone(),
+    };
+    let seller = create_user(&db, "seller").await?;
+    let other_seller = create_user(&db, "otherSeller").await?;
+    let buyer = create_user(&db, "buyer").await?;
+    let listed = create_listing(&db, &seller, "laptop").await?;
+    let reserved = create_listing(&db, &seller, "phone").await?;
+    entity::product::ActiveModel {
+        id: Set(reserved),
+        status: Set("reserved".into()),
+        reserved_at: Set(Some(chrono::Utc::now().naive_utc())),
+        ..Default::default()
+    }
+    .update(&db)
+    .await?;
+    let sold_elsewhere = create_listing(&db, &other_seller, "tablet").await?;
+    mark_sold(&db, sold_elsewhere).await?;
+
+    let unsold = profile_service
+        .review_seller(&buyer, seller.id, review(listed, 5))
+        .await;
+    assert!(matches!(
+        unsold,
+        Err(ProfileServiceError::ListingNotSold(_))
+    ));
+    let other_listing = profile_service
+        .review_seller(&buyer, seller.id, review(sold_elsewhere, 5))
+        .await;
+    assert!(matches!(
+        other_listing,
+        Err(ProfileServiceError::ListingNotSold(_))
+    ));
+
+    let unverified_id = UserService::new(db.clone())
+        .create_user(
+            UserRegister {
+                username: "unverified".into(),
+                email: "unverified@test.com".into(),
+                password: "testPass".into(),
+            },
+            false,
+        )
+        .await?;
+    let unverified = UserJwtDto {
+        id: unverified_id,
+        username: "unverified".into(),
+        role: Role::USER,
+    };
+    let res = profile_service
+        .review_seller(&unverified, seller.id, review(reserved, 5))
+        .await;
+    assert!(matches!(res, Err(ProfileServiceError::Unverified(_))));
+
+    profile_service
+        .review_seller(&buyer, seller.id, review(reserved, 5))
+        .await?;
+    let review = SellerReviewEntity::find().one(&db).await?.unwrap();
+    assert_eq!(review.reviewer_id, Some(buyer.id));
+    assert_eq!(review.product_id, Some(reserved));
+
+    Ok(())
+}
@@ -1,17 +1,26 @@
 use super::auth_service::AuthServiceError;
 use crate::{
-    dtos::auth::LoginReturn,
+    dtos::auth::{LoginOutcome, TwoFactorChallengeReturn},
+    mail::{frontend_url, Mail, MailSender},
     models::{
         role::Role,
-        user::{UserLogin, UserRegister},
+        session::SessionMetadata,
+        user::{normalize_identity, UserLogin, UserRegister},
     },
-    services::auth_service::AuthService,
+    services::{auth_service::AuthService, BlocklistService},
     AnyhowResponder,
 };
 use anyhow::anyhow;
-use entity::user::{ActiveModel as UserActiveModel, Entity as UserEntity, Model as UserModel};
-use lazy_static::lazy_static;
-use regex::Regex;
+use chrono::Utc;
+use entity::{
+    email_verification_token::{
+        self, ActiveModel as EmailVerificationActiveModel, Entity as EmailVerificationEntity,
+    },
+    password_reset_token::{
+        self, ActiveModel as PasswordResetActiveModel, Entity as PasswordResetEntity,
+    },
+    user::{ActiveModel as UserActiveModel, Entity as UserEntity, Model as UserModel},
+};
 use rocket::{
     outcome::IntoOutcome,
     request::{self, FromRequest},
@@ -20,13 +29,15 @@ use rocket::{
 };
 use sea_orm::{prelude::*, query::Condition, ActiveValue, DatabaseConnection, Set};
 use thiserror::Error;
+mod deletion;
+mod profile;
 #[cfg(test)]
 mod test;
+mod two_factor;
 
-lazy_static! {
-    static ref INVALID_USERNAME_REGEX: Regex =
-        Regex::new(r"(?i)(admin|moderator|fuck|ass|shit|cunt|piss|wank|twat)").unwrap();
-}
+const PASSWORD_RESET_VALIDITY_MINUTES: i64 = 60;
+const EMAIL_VERIFICATION_VALIDITY_HOURS: i64 = 24;
+const EMAIL_VERIFICATION_RESEND_SECONDS: i64 = 60;
 
 #[derive(Error, Debug, Responder)]
 pub enum UserServiceError {
@@ -42,6 +53,33 @@ pub enum UserServiceError {
     #[error("The request contains forbidden words")]
     #[response(status = 400)]
     ForbiddenWords(AnyhowResponder),
+    #[error("This password reset link is invalid or has expired")]
+    #[response(status = 400)]
+    InvalidResetToken(AnyhowResponder),
+    #[error("This verification link is invalid or has expired")]
+    #[response(status = 400)]
+    InvalidVerificationToken(AnyhowResponder),
+    #[error("This email address is already verified")]
+    #[response(status = 400)]
+    AlreadyVerified(AnyhowResponder),
+    #[error("A verification email was sent recently, please wait before requesting another")]
+    #[response(status = 429)]
+    VerificationRecentlySent(AnyhowResponder),
+    #[error("The username was changed too recently")]
+    #[response(status = 429)]
+    UsernameChangeTooSoon(AnyhowResponder),
+    #[error("The request is invalid")]
+    #[response(status = 400)]
+    InvalidRequest(AnyhowResponder),
+    #[error("Two factor authentication is already enabled")]
+    #[response(status = 400)]
+    TwoFactorAlreadyEnabled(AnyhowResponder),
+    #[error("Two factor authentication is not enabled")]
+    #[response(status = 400)]
+    TwoFactorNotEnabled(AnyhowResponder),
+    #[error("The two factor code is invalid")]
+    #[response(status = 401)]
+    InvalidTwoFactorCode(AnyhowResponder),
     #[error(transparent)]
     AuthServiceError(AuthServiceError),
 }
@@ -77,11 +115,16 @@ impl UserService {
     ) -> Result<i64, UserServiceError> {
         use entity::user;
 
+        register.username = register.username.trim().to_owned();
+        register.email = register.email.trim().to_owned();
+        let username_normalized = normalize_identity(&register.username);
+        let email_normalized = normalize_identity(&register.email);
+
         let found_users = UserEntity::find()
             .filter(
                 Condition::any()
-                    .add(user::Column::Username.like(&register.username))
-                    .add(user::Column::Email.like(&register.email)),
+                    .add(user::Column::UsernameNormalized.eq(username_normalized.as_str()))
+                    .add(user::Column::EmailNormalized.eq(email_normalized.as_str())),
             )
             .count(&self.db_connection)
             .await
@@ -93,7 +136,7 @@ impl UserService {
             )));
         }
 
-        if !bypass_name_check && INVALID_USERNAME_REGEX.is_match(&register.username) {
+        if !bypass_name_check && self.contains_blocked_words(&register.username).await? {
             return Err(UserServiceError::ForbiddenWords(AnyhowResponder(anyhow!(
                 "User attempted signup with a forbidden word in username"
             ))));
@@ -106,6 +149,8 @@ impl UserService {
             email: ActiveValue::Set(register.email),
             password: ActiveValue::Set(register.password),
             username: ActiveValue::Set(register.username),
+            username_normalized: ActiveValue::Set(username_normalized),
+            email_normalized: ActiveValue::Set(email_normalized),
             ..Default::default()
         }
         .insert(&self.db_connection)
@@ -115,10 +160,17 @@ impl UserService {
         Ok(id)
     }
 
-    async fn get_by_email(&self, email: &str) -> Result<Option<UserModel>, UserServiceError> {
+    async fn contains_blocked_words(&self, text: &str) -> Result<bool, UserServiceError> {
+        BlocklistService::new(self.db_connection.clone())
+            .is_blocked(text)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    pub async fn get_by_email(&self, email: &str) -> Result<Option<UserModel>, UserServiceError> {
         use entity::user;
         let found = UserEntity::find()
-            .filter(Condition::all().add(user::Column::Email.like(email)))
+            .filter(user::Column::EmailNormalized.eq(normalize_identity(email)))
             .one(&self.db_connection)
             .await
             .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
@@ -129,7 +181,7 @@ impl UserService {
     async fn get_by_username(&self, username: &str) -> Result<Option<UserModel>, UserServiceError> {
         use entity::user;
         let found = UserEntity::find()
-            .filter(Condition::all().add(user::Column::Username.like(username)))
+            .filter(user::Column::UsernameNormalized.eq(normalize_identity(username)))
             .one(&self.db_connection)
             .await
             .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
@@ -148,7 +200,7 @@ impl UserService {
         use entity::user;
 
         let found_count = UserEntity::find()
-            .filter(Condition::any().add(user::Column::Username.like(username)))
+            .filter(user::Column::UsernameNormalized.eq(normalize_identity(username)))
             .count(&self.db_connection)
             .await
             .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
@@ -160,7 +212,7 @@ impl UserService {
         use entity::user;
 
         let found_count = UserEntity::find()
-            .filter(Condition::any().add(user::Column::Email.like(email)))
+            .filter(user::Column::EmailNormalized.eq(normalize_identity(email)))
             .count(&self.db_connection)
             .await
             .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
@@ -172,7 +224,8 @@ impl UserService {
         &self,
         login: UserLogin,
         mut auth_service: AuthService,
-    ) -> Result<LoginReturn, UserServiceError> {
+        session: SessionMetadata,
+    ) -> Result<LoginOutcome, UserServiceError> {
         let mut user: Option<UserModel> = None;
 
         if let Some(ref email) = login.email {
@@ -181,20 +234,75 @@ impl UserService {
             user = self.get_by_username(username).await?;
         }
 
+        let ip_address = session.ip_address.clone();
+        auth_service
+            .check_login_throttle(user.as_ref().map(|u| u.id), ip_address.as_deref())
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+
         if user.is_none() {
+            auth_service
+                .record_login_failure(None, ip_address.as_deref())
+                .await
+                .map_err(UserServiceError::AuthServiceError)?;
+
             return Err(UserServiceError::UserNotFound(AnyhowResponder(anyhow!(
                 "Requested user is not found"
             ))));
         }
 
         let user: UserModel = user.unwrap();
+        let session = session.with_device_name(login.device_name);
+
+        if user.totp_enabled_at.is_none() {
+            return match auth_service.login(login.password, &user, &session).await {
+                Ok(login_return) => {
+                    auth_service
+                        .clear_login_failures(user.id)
+                        .await
+                        .map_err(UserServiceError::AuthServiceError)?;
+
+                    Ok(LoginOutcome::Authenticated(login_return))
+                }
+                Err(AuthServiceError::LoginError(e)) => {
+                    auth_service
+                        .record_login_failure(Some(user.id), ip_address.as_deref())
+                        .await
+                        .map_err(UserServiceError::AuthServiceError)?;
+
+                    Err(UserServiceError::AuthServiceError(
+                        AuthServiceError::LoginError(e),
+                    ))
+                }
+                Err(e) => Err(UserServiceError::AuthServiceError(e)),
+            };
+        }
 
-        let login_return = auth_service
-            .login(login.password, &user)
+        if !AuthService::verify_password(&user.password, &login.password)
+            .map_err(UserServiceError::AuthServiceError)?
+        {
+            auth_service
+                .record_login_failure(Some(user.id), ip_address.as_deref())
+                .await
+                .map_err(UserServiceError::AuthServiceError)?;
+
+            return Err(UserServiceError::AuthServiceError(
+                AuthServiceError::LoginError(AnyhowResponder(anyhow!(
+                    "Unable to validate password"
+                ))),
+            ));
+        }
+
+        // Failures are only cleared once the second factor is right too,
+        // otherwise a known password would allow guessing codes without limit
+        let challenge = auth_service
+            .generate_two_factor_challenge(user.id, session.device_name)
             .await
             .map_err(UserServiceError::AuthServiceError)?;
 
-        Ok(login_return)
+        Ok(LoginOutcome::TwoFactorRequired(TwoFactorChallengeReturn {
+            challenge,
+        }))
     }
 
     pub async fn update_role_for_user(
@@ -210,7 +318,289 @@ impl UserService {
             ))))?
             .into();
 
-        user.role = Set(new_role as i16);
+        user.role = Set(new_role.bits());
+        user.update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+
+    /// Mails a single-use reset link to the owner of `email`. Succeeds whether or
+    /// not the account exists so the endpoint can't be used to discover emails.
+    pub async fn request_password_reset(
+        &self,
+        email: &str,
+        mailer: &dyn MailSender,
+    ) -> Result<(), UserServiceError> {
+        let user = match self.get_by_email(email).await? {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+
+        let now = Utc::now().naive_utc();
+
+        // Only the most recent link stays usable
+        PasswordResetEntity::delete_many()
+            .filter(password_reset_token::Column::UserId.eq(user.id))
+            .filter(password_reset_token::Column::UsedAt.is_null())
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let token = AuthService::generate_link_token();
+        PasswordResetActiveModel {
+            user_id: ActiveValue::Set(user.id),
+            token_hash: ActiveValue::Set(AuthService::hash_token(&token)),
+            created_at: ActiveValue::Set(now),
+            expires_at: ActiveValue::Set(
+                now + chrono::Duration::minutes(PASSWORD_RESET_VALIDITY_MINUTES),
+            ),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let link = format!("{}/reset-password?token={token}", frontend_url());
+        let mail = Mail {
+            to: user.email,
+            subject: String::from("Reset your Tekxchange password"),
+            body: format!(
+                "Hi {},\n\nUse the link below to choose a new password. It expires in {PASSWORD_RESET_VALIDITY_MINUTES} minutes.\n\n{link}\n\nIf you did not ask for this, you can ignore this email.",
+                user.username
+            ),
+        };
+
+        if let Err(e) = mailer.send(mail).await {
+            tracing::error!(message = "Unable to send password reset mail", user_id = user.id, error = %e);
+        }
+
+        Ok(())
+    }
+
+    /// Sets a new password from a reset link and signs the user out everywhere
+    pub async fn confirm_password_reset(
+        &self,
+        token: &str,
+        new_password: &str,
+        auth_service: &mut AuthService,
+    ) -> Result<(), UserServiceError> {
+        let now = Utc::now().naive_utc();
+        let reset = PasswordResetEntity::find()
+            .filter(password_reset_token::Column::TokenHash.eq(AuthService::hash_token(token)))
+            .filter(password_reset_token::Column::UsedAt.is_null())
+            .filter(password_reset_token::Column::ExpiresAt.gt(now))
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(UserServiceError::InvalidResetToken(AnyhowResponder(
+                anyhow!("Password reset token is unknown, used, or expired"),
+            )))?;
+
+        // Claim the token before changing anything so concurrent requests can't both use it
+        let claimed = PasswordResetEntity::update_many()
+            .col_expr(password_reset_token::Column::UsedAt, Expr::value(now))
+            .filter(password_reset_token::Column::Id.eq(reset.id))
+            .filter(password_reset_token::Column::UsedAt.is_null())
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if claimed.rows_affected != 1 {
+            return Err(UserServiceError::InvalidResetToken(AnyhowResponder(
+                anyhow!("Password reset token was used concurrently"),
+            )));
+        }
+
+        let user =
+            self.get_user_by_id(&reset.user_id)
+                .await?
+                .ok_or(UserServiceError::UserNotFound(AnyhowResponder(anyhow!(
+                    "Requested user is not found"
+                ))))?;
+
+        let mut active_user: UserActiveModel = user.clone().into();
+        active_user.password =
+            Set(AuthService::hash_password(new_password)
+                .map_err(UserServiceError::AuthServiceError)?);
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        auth_service
+            .revoke_refresh_token(&user)
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+
+        Ok(())
+    }
+
+    /// Marks the user's current email as verified without sending a link
+    pub async fn mark_verified(&self, user_id: i64) -> Result<(), UserServiceError> {
+        let mut user: UserActiveModel = self
+            .get_user_by_id(&user_id)
+            .await?
+            .ok_or(UserServiceError::UserNotFound(AnyhowResponder(anyhow!(
+                "Requested user is not found"
+            ))))?
+            .into();
+
+        user.verified_at = Set(Some(Utc::now().naive_utc()));
+        user.update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+
+    /// Mails a link confirming that `user_id` owns their current email address
+    pub async fn send_verification_email(
+        &self,
+        user_id: i64,
+        mailer: &dyn MailSender,
+    ) -> Result<(), UserServiceError> {
+        let user = self
+            .get_user_by_id(&user_id)
+            .await?
+            .ok_or(UserServiceError::UserNotFound(AnyhowResponder(anyhow!(
+                "Requested user is not found"
+            ))))?;
+
+        if user.verified_at.is_some() {
+            return Err(UserServiceError::AlreadyVerified(AnyhowResponder(anyhow!(
+                "User {user_id} requested verification for a verified email"
+            ))));
+        }
+
+        let email = user.email.clone();
+        self.issue_verification(&user, &email, mailer).await
+    }
+
+    async fn issue_verification(
+        &self,
+        user: &UserModel,
+        email: &str,
+        mailer: &dyn MailSender,
+    ) -> Result<(), UserServiceError> {
+        let now = Utc::now().naive_utc();
+        let recently_sent = EmailVerificationEntity::find()
+            .filter(email_verification_token::Column::UserId.eq(user.id))
+            .filter(email_verification_token::Column::UsedAt.is_null())
+            .filter(
+                email_verification_token::Column::CreatedAt
+                    .gt(now - chrono::Duration::seconds(EMAIL_VERIFICATION_RESEND_SECONDS)),
+            )
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if recently_sent > 0 {
+            return Err(UserServiceError::VerificationRecentlySent(AnyhowResponder(
+                anyhow!("User {} requested verification emails too quickly", user.id),
+            )));
+        }
+
+        // Only the most recent link stays usable
+        EmailVerificationEntity::delete_many()
+            .filter(email_verification_token::Column::UserId.eq(user.id))
+            .filter(email_verification_token::Column::UsedAt.is_null())
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let token = AuthService::generate_link_token();
+        EmailVerificationActiveModel {
+            user_id: ActiveValue::Set(user.id),
+            email: ActiveValue::Set(email.to_owned()),
+            token_hash: ActiveValue::Set(AuthService::hash_token(&token)),
+            created_at: ActiveValue::Set(now),
+            expires_at: ActiveValue::Set(
+                now + chrono::Duration::hours(EMAIL_VERIFICATION_VALIDITY_HOURS),
+            ),
+            ..Default::default()
+        }
+        .insert(&self.db_connection)
+        .await
+        .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let link = format!("{}/verify-email?token={token}", frontend_url());
+        let mail = Mail {
+            to: email.to_owned(),
+            subject: String::from("Verify your Tekxchange email"),
+            body: format!(
+                "Hi {},\n\nConfirm your email address with the link below. It expires in {EMAIL_VERIFICATION_VALIDITY_HOURS} hours.\n\n{link}",
+                user.username
+            ),
+        };
+
+        if let Err(e) = mailer.send(mail).await {
+            tracing::error!(message = "Unable to send verification mail", user_id = user.id, error = %e);
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the email address a verification link was sent to
+    pub async fn verify_email(&self, token: &str) -> Result<(), UserServiceError> {
+        let now = Utc::now().naive_utc();
+        let verification = EmailVerificationEntity::find()
+            .filter(email_verification_token::Column::TokenHash.eq(AuthService::hash_token(token)))
+            .filter(email_verification_token::Column::UsedAt.is_null())
+            .filter(email_verification_token::Column::ExpiresAt.gt(now))
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?
+            .ok_or(UserServiceError::InvalidVerificationToken(AnyhowResponder(
+                anyhow!("Verification token is unknown, used, or expired"),
+            )))?;
+
+        let claimed = EmailVerificationEntity::update_many()
+            .col_expr(email_verification_token::Column::UsedAt, Expr::value(now))
+            .filter(email_verification_token::Column::Id.eq(verification.id))
+            .filter(email_verification_token::Column::UsedAt.is_null())
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if claimed.rows_affected != 1 {
+            return Err(UserServiceError::InvalidVerificationToken(AnyhowResponder(
+                anyhow!("Verification token was used concurrently"),
+            )));
+        }
+
+        let mut user: UserActiveModel = self
+            .get_user_by_id(&verification.user_id)
+            .await?
+            .ok_or(UserServiceError::UserNotFound(AnyhowResponder(anyhow!(
+                "Requested user is not found"
+            ))))?
+            .into();
+
+        // The address may have been claimed by another account since the link was sent
+        let taken = UserEntity::find()
+            .filter(
+                entity::user::Column::EmailNormalized.eq(normalize_identity(&verification.email)),
+            )
+            .filter(entity::user::Column::Id.ne(verification.user_id))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if taken > 0 {
+            return Err(UserServiceError::DuplicateUserError(AnyhowResponder(
+                anyhow!(
+                    "Email {} was claimed before user {} verified it",
+                    verification.email,
+                    verification.user_id
+                ),
+            )));
+        }
+
+        user.email_normalized = Set(normalize_identity(&verification.email));
+        user.email = Set(verification.email);
+        user.verified_at = Set(Some(now));
         user.update(&self.db_connection)
             .await
             .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
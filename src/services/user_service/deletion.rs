@@ -0,0 +1,319 @@
+use super::{profile::check_password, UserService, UserServiceError};
+use crate::{
+    mail::{frontend_url, Mail, MailSender},
+    models::role::Role,
+    services::AuthService,
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDateTime, Utc};
+use entity::{
+    product::{self, ActiveModel as ProductActiveModel, Entity as ProductEntity},
+    user::{self, ActiveModel as UserActiveModel, Entity as UserEntity},
+};
+use sea_orm::{prelude::*, DatabaseTransaction, Set, TransactionTrait};
+
+pub const ACCOUNT_DELETION_GRACE_DAYS: i64 = 14;
+const REMOVED_LISTING_TITLE: &str = "Removed listing";
+
+impl UserService {
+    /// Schedules the account for deletion once the grace period has passed,
+    /// returning when that will happen. Asking again keeps the original date.
+    pub async fn request_deletion(
+        &self,
+        user_id: i64,
+        password: &str,
+        mailer: &dyn MailSender,
+    ) -> Result<NaiveDateTime, UserServiceError> {
+        let user = self.find_user(user_id).await?;
+        check_password(&user, password)?;
+
+        if let Some(requested_at) = user.deletion_requested_at {
+            return Ok(requested_at + chrono::Duration::days(ACCOUNT_DELETION_GRACE_DAYS));
+        }
+
+        let now = Utc::now().naive_utc();
+        let scheduled_for = now + chrono::Duration::days(ACCOUNT_DELETION_GRACE_DAYS);
+        let mail = Mail {
+            to: user.email.clone(),
+            subject: String::from("Your Tekxchange account will be deleted"),
+            body: format!(
+                "Hi {},\n\nYour account and listings will be deleted on {}. You can cancel this from your account settings until then.\n\n{}/account",
+                user.username,
+                scheduled_for.format("%Y-%m-%d"),
+                frontend_url()
+            ),
+        };
+
+        let mut active_user: UserActiveModel = user.into();
+        active_user.deletion_requested_at = Set(Some(now));
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if let Err(e) = mailer.send(mail).await {
+            tracing::error!(message = "Unable to send account deletion mail", user_id, error = %e);
+        }
+
+        Ok(scheduled_for)
+    }
+
+    pub async fn cancel_deletion(&self, user_id: i64) -> Result<(), UserServiceError> {
+        let user = self.find_user(user_id).await?;
+
+        if user.deletion_requested_at.is_none() {
+            return Err(UserServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "User {user_id} has no pending deletion"
+            ))));
+        }
+
+        let mut active_user: UserActiveModel = user.into();
+        active_user.deletion_requested_at = Set(None);
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+
+    /// Deletes every account whose grace period is over, returning how many were
+    /// deleted and how many failed. A failing account is logged and retried on the
+    /// next run without holding up the others.
+    pub async fn purge_due_deletions(
+        &self,
+        auth_service: &mut AuthService,
+    ) -> Result<(usize, usize), UserServiceError> {
+        let due =
+            UserEntity::find()
+                .filter(user::Column::DeletedAt.is_null())
+                .filter(user::Column::DeletionRequestedAt.lte(
+                    Utc::now().naive_utc() - chrono::Duration::days(ACCOUNT_DELETION_GRACE_DAYS),
+                ))
+                .all(&self.db_connection)
+                .await
+                .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let (mut deleted, mut failed) = (0, 0);
+        for user in due {
+            match self.delete_account(user.id, auth_service).await {
+                Ok(()) => deleted += 1,
+                Err(e) => {
+                    tracing::error!(message = "Unable to delete account", user_id = user.id, error = %e);
+                    failed += 1;
+                }
+            }
+        }
+
+        Ok((deleted, failed))
+    }
+
+    /// Signs the user out everywhere, removes their listings, files and account data,
+    /// and anonymizes the user row. The row itself stays so moderation history that
+    /// points at it (`product_audit`, `admin_audit_log`) keeps working. Listings with
+    /// audits are anonymized and hidden instead of deleted for the same reason.
+    ///
+    /// The database changes are made in one transaction, and files are only removed
+    /// from disk once it has committed.
+    pub async fn delete_account(
+        &self,
+        user_id: i64,
+        auth_service: &mut AuthService,
+    ) -> Result<(), UserServiceError> {
+        let internal = |e: DbErr| UserServiceError::InternalError(AnyhowResponder(anyhow!(e)));
+        let user = self.find_user(user_id).await?;
+
+        if user.deleted_at.is_some() {
+            return Ok(());
+        }
+
+        auth_service
+            .revoke_refresh_token(&user)
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+        auth_service
+            .deny_access_tokens(user_id, None)
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+
+        let txn = self.db_connection.begin().await.map_err(internal)?;
+        // Dropping the transaction on an error rolls it back
+        let file_locations = Self::delete_account_data(&txn, user).await?;
+        txn.commit().await.map_err(internal)?;
+
+        for location in file_locations {
+            let _ = std::fs::remove_file(location);
+        }
+
+        tracing::info!(message = "Deleted account", user_id);
+
+        Ok(())
+    }
+
+    /// Database half of `delete_account`, returning the files left to remove from disk
+    async fn delete_account_data(
+        db: &DatabaseTransaction,
+        user: user::Model,
+    ) -> Result<Vec<String>, UserServiceError> {
+        let internal = |e: DbErr| UserServiceError::InternalError(AnyhowResponder(anyhow!(e)));
+        let user_id = user.id;
+
+        let products = ProductEntity::find()
+            .filter(product::Column::CreatedBy.eq(user_id))
+            .all(db)
+            .await
+            .map_err(internal)?;
+
+        for listing in products {
+            entity::product_picture::Entity::delete_many()
+                .filter(entity::product_picture::Column::ProductId.eq(listing.id))
+                .exec(db)
+                .await
+                .map_err(internal)?;
+            entity::product_category::Entity::delete_many()
+                .filter(entity::product_category::Column::ProductId.eq(listing.id))
+                .exec(db)
+                .await
+                .map_err(internal)?;
+            entity::product_attribute::Entity::delete_many()
+                .filter(entity::product_attribute::Column::ProductId.eq(listing.id))
+                .exec(db)
+                .await
+                .map_err(internal)?;
+            // Old revisions would still hold the listing's text
+            entity::product_revision::Entity::delete_many()
+                .filter(entity::product_revision::Column::ProductId.eq(listing.id))
+                .exec(db)
+                .await
+                .map_err(internal)?;
+            entity::product_price::Entity::delete_many()
+                .filter(entity::product_price::Column::ProductId.eq(listing.id))
+                .exec(db)
+                .await
+                .map_err(internal)?;
+
+            let audits = entity::product_audit::Entity::find()
+                .filter(entity::product_audit::Column::ProductId.eq(listing.id))
+                .count(db)
+                .await
+                .map_err(internal)?;
+
+            if audits == 0 {
+                ProductEntity::delete_by_id(listing.id)
+                    .exec(db)
+                    .await
+                    .map_err(internal)?;
+                continue;
+            }
+
+            let mut active_listing: ProductActiveModel = listing.into();
+            active_listing.product_title = Set(REMOVED_LISTING_TITLE.to_owned());
+            active_listing.description = Set(String::new());
+            active_listing.location_country = Set(String::new());
+            active_listing.location_state = Set(String::new());
+            active_listing.location_city = Set(String::new());
+            active_listing.location_zip = Set(String::new());
+            active_listing.location_latitude = Set(None);
+            active_listing.location_longitude = Set(None);
+            active_listing.update(db).await.map_err(internal)?;
+        }
+
+        let files = entity::file::Entity::find()
+            .filter(entity::file::Column::CreatedBy.eq(user_id))
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let exports = entity::data_export::Entity::find()
+            .filter(entity::data_export::Column::UserId.eq(user_id))
+            .all(db)
+            .await
+            .map_err(internal)?;
+        let file_locations = files
+            .into_iter()
+            .map(|file| file.file_location)
+            .chain(
+                exports
+                    .into_iter()
+                    .filter_map(|export| export.file_location),
+            )
+            .collect();
+
+        entity::data_export::Entity::delete_many()
+            .filter(entity::data_export::Column::UserId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+        entity::personal_access_token::Entity::delete_many()
+            .filter(entity::personal_access_token::Column::UserId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+        entity::user_identity::Entity::delete_many()
+            .filter(entity::user_identity::Column::UserId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+        entity::recovery_code::Entity::delete_many()
+            .filter(entity::recovery_code::Column::UserId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+        entity::email_verification_token::Entity::delete_many()
+            .filter(entity::email_verification_token::Column::UserId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+        entity::password_reset_token::Entity::delete_many()
+            .filter(entity::password_reset_token::Column::UserId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+        entity::login_lockout::Entity::delete_many()
+            .filter(entity::login_lockout::Column::UserId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+        entity::user_preference::Entity::delete_many()
+            .filter(entity::user_preference::Column::UserId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+        entity::seller_review::Entity::delete_many()
+            .filter(entity::seller_review::Column::SellerId.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+
+        let placeholder = format!("deleted-{}", uuid::Uuid::new_v4().simple());
+        let password = AuthService::hash_password(&AuthService::generate_link_token())
+            .map_err(UserServiceError::AuthServiceError)?;
+
+        let mut active_user: UserActiveModel = user.into();
+        active_user.email = Set(format!("{placeholder}@deleted.invalid"));
+        active_user.email_normalized = Set(format!("{placeholder}@deleted.invalid"));
+        active_user.username_normalized = Set(placeholder.clone());
+        active_user.username = Set(placeholder);
+        active_user.password = Set(password);
+        active_user.role = Set(Role::USER.bits());
+        active_user.verified_at = Set(None);
+        active_user.totp_secret = Set(None);
+        active_user.totp_enabled_at = Set(None);
+        active_user.totp_last_step = Set(None);
+        active_user.bio = Set(None);
+        active_user.avatar_file_id = Set(None);
+        active_user.username_changed_at = Set(None);
+        active_user.deletion_requested_at = Set(None);
+        active_user.deleted_at = Set(Some(Utc::now().naive_utc()));
+        active_user.update(db).await.map_err(internal)?;
+
+        // Only once the avatar no longer points at them
+        entity::file::Entity::delete_many()
+            .filter(entity::file::Column::CreatedBy.eq(user_id))
+            .exec(db)
+            .await
+            .map_err(internal)?;
+
+        Ok(file_locations)
+    }
+}
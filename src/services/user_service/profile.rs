@@ -0,0 +1,290 @@
+use super::{UserService, UserServiceError};
+use crate::{
+    mail::MailSender,
+    models::user::{
+        normalize_identity, EmailChange, PasswordChange, ProfilePrivacy, UserPreferences,
+    },
+    services::auth_service::{AuthService, AuthServiceError},
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use entity::{
+    user::{self, ActiveModel as UserActiveModel, Entity as UserEntity, Model as UserModel},
+    user_preference::{
+        self, ActiveModel as UserPreferenceActiveModel, Entity as UserPreferenceEntity,
+        Model as UserPreferenceModel,
+    },
+};
+use rust_decimal::Decimal;
+use sea_orm::{prelude::*, ActiveValue, Set};
+
+const USERNAME_CHANGE_COOLDOWN_DAYS: i64 = 30;
+const MAX_SEARCH_RADIUS: i64 = 500;
+const MAX_BIO_LENGTH: usize = 1000;
+
+pub(super) fn check_password(user: &UserModel, password: &str) -> Result<(), UserServiceError> {
+    if !AuthService::verify_password(&user.password, password)
+        .map_err(UserServiceError::AuthServiceError)?
+    {
+        return Err(UserServiceError::AuthServiceError(
+            AuthServiceError::LoginError(AnyhowResponder(anyhow!("Unable to validate password"))),
+        ));
+    }
+
+    Ok(())
+}
+
+impl UserService {
+    /// Changes the password and signs out every session except `current_refresh`
+    pub async fn change_password(
+        &self,
+        user_id: i64,
+        change: PasswordChange,
+        current_refresh: Option<&str>,
+        auth_service: &mut AuthService,
+    ) -> Result<(), UserServiceError> {
+        let user = self.find_user(user_id).await?;
+        check_password(&user, &change.current_password)?;
+
+        if change.new_password.is_empty() {
+            return Err(UserServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "User {user_id} attempted to set an empty password"
+            ))));
+        }
+
+        let mut active_user: UserActiveModel = user.into();
+        active_user.password = Set(AuthService::hash_password(&change.new_password)
+            .map_err(UserServiceError::AuthServiceError)?);
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        auth_service
+            .revoke_other_sessions(user_id, current_refresh)
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+
+        Ok(())
+    }
+
+    /// Mails a verification link to the new address, the current email stays
+    /// in place until that link is used
+    pub async fn request_email_change(
+        &self,
+        user_id: i64,
+        change: EmailChange,
+        mailer: &dyn MailSender,
+    ) -> Result<(), UserServiceError> {
+        let user = self.find_user(user_id).await?;
+        check_password(&user, &change.password)?;
+
+        if change.email.trim().is_empty() {
+            return Err(UserServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "User {user_id} attempted to change to an empty email"
+            ))));
+        }
+
+        if self.email_exists(&change.email).await? {
+            return Err(UserServiceError::DuplicateUserError(AnyhowResponder(
+                anyhow!("User {user_id} attempted to change to an email that is in use"),
+            )));
+        }
+
+        self.issue_verification(&user, &change.email, mailer).await
+    }
+
+    /// Renames the user, at most once every `USERNAME_CHANGE_COOLDOWN_DAYS`
+    pub async fn change_username(
+        &self,
+        user_id: i64,
+        username: &str,
+    ) -> Result<(), UserServiceError> {
+        let user = self.find_user(user_id).await?;
+        let now = Utc::now().naive_utc();
+
+        if let Some(changed_at) = user.username_changed_at {
+            if changed_at + chrono::Duration::days(USERNAME_CHANGE_COOLDOWN_DAYS) > now {
+                return Err(UserServiceError::UsernameChangeTooSoon(AnyhowResponder(
+                    anyhow!("User {user_id} changed their username on {changed_at}"),
+                )));
+            }
+        }
+
+        if username.trim().is_empty() {
+            return Err(UserServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                "User {user_id} attempted to change to an empty username"
+            ))));
+        }
+
+        if self.contains_blocked_words(username).await? {
+            return Err(UserServiceError::ForbiddenWords(AnyhowResponder(anyhow!(
+                "User {user_id} attempted a rename with a forbidden word in username"
+            ))));
+        }
+
+        let taken = UserEntity::find()
+            .filter(user::Column::UsernameNormalized.eq(normalize_identity(username)))
+            .filter(user::Column::Id.ne(user_id))
+            .count(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        if taken > 0 {
+            return Err(UserServiceError::DuplicateUserError(AnyhowResponder(
+                anyhow!("User {user_id} attempted to rename to a username that is in use"),
+            )));
+        }
+
+        let mut active_user: UserActiveModel = user.into();
+        active_user.username = Set(username.trim().to_owned());
+        active_user.username_normalized = Set(normalize_identity(username));
+        active_user.username_changed_at = Set(Some(now));
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+
+    async fn stored_preferences(
+        &self,
+        user_id: i64,
+    ) -> Result<Option<UserPreferenceModel>, UserServiceError> {
+        UserPreferenceEntity::find()
+            .filter(user_preference::Column::UserId.eq(user_id))
+            .one(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+    }
+
+    /// The user's preference row, or a new one if nothing was saved yet
+    async fn preference_row(
+        &self,
+        user_id: i64,
+    ) -> Result<UserPreferenceActiveModel, UserServiceError> {
+        Ok(match self.stored_preferences(user_id).await? {
+            Some(existing) => existing.into(),
+            None => UserPreferenceActiveModel {
+                user_id: ActiveValue::Set(self.find_user(user_id).await?.id),
+                ..Default::default()
+            },
+        })
+    }
+
+    pub async fn get_preferences(&self, user_id: i64) -> Result<UserPreferences, UserServiceError> {
+        let Some(stored) = self.stored_preferences(user_id).await? else {
+            return Ok(UserPreferences::default());
+        };
+
+        Ok(UserPreferences {
+            distance_unit: stored
+                .distance_unit
+                .and_then(|unit| serde_json::from_value(serde_json::Value::String(unit)).ok()),
+            search_radius: stored.search_radius,
+        })
+    }
+
+    pub async fn update_preferences(
+        &self,
+        user_id: i64,
+        preferences: UserPreferences,
+    ) -> Result<UserPreferences, UserServiceError> {
+        if let Some(radius) = preferences.search_radius {
+            if radius <= Decimal::ZERO || radius > Decimal::from(MAX_SEARCH_RADIUS) {
+                return Err(UserServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                    "Search radius must be above 0 and at most {MAX_SEARCH_RADIUS}"
+                ))));
+            }
+        }
+
+        let distance_unit = match &preferences.distance_unit {
+            Some(unit) => match serde_json::to_value(unit) {
+                Ok(serde_json::Value::String(unit)) => Some(unit),
+                _ => None,
+            },
+            None => None,
+        };
+
+        let mut active = self.preference_row(user_id).await?;
+        active.distance_unit = Set(distance_unit);
+        active.search_radius = Set(preferences.search_radius);
+        active.updated_at = Set(Utc::now().naive_utc());
+        active
+            .save(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(preferences)
+    }
+
+    pub async fn get_privacy(&self, user_id: i64) -> Result<ProfilePrivacy, UserServiceError> {
+        Ok(self
+            .stored_preferences(user_id)
+            .await?
+            .map(|stored| ProfilePrivacy {
+                show_member_since: stored.show_member_since,
+                show_listing_count: stored.show_listing_count,
+                show_sales_count: stored.show_sales_count,
+                show_rating: stored.show_rating,
+                show_listings: stored.show_listings,
+            })
+            .unwrap_or_default())
+    }
+
+    pub async fn update_privacy(
+        &self,
+        user_id: i64,
+        privacy: ProfilePrivacy,
+    ) -> Result<ProfilePrivacy, UserServiceError> {
+        let mut active = self.preference_row(user_id).await?;
+        active.show_member_since = Set(privacy.show_member_since);
+        active.show_listing_count = Set(privacy.show_listing_count);
+        active.show_sales_count = Set(privacy.show_sales_count);
+        active.show_rating = Set(privacy.show_rating);
+        active.show_listings = Set(privacy.show_listings);
+        active.updated_at = Set(Utc::now().naive_utc());
+        active
+            .save(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(privacy)
+    }
+
+    /// Sets or clears the bio shown on the public profile
+    pub async fn update_bio(
+        &self,
+        user_id: i64,
+        bio: Option<String>,
+    ) -> Result<(), UserServiceError> {
+        let bio = bio
+            .map(|bio| bio.trim().to_owned())
+            .filter(|bio| !bio.is_empty());
+
+        if let Some(ref bio) = bio {
+            if bio.chars().count() > MAX_BIO_LENGTH {
+                return Err(UserServiceError::InvalidRequest(AnyhowResponder(anyhow!(
+                    "Bio must be at most {MAX_BIO_LENGTH} characters"
+                ))));
+            }
+
+            if self.contains_blocked_words(bio).await? {
+                return Err(UserServiceError::ForbiddenWords(AnyhowResponder(anyhow!(
+                    "User {user_id} attempted to set a bio with a forbidden word"
+                ))));
+            }
+        }
+
+        let mut active_user: UserActiveModel = self.find_user(user_id).await?.into();
+        active_user.bio = Set(bio);
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+}
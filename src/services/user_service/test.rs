@@ -88,6 +88,28 @@ mod get_by_email {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn wildcards_are_matched_literally() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        user_service
+            .create_user(
+                UserRegister {
+                    username: String::from("test"),
+                    email: String::from("test@test.com"),
+                    password: String::from("testpass"),
+                },
+                true,
+            )
+            .await?;
+
+        assert!(!user_service.email_exists("%").await?);
+        assert!(!user_service.email_exists("test@test.co_").await?);
+        assert!(user_service.get_by_email("%@test.com").await?.is_none());
+
+        Ok(())
+    }
 }
 
 mod get_by_username {
@@ -114,6 +136,42 @@ mod get_by_username {
         assert!(res.is_some());
         let res = user_service.get_by_username("testing").await?;
         assert!(res.is_none());
+        let res = user_service.get_by_username("testing%").await?;
+        assert!(res.is_none());
+        assert!(!user_service.username_exists("_esting123").await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn usernames_are_compared_normalized() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        user_service
+            .create_user(
+                UserRegister {
+                    username: " Testing123 ".into(),
+                    email: "test@test.com".into(),
+                    password: "testPass".into(),
+                },
+                false,
+            )
+            .await?;
+
+        let found = user_service.get_by_username("ｔｅｓｔｉｎｇ１２３").await?;
+        assert_eq!(found.unwrap().username, "Testing123");
+
+        let duplicate = user_service
+            .create_user(
+                UserRegister {
+                    username: "TESTING123".into(),
+                    email: "other@test.com".into(),
+                    password: "testPass".into(),
+                },
+                false,
+            )
+            .await;
+        assert!(duplicate.is_err());
 
         Ok(())
     }
@@ -154,3 +212,1108 @@ mod get_by_id {
         Ok(())
     }
 }
+
+mod password_reset {
+    use super::*;
+    use crate::{
+        db::MockRedisRefresh,
+        mail::test::CapturingMailSender,
+        services::{AuthService, KeyEncryptionKey, KeyRing, UserServiceError},
+    };
+    use entity::password_reset_token;
+
+    async fn create_user(user_service: &UserService) -> Result<i64, UserServiceError> {
+        user_service
+            .create_user(
+                UserRegister {
+                    username: "test".into(),
+                    email: "test@test.com".into(),
+                    password: "oldPassword".into(),
+                },
+                false,
+            )
+            .await
+    }
+
+    fn token_from_mail(mailer: &CapturingMailSender) -> String {
+        let mail = mailer.sent().pop().unwrap();
+        mail.body
+            .split("token=")
+            .nth(1)
+            .unwrap()
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .to_owned()
+    }
+
+    async fn auth_service(
+        db: &DatabaseConnection,
+    ) -> Result<AuthService, Box<dyn std::error::Error>> {
+        let mut redis = MockRedisRefresh::default();
+        redis.expect_delete_item().returning(|_| Ok(()));
+
+        Ok(AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(db, KeyEncryptionKey::test()).await?,
+        ))
+    }
+
+    #[tokio::test]
+    async fn mails_a_link_and_stores_only_the_hash() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let mailer = CapturingMailSender::default();
+        create_user(&user_service).await?;
+
+        user_service
+            .request_password_reset("test@test.com", &mailer)
+            .await?;
+
+        assert_eq!(mailer.sent().len(), 1);
+        assert_eq!(mailer.sent()[0].to, "test@test.com");
+        let token = token_from_mail(&mailer);
+        let stored = password_reset_token::Entity::find().all(&db).await?;
+        assert_eq!(stored.len(), 1);
+        assert_ne!(stored[0].token_hash, token);
+        assert_eq!(stored[0].token_hash, AuthService::hash_token(&token));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unknown_email_sends_nothing() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let mailer = CapturingMailSender::default();
+
+        user_service
+            .request_password_reset("nobody@test.com", &mailer)
+            .await?;
+
+        assert!(mailer.sent().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn confirm_changes_password_and_revokes_sessions() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let mailer = CapturingMailSender::default();
+        let user_id = create_user(&user_service).await?;
+        entity::refresh_token::ActiveModel {
+            token: sea_orm::ActiveValue::Set("refresh".into()),
+            family_id: sea_orm::ActiveValue::Set("refresh".into()),
+            user_id: sea_orm::ActiveValue::Set(user_id),
+            expires_at: sea_orm::ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await?;
+
+        user_service
+            .request_password_reset("test@test.com", &mailer)
+            .await?;
+        let token = token_from_mail(&mailer);
+        let mut auth_service = auth_service(&db).await?;
+
+        user_service
+            .confirm_password_reset(&token, "newPassword", &mut auth_service)
+            .await?;
+
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert!(AuthService::verify_password(&user.password, "newPassword")?);
+        assert_eq!(entity::refresh_token::Entity::find().count(&db).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn token_is_single_use() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let mailer = CapturingMailSender::default();
+        create_user(&user_service).await?;
+        user_service
+            .request_password_reset("test@test.com", &mailer)
+            .await?;
+        let token = token_from_mail(&mailer);
+        let mut auth_service = auth_service(&db).await?;
+
+        user_service
+            .confirm_password_reset(&token, "newPassword", &mut auth_service)
+            .await?;
+        let second = user_service
+            .confirm_password_reset(&token, "otherPassword", &mut auth_service)
+            .await;
+
+        assert!(matches!(
+            second,
+            Err(UserServiceError::InvalidResetToken(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_rejected() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let mailer = CapturingMailSender::default();
+        create_user(&user_service).await?;
+        user_service
+            .request_password_reset("test@test.com", &mailer)
+            .await?;
+        let token = token_from_mail(&mailer);
+        password_reset_token::Entity::update_many()
+            .col_expr(
+                password_reset_token::Column::ExpiresAt,
+                Expr::value(chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1)),
+            )
+            .exec(&db)
+            .await?;
+        let mut auth_service = auth_service(&db).await?;
+
+        let result = user_service
+            .confirm_password_reset(&token, "newPassword", &mut auth_service)
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(UserServiceError::InvalidResetToken(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn a_new_request_invalidates_the_previous_link() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let mailer = CapturingMailSender::default();
+        create_user(&user_service).await?;
+        user_service
+            .request_password_reset("test@test.com", &mailer)
+            .await?;
+        let first = token_from_mail(&mailer);
+        user_service
+            .request_password_reset("test@test.com", &mailer)
+            .await?;
+        let mut auth_service = auth_service(&db).await?;
+
+        let result = user_service
+            .confirm_password_reset(&first, "newPassword", &mut auth_service)
+            .await;
+
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
+
+mod email_verification {
+    use super::*;
+    use crate::{mail::test::CapturingMailSender, services::UserServiceError};
+    use entity::email_verification_token;
+
+    async fn create_user(user_service: &UserService) -> Result<i64, UserServiceError> {
+        user_service
+            .create_user(
+                UserRegister {
+                    username: "test".into(),
+                    email: "test@test.com".into(),
+                    password: "password".into(),
+                },
+                false,
+            )
+            .await
+    }
+
+    fn token_from_mail(mailer: &CapturingMailSender) -> String {
+        let mail = mailer.sent().pop().unwrap();
+        mail.body
+            .split("token=")
+            .nth(1)
+            .unwrap()
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .to_owned()
+    }
+
+    async fn is_verified(
+        user_service: &UserService,
+        user_id: i64,
+    ) -> Result<bool, UserServiceError> {
+        Ok(user_service
+            .get_user_by_id(&user_id)
+            .await?
+            .unwrap()
+            .verified_at
+            .is_some())
+    }
+
+    #[tokio::test]
+    async fn new_users_are_unverified() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let user_id = create_user(&user_service).await?;
+
+        assert!(!is_verified(&user_service, user_id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verifies_with_the_mailed_token() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let mailer = CapturingMailSender::default();
+        let user_id = create_user(&user_service).await?;
+
+        user_service
+            .send_verification_email(user_id, &mailer)
+            .await?;
+        assert_eq!(mailer.sent()[0].to, "test@test.com");
+        user_service.verify_email(&token_from_mail(&mailer)).await?;
+
+        assert!(is_verified(&user_service, user_id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_and_expired_tokens() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let mailer = CapturingMailSender::default();
+        let user_id = create_user(&user_service).await?;
+        user_service
+            .send_verification_email(user_id, &mailer)
+            .await?;
+        email_verification_token::Entity::update_many()
+            .col_expr(
+                email_verification_token::Column::ExpiresAt,
+                Expr::value(chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1)),
+            )
+            .exec(&db)
+            .await?;
+
+        let unknown = user_service.verify_email("not-a-token").await;
+        let expired = user_service.verify_email(&token_from_mail(&mailer)).await;
+
+        assert!(matches!(
+            unknown,
+            Err(UserServiceError::InvalidVerificationToken(_))
+        ));
+        assert!(matches!(
+            expired,
+            Err(UserServiceError::InvalidVerificationToken(_))
+        ));
+        assert!(!is_verified(&user_service, user_id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resend_is_throttled() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let mailer = CapturingMailSender::default();
+        let user_id = create_user(&user_service).await?;
+
+        user_service
+            .send_verification_email(user_id, &mailer)
+            .await?;
+        let second = user_service.send_verification_email(user_id, &mailer).await;
+
+        assert!(matches!(
+            second,
+            Err(UserServiceError::VerificationRecentlySent(_))
+        ));
+        assert_eq!(mailer.sent().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verified_users_cannot_resend() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let mailer = CapturingMailSender::default();
+        let user_id = create_user(&user_service).await?;
+        user_service.mark_verified(user_id).await?;
+
+        let result = user_service.send_verification_email(user_id, &mailer).await;
+
+        assert!(matches!(result, Err(UserServiceError::AlreadyVerified(_))));
+
+        Ok(())
+    }
+}
+
+mod two_factor {
+    use super::*;
+    use crate::{
+        db::test::in_memory_redis,
+        dtos::auth::LoginOutcome,
+        models::{session::SessionMetadata, user::UserLogin},
+        services::{AuthService, AuthServiceError, KeyEncryptionKey, KeyRing, UserServiceError},
+    };
+    use totp_rs::{Algorithm, Secret, TOTP};
+
+    async fn auth_service(
+        db: &DatabaseConnection,
+    ) -> Result<AuthService, Box<dyn std::error::Error>> {
+        Ok(AuthService::new(
+            db.clone(),
+            Box::new(in_memory_redis()),
+            KeyRing::load(db, KeyEncryptionKey::test()).await?,
+        ))
+    }
+
+    fn code_for(secret: &str, offset_steps: i64) -> String {
+        let totp = TOTP::new(
+            Algorithm::SHA1,
+            6,
+            0,
+            30,
+            Secret::Encoded(secret.to_owned()).to_bytes().unwrap(),
+            None,
+            String::from("test"),
+        )
+        .unwrap();
+        let time = chrono::Utc::now().timestamp() + offset_steps * 30;
+
+        totp.generate(time as u64)
+    }
+
+    /// Creates a user with 2FA turned on, returning its id, secret and recovery codes
+    async fn enrolled_user(
+        user_service: &UserService,
+    ) -> Result<(i64, String, Vec<String>), Box<dyn std::error::Error>> {
+        let user_id = user_service
+            .create_user(
+                UserRegister {
+                    username: "test".into(),
+                    email: "test@test.com".into(),
+                    password: "password".into(),
+                },
+                false,
+            )
+            .await?;
+        let enrollment = user_service.begin_totp_enrollment(user_id).await?;
+        let codes = user_service
+            .confirm_totp_enrollment(user_id, &code_for(&enrollment.secret, -1))
+            .await?;
+
+        Ok((user_id, enrollment.secret, codes))
+    }
+
+    async fn challenge_for(
+        user_service: &UserService,
+        db: &DatabaseConnection,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let outcome = user_service
+            .login(
+                UserLogin {
+                    username: Some("test".into()),
+                    email: None,
+                    password: "password".into(),
+                    device_name: Some("phone".into()),
+                },
+                auth_service(db).await?,
+                SessionMetadata::default(),
+            )
+            .await?;
+
+        match outcome {
+            LoginOutcome::TwoFactorRequired(challenge) => Ok(challenge.challenge),
+            LoginOutcome::Authenticated(_) => Err("expected a two factor challenge".into()),
+        }
+    }
+
+    #[tokio::test]
+    async fn enrollment_returns_an_otpauth_uri() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let user_id = user_service
+            .create_user(
+                UserRegister {
+                    username: "test".into(),
+                    email: "test@test.com".into(),
+                    password: "password".into(),
+                },
+                false,
+            )
+            .await?;
+
+        let enrollment = user_service.begin_totp_enrollment(user_id).await?;
+
+        assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+        assert!(enrollment.otpauth_uri.contains(&enrollment.secret));
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert!(user.totp_enabled_at.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn confirmation_enables_2fa_and_hashes_recovery_codes() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+
+        let (user_id, _, codes) = enrolled_user(&user_service).await?;
+
+        assert_eq!(codes.len(), 10);
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert!(user.totp_enabled_at.is_some());
+        let stored = entity::recovery_code::Entity::find().all(&db).await?;
+        assert_eq!(stored.len(), 10);
+        assert!(stored.iter().all(|code| !codes.contains(&code.code_hash)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wrong_code_does_not_confirm() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let user_id = user_service
+            .create_user(
+                UserRegister {
+                    username: "test".into(),
+                    email: "test@test.com".into(),
+                    password: "password".into(),
+                },
+                false,
+            )
+            .await?;
+        let enrollment = user_service.begin_totp_enrollment(user_id).await?;
+
+        let result = user_service
+            .confirm_totp_enrollment(user_id, &code_for(&enrollment.secret, 10))
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(UserServiceError::InvalidTwoFactorCode(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn login_requires_the_second_step() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let (_, secret, _) = enrolled_user(&user_service).await?;
+
+        let challenge = challenge_for(&user_service, &db).await?;
+        let mut auth_service = auth_service(&db).await?;
+        let login = user_service
+            .complete_two_factor_login(
+                &challenge,
+                &code_for(&secret, 0),
+                &mut auth_service,
+                SessionMetadata::default(),
+            )
+            .await?;
+
+        assert!(auth_service.validate_jwt(login.jwt, None).await.is_ok());
+        let session = entity::refresh_token::Entity::find()
+            .one(&db)
+            .await?
+            .unwrap();
+        assert_eq!(session.device_name.as_deref(), Some("phone"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn challenge_is_not_an_access_token() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        enrolled_user(&user_service).await?;
+
+        let challenge = challenge_for(&user_service, &db).await?;
+        let mut auth_service = auth_service(&db).await?;
+
+        assert!(auth_service.validate_jwt(challenge, None).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn codes_cannot_be_replayed() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let (_, secret, _) = enrolled_user(&user_service).await?;
+        let mut auth_service = auth_service(&db).await?;
+
+        let code = code_for(&secret, 0);
+        let first = challenge_for(&user_service, &db).await?;
+        user_service
+            .complete_two_factor_login(&first, &code, &mut auth_service, SessionMetadata::default())
+            .await?;
+        let second = challenge_for(&user_service, &db).await?;
+        let replay = user_service
+            .complete_two_factor_login(
+                &second,
+                &code,
+                &mut auth_service,
+                SessionMetadata::default(),
+            )
+            .await;
+
+        assert!(matches!(
+            replay,
+            Err(UserServiceError::InvalidTwoFactorCode(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recovery_codes_work_once() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let (_, _, codes) = enrolled_user(&user_service).await?;
+        let mut auth_service = auth_service(&db).await?;
+
+        let first = challenge_for(&user_service, &db).await?;
+        let used = user_service
+            .complete_two_factor_login(
+                &first,
+                &codes[0],
+                &mut auth_service,
+                SessionMetadata::default(),
+            )
+            .await;
+        let second = challenge_for(&user_service, &db).await?;
+        let reused = user_service
+            .complete_two_factor_login(
+                &second,
+                &codes[0],
+                &mut auth_service,
+                SessionMetadata::default(),
+            )
+            .await;
+
+        assert!(used.is_ok());
+        assert!(reused.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn challenge_locks_after_too_many_attempts() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let (_, secret, _) = enrolled_user(&user_service).await?;
+        let mut auth_service = auth_service(&db).await?;
+        let challenge = challenge_for(&user_service, &db).await?;
+
+        for _ in 0..5 {
+            let _ = user_service
+                .complete_two_factor_login(
+                    &challenge,
+                    "000000",
+                    &mut auth_service,
+                    SessionMetadata::default(),
+                )
+                .await;
+        }
+        let result = user_service
+            .complete_two_factor_login(
+                &challenge,
+                &code_for(&secret, 0),
+                &mut auth_service,
+                SessionMetadata::default(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(UserServiceError::AuthServiceError(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wrong_codes_count_towards_the_login_lockout() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let (_, secret, _) = enrolled_user(&user_service).await?;
+        let mut auth_service = auth_service(&db).await?;
+
+        // A fresh challenge for every guess doesn't reset the count
+        for _ in 0..4 {
+            let challenge = challenge_for(&user_service, &db).await?;
+            let _ = user_service
+                .complete_two_factor_login(
+                    &challenge,
+                    "000000",
+                    &mut auth_service,
+                    SessionMetadata::default(),
+                )
+                .await;
+        }
+        let challenge = challenge_for(&user_service, &db).await?;
+        let result = user_service
+            .complete_two_factor_login(
+                &challenge,
+                &code_for(&secret, 0),
+                &mut auth_service,
+                SessionMetadata::default(),
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(UserServiceError::AuthServiceError(
+                AuthServiceError::TooManyAttempts(_)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disable_requires_password_and_code() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let (user_id, secret, _) = enrolled_user(&user_service).await?;
+
+        let wrong_password = user_service
+            .disable_totp(user_id, "wrong", &code_for(&secret, 0))
+            .await;
+        assert!(wrong_password.is_err());
+
+        user_service
+            .disable_totp(user_id, "password", &code_for(&secret, 0))
+            .await?;
+
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert!(user.totp_enabled_at.is_none());
+        assert!(user.totp_secret.is_none());
+        assert_eq!(entity::recovery_code::Entity::find().count(&db).await?, 0);
+
+        Ok(())
+    }
+}
+
+mod profile {
+    use super::*;
+    use crate::{
+        db::MockRedisRefresh,
+        mail::test::CapturingMailSender,
+        models::user::{EmailChange, PasswordChange, UserPreferences},
+        services::{AuthService, KeyEncryptionKey, KeyRing, UserServiceError},
+    };
+    use geolocation_utils::DistanceUnit;
+    use rust_decimal::Decimal;
+    use sea_orm::{ActiveValue, Set};
+
+    async fn create_user(user_service: &UserService, name: &str) -> Result<i64, UserServiceError> {
+        user_service
+            .create_user(
+                UserRegister {
+                    username: name.into(),
+                    email: format!("{name}@test.com"),
+                    password: "password".into(),
+                },
+                false,
+            )
+            .await
+    }
+
+    async fn add_session(db: &DatabaseConnection, user_id: i64, token: &str) -> E {
+        entity::refresh_token::ActiveModel {
+            token: ActiveValue::Set(token.into()),
+            family_id: ActiveValue::Set(token.into()),
+            user_id: ActiveValue::Set(user_id),
+            expires_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn password_change_keeps_only_the_current_session() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let user_id = create_user(&user_service, "test").await?;
+        add_session(&db, user_id, "current").await?;
+        add_session(&db, user_id, "other").await?;
+        let mut redis = MockRedisRefresh::default();
+        redis.expect_delete_item().returning(|_| Ok(()));
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let wrong = user_service
+            .change_password(
+                user_id,
+                PasswordChange {
+                    current_password: "wrong".into(),
+                    new_password: "newPassword".into(),
+                },
+                Some("current"),
+                &mut auth_service,
+            )
+            .await;
+        assert!(matches!(wrong, Err(UserServiceError::AuthServiceError(_))));
+
+        user_service
+            .change_password(
+                user_id,
+                PasswordChange {
+                    current_password: "password".into(),
+                    new_password: "newPassword".into(),
+                },
+                Some("current"),
+                &mut auth_service,
+            )
+            .await?;
+
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert!(AuthService::verify_password(&user.password, "newPassword")?);
+        let remaining = entity::refresh_token::Entity::find().all(&db).await?;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].token, "current");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn email_change_waits_for_verification() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let mailer = CapturingMailSender::default();
+        let user_id = create_user(&user_service, "test").await?;
+        create_user(&user_service, "other").await?;
+
+        let taken = user_service
+            .request_email_change(
+                user_id,
+                EmailChange {
+                    email: "other@test.com".into(),
+                    password: "password".into(),
+                },
+                &mailer,
+            )
+            .await;
+        assert!(matches!(
+            taken,
+            Err(UserServiceError::DuplicateUserError(_))
+        ));
+
+        user_service
+            .request_email_change(
+                user_id,
+                EmailChange {
+                    email: "new@test.com".into(),
+                    password: "password".into(),
+                },
+                &mailer,
+            )
+            .await?;
+
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert_eq!(user.email, "test@test.com");
+
+        let mail = mailer.sent().pop().unwrap();
+        assert_eq!(mail.to, "new@test.com");
+        let token = mail.body.split("token=").nth(1).unwrap().trim();
+        user_service.verify_email(token).await?;
+
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert_eq!(user.email, "new@test.com");
+        assert!(user.verified_at.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn username_change_is_checked_and_throttled() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let user_id = create_user(&user_service, "test").await?;
+        create_user(&user_service, "other").await?;
+
+        let forbidden = user_service.change_username(user_id, "siteAdmin").await;
+        assert!(matches!(
+            forbidden,
+            Err(UserServiceError::ForbiddenWords(_))
+        ));
+        let taken = user_service.change_username(user_id, "OTHER").await;
+        assert!(matches!(
+            taken,
+            Err(UserServiceError::DuplicateUserError(_))
+        ));
+
+        user_service.change_username(user_id, "renamed").await?;
+        let too_soon = user_service.change_username(user_id, "renamedAgain").await;
+        assert!(matches!(
+            too_soon,
+            Err(UserServiceError::UsernameChangeTooSoon(_))
+        ));
+
+        let mut user: entity::user::ActiveModel =
+            user_service.get_user_by_id(&user_id).await?.unwrap().into();
+        user.username_changed_at = Set(Some(
+            chrono::Utc::now().naive_utc() - chrono::Duration::days(31),
+        ));
+        user.update(&db).await?;
+
+        user_service
+            .change_username(user_id, "renamedAgain")
+            .await?;
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert_eq!(user.username, "renamedAgain");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn preferences_round_trip() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let user_id = create_user(&user_service, "test").await?;
+
+        assert_eq!(
+            user_service.get_preferences(user_id).await?,
+            UserPreferences::default()
+        );
+
+        let preferences = UserPreferences {
+            distance_unit: Some(DistanceUnit::Kilometers),
+            search_radius: Some(Decimal::from(25)),
+        };
+        user_service
+            .update_preferences(user_id, preferences.clone())
+            .await?;
+        assert_eq!(user_service.get_preferences(user_id).await?, preferences);
+
+        let invalid = user_service
+            .update_preferences(
+                user_id,
+                UserPreferences {
+                    distance_unit: None,
+                    search_radius: Some(Decimal::ZERO),
+                },
+            )
+            .await;
+        assert!(matches!(invalid, Err(UserServiceError::InvalidRequest(_))));
+
+        Ok(())
+    }
+}
+
+mod deletion {
+    use super::*;
+    use crate::{
+        db::MockRedisRefresh,
+        mail::test::CapturingMailSender,
+        models::user::AccountStatus,
+        services::{
+            user_service::deletion::ACCOUNT_DELETION_GRACE_DAYS, AuthService, KeyEncryptionKey,
+            KeyRing, UserServiceError,
+        },
+    };
+    use rust_decimal::Decimal;
+    use sea_orm::ActiveValue;
+
+    async fn create_user(user_service: &UserService, name: &str) -> Result<i64, UserServiceError> {
+        user_service
+            .create_user(
+                UserRegister {
+                    username: name.into(),
+                    email: format!("{name}@test.com"),
+                    password: "password".into(),
+                },
+                false,
+            )
+            .await
+    }
+
+    async fn create_listing(
+        db: &DatabaseConnection,
+        user_id: i64,
+        title: &str,
+    ) -> Result<i64, Box<dyn std::error::Error>> {
+        let now = chrono::Utc::now().naive_utc();
+        let product = entity::product::ActiveModel {
+            product_title: ActiveValue::Set(title.into()),
+            description: ActiveValue::Set("description".into()),
+            price: ActiveValue::Set(Decimal::new(5, 0)),
+            location_country: ActiveValue::Set("country".into()),
+            location_state: ActiveValue::Set("state".into()),
+            location_city: ActiveValue::Set("city".into()),
+            location_zip: ActiveValue::Set("zip".into()),
+            created_by: ActiveValue::Set(user_id),
+            created_at: ActiveValue::Set(now),
+            updated_at: ActiveValue::Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        Ok(product.id)
+    }
+
+    #[tokio::test]
+    async fn deletion_can_be_cancelled_during_the_grace_period() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db);
+        let mailer = CapturingMailSender::default();
+        let user_id = create_user(&user_service, "test").await?;
+
+        let wrong = user_service
+            .request_deletion(user_id, "wrong", &mailer)
+            .await;
+        assert!(matches!(wrong, Err(UserServiceError::AuthServiceError(_))));
+
+        let scheduled = user_service
+            .request_deletion(user_id, "password", &mailer)
+            .await?;
+        let again = user_service
+            .request_deletion(user_id, "password", &mailer)
+            .await?;
+        assert_eq!(scheduled, again);
+        assert_eq!(mailer.sent().len(), 1);
+
+        user_service.cancel_deletion(user_id).await?;
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert!(user.deletion_requested_at.is_none());
+
+        let nothing_pending = user_service.cancel_deletion(user_id).await;
+        assert!(matches!(
+            nothing_pending,
+            Err(UserServiceError::InvalidRequest(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deleting_anonymizes_the_account() -> E {
+        let db = establish_connection().await?;
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+        let user_service = UserService::new(db.clone());
+        let user_id = create_user(&user_service, "test").await?;
+        let reviewer_id = create_user(&user_service, "reviewer").await?;
+
+        let plain = create_listing(&db, user_id, "plain").await?;
+        let audited = create_listing(&db, user_id, "audited").await?;
+        let now = chrono::Utc::now().naive_utc();
+        entity::product_audit::ActiveModel {
+            created_at: ActiveValue::Set(now),
+            updated_at: ActiveValue::Set(now),
+            reviewer_id: ActiveValue::Set(reviewer_id),
+            product_id: ActiveValue::Set(audited),
+            review_status: ActiveValue::Set(0),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await?;
+
+        let location = dir.join("picture.png");
+        std::fs::write(&location, b"picture")?;
+        entity::file::ActiveModel {
+            created_by: ActiveValue::Set(user_id),
+            file_location: ActiveValue::Set(location.to_str().unwrap().to_owned()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await?;
+
+        let mut redis = MockRedisRefresh::default();
+        redis.expect_delete_item().returning(|_| Ok(()));
+        redis
+            .expect_set_item_with_expiry()
+            .returning(|_, _, _| Ok(()));
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        user_service
+            .delete_account(user_id, &mut auth_service)
+            .await?;
+
+        let user = user_service.get_user_by_id(&user_id).await?.unwrap();
+        assert_eq!(AccountStatus::of(&user), AccountStatus::Deleted);
+        assert!(user.username.starts_with("deleted-"));
+        assert!(!AuthService::verify_password(&user.password, "password")?);
+        assert!(!user_service.username_exists("test").await?);
+
+        assert!(entity::product::Entity::find_by_id(plain)
+            .one(&db)
+            .await?
+            .is_none());
+        let kept = entity::product::Entity::find_by_id(audited)
+            .one(&db)
+            .await?
+            .unwrap();
+        assert_ne!(kept.product_title, "audited");
+
+        assert!(!location.exists());
+        assert_eq!(entity::file::Entity::find().count(&db).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn a_failing_account_does_not_stop_the_purge() -> E {
+        let db = establish_connection().await?;
+        let user_service = UserService::new(db.clone());
+        let failing = create_user(&user_service, "failing").await?;
+        let due = create_user(&user_service, "due").await?;
+        let requested_at = chrono::Utc::now().naive_utc()
+            - chrono::Duration::days(ACCOUNT_DELETION_GRACE_DAYS + 1);
+        for user_id in [failing, due] {
+            entity::user::ActiveModel {
+                id: ActiveValue::Unchanged(user_id),
+                deletion_requested_at: ActiveValue::Set(Some(requested_at)),
+                ..Default::default()
+            }
+            .update(&db)
+            .await?;
+        }
+
+        let mut redis = MockRedisRefresh::default();
+        let failing_key = format!("jwt_denylist:user:{failing}");
+        redis
+            .expect_set_item_with_expiry()
+            .returning(move |key, _, _| {
+                if key == failing_key {
+                    return Err("redis went away".into());
+                }
+                Ok(())
+            });
+        let mut auth_service = AuthService::new(
+            db.clone(),
+            Box::new(redis),
+            KeyRing::load(&db, KeyEncryptionKey::test()).await?,
+        );
+
+        let purged = user_service.purge_due_deletions(&mut auth_service).await?;
+        assert_eq!(purged, (1, 1));
+
+        let failing = user_service.get_user_by_id(&failing).await?.unwrap();
+        assert_eq!(AccountStatus::of(&failing), AccountStatus::Active);
+        let due = user_service.get_user_by_id(&due).await?.unwrap();
+        assert_eq!(AccountStatus::of(&due), AccountStatus::Deleted);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+use super::{UserService, UserServiceError};
+use crate::{
+    dtos::auth::{LoginReturn, TotpEnrollment},
+    models::session::SessionMetadata,
+    services::auth_service::{AuthService, AuthServiceError},
+    AnyhowResponder,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use entity::{
+    recovery_code::{self, ActiveModel as RecoveryCodeActiveModel, Entity as RecoveryCodeEntity},
+    user::{ActiveModel as UserActiveModel, Model as UserModel},
+};
+use sea_orm::{prelude::*, ActiveValue, Set};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const TOTP_ISSUER: &str = "Tekxchange";
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP_SECONDS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+fn totp_for(secret: &str, user: &UserModel) -> Result<TOTP, UserServiceError> {
+    let secret = Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!("{e:?}"))))?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        0,
+        TOTP_STEP_SECONDS,
+        secret,
+        Some(TOTP_ISSUER.to_owned()),
+        user.username.replace(':', ""),
+    )
+    .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))
+}
+
+/// Time step `code` belongs to, allowing one step of clock drift either way
+fn matching_step(totp: &TOTP, code: &str, now: u64) -> Option<i64> {
+    let current = now / TOTP_STEP_SECONDS;
+
+    [current.saturating_sub(1), current, current + 1]
+        .into_iter()
+        .find(|step| totp.check(code, step * TOTP_STEP_SECONDS))
+        .map(|step| step as i64)
+}
+
+fn generate_recovery_code() -> String {
+    let raw = uuid::Uuid::new_v4().simple().to_string();
+    format!("{}-{}", &raw[0..5], &raw[5..10])
+}
+
+impl UserService {
+    pub(super) async fn find_user(&self, user_id: i64) -> Result<UserModel, UserServiceError> {
+        self.get_user_by_id(&user_id)
+            .await?
+            .ok_or(UserServiceError::UserNotFound(AnyhowResponder(anyhow!(
+                "Requested user is not found"
+            ))))
+    }
+
+    /// Generates a new TOTP secret. It only protects logins once confirmed with a code.
+    pub async fn begin_totp_enrollment(
+        &self,
+        user_id: i64,
+    ) -> Result<TotpEnrollment, UserServiceError> {
+        let user = self.find_user(user_id).await?;
+        if user.totp_enabled_at.is_some() {
+            return Err(UserServiceError::TwoFactorAlreadyEnabled(AnyhowResponder(
+                anyhow!("User {user_id} attempted to enroll in 2FA twice"),
+            )));
+        }
+
+        let secret = match Secret::generate_secret().to_encoded() {
+            Secret::Encoded(secret) => secret,
+            Secret::Raw(_) => unreachable!("to_encoded always returns an encoded secret"),
+        };
+        let totp = totp_for(&secret, &user)?;
+
+        let mut active_user: UserActiveModel = user.into();
+        active_user.totp_secret = Set(Some(secret.clone()));
+        active_user.totp_last_step = Set(None);
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(TotpEnrollment {
+            secret,
+            otpauth_uri: totp.get_url(),
+        })
+    }
+
+    /// Turns on 2FA once the user proves their authenticator works, returning
+    /// recovery codes that are only ever shown this once
+    pub async fn confirm_totp_enrollment(
+        &self,
+        user_id: i64,
+        code: &str,
+    ) -> Result<Vec<String>, UserServiceError> {
+        let user = self.find_user(user_id).await?;
+        if user.totp_enabled_at.is_some() {
+            return Err(UserServiceError::TwoFactorAlreadyEnabled(AnyhowResponder(
+                anyhow!("User {user_id} attempted to confirm 2FA twice"),
+            )));
+        }
+        if user.totp_secret.is_none() {
+            return Err(UserServiceError::TwoFactorNotEnabled(AnyhowResponder(
+                anyhow!("User {user_id} confirmed 2FA without enrolling"),
+            )));
+        }
+
+        if !self.verify_totp(&user, code).await? {
+            return Err(UserServiceError::InvalidTwoFactorCode(AnyhowResponder(
+                anyhow!("User {user_id} sent a wrong code while confirming 2FA"),
+            )));
+        }
+
+        let mut active_user: UserActiveModel = self.find_user(user_id).await?.into();
+        active_user.totp_enabled_at = Set(Some(Utc::now().naive_utc()));
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        self.replace_recovery_codes(user_id).await
+    }
+
+    /// Turns off 2FA. Needs the password and a current code or recovery code.
+    pub async fn disable_totp(
+        &self,
+        user_id: i64,
+        password: &str,
+        code: &str,
+    ) -> Result<(), UserServiceError> {
+        let user = self.find_user(user_id).await?;
+        if user.totp_enabled_at.is_none() {
+            return Err(UserServiceError::TwoFactorNotEnabled(AnyhowResponder(
+                anyhow!("User {user_id} attempted to disable 2FA while it is off"),
+            )));
+        }
+
+        if !AuthService::verify_password(&user.password, password)
+            .map_err(UserServiceError::AuthServiceError)?
+        {
+            return Err(UserServiceError::AuthServiceError(
+                AuthServiceError::LoginError(AnyhowResponder(anyhow!(
+                    "Unable to validate password"
+                ))),
+            ));
+        }
+
+        if !self.verify_second_factor(&user, code).await? {
+            return Err(UserServiceError::InvalidTwoFactorCode(AnyhowResponder(
+                anyhow!("User {user_id} sent a wrong code while disabling 2FA"),
+            )));
+        }
+
+        let mut active_user: UserActiveModel = user.into();
+        active_user.totp_secret = Set(None);
+        active_user.totp_enabled_at = Set(None);
+        active_user.totp_last_step = Set(None);
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        RecoveryCodeEntity::delete_many()
+            .filter(recovery_code::Column::UserId.eq(user_id))
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(())
+    }
+
+    /// Second login step, exchanging a challenge from `login` and a code for tokens
+    pub async fn complete_two_factor_login(
+        &self,
+        challenge: &str,
+        code: &str,
+        auth_service: &mut AuthService,
+        session: SessionMetadata,
+    ) -> Result<LoginReturn, UserServiceError> {
+        let challenge = auth_service
+            .validate_two_factor_challenge(challenge)
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+        let user = self.find_user(challenge.user_id).await?;
+        let ip_address = session.ip_address.clone();
+        auth_service
+            .check_login_throttle(Some(user.id), ip_address.as_deref())
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+        auth_service
+            .take_two_factor_attempt(&challenge)
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+
+        if user.totp_enabled_at.is_none() || !self.verify_second_factor(&user, code).await? {
+            auth_service
+                .record_login_failure(Some(user.id), ip_address.as_deref())
+                .await
+                .map_err(UserServiceError::AuthServiceError)?;
+
+            return Err(UserServiceError::InvalidTwoFactorCode(AnyhowResponder(
+                anyhow!("User {} sent a wrong two factor code", user.id),
+            )));
+        }
+
+        auth_service
+            .consume_two_factor_challenge(&challenge)
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+        auth_service
+            .clear_login_failures(user.id)
+            .await
+            .map_err(UserServiceError::AuthServiceError)?;
+
+        let session = session.with_device_name(challenge.device_name);
+        auth_service
+            .issue_login(&user, &session)
+            .await
+            .map_err(UserServiceError::AuthServiceError)
+    }
+
+    /// Accepts either a TOTP code or an unused recovery code
+    async fn verify_second_factor(
+        &self,
+        user: &UserModel,
+        code: &str,
+    ) -> Result<bool, UserServiceError> {
+        let code = code.trim();
+        if code.len() == TOTP_DIGITS && code.chars().all(|c| c.is_ascii_digit()) {
+            return self.verify_totp(user, code).await;
+        }
+
+        self.use_recovery_code(user.id, code).await
+    }
+
+    /// Checks a TOTP code, refusing codes from a step that was already used
+    async fn verify_totp(&self, user: &UserModel, code: &str) -> Result<bool, UserServiceError> {
+        let secret = match user.totp_secret {
+            Some(ref secret) => secret,
+            None => return Ok(false),
+        };
+
+        let totp = totp_for(secret, user)?;
+        let now = Utc::now().timestamp() as u64;
+        let step = match matching_step(&totp, code, now) {
+            Some(step) => step,
+            None => return Ok(false),
+        };
+
+        // Claim the step atomically so a code can't be replayed, even concurrently
+        let claimed = entity::user::Entity::update_many()
+            .col_expr(entity::user::Column::TotpLastStep, Expr::value(step))
+            .filter(entity::user::Column::Id.eq(user.id))
+            .filter(
+                sea_orm::Condition::any()
+                    .add(entity::user::Column::TotpLastStep.is_null())
+                    .add(entity::user::Column::TotpLastStep.lt(step)),
+            )
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(claimed.rows_affected == 1)
+    }
+
+    async fn use_recovery_code(&self, user_id: i64, code: &str) -> Result<bool, UserServiceError> {
+        let codes = RecoveryCodeEntity::find()
+            .filter(recovery_code::Column::UserId.eq(user_id))
+            .filter(recovery_code::Column::UsedAt.is_null())
+            .all(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        for recovery in codes {
+            if !AuthService::verify_password(&recovery.code_hash, code)
+                .map_err(UserServiceError::AuthServiceError)?
+            {
+                continue;
+            }
+
+            let claimed = RecoveryCodeEntity::update_many()
+                .col_expr(
+                    recovery_code::Column::UsedAt,
+                    Expr::value(Utc::now().naive_utc()),
+                )
+                .filter(recovery_code::Column::Id.eq(recovery.id))
+                .filter(recovery_code::Column::UsedAt.is_null())
+                .exec(&self.db_connection)
+                .await
+                .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+            return Ok(claimed.rows_affected == 1);
+        }
+
+        Ok(false)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: i64) -> Result<Vec<String>, UserServiceError> {
+        RecoveryCodeEntity::delete_many()
+            .filter(recovery_code::Column::UserId.eq(user_id))
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+
+        let models = codes
+            .iter()
+            .map(|code| {
+                Ok(RecoveryCodeActiveModel {
+                    user_id: ActiveValue::Set(user_id),
+                    code_hash: ActiveValue::Set(
+                        AuthService::hash_password(code)
+                            .map_err(UserServiceError::AuthServiceError)?,
+                    ),
+                    created_at: ActiveValue::Set(Utc::now().naive_utc()),
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, UserServiceError>>()?;
+
+        RecoveryCodeEntity::insert_many(models)
+            .exec(&self.db_connection)
+            .await
+            .map_err(|e| UserServiceError::InternalError(AnyhowResponder(anyhow!(e))))?;
+
+        Ok(codes)
+    }
+}
@@ -11,14 +11,23 @@ pub struct Model {
     pub updated_at: DateTime,
     #[sea_orm(unique)]
     pub category_name: String,
+    pub parent_id: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::category_attribute::Entity")]
+    CategoryAttribute,
     #[sea_orm(has_many = "super::product_category::Entity")]
     ProductCategory,
 }
 
+impl Related<super::category_attribute::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CategoryAttribute.def()
+    }
+}
+
 impl Related<super::product_category::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::ProductCategory.def()
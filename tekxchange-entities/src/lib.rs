@@ -2,11 +2,27 @@
 
 pub mod prelude;
 
+pub mod admin_audit_log;
+pub mod blocked_term;
 pub mod category;
+pub mod category_attribute;
+pub mod data_export;
+pub mod email_verification_token;
 pub mod file;
+pub mod login_lockout;
+pub mod password_reset_token;
+pub mod personal_access_token;
 pub mod product;
+pub mod product_attribute;
 pub mod product_audit;
 pub mod product_category;
 pub mod product_picture;
+pub mod product_price;
+pub mod product_revision;
+pub mod recovery_code;
 pub mod refresh_token;
+pub mod seller_review;
+pub mod signing_key;
 pub mod user;
+pub mod user_identity;
+pub mod user_preference;
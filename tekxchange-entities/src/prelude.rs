@@ -1,10 +1,26 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
+pub use super::admin_audit_log::Entity as AdminAuditLog;
+pub use super::blocked_term::Entity as BlockedTerm;
 pub use super::category::Entity as Category;
+pub use super::category_attribute::Entity as CategoryAttribute;
+pub use super::data_export::Entity as DataExport;
+pub use super::email_verification_token::Entity as EmailVerificationToken;
 pub use super::file::Entity as File;
+pub use super::login_lockout::Entity as LoginLockout;
+pub use super::password_reset_token::Entity as PasswordResetToken;
+pub use super::personal_access_token::Entity as PersonalAccessToken;
 pub use super::product::Entity as Product;
+pub use super::product_attribute::Entity as ProductAttribute;
 pub use super::product_audit::Entity as ProductAudit;
 pub use super::product_category::Entity as ProductCategory;
 pub use super::product_picture::Entity as ProductPicture;
+pub use super::product_price::Entity as ProductPrice;
+pub use super::product_revision::Entity as ProductRevision;
+pub use super::recovery_code::Entity as RecoveryCode;
 pub use super::refresh_token::Entity as RefreshToken;
+pub use super::seller_review::Entity as SellerReview;
+pub use super::signing_key::Entity as SigningKey;
 pub use super::user::Entity as User;
+pub use super::user_identity::Entity as UserIdentity;
+pub use super::user_preference::Entity as UserPreference;
@@ -19,6 +19,16 @@ pub struct Model {
     pub created_by: i64,
     pub created_at: DateTime,
     pub updated_at: DateTime,
+    pub sold_at: Option<DateTime>,
+    pub status: String,
+    pub published_at: Option<DateTime>,
+    pub reserved_at: Option<DateTime>,
+    pub expired_at: Option<DateTime>,
+    pub removed_at: Option<DateTime>,
+    pub expires_at: Option<DateTime>,
+    pub expiry_notified_at: Option<DateTime>,
+    pub bumped_at: Option<DateTime>,
+    pub ranked_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
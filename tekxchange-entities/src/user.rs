@@ -15,18 +15,63 @@ pub struct Model {
     pub role: i16,
     pub created_at: DateTime,
     pub updated_at: DateTime,
+    pub verified_at: Option<DateTime>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime>,
+    pub totp_last_step: Option<i64>,
+    pub suspended_at: Option<DateTime>,
+    pub suspended_until: Option<DateTime>,
+    pub suspension_reason: Option<String>,
+    pub banned: bool,
+    pub username_changed_at: Option<DateTime>,
+    pub bio: Option<String>,
+    pub avatar_file_id: Option<i64>,
+    pub deletion_requested_at: Option<DateTime>,
+    pub deleted_at: Option<DateTime>,
+    #[sea_orm(unique)]
+    pub username_normalized: String,
+    #[sea_orm(unique)]
+    pub email_normalized: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::data_export::Entity")]
+    DataExport,
+    #[sea_orm(has_many = "super::email_verification_token::Entity")]
+    EmailVerificationToken,
     #[sea_orm(has_many = "super::file::Entity")]
     File,
+    #[sea_orm(has_many = "super::login_lockout::Entity")]
+    LoginLockout,
+    #[sea_orm(has_many = "super::password_reset_token::Entity")]
+    PasswordResetToken,
+    #[sea_orm(has_many = "super::personal_access_token::Entity")]
+    PersonalAccessToken,
     #[sea_orm(has_many = "super::product::Entity")]
     Product,
     #[sea_orm(has_many = "super::product_audit::Entity")]
     ProductAudit,
+    #[sea_orm(has_many = "super::recovery_code::Entity")]
+    RecoveryCode,
     #[sea_orm(has_many = "super::refresh_token::Entity")]
     RefreshToken,
+    #[sea_orm(has_many = "super::user_identity::Entity")]
+    UserIdentity,
+    #[sea_orm(has_one = "super::user_preference::Entity")]
+    UserPreference,
+}
+
+impl Related<super::data_export::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DataExport.def()
+    }
+}
+
+impl Related<super::email_verification_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::EmailVerificationToken.def()
+    }
 }
 
 impl Related<super::file::Entity> for Entity {
@@ -35,6 +80,24 @@ impl Related<super::file::Entity> for Entity {
     }
 }
 
+impl Related<super::login_lockout::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginLockout.def()
+    }
+}
+
+impl Related<super::password_reset_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordResetToken.def()
+    }
+}
+
+impl Related<super::personal_access_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PersonalAccessToken.def()
+    }
+}
+
 impl Related<super::product::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Product.def()
@@ -47,10 +110,28 @@ impl Related<super::product_audit::Entity> for Entity {
     }
 }
 
+impl Related<super::recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCode.def()
+    }
+}
+
 impl Related<super::refresh_token::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::RefreshToken.def()
     }
 }
 
+impl Related<super::user_identity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserIdentity.def()
+    }
+}
+
+impl Related<super::user_preference::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserPreference.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
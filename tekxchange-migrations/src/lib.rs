@@ -7,6 +7,30 @@ mod m20230108_031029_product_audit;
 mod m20230109_234237_category;
 mod m20230118_011838_file;
 mod m20230521_213320_refresh;
+mod m20261018_000001_refresh_sessions;
+mod m20261018_000002_refresh_families;
+mod m20261018_000003_signing_keys;
+mod m20261018_000004_password_reset_tokens;
+mod m20261018_000005_email_verification;
+mod m20261018_000006_two_factor;
+mod m20261018_000007_user_identities;
+mod m20261018_000008_personal_access_tokens;
+mod m20261018_000009_login_lockouts;
+mod m20261018_000010_admin_audit_log;
+mod m20261018_000011_user_suspensions;
+mod m20261018_000012_user_profiles;
+mod m20261018_000013_seller_profiles;
+mod m20261018_000014_data_exports;
+mod m20261018_000015_account_deletion;
+mod m20261018_000016_normalized_identities;
+mod m20261018_000017_blocklist;
+mod m20261018_000018_category_tree;
+mod m20261018_000019_category_attributes;
+mod m20261018_000020_listing_status;
+mod m20261018_000021_listing_expiry;
+mod m20261018_000022_product_history;
+mod m20261018_000023_encrypted_signing_keys;
+mod m20261018_000024_review_listings;
 mod utils;
 
 pub struct Migrator;
@@ -22,6 +46,30 @@ impl MigratorTrait for Migrator {
             Box::new(m20230109_234237_category::Migration),
             Box::new(m20230118_011838_file::Migration),
             Box::new(m20230521_213320_refresh::Migration),
+            Box::new(m20261018_000001_refresh_sessions::Migration),
+            Box::new(m20261018_000002_refresh_families::Migration),
+            Box::new(m20261018_000003_signing_keys::Migration),
+            Box::new(m20261018_000004_password_reset_tokens::Migration),
+            Box::new(m20261018_000005_email_verification::Migration),
+            Box::new(m20261018_000006_two_factor::Migration),
+            Box::new(m20261018_000007_user_identities::Migration),
+            Box::new(m20261018_000008_personal_access_tokens::Migration),
+            Box::new(m20261018_000009_login_lockouts::Migration),
+            Box::new(m20261018_000010_admin_audit_log::Migration),
+            Box::new(m20261018_000011_user_suspensions::Migration),
+            Box::new(m20261018_000012_user_profiles::Migration),
+            Box::new(m20261018_000013_seller_profiles::Migration),
+            Box::new(m20261018_000014_data_exports::Migration),
+            Box::new(m20261018_000015_account_deletion::Migration),
+            Box::new(m20261018_000016_normalized_identities::Migration),
+            Box::new(m20261018_000017_blocklist::Migration),
+            Box::new(m20261018_000018_category_tree::Migration),
+            Box::new(m20261018_000019_category_attributes::Migration),
+            Box::new(m20261018_000020_listing_status::Migration),
+            Box::new(m20261018_000021_listing_expiry::Migration),
+            Box::new(m20261018_000022_product_history::Migration),
+            Box::new(m20261018_000023_encrypted_signing_keys::Migration),
+            Box::new(m20261018_000024_review_listings::Migration),
         ]
     }
 }
@@ -137,7 +137,7 @@ impl MigrationTrait for Migration {
 
 /// Learn more at https://docs.rs/sea-query#iden
 #[derive(Iden)]
-enum Category {
+pub enum Category {
     Table,
     Id,
     CreatedAt,
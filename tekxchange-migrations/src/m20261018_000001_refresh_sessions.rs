@@ -0,0 +1,193 @@
+use crate::m20220101_000001_create_table::User;
+use sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+/// Turns `refresh_token` into a per-device session table. The primary key
+/// moves from the token to a numeric id so sessions can be listed and
+/// revoked without exposing the token itself. Postgres keeps index names
+/// when a table is renamed, so the new table is built alongside the old one
+/// and swapped in afterwards.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut primary_key = ColumnDef::new(RefreshToken::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RefreshSession::Table)
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(RefreshToken::Token)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RefreshToken::UserId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RefreshToken::DeviceName).string_len(128))
+                    .col(ColumnDef::new(RefreshToken::UserAgent).string_len(512))
+                    .col(ColumnDef::new(RefreshToken::IpAddress).string_len(64))
+                    .col(
+                        ColumnDef::new(RefreshToken::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .col(
+                        ColumnDef::new(RefreshToken::LastUsedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .col(
+                        ColumnDef::new(RefreshToken::ExpiresAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(RefreshSession::Table, RefreshToken::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        #[cfg(not(feature = "sqlite"))]
+        let expires_at = "created_at + INTERVAL '30 days'";
+
+        #[cfg(feature = "sqlite")]
+        let expires_at = "datetime(created_at, '+30 days')";
+
+        let stmt = Statement::from_string(
+            manager.get_database_backend(),
+            format!(
+                r#"
+                INSERT INTO refresh_session (token, user_id, created_at, last_used_at, expires_at)
+                SELECT token, user_id, created_at, created_at, {expires_at} FROM refresh_token
+                "#
+            ),
+        );
+        manager.get_connection().execute(stmt).await?;
+
+        manager
+            .drop_table(Table::drop().table(RefreshToken::Table).to_owned())
+            .await?;
+
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(RefreshSession::Table, RefreshToken::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("refresh_token-user_id_index")
+                    .table(RefreshToken::Table)
+                    .col(RefreshToken::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RefreshSession::Table)
+                    .col(
+                        ColumnDef::new(RefreshToken::Token)
+                            .string()
+                            .not_null()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RefreshToken::UserId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RefreshToken::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(RefreshSession::Table, RefreshToken::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let stmt = Statement::from_string(
+            manager.get_database_backend(),
+            String::from(
+                r#"
+                INSERT INTO refresh_session (token, user_id, created_at)
+                SELECT token, user_id, created_at FROM refresh_token
+                "#,
+            ),
+        );
+        manager.get_connection().execute(stmt).await?;
+
+        manager
+            .drop_table(Table::drop().table(RefreshToken::Table).to_owned())
+            .await?;
+
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(RefreshSession::Table, RefreshToken::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+pub enum RefreshToken {
+    Table,
+    Id,
+    Token,
+    UserId,
+    DeviceName,
+    UserAgent,
+    IpAddress,
+    CreatedAt,
+    LastUsedAt,
+    ExpiresAt,
+}
+
+/// Temporary name used while the table is rebuilt
+#[derive(Iden)]
+enum RefreshSession {
+    Table,
+}
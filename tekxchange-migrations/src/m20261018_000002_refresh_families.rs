@@ -0,0 +1,105 @@
+use crate::m20261018_000001_refresh_sessions::RefreshToken;
+use sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RefreshToken::Table)
+                    .add_column(
+                        ColumnDef::new(RefreshFamily::FamilyId)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RefreshToken::Table)
+                    .add_column(ColumnDef::new(RefreshFamily::RotatedAt).timestamp())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Every existing session becomes the head of its own family
+        let stmt = Statement::from_string(
+            manager.get_database_backend(),
+            String::from("UPDATE refresh_token SET family_id = token"),
+        );
+        manager.get_connection().execute(stmt).await?;
+
+        #[cfg(not(feature = "sqlite"))]
+        {
+            let stmt = Statement::from_string(
+                manager.get_database_backend(),
+                String::from("ALTER TABLE refresh_token ALTER COLUMN family_id DROP DEFAULT"),
+            );
+            manager.get_connection().execute(stmt).await?;
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("refresh_token-family_id_index")
+                    .table(RefreshToken::Table)
+                    .col(RefreshFamily::FamilyId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("refresh_token-family_id_index")
+                    .table(RefreshToken::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Rotated tokens have no meaning without their family
+        let stmt = Statement::from_string(
+            manager.get_database_backend(),
+            String::from("DELETE FROM refresh_token WHERE rotated_at IS NOT NULL"),
+        );
+        manager.get_connection().execute(stmt).await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RefreshToken::Table)
+                    .drop_column(RefreshFamily::RotatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RefreshToken::Table)
+                    .drop_column(RefreshFamily::FamilyId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+pub enum RefreshFamily {
+    FamilyId,
+    RotatedAt,
+}
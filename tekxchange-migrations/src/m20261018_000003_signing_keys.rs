@@ -0,0 +1,55 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SigningKey::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SigningKey::Kid)
+                            .string_len(64)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(SigningKey::PrivateKey).text().not_null())
+                    .col(
+                        ColumnDef::new(SigningKey::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .col(ColumnDef::new(SigningKey::RetiredAt).timestamp())
+                    .col(ColumnDef::new(SigningKey::ExpiresAt).timestamp())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(SigningKey::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum SigningKey {
+    Table,
+    Kid,
+    PrivateKey,
+    CreatedAt,
+    RetiredAt,
+    ExpiresAt,
+}
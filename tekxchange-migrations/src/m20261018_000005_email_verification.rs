@@ -0,0 +1,133 @@
+use crate::m20220101_000001_create_table::User;
+use sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(UserVerification::VerifiedAt).timestamp())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Accounts created before verification existed are trusted as they are
+        let stmt = Statement::from_string(
+            manager.get_database_backend(),
+            String::from(r#"UPDATE "user" SET verified_at = created_at"#),
+        );
+        manager.get_connection().execute(stmt).await?;
+
+        let mut primary_key = ColumnDef::new(EmailVerificationToken::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(EmailVerificationToken::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::UserId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::Email)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::ExpiresAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(EmailVerificationToken::UsedAt).timestamp())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(
+                                EmailVerificationToken::Table,
+                                EmailVerificationToken::UserId,
+                            )
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("email_verification_token-user_id_index")
+                    .table(EmailVerificationToken::Table)
+                    .col(EmailVerificationToken::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(EmailVerificationToken::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(UserVerification::VerifiedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum UserVerification {
+    VerifiedAt,
+}
+
+#[derive(Iden)]
+enum EmailVerificationToken {
+    Table,
+    Id,
+    UserId,
+    Email,
+    TokenHash,
+    CreatedAt,
+    ExpiresAt,
+    UsedAt,
+}
@@ -0,0 +1,128 @@
+use crate::m20220101_000001_create_table::User;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only supports one change per ALTER TABLE
+        for mut column in [
+            ColumnDef::new(UserTwoFactor::TotpSecret)
+                .string_len(64)
+                .to_owned(),
+            ColumnDef::new(UserTwoFactor::TotpEnabledAt)
+                .timestamp()
+                .to_owned(),
+            ColumnDef::new(UserTwoFactor::TotpLastStep)
+                .big_integer()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        let mut primary_key = ColumnDef::new(RecoveryCode::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecoveryCode::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(RecoveryCode::UserId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RecoveryCode::CodeHash).string().not_null())
+                    .col(
+                        ColumnDef::new(RecoveryCode::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .col(ColumnDef::new(RecoveryCode::UsedAt).timestamp())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(RecoveryCode::Table, RecoveryCode::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("recovery_code-user_id_index")
+                    .table(RecoveryCode::Table)
+                    .col(RecoveryCode::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(RecoveryCode::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [
+            UserTwoFactor::TotpLastStep,
+            UserTwoFactor::TotpEnabledAt,
+            UserTwoFactor::TotpSecret,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum UserTwoFactor {
+    TotpSecret,
+    TotpEnabledAt,
+    TotpLastStep,
+}
+
+#[derive(Iden)]
+enum RecoveryCode {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    CreatedAt,
+    UsedAt,
+}
@@ -0,0 +1,120 @@
+use crate::m20220101_000001_create_table::User;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(UserSuspension::SuspendedAt).timestamp())
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut primary_key = ColumnDef::new(AdminAuditLog::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AdminAuditLog::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(AdminAuditLog::ActorId).big_integer())
+                    .col(ColumnDef::new(AdminAuditLog::TargetUserId).big_integer())
+                    .col(
+                        ColumnDef::new(AdminAuditLog::Action)
+                            .string_len(32)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AdminAuditLog::Before).text())
+                    .col(ColumnDef::new(AdminAuditLog::After).text())
+                    .col(ColumnDef::new(AdminAuditLog::Reason).text().not_null())
+                    .col(
+                        ColumnDef::new(AdminAuditLog::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    // Entries outlive the accounts they mention
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AdminAuditLog::Table, AdminAuditLog::ActorId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AdminAuditLog::Table, AdminAuditLog::TargetUserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("admin_audit_log-target_user_id_index")
+                    .table(AdminAuditLog::Table)
+                    .col(AdminAuditLog::TargetUserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(AdminAuditLog::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(UserSuspension::SuspendedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum UserSuspension {
+    SuspendedAt,
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum AdminAuditLog {
+    Table,
+    Id,
+    ActorId,
+    TargetUserId,
+    Action,
+    Before,
+    After,
+    Reason,
+    CreatedAt,
+}
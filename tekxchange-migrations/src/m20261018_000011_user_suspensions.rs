@@ -0,0 +1,63 @@
+use crate::m20220101_000001_create_table::User;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only supports one change per ALTER TABLE
+        for mut column in [
+            ColumnDef::new(UserSuspension::SuspendedUntil)
+                .timestamp()
+                .to_owned(),
+            ColumnDef::new(UserSuspension::SuspensionReason)
+                .text()
+                .to_owned(),
+            ColumnDef::new(UserSuspension::Banned)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            UserSuspension::SuspendedUntil,
+            UserSuspension::SuspensionReason,
+            UserSuspension::Banned,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum UserSuspension {
+    SuspendedUntil,
+    SuspensionReason,
+    Banned,
+}
@@ -0,0 +1,198 @@
+use crate::{m20220101_000001_create_table::User, m20230107_225831_products::Product};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only supports one change per ALTER TABLE
+        for mut column in [
+            ColumnDef::new(SellerProfile::Bio).text().to_owned(),
+            ColumnDef::new(SellerProfile::AvatarFileId)
+                .big_integer()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Product::Table)
+                    .add_column(ColumnDef::new(SellerProfile::SoldAt).timestamp())
+                    .to_owned(),
+            )
+            .await?;
+
+        for mut column in [
+            ColumnDef::new(ProfilePrivacy::ShowMemberSince),
+            ColumnDef::new(ProfilePrivacy::ShowListingCount),
+            ColumnDef::new(ProfilePrivacy::ShowSalesCount),
+            ColumnDef::new(ProfilePrivacy::ShowRating),
+            ColumnDef::new(ProfilePrivacy::ShowListings),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ProfilePrivacy::UserPreference)
+                        .add_column(column.boolean().not_null().default(true))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        let mut primary_key = ColumnDef::new(SellerReview::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SellerReview::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(SellerReview::SellerId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SellerReview::ReviewerId).big_integer())
+                    .col(
+                        ColumnDef::new(SellerReview::Rating)
+                            .small_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SellerReview::Comment).text())
+                    .col(
+                        ColumnDef::new(SellerReview::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SellerReview::Table, SellerReview::SellerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SellerReview::Table, SellerReview::ReviewerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("seller_review-seller_id-reviewer_id_index")
+                    .table(SellerReview::Table)
+                    .col(SellerReview::SellerId)
+                    .col(SellerReview::ReviewerId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(SellerReview::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [
+            ProfilePrivacy::ShowMemberSince,
+            ProfilePrivacy::ShowListingCount,
+            ProfilePrivacy::ShowSalesCount,
+            ProfilePrivacy::ShowRating,
+            ProfilePrivacy::ShowListings,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ProfilePrivacy::UserPreference)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Product::Table)
+                    .drop_column(SellerProfile::SoldAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [SellerProfile::Bio, SellerProfile::AvatarFileId] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum SellerProfile {
+    Bio,
+    AvatarFileId,
+    SoldAt,
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum ProfilePrivacy {
+    UserPreference,
+    ShowMemberSince,
+    ShowListingCount,
+    ShowSalesCount,
+    ShowRating,
+    ShowListings,
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum SellerReview {
+    Table,
+    Id,
+    SellerId,
+    ReviewerId,
+    Rating,
+    Comment,
+    CreatedAt,
+}
@@ -0,0 +1,82 @@
+use crate::m20220101_000001_create_table::User;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut primary_key = ColumnDef::new(DataExport::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(DataExport::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(DataExport::UserId).big_integer().not_null())
+                    .col(ColumnDef::new(DataExport::Status).string_len(16).not_null())
+                    .col(ColumnDef::new(DataExport::FileLocation).string())
+                    .col(
+                        ColumnDef::new(DataExport::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .col(ColumnDef::new(DataExport::CompletedAt).timestamp())
+                    .col(ColumnDef::new(DataExport::ExpiresAt).timestamp())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(DataExport::Table, DataExport::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("data_export-user_id_index")
+                    .table(DataExport::Table)
+                    .col(DataExport::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(DataExport::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum DataExport {
+    Table,
+    Id,
+    UserId,
+    Status,
+    FileLocation,
+    CreatedAt,
+    CompletedAt,
+    ExpiresAt,
+}
@@ -0,0 +1,162 @@
+use crate::m20220101_000001_create_table::User;
+use sea_orm::ConnectionTrait;
+use sea_orm_migration::prelude::*;
+use std::collections::HashSet;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Kept in step with `normalize_identity` in the backend. Copied here so this
+/// migration keeps producing the same values if that one ever changes.
+fn normalize(value: &str) -> String {
+    value.trim().nfkc().collect::<String>().to_lowercase()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only supports one change per ALTER TABLE
+        for mut column in [
+            ColumnDef::new(NormalizedIdentity::UsernameNormalized)
+                .string()
+                .not_null()
+                .default("")
+                .to_owned(),
+            ColumnDef::new(NormalizedIdentity::EmailNormalized)
+                .string()
+                .not_null()
+                .default("")
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        let backend = manager.get_database_backend();
+        let connection = manager.get_connection();
+        let users = connection
+            .query_all(
+                backend.build(
+                    Query::select()
+                        .columns([User::Id, User::Username, User::Email])
+                        .from(User::Table)
+                        .order_by(User::Id, Order::Asc),
+                ),
+            )
+            .await?;
+
+        // The oldest account keeps an identity that now collides with another one.
+        // Newer ones get their id appended so the unique index holds, they are
+        // listed below and can't sign in with that identity until it is changed.
+        let mut usernames = HashSet::new();
+        let mut emails = HashSet::new();
+        for row in users {
+            let id: i64 = row.try_get("", "id")?;
+            let mut username = normalize(&row.try_get::<String>("", "username")?);
+            let mut email = normalize(&row.try_get::<String>("", "email")?);
+
+            if !usernames.insert(username.clone()) {
+                tracing::warn!(
+                    message = "Username collides with an older account after normalization",
+                    user_id = id,
+                    username
+                );
+                username = format!("{username}#{id}");
+            }
+            if !emails.insert(email.clone()) {
+                tracing::warn!(
+                    message = "Email collides with an older account after normalization",
+                    user_id = id,
+                    email
+                );
+                email = format!("{email}#{id}");
+            }
+
+            connection
+                .execute(
+                    backend.build(
+                        Query::update()
+                            .table(User::Table)
+                            .value(NormalizedIdentity::UsernameNormalized, username)
+                            .value(NormalizedIdentity::EmailNormalized, email)
+                            .and_where(Expr::col(User::Id).eq(id)),
+                    ),
+                )
+                .await?;
+        }
+
+        #[cfg(not(feature = "sqlite"))]
+        for column in ["username_normalized", "email_normalized"] {
+            let stmt = sea_orm::Statement::from_string(
+                backend,
+                format!(r#"ALTER TABLE "user" ALTER COLUMN {column} DROP DEFAULT"#),
+            );
+            connection.execute(stmt).await?;
+        }
+
+        for (name, column) in [
+            (
+                "user-username_normalized_index",
+                NormalizedIdentity::UsernameNormalized,
+            ),
+            (
+                "user-email_normalized_index",
+                NormalizedIdentity::EmailNormalized,
+            ),
+        ] {
+            manager
+                .create_index(
+                    Index::create()
+                        .name(name)
+                        .table(User::Table)
+                        .col(column)
+                        .unique()
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for name in [
+            "user-username_normalized_index",
+            "user-email_normalized_index",
+        ] {
+            manager
+                .drop_index(Index::drop().name(name).table(User::Table).to_owned())
+                .await?;
+        }
+
+        for column in [
+            NormalizedIdentity::UsernameNormalized,
+            NormalizedIdentity::EmailNormalized,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum NormalizedIdentity {
+    UsernameNormalized,
+    EmailNormalized,
+}
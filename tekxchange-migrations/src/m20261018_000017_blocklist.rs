@@ -0,0 +1,105 @@
+use crate::m20220101_000001_create_table::User;
+use sea_orm::ConnectionTrait;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// What the hard-coded username regex used to block. Words that commonly
+/// appear inside legitimate names only match on their own now, staff titles
+/// still match anywhere so `realadmin` or `admin2` can't impersonate anyone.
+const DEFAULT_TERMS: [(&str, &str); 11] = [
+    ("admin", "contains"),
+    ("moderator", "contains"),
+    ("ass", "word"),
+    ("piss", "word"),
+    ("fuck", "contains"),
+    ("shit", "contains"),
+    ("cunt", "contains"),
+    ("wank", "contains"),
+    ("twat", "contains"),
+    ("scunthorpe", "allow"),
+    ("badminton", "allow"),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut primary_key = ColumnDef::new(BlockedTerm::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(BlockedTerm::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(BlockedTerm::Term)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(BlockedTerm::Kind).string_len(16).not_null())
+                    .col(ColumnDef::new(BlockedTerm::CreatedBy).big_integer())
+                    .col(
+                        ColumnDef::new(BlockedTerm::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(BlockedTerm::Table, BlockedTerm::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut insert = Query::insert();
+        insert
+            .into_table(BlockedTerm::Table)
+            .columns([BlockedTerm::Term, BlockedTerm::Kind]);
+        for (term, kind) in DEFAULT_TERMS {
+            insert.values_panic([term.into(), kind.into()]);
+        }
+
+        let backend = manager.get_database_backend();
+        manager
+            .get_connection()
+            .execute(backend.build(&insert))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(BlockedTerm::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum BlockedTerm {
+    Table,
+    Id,
+    Term,
+    Kind,
+    CreatedBy,
+    CreatedAt,
+}
@@ -0,0 +1,190 @@
+use crate::{m20230107_225831_products::Product, m20230109_234237_category::Category};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut primary_key = ColumnDef::new(CategoryAttribute::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(CategoryAttribute::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(CategoryAttribute::CategoryId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CategoryAttribute::Name)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CategoryAttribute::Kind)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CategoryAttribute::Unit).string_len(16))
+                    .col(ColumnDef::new(CategoryAttribute::Options).text())
+                    .col(
+                        ColumnDef::new(CategoryAttribute::Required)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(CategoryAttribute::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(CategoryAttribute::Table, CategoryAttribute::CategoryId)
+                            .to(Category::Table, Category::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("category_attribute-category_id-name_index")
+                    .table(CategoryAttribute::Table)
+                    .col(CategoryAttribute::CategoryId)
+                    .col(CategoryAttribute::Name)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut primary_key = ColumnDef::new(ProductAttribute::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ProductAttribute::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(ProductAttribute::ProductId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ProductAttribute::AttributeId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ProductAttribute::TextValue).string_len(256))
+                    .col(ColumnDef::new(ProductAttribute::IntegerValue).big_integer())
+                    .col(ColumnDef::new(ProductAttribute::BooleanValue).boolean())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ProductAttribute::Table, ProductAttribute::ProductId)
+                            .to(Product::Table, Product::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ProductAttribute::Table, ProductAttribute::AttributeId)
+                            .to(CategoryAttribute::Table, CategoryAttribute::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("product_attribute-product_id-attribute_id_index")
+                    .table(ProductAttribute::Table)
+                    .col(ProductAttribute::ProductId)
+                    .col(ProductAttribute::AttributeId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("product_attribute-attribute_id_index")
+                    .table(ProductAttribute::Table)
+                    .col(ProductAttribute::AttributeId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(ProductAttribute::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(CategoryAttribute::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum CategoryAttribute {
+    Table,
+    Id,
+    CategoryId,
+    Name,
+    Kind,
+    Unit,
+    Options,
+    Required,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum ProductAttribute {
+    Table,
+    Id,
+    ProductId,
+    AttributeId,
+    TextValue,
+    IntegerValue,
+    BooleanValue,
+}
@@ -0,0 +1,117 @@
+use crate::m20230107_225831_products::Product;
+use sea_orm::ConnectionTrait;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only supports one change per ALTER TABLE
+        for mut column in [
+            ColumnDef::new(ListingStatus::Status)
+                .string_len(16)
+                .not_null()
+                .default("active")
+                .to_owned(),
+            ColumnDef::new(ListingStatus::PublishedAt)
+                .timestamp()
+                .to_owned(),
+            ColumnDef::new(ListingStatus::ReservedAt)
+                .timestamp()
+                .to_owned(),
+            ColumnDef::new(ListingStatus::ExpiredAt)
+                .timestamp()
+                .to_owned(),
+            ColumnDef::new(ListingStatus::RemovedAt)
+                .timestamp()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Product::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // Every existing listing was public, the ones marked as sold keep that
+        let backend = manager.get_database_backend();
+        let connection = manager.get_connection();
+        connection
+            .execute(
+                backend.build(
+                    Query::update()
+                        .table(Product::Table)
+                        .value(ListingStatus::PublishedAt, Expr::col(Product::CreatedAt)),
+                ),
+            )
+            .await?;
+        connection
+            .execute(
+                backend.build(
+                    Query::update()
+                        .table(Product::Table)
+                        .value(ListingStatus::Status, "sold")
+                        .and_where(Expr::col(ListingStatus::SoldAt).is_not_null()),
+                ),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("product-status_index")
+                    .table(Product::Table)
+                    .col(ListingStatus::Status)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("product-status_index")
+                    .table(Product::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [
+            ListingStatus::Status,
+            ListingStatus::PublishedAt,
+            ListingStatus::ReservedAt,
+            ListingStatus::ExpiredAt,
+            ListingStatus::RemovedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Product::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum ListingStatus {
+    Status,
+    PublishedAt,
+    ReservedAt,
+    SoldAt,
+    ExpiredAt,
+    RemovedAt,
+}
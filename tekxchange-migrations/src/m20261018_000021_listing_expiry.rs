@@ -0,0 +1,99 @@
+use crate::m20230107_225831_products::Product;
+use sea_orm::ConnectionTrait;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only supports one change per ALTER TABLE
+        for mut column in [
+            ColumnDef::new(ListingExpiry::ExpiresAt)
+                .timestamp()
+                .to_owned(),
+            ColumnDef::new(ListingExpiry::ExpiryNotifiedAt)
+                .timestamp()
+                .to_owned(),
+            ColumnDef::new(ListingExpiry::BumpedAt)
+                .timestamp()
+                .to_owned(),
+            ColumnDef::new(ListingExpiry::RankedAt)
+                .timestamp()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Product::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // Existing listings keep their order, they get an expiry date the first time expirations run
+        let backend = manager.get_database_backend();
+        manager
+            .get_connection()
+            .execute(backend.build(Query::update().table(Product::Table).value(
+                ListingExpiry::RankedAt,
+                Expr::col(ListingExpiry::PublishedAt),
+            )))
+            .await?;
+
+        for (name, column) in [
+            ("product-expires_at_index", ListingExpiry::ExpiresAt),
+            ("product-ranked_at_index", ListingExpiry::RankedAt),
+        ] {
+            manager
+                .create_index(
+                    Index::create()
+                        .name(name)
+                        .table(Product::Table)
+                        .col(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for name in ["product-expires_at_index", "product-ranked_at_index"] {
+            manager
+                .drop_index(Index::drop().name(name).table(Product::Table).to_owned())
+                .await?;
+        }
+
+        for column in [
+            ListingExpiry::ExpiresAt,
+            ListingExpiry::ExpiryNotifiedAt,
+            ListingExpiry::BumpedAt,
+            ListingExpiry::RankedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Product::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum ListingExpiry {
+    PublishedAt,
+    ExpiresAt,
+    ExpiryNotifiedAt,
+    BumpedAt,
+    RankedAt,
+}
@@ -0,0 +1,179 @@
+use crate::{m20220101_000001_create_table::User, m20230107_225831_products::Product};
+use sea_orm::ConnectionTrait;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut primary_key = ColumnDef::new(ProductRevision::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ProductRevision::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(ProductRevision::ProductId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ProductRevision::EditedBy).big_integer())
+                    .col(ColumnDef::new(ProductRevision::Changes).text().not_null())
+                    .col(
+                        ColumnDef::new(ProductRevision::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ProductRevision::Table, ProductRevision::ProductId)
+                            .to(Product::Table, Product::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ProductRevision::Table, ProductRevision::EditedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("product_revision-product_id_index")
+                    .table(ProductRevision::Table)
+                    .col(ProductRevision::ProductId)
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut primary_key = ColumnDef::new(ProductPrice::Id);
+
+        #[cfg(not(feature = "sqlite"))]
+        primary_key.big_integer();
+
+        #[cfg(feature = "sqlite")]
+        primary_key.integer();
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ProductPrice::Table)
+                    .if_not_exists()
+                    .col(primary_key.not_null().auto_increment().primary_key())
+                    .col(
+                        ColumnDef::new(ProductPrice::ProductId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ProductPrice::Price).decimal().not_null())
+                    .col(
+                        ColumnDef::new(ProductPrice::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra(String::from("DEFAULT CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ProductPrice::Table, ProductPrice::ProductId)
+                            .to(Product::Table, Product::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("product_price-product_id_index")
+                    .table(ProductPrice::Table)
+                    .col(ProductPrice::ProductId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Price history starts with the price every listing has now
+        let backend = manager.get_database_backend();
+        manager
+            .get_connection()
+            .execute(
+                backend.build(
+                    Query::insert()
+                        .into_table(ProductPrice::Table)
+                        .columns([
+                            ProductPrice::ProductId,
+                            ProductPrice::Price,
+                            ProductPrice::CreatedAt,
+                        ])
+                        .select_from(
+                            Query::select()
+                                .columns([Product::Id, Product::Price, Product::UpdatedAt])
+                                .from(Product::Table)
+                                .to_owned(),
+                        )
+                        .map_err(|e| DbErr::Custom(e.to_string()))?,
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(ProductPrice::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(ProductRevision::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum ProductRevision {
+    Table,
+    Id,
+    ProductId,
+    EditedBy,
+    Changes,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum ProductPrice {
+    Table,
+    Id,
+    ProductId,
+    Price,
+    CreatedAt,
+}
@@ -0,0 +1,26 @@
+use sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Signing keys used to be stored in plain text. They can't be encrypted here
+        // since the key encryption key never reaches the database, so they are dropped
+        // and the backend generates an encrypted one on startup. Access tokens signed
+        // with the old keys stop validating and clients fall back to their refresh token.
+        let stmt = Statement::from_string(
+            manager.get_database_backend(),
+            String::from("DELETE FROM signing_key"),
+        );
+        manager.get_connection().execute(stmt).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
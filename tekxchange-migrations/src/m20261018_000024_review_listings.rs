@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The listing a review is about. Reviews written before this stay without one.
+        // No foreign key since sqlite can't add one to an existing table, and listings
+        // of deleted accounts are removed while the reviews of their buyers remain.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ReviewListing::SellerReview)
+                    .add_column(ColumnDef::new(ReviewListing::ProductId).big_integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ReviewListing::SellerReview)
+                    .drop_column(ReviewListing::ProductId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum ReviewListing {
+    SellerReview,
+    ProductId,
+}